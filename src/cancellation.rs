@@ -0,0 +1,129 @@
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use tokio::sync::Notify;
+
+tokio::task_local! {
+    static REQUEST_CANCELLATION: CancellationToken;
+}
+
+/**
+    A token that may be used to signal cancellation of some ongoing work.
+
+    Cheap to clone - all clones share the same underlying cancellation state,
+    so cancelling any one of them will cancel all of them.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<CancellationTokenInner>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationTokenInner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    /**
+        Creates a new, not yet cancelled, token.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Cancels the token, and wakes up anyone waiting for it to be cancelled.
+
+        Cancelling an already cancelled token does nothing.
+    */
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            self.inner.notify.notify_waiters();
+        }
+    }
+
+    /**
+        Returns `true` if the token has been cancelled, otherwise `false`.
+    */
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /**
+        Waits until the token has been cancelled.
+
+        Returns immediately if the token has already been cancelled.
+    */
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            let mut notified = std::pin::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.is_cancelled() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
+/**
+    Runs the given future with a cancellation token for the current request.
+*/
+pub(crate) fn scope_request_cancellation<F: Future>(
+    token: CancellationToken,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    REQUEST_CANCELLATION.scope(token, future)
+}
+
+/**
+    Gets the cancellation token for the request currently being
+    handled, or a token that is never cancelled if there is none.
+*/
+pub(crate) fn current_request_cancellation() -> CancellationToken {
+    REQUEST_CANCELLATION
+        .try_with(CancellationToken::clone)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancellationToken;
+
+    #[test]
+    fn cancelling_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+
+        assert!(token.is_cancelled());
+        assert!(clone.is_cancelled());
+    }
+
+    #[test]
+    fn cancelled_resolves_once_cancelled() {
+        let token = CancellationToken::new();
+        let waiting = token.clone();
+
+        let handle = std::thread::spawn(move || {
+            futures::executor::block_on(waiting.cancelled());
+        });
+        token.cancel();
+
+        handle.join().expect("waiting thread does not panic");
+        futures::executor::block_on(token.cancelled());
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::HashMap,
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+};
+
+use async_lsp::lsp_types::{Position, Url, notification::Notification as LspNotification};
+use serde_json::Value;
+
+use crate::{
+    requests::{modify_incoming_position, modify_outgoing_position},
+    result::ServerResult,
+    server_state::ServerState,
+    server_trait::Server,
+    server_with_state::panic_message,
+};
+
+/**
+    A custom, server-specific, JSON-RPC notification.
+
+    Custom notifications are defined just like any other [`lsp_types`] notification,
+    and may optionally implement the methods in this trait to opt into the same
+    position encoding conversion that is used for the built-in notifications.
+
+    Custom notifications may be sent in both directions - from the client to
+    the server, which requires registering a handler for the notification using
+    [`Server::server_custom_notifications`], and from the server to the client,
+    using [`ServerState::notify_custom`].
+
+    [`lsp_types`]: crate::lsp_types
+*/
+pub trait CustomNotification: LspNotification {
+    /**
+        Extracts the URL of the document that the notification is for, if any.
+
+        A URL must be returned, and the document must be tracked by
+        the server, for positions in the notification to be converted.
+    */
+    #[allow(unused_variables)]
+    fn extract_url(params: &Self::Params) -> Option<Url> {
+        None
+    }
+
+    /**
+        Returns all positions in the notification parameters that should be
+        converted between UTF-8 and the negotiated position encoding.
+    */
+    #[allow(unused_variables)]
+    fn params_positions(params: &mut Self::Params) -> Vec<&mut Position> {
+        Vec::new()
+    }
+}
+
+/**
+    A handler for a [`CustomNotification`] sent by the client, implemented by a [`Server`].
+
+    Handlers must also be registered using [`Server::server_custom_notifications`].
+
+    Just like all other notifications, custom notifications are
+    handled synchronously, and in the order they were received.
+*/
+pub trait CustomNotificationHandler<N: CustomNotification>: Server {
+    /**
+        Handles the notification.
+
+        # Errors
+
+        Errors returned from this method will be logged, but are
+        otherwise ignored, and will not stop the server from running.
+    */
+    fn handle_custom_notification(&self, state: ServerState, params: N::Params)
+    -> ServerResult<()>;
+}
+
+type CustomNotificationFn<S> =
+    Box<dyn Fn(&S, ServerState, Value) -> ControlFlow<async_lsp::Result<()>> + Send + Sync>;
+
+/**
+    A set of custom notifications that a [`Server`] can handle.
+
+    Created using [`CustomNotifications::new`], and returned from
+    [`Server::server_custom_notifications`].
+*/
+pub struct CustomNotifications<S> {
+    handlers: HashMap<&'static str, CustomNotificationFn<S>>,
+}
+
+impl<S> CustomNotifications<S> {
+    /**
+        Creates a new, empty, set of custom notifications.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /**
+        Adds the given custom notification, handled by the server.
+
+        If a handler for the same method was already added, it will be replaced.
+    */
+    #[must_use]
+    pub fn with_notification<N>(mut self) -> Self
+    where
+        N: CustomNotification + 'static,
+        S: CustomNotificationHandler<N> + 'static,
+    {
+        self.handlers.insert(
+            N::METHOD,
+            Box::new(dispatch::<S, N>) as CustomNotificationFn<S>,
+        );
+        self
+    }
+
+    /**
+        Adds all custom notifications from another set, handled by a server that can
+        be reached from this one, keeping existing handlers for any duplicate methods.
+    */
+    pub(crate) fn merge_projected<U>(
+        mut self,
+        other: CustomNotifications<U>,
+        project: fn(&S) -> &U,
+    ) -> Self
+    where
+        S: 'static,
+        U: 'static,
+    {
+        for (method, handler) in other.handlers {
+            self.handlers.entry(method).or_insert_with(|| {
+                Box::new(move |server: &S, state, params| handler(project(server), state, params))
+            });
+        }
+        self
+    }
+
+    pub(crate) fn handle(
+        &self,
+        server: &S,
+        state: ServerState,
+        method: &str,
+        params: Value,
+    ) -> ControlFlow<async_lsp::Result<()>> {
+        if let Some(handler) = self.handlers.get(method) {
+            handler(server, state, params)
+        } else if method.starts_with("$/") {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(Err(async_lsp::Error::Routing(format!(
+                "Unhandled notification: {method}"
+            ))))
+        }
+    }
+}
+
+impl<S> Default for CustomNotifications<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dispatch<S, N>(
+    server: &S,
+    state: ServerState,
+    params: Value,
+) -> ControlFlow<async_lsp::Result<()>>
+where
+    N: CustomNotification,
+    S: CustomNotificationHandler<N>,
+{
+    let mut params = match serde_json::from_value::<N::Params>(params) {
+        Ok(params) => params,
+        Err(e) => return ControlFlow::Break(Err(e.into())),
+    };
+
+    if let Some(doc) = N::extract_url(&params).and_then(|url| state.document(&url)) {
+        for position in N::params_positions(&mut params) {
+            // NOTE: Notifications can not be answered with an error,
+            // so notifications with invalid positions are dropped instead
+            #[allow(unused_variables)]
+            if let Err(e) = modify_incoming_position(&state, &doc, position) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    "Dropping notification {} with invalid position: {e}",
+                    N::METHOD
+                );
+                return ControlFlow::Continue(());
+            }
+        }
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        server.handle_custom_notification(state, params)
+    }));
+
+    #[allow(unused_variables)]
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to handle notification '{}': {e}", N::METHOD);
+        }
+        Err(payload) => {
+            let message = panic_message(payload.as_ref());
+            #[cfg(feature = "tracing")]
+            tracing::error!(
+                "Handler for notification '{}' panicked: {message}",
+                N::METHOD
+            );
+        }
+    }
+
+    ControlFlow::Continue(())
+}
+
+pub(crate) fn convert_outgoing<N: CustomNotification>(state: &ServerState, params: &mut N::Params) {
+    if let Some(doc) = N::extract_url(params).and_then(|url| state.document(&url)) {
+        for position in N::params_positions(params) {
+            modify_outgoing_position(state, &doc, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_lsp::{
+        AnyNotification, ClientSocket, LanguageServer,
+        lsp_types::{
+            DidOpenTextDocumentParams, Hover, HoverParams, InitializeParams, Position,
+            TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, Url,
+            notification::Notification,
+        },
+    };
+
+    use crate::{
+        server::{
+            CustomNotification, CustomNotificationHandler, CustomNotifications, Server,
+            ServerResult, ServerState,
+        },
+        server_with_state::LanguageServerWithState,
+        testing::TestServer as TestHarness,
+    };
+
+    enum CursorMoved {}
+
+    impl Notification for CursorMoved {
+        type Params = TextDocumentPositionParams;
+        const METHOD: &'static str = "test/cursorMoved";
+    }
+
+    impl CustomNotification for CursorMoved {
+        fn extract_url(params: &Self::Params) -> Option<Url> {
+            Some(params.text_document.uri.clone())
+        }
+
+        fn params_positions(params: &mut Self::Params) -> Vec<&mut Position> {
+            vec![&mut params.position]
+        }
+    }
+
+    #[derive(Default)]
+    struct TestServer {
+        received: Arc<Mutex<Vec<Position>>>,
+    }
+
+    impl Server for TestServer {
+        fn server_custom_notifications() -> CustomNotifications<Self> {
+            CustomNotifications::new().with_notification::<CursorMoved>()
+        }
+    }
+
+    impl CustomNotificationHandler<CursorMoved> for TestServer {
+        fn handle_custom_notification(
+            &self,
+            _: ServerState,
+            params: TextDocumentPositionParams,
+        ) -> ServerResult<()> {
+            self.received.lock().unwrap().push(params.position);
+            Ok(())
+        }
+    }
+
+    fn notification(method: &str, params: &serde_json::Value) -> AnyNotification {
+        serde_json::from_value(serde_json::json!({
+            "method": method,
+            "params": params,
+        }))
+        .expect("notification is valid")
+    }
+
+    #[test]
+    fn custom_notifications_convert_positions() {
+        let uri = Url::parse("file:///tmp/custom.test").unwrap();
+        let server = TestServer::default();
+        let received = Arc::clone(&server.received);
+
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), server);
+        futures::executor::block_on(server.initialize(InitializeParams::default()))
+            .expect("server can initialize");
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "test".into(), 1, "🙂abc".into()),
+        });
+
+        let params =
+            TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri), Position::new(0, 2));
+        let flow = server.custom_notification(notification(
+            CursorMoved::METHOD,
+            &serde_json::to_value(params).unwrap(),
+        ));
+
+        assert!(flow.is_continue());
+        assert_eq!(*received.lock().unwrap(), vec![Position::new(0, 4)]);
+    }
+
+    #[test]
+    fn unknown_custom_notifications_are_ignored_only_for_protocol_methods() {
+        let mut server =
+            LanguageServerWithState::new(ClientSocket::new_closed(), TestServer::default());
+
+        let flow = server.custom_notification(notification("$/unknown", &serde_json::Value::Null));
+        assert!(flow.is_continue());
+
+        let flow =
+            server.custom_notification(notification("test/unknown", &serde_json::Value::Null));
+        assert!(flow.is_break());
+    }
+
+    #[derive(Clone)]
+    struct NotifyingServer;
+
+    impl Server for NotifyingServer {
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            state.notify_custom::<CursorMoved>(params.text_document_position_params)?;
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn notify_custom_sends_converted_positions() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let server = TestHarness::spawn(NotifyingServer)
+                .await
+                .expect("server spawns");
+            let uri = Url::parse("file:///tmp/custom.test").unwrap();
+            server.open_document(&uri, "test", "🙂abc").unwrap();
+            server.hover(&uri, Position::new(0, 2)).await.unwrap();
+
+            let params = server
+                .client()
+                .expect_notification::<CursorMoved>()
+                .await
+                .unwrap();
+            assert_eq!(params.text_document.uri, uri);
+            assert_eq!(params.position, Position::new(0, 2));
+        });
+    }
+}
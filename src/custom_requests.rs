@@ -0,0 +1,442 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use async_lsp::{
+    ErrorCode, ResponseError,
+    lsp_types::{Position, Url, WorkspaceEdit, request::Request as LspRequest},
+};
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+use crate::{
+    requests::{find_read_only_edit, modify_incoming_position, modify_outgoing_position},
+    result::ServerResult,
+    server_state::ServerState,
+    server_trait::Server,
+    server_with_state::{read_only_error, with_timeout},
+};
+
+/**
+    A custom, server-specific, JSON-RPC request.
+
+    Custom requests are defined just like any other [`lsp_types`] request,
+    and may optionally implement the methods in this trait to opt into the
+    same document tracking and position encoding conversion that is used
+    for the built-in requests.
+
+    [`lsp_types`]: crate::lsp_types
+*/
+pub trait CustomRequest: LspRequest {
+    /**
+        Whether the request edits the document that it is for, and should
+        be rejected without calling its handler if that document is read-only.
+
+        Custom requests are not rejected by default, so handlers that edit documents
+        must either set this, or check [`Document::is_read_only`] themselves.
+
+        [`Document::is_read_only`]: crate::server::Document::is_read_only
+    */
+    const REJECTS_READ_ONLY: bool = false;
+
+    /**
+        Extracts the URL of the document that the request is for, if any.
+
+        If a URL is returned, and the document is tracked by the server, the
+        request will be cancelled if the document changes during processing.
+    */
+    #[allow(unused_variables)]
+    fn extract_url(params: &Self::Params) -> Option<Url> {
+        None
+    }
+
+    /**
+        Returns all positions in the request parameters that should be
+        converted from the negotiated position encoding, to UTF-8.
+    */
+    #[allow(unused_variables)]
+    fn params_positions(params: &mut Self::Params) -> Vec<&mut Position> {
+        Vec::new()
+    }
+
+    /**
+        Returns all positions in the request result that should be
+        converted from UTF-8, to the negotiated position encoding.
+    */
+    #[allow(unused_variables)]
+    fn result_positions(result: &mut Self::Result) -> Vec<&mut Position> {
+        Vec::new()
+    }
+
+    /**
+        Returns all workspace edits in the request result, which will be rejected
+        as a whole if any of them edit read-only documents, just like the edits
+        returned for built-in requests such as renames.
+    */
+    #[allow(unused_variables)]
+    fn result_workspace_edits(result: &Self::Result) -> Vec<&WorkspaceEdit> {
+        Vec::new()
+    }
+}
+
+/**
+    A handler for a [`CustomRequest`], implemented by a [`Server`].
+
+    Handlers must also be registered using [`Server::server_custom_requests`].
+*/
+pub trait CustomRequestHandler<R: CustomRequest>: Server {
+    fn handle_custom_request(
+        &self,
+        state: ServerState,
+        params: R::Params,
+    ) -> impl Future<Output = ServerResult<R::Result>> + Send;
+}
+
+type CustomRequestFn<S> = Box<
+    dyn Fn(Arc<S>, ServerState, Value, Option<Duration>) -> BoxFuture<'static, ResponseResult>
+        + Send
+        + Sync,
+>;
+
+type ResponseResult = Result<Value, ResponseError>;
+
+/**
+    A set of custom requests that a [`Server`] can handle.
+
+    Created using [`CustomRequests::new`], and returned from [`Server::server_custom_requests`].
+*/
+pub struct CustomRequests<S> {
+    handlers: HashMap<&'static str, CustomRequestFn<S>>,
+}
+
+impl<S> CustomRequests<S> {
+    /**
+        Creates a new, empty, set of custom requests.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /**
+        Adds the given custom request, handled by the server.
+
+        If a handler for the same method was already added, it will be replaced.
+    */
+    #[must_use]
+    pub fn with_request<R>(mut self) -> Self
+    where
+        R: CustomRequest + 'static,
+        S: CustomRequestHandler<R> + Send + Sync + 'static,
+    {
+        self.handlers.insert(
+            R::METHOD,
+            Box::new(|server, state, params, timeout| {
+                Box::pin(dispatch::<S, R>(server, state, params, timeout))
+            }),
+        );
+        self
+    }
+
+    /**
+        Adds all custom requests from another set, handled by a server that can be
+        reached from this one, keeping existing handlers for any duplicate methods.
+    */
+    pub(crate) fn merge_projected<U>(
+        mut self,
+        other: CustomRequests<U>,
+        project: fn(&S) -> Arc<U>,
+    ) -> Self
+    where
+        S: 'static,
+        U: 'static,
+    {
+        for (method, handler) in other.handlers {
+            self.handlers.entry(method).or_insert_with(|| {
+                Box::new(move |server: Arc<S>, state, params, timeout| {
+                    handler(project(&server), state, params, timeout)
+                })
+            });
+        }
+        self
+    }
+
+    pub(crate) fn handle(
+        &self,
+        server: Arc<S>,
+        state: ServerState,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> BoxFuture<'static, ResponseResult> {
+        if let Some(handler) = self.handlers.get(method) {
+            handler(server, state, params, timeout)
+        } else {
+            let message = format!("No such method {method}");
+            Box::pin(async move { Err(ResponseError::new(ErrorCode::METHOD_NOT_FOUND, message)) })
+        }
+    }
+}
+
+impl<S> Default for CustomRequests<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn dispatch<S, R>(
+    server: Arc<S>,
+    state: ServerState,
+    params: Value,
+    timeout: Option<Duration>,
+) -> ResponseResult
+where
+    R: CustomRequest,
+    S: CustomRequestHandler<R>,
+{
+    let mut params = serde_json::from_value::<R::Params>(params).map_err(|e| {
+        ResponseError::new(
+            ErrorCode::INVALID_PARAMS,
+            format!("Failed to deserialize parameters: {e}"),
+        )
+    })?;
+
+    // 1. Associate the state with the cancellation token for this request
+    let cancellation = crate::cancellation::current_request_cancellation();
+    let state = state.with_request_cancellation(cancellation.clone());
+
+    // 2. If we got an URL, reject read-only documents, track the document version,
+    //    and convert incoming positions
+    let url = R::extract_url(&params);
+    if R::REJECTS_READ_ONLY
+        && let Some(url) = url.as_ref().filter(|url| state.url_is_read_only(url))
+    {
+        return Err(read_only_error(url));
+    }
+    let mut ver = None;
+    if let Some(doc) = url.as_ref().and_then(|url| state.document(url)) {
+        ver.replace(doc.version());
+        #[cfg(feature = "tracing")]
+        crate::layers::record_document_version(doc.version());
+        for position in R::params_positions(&mut params) {
+            modify_incoming_position(&state, &doc, position)
+                .map_err(|e| ResponseError::new(ErrorCode::INVALID_PARAMS, e.to_string()))?;
+        }
+    }
+
+    // 3. Call the user-defined handler
+    let mut result = with_timeout(
+        server.handle_custom_request(state.clone(), params),
+        timeout,
+        &cancellation,
+    )
+    .await??;
+    if cancellation.is_cancelled() {
+        return Err(ResponseError::new(
+            ErrorCode::REQUEST_CANCELLED,
+            "request was cancelled during processing",
+        ));
+    }
+
+    // 4. Check our document again, and convert outgoing positions if not stale
+    if let Some(doc) = url.as_ref().and_then(|url| state.document(url)) {
+        if ver.is_some_and(|v| v != doc.version()) {
+            return Err(ResponseError::new(
+                ErrorCode::CONTENT_MODIFIED,
+                "document was modified during processing",
+            ));
+        }
+        for position in R::result_positions(&mut result) {
+            modify_outgoing_position(&state, &doc, position);
+        }
+    }
+
+    // 5. Make sure that no edits for read-only documents reach the client
+    for edit in R::result_workspace_edits(&result) {
+        if let Some(url) = find_read_only_edit(&state, edit) {
+            return Err(read_only_error(&url));
+        }
+    }
+
+    serde_json::to_value(result).map_err(|e| {
+        ResponseError::new(
+            ErrorCode::INTERNAL_ERROR,
+            format!("Failed to serialize result: {e}"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_lsp::{
+        AnyRequest, ClientSocket, ErrorCode, LanguageServer,
+        lsp_types::{
+            DidOpenTextDocumentParams, InitializeParams, Position, Range, TextDocumentIdentifier,
+            TextDocumentItem, TextDocumentPositionParams, TextEdit, Url, WorkspaceEdit,
+            request::Request,
+        },
+    };
+
+    use crate::{
+        server::{
+            CustomRequest, CustomRequestHandler, CustomRequests, DocumentMatcher, Server,
+            ServerResult, ServerState,
+        },
+        server_with_state::LanguageServerWithState,
+    };
+
+    enum WordRange {}
+
+    impl Request for WordRange {
+        type Params = TextDocumentPositionParams;
+        type Result = Option<Range>;
+        const METHOD: &'static str = "test/wordRange";
+    }
+
+    impl CustomRequest for WordRange {
+        fn extract_url(params: &Self::Params) -> Option<Url> {
+            Some(params.text_document.uri.clone())
+        }
+
+        fn params_positions(params: &mut Self::Params) -> Vec<&mut Position> {
+            vec![&mut params.position]
+        }
+
+        fn result_positions(result: &mut Self::Result) -> Vec<&mut Position> {
+            result
+                .iter_mut()
+                .flat_map(|range| [&mut range.start, &mut range.end])
+                .collect()
+        }
+    }
+
+    enum Generate {}
+
+    impl Request for Generate {
+        type Params = TextDocumentIdentifier;
+        type Result = WorkspaceEdit;
+        const METHOD: &'static str = "test/generate";
+    }
+
+    impl CustomRequest for Generate {
+        const REJECTS_READ_ONLY: bool = true;
+
+        fn extract_url(params: &Self::Params) -> Option<Url> {
+            Some(params.uri.clone())
+        }
+
+        fn result_workspace_edits(result: &Self::Result) -> Vec<&WorkspaceEdit> {
+            vec![result]
+        }
+    }
+
+    struct TestServer;
+
+    impl Server for TestServer {
+        fn server_document_matchers() -> Vec<DocumentMatcher> {
+            vec![
+                DocumentMatcher::new("Test")
+                    .with_url_globs(["**/*.test"])
+                    .with_read_only_globs(["**/generated/**"]),
+            ]
+        }
+
+        fn server_custom_requests() -> CustomRequests<Self> {
+            CustomRequests::new()
+                .with_request::<WordRange>()
+                .with_request::<Generate>()
+        }
+    }
+
+    impl CustomRequestHandler<Generate> for TestServer {
+        async fn handle_custom_request(
+            &self,
+            _: ServerState,
+            _: TextDocumentIdentifier,
+        ) -> ServerResult<WorkspaceEdit> {
+            let target = Url::parse("file:///tmp/generated/output.test").unwrap();
+            let edit = TextEdit::new(Range::default(), "generated".into());
+            Ok(WorkspaceEdit::new(HashMap::from([(target, vec![edit])])))
+        }
+    }
+
+    impl CustomRequestHandler<WordRange> for TestServer {
+        async fn handle_custom_request(
+            &self,
+            _: ServerState,
+            params: TextDocumentPositionParams,
+        ) -> ServerResult<Option<Range>> {
+            let end = Position::new(params.position.line, params.position.character + 1);
+            Ok(Some(Range::new(params.position, end)))
+        }
+    }
+
+    fn request(method: &str, params: &serde_json::Value) -> AnyRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "method": method,
+            "params": params,
+        }))
+        .expect("request is valid")
+    }
+
+    #[test]
+    fn custom_requests_convert_positions() {
+        let uri = Url::parse("file:///tmp/custom.test").unwrap();
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+        futures::executor::block_on(server.initialize(InitializeParams::default()))
+            .expect("server can initialize");
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "test".into(), 1, "🙂abc".into()),
+        });
+
+        let params =
+            TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri), Position::new(0, 2));
+        let result = futures::executor::block_on(server.custom_request(request(
+            WordRange::METHOD,
+            &serde_json::to_value(params).unwrap(),
+        )))
+        .expect("custom request succeeds");
+
+        let range: Option<Range> = serde_json::from_value(result).unwrap();
+        assert_eq!(
+            range,
+            Some(Range::new(Position::new(0, 2), Position::new(0, 3)))
+        );
+    }
+
+    #[test]
+    fn unknown_custom_requests_are_not_found() {
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+
+        let error = futures::executor::block_on(
+            server.custom_request(request("test/unknown", &serde_json::Value::Null)),
+        )
+        .expect_err("custom request is unknown");
+
+        assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn custom_requests_reject_read_only_documents() {
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+        let mut generate = |path: &str| {
+            let params = TextDocumentIdentifier::new(Url::parse(path).unwrap());
+            futures::executor::block_on(server.custom_request(request(
+                Generate::METHOD,
+                &serde_json::to_value(params).unwrap(),
+            )))
+        };
+
+        let error = generate("file:///tmp/generated/input.test")
+            .expect_err("read-only documents are rejected up front");
+        assert_eq!(error.code, ErrorCode::REQUEST_FAILED);
+
+        let error = generate("file:///tmp/input.test")
+            .expect_err("edits to read-only documents are rejected");
+        assert_eq!(error.code, ErrorCode::REQUEST_FAILED);
+        assert!(error.message.contains("generated/output.test"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use async_lsp::lsp_types::{
+    Diagnostic, DocumentDiagnosticReport, DocumentDiagnosticReportKind,
+    DocumentDiagnosticReportResult, RelatedUnchangedDocumentDiagnosticReport,
+    UnchangedDocumentDiagnosticReport, Url,
+};
+use dashmap::DashMap;
+
+/**
+    The most recent pull diagnostics result for each document, together with
+    the document version that it was computed for, so that pulls for unchanged
+    documents can be answered without running the diagnostics handler again.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DiagnosticResults {
+    inner: Arc<DashMap<Url, DiagnosticResult>>,
+    counter: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone)]
+struct DiagnosticResult {
+    version: i32,
+    result_id: String,
+    items: Vec<Diagnostic>,
+}
+
+impl DiagnosticResults {
+    /**
+        Returns an unchanged report if the previous result id from the client
+        is still the current one for the document, at the given version.
+    */
+    pub(crate) fn unchanged(
+        &self,
+        url: &Url,
+        version: i32,
+        previous_result_id: Option<&str>,
+    ) -> Option<DocumentDiagnosticReportResult> {
+        let previous_result_id = previous_result_id?;
+        let result = self.inner.get(url)?;
+        if result.version != version || result.result_id != previous_result_id {
+            return None;
+        }
+        Some(unchanged_report(result.result_id.clone(), None))
+    }
+
+    /**
+        Makes sure that a full report has a `result_id`, reusing the current one
+        if the diagnostics did not change, and remembers it for the given version.
+
+        The report is replaced with an unchanged report if the
+        id matches the previous one from the client.
+    */
+    pub(crate) fn record(
+        &self,
+        url: &Url,
+        version: Option<i32>,
+        previous_result_id: Option<&str>,
+        response: &mut DocumentDiagnosticReportResult,
+    ) {
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) =
+            response
+        else {
+            return;
+        };
+
+        let full = &mut report.full_document_diagnostic_report;
+        let current = self.inner.get(url).map(|result| result.clone());
+        let result_id = full
+            .result_id
+            .get_or_insert_with(|| match current {
+                Some(current) if current.items == full.items => current.result_id,
+                _ => self.next_result_id(),
+            })
+            .clone();
+
+        if let Some(version) = version {
+            self.inner.insert(
+                url.clone(),
+                DiagnosticResult {
+                    version,
+                    result_id: result_id.clone(),
+                    items: full.items.clone(),
+                },
+            );
+        }
+
+        if previous_result_id == Some(result_id.as_str()) {
+            let related_documents = report.related_documents.take();
+            *response = unchanged_report(result_id, related_documents);
+        }
+    }
+
+    pub(crate) fn remove(&self, url: &Url) {
+        self.inner.remove(url);
+    }
+
+    /**
+        Forgets all results, so that the next pull for
+        every document runs the diagnostics handler again.
+    */
+    pub(crate) fn clear(&self) {
+        self.inner.clear();
+    }
+
+    fn next_result_id(&self) -> String {
+        self.counter.fetch_add(1, Ordering::Relaxed).to_string()
+    }
+}
+
+fn unchanged_report(
+    result_id: String,
+    related_documents: Option<HashMap<Url, DocumentDiagnosticReportKind>>,
+) -> DocumentDiagnosticReportResult {
+    DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(
+        RelatedUnchangedDocumentDiagnosticReport {
+            related_documents,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{
+        Diagnostic, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+        FullDocumentDiagnosticReport, Position, Range, RelatedFullDocumentDiagnosticReport, Url,
+    };
+
+    use super::DiagnosticResults;
+
+    fn full(message: &str) -> DocumentDiagnosticReportResult {
+        DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(
+            RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: None,
+                    items: vec![Diagnostic::new_simple(
+                        Range::new(Position::new(0, 0), Position::new(0, 1)),
+                        message.into(),
+                    )],
+                },
+            },
+        ))
+    }
+
+    fn result_id(response: &DocumentDiagnosticReportResult) -> String {
+        match response {
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) => {
+                report.full_document_diagnostic_report.result_id.clone()
+            }
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(report)) => {
+                Some(
+                    report
+                        .unchanged_document_diagnostic_report
+                        .result_id
+                        .clone(),
+                )
+            }
+            DocumentDiagnosticReportResult::Partial(_) => None,
+        }
+        .expect("report has a result id")
+    }
+
+    #[test]
+    fn results_are_unchanged_until_the_version_changes() {
+        let results = DiagnosticResults::default();
+        let url = Url::parse("file:///tmp/results.test").unwrap();
+
+        let mut response = full("a");
+        results.record(&url, Some(1), None, &mut response);
+        let id = result_id(&response);
+
+        assert!(results.unchanged(&url, 1, Some(&id)).is_some());
+        assert!(results.unchanged(&url, 1, Some("other")).is_none());
+        assert!(results.unchanged(&url, 1, None).is_none());
+        assert!(results.unchanged(&url, 2, Some(&id)).is_none());
+
+        results.clear();
+        assert!(results.unchanged(&url, 1, Some(&id)).is_none());
+    }
+
+    #[test]
+    fn identical_diagnostics_keep_their_result_id() {
+        let results = DiagnosticResults::default();
+        let url = Url::parse("file:///tmp/results.test").unwrap();
+
+        let mut first = full("a");
+        results.record(&url, Some(1), None, &mut first);
+        let id = result_id(&first);
+
+        let mut second = full("a");
+        results.record(&url, Some(2), Some(&id), &mut second);
+        assert!(matches!(
+            second,
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_))
+        ));
+        assert!(results.unchanged(&url, 2, Some(&id)).is_some());
+
+        let mut third = full("b");
+        results.record(&url, Some(3), Some(&id), &mut third);
+        assert_ne!(result_id(&third), id);
+    }
+}
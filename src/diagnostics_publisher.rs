@@ -0,0 +1,339 @@
+use std::sync::Arc;
+
+use async_lsp::lsp_types::{
+    Diagnostic, PublishDiagnosticsParams, Url, notification::PublishDiagnostics,
+};
+use dashmap::DashMap;
+
+use crate::{
+    requests::modify_outgoing_diagnostic, result::ServerResult, server_state::ServerState,
+};
+
+/**
+    The most recently published diagnostics for each document,
+    stored using UTF-8 positions, exactly as given by the server.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PublishedDiagnostics {
+    inner: Arc<DashMap<Url, PublishedEntry>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PublishedEntry {
+    version: Option<i32>,
+    diagnostics: Vec<Diagnostic>,
+    syntax: Vec<Diagnostic>,
+}
+
+impl PublishedDiagnostics {
+    fn is_unchanged(&self, url: &Url, entry: &PublishedEntry) -> bool {
+        self.inner.get(url).is_some_and(|e| *e == *entry)
+    }
+
+    fn insert(&self, url: Url, entry: PublishedEntry) {
+        self.inner.insert(url, entry);
+    }
+
+    fn diagnostics(&self, url: &Url) -> Option<Vec<Diagnostic>> {
+        self.inner.get(url).map(|e| e.diagnostics.clone())
+    }
+
+    pub(crate) fn remove(&self, url: &Url) -> bool {
+        self.inner.remove(url).is_some()
+    }
+}
+
+/**
+    A publisher for push diagnostics, using `textDocument/publishDiagnostics`.
+
+    Created by calling [`ServerState::diagnostics`].
+
+    Diagnostics given to the publisher should use UTF-8 offsets, just like
+    all other positions given to the server, and are converted to the
+    negotiated position encoding automatically for tracked documents.
+
+    The publisher also takes care of:
+
+    - Attaching the current version of the document to published diagnostics
+    - Skipping publishing if the diagnostics for a document did not change
+    - Clearing published diagnostics when the document is closed by the client
+    - Including syntax diagnostics, if enabled using [`ServerOptions::with_syntax_diagnostics`]
+
+    [`ServerOptions::with_syntax_diagnostics`]: crate::server::ServerOptions::with_syntax_diagnostics
+*/
+#[derive(Debug, Clone)]
+pub struct DiagnosticsPublisher {
+    state: ServerState,
+}
+
+impl DiagnosticsPublisher {
+    pub(crate) fn new(state: ServerState) -> Self {
+        Self { state }
+    }
+
+    /**
+        Publishes the given diagnostics for the document at the given URL,
+        replacing any diagnostics previously published for that document.
+
+        Returns `true` if the diagnostics were sent to the client, or `false` if
+        they were identical to the most recently published ones for the document.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn publish(&self, url: Url, diagnostics: Vec<Diagnostic>) -> ServerResult<bool> {
+        let document = self.state.document(&url);
+        let entry = PublishedEntry {
+            version: document.as_ref().map(crate::server::Document::version),
+            syntax: self
+                .state
+                .syntax_diagnostics(document.as_ref())
+                .unwrap_or_default(),
+            diagnostics,
+        };
+
+        let published = self.state.published_diagnostics();
+        if published.is_unchanged(&url, &entry) {
+            return Ok(false);
+        }
+
+        let mut diagnostics = entry.diagnostics.clone();
+        diagnostics.extend(entry.syntax.iter().cloned());
+        if let Some(document) = document.as_ref() {
+            for diag in &mut diagnostics {
+                modify_outgoing_diagnostic(&self.state, document, diag);
+            }
+        }
+
+        self.send(url.clone(), diagnostics, entry.version)?;
+        published.insert(url, entry);
+        Ok(true)
+    }
+
+    /**
+        Publishes the current syntax diagnostics for the document at the given URL,
+        together with the diagnostics most recently published for it, if any.
+
+        Does nothing if syntax diagnostics are not enabled, or if there are
+        no diagnostics to publish and none were previously published.
+    */
+    pub(crate) fn publish_syntax(&self, url: &Url) -> ServerResult<bool> {
+        let Some(syntax) = self
+            .state
+            .syntax_diagnostics(self.state.document(url).as_ref())
+        else {
+            return Ok(false);
+        };
+        match self.state.published_diagnostics().diagnostics(url) {
+            Some(diagnostics) => self.publish(url.clone(), diagnostics),
+            None if syntax.is_empty() => Ok(false),
+            None => self.publish(url.clone(), Vec::new()),
+        }
+    }
+
+    /**
+        Clears any diagnostics previously published for the document at the given URL.
+
+        Returns `true` if there were diagnostics to clear, otherwise `false`.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn clear(&self, url: &Url) -> ServerResult<bool> {
+        if !self.state.published_diagnostics().remove(url) {
+            return Ok(false);
+        }
+        self.send(url.clone(), Vec::new(), None)?;
+        Ok(true)
+    }
+
+    fn send(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    ) -> ServerResult<()> {
+        self.state
+            .client()
+            .notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                uri,
+                diagnostics,
+                version,
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_lsp::{
+        ClientSocket,
+        lsp_types::{
+            Diagnostic, DidOpenTextDocumentParams, Hover, HoverContents, HoverParams, MarkedString,
+            Position, Range, TextDocumentItem, Url, notification::PublishDiagnostics,
+        },
+    };
+
+    use crate::{
+        server::{Server, ServerResult, ServerState},
+        testing::TestServer as TestHarness,
+    };
+
+    use super::PublishedEntry;
+
+    struct TestServer;
+
+    impl Server for TestServer {}
+
+    fn diagnostic(message: &str) -> Diagnostic {
+        Diagnostic::new_simple(
+            Range::new(Position::new(0, 4), Position::new(0, 5)),
+            message.into(),
+        )
+    }
+
+    #[derive(Clone)]
+    struct PublishingServer;
+
+    impl Server for PublishingServer {
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            let url = params.text_document_position_params.text_document.uri;
+            let published = state.diagnostics().publish(url, vec![diagnostic("a")])?;
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(published.to_string())),
+                range: None,
+            }))
+        }
+    }
+
+    async fn publish(server: &TestHarness, url: &Url) -> String {
+        let hover = server.hover(url, Position::new(0, 0)).await.unwrap();
+        match hover.map(|h| h.contents) {
+            Some(HoverContents::Scalar(MarkedString::String(s))) => s,
+            other => panic!("expected plain string hover, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unchanged_diagnostics_are_published_once() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let server = TestHarness::spawn(PublishingServer)
+                .await
+                .expect("server spawns");
+            let url = Url::parse("file:///tmp/publish.test").unwrap();
+            server.open_document(&url, "test", "🙂abc").unwrap();
+
+            assert_eq!(publish(&server, &url).await, "true");
+            assert_eq!(publish(&server, &url).await, "false");
+
+            let client = server.client();
+            let params = client
+                .expect_notification::<PublishDiagnostics>()
+                .await
+                .unwrap();
+            assert_eq!(params.uri, url);
+            assert_eq!(params.version, Some(1));
+            assert_eq!(params.diagnostics.len(), 1);
+            assert_eq!(
+                params.diagnostics[0].range,
+                Range::new(Position::new(0, 2), Position::new(0, 3))
+            );
+            client
+                .expect_silence(Duration::from_millis(20))
+                .await
+                .unwrap();
+
+            server.change_document(&url, "🙂abcd").unwrap();
+            assert_eq!(publish(&server, &url).await, "true");
+            let params = client
+                .expect_notification::<PublishDiagnostics>()
+                .await
+                .unwrap();
+            assert_eq!(params.version, Some(2));
+        });
+    }
+
+    #[test]
+    fn failed_publishes_are_not_recorded() {
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/publish.test").unwrap();
+        for _ in 0..2 {
+            assert!(
+                state
+                    .diagnostics()
+                    .publish(url.clone(), vec![diagnostic("a")])
+                    .is_err()
+            );
+        }
+        assert!(state.published_diagnostics().diagnostics(&url).is_none());
+    }
+
+    #[test]
+    fn unchanged_diagnostics_are_detected_using_versions() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/publish.test").unwrap();
+        let _ = state.handle_document_open::<TestServer>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(url.clone(), "test".into(), 3, "abcdef".into()),
+        });
+
+        let published = state.published_diagnostics();
+        let entry = PublishedEntry {
+            version: Some(3),
+            diagnostics: vec![diagnostic("a")],
+            syntax: Vec::new(),
+        };
+        published.insert(url.clone(), entry.clone());
+
+        assert!(published.is_unchanged(&url, &entry));
+        assert!(!published.is_unchanged(
+            &url,
+            &PublishedEntry {
+                version: Some(4),
+                ..entry.clone()
+            }
+        ));
+        assert!(!published.is_unchanged(
+            &url,
+            &PublishedEntry {
+                diagnostics: vec![diagnostic("b")],
+                ..entry
+            }
+        ));
+        assert!(matches!(
+            state.diagnostics().publish(url, vec![diagnostic("a")]),
+            Ok(false)
+        ));
+    }
+
+    #[test]
+    fn clearing_forgets_published_diagnostics() {
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/publish.test").unwrap();
+
+        assert!(matches!(state.diagnostics().clear(&url), Ok(false)));
+
+        state.published_diagnostics().insert(
+            url.clone(),
+            PublishedEntry {
+                version: None,
+                diagnostics: vec![diagnostic("a")],
+                syntax: Vec::new(),
+            },
+        );
+        assert!(state.diagnostics().clear(&url).is_err());
+        assert!(!state.published_diagnostics().remove(&url));
+    }
+}
@@ -0,0 +1,159 @@
+use std::ops::Range as ByteRange;
+
+use async_lsp::lsp_types::{TextEdit, Url};
+
+#[cfg(feature = "tree-sitter")]
+use tree_sitter::{InputEdit, Parser, Point};
+
+#[cfg(feature = "tree-sitter")]
+use crate::{text_utils::byte_offset_to_ts, tree_sitter_utils::parse_rope};
+
+use crate::text_utils::{OverlappingEdits, sort_edits};
+
+use super::{Document, DocumentExtensions};
+
+/**
+    A structured description of a change made to a document by the client.
+
+    Edits are given as byte ranges in the document, and must be applied in
+    order - each edit is relative to the document contents after applying
+    all previous edits, and the first edit is relative to the contents of
+    the document at `old_version`.
+
+    See [`Server::document_edited`] for more information.
+
+    [`Server::document_edited`]: crate::server::Server::document_edited
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentChangeEvent {
+    pub url: Url,
+    pub old_version: i32,
+    pub new_version: i32,
+    pub edits: Vec<(ByteRange<usize>, String)>,
+}
+
+impl Document {
+    /**
+        Returns a copy of the document with the given edits applied to it,
+        without changing the document itself, or any state in the server.
+
+        All edits must use UTF-8 positions and be relative to the current
+        contents of the document, just like in a [`WorkspaceEdit`], meaning
+        that this may be used to preview the results of formatting or code
+        actions. The returned document keeps the version of this document,
+        has its syntax tree re-parsed if this document had one, and does
+        not share any of the [`Document::extensions`] of this document.
+
+        Edits are applied in the same way as by [`apply_edits`].
+
+        # Errors
+
+        - If the ranges of any two edits overlap.
+
+        [`WorkspaceEdit`]: async_lsp::lsp_types::WorkspaceEdit
+        [`apply_edits`]: crate::text_utils::apply_edits
+    */
+    pub fn with_edits(&self, edits: &[TextEdit]) -> Result<Document, OverlappingEdits> {
+        let mut document = self.clone();
+        document.apply_text_edits(edits)?;
+        document.inner_mut().extensions = DocumentExtensions::default();
+
+        #[cfg(feature = "tree-sitter")]
+        {
+            let inner = document.inner_mut();
+            if let (Some(lang), Some(tree)) = (&inner.tree_sitter_lang, &inner.tree_sitter_tree) {
+                let mut parser = Parser::new();
+                inner.tree_sitter_tree = if parser.set_language(lang).is_ok() {
+                    parse_rope(&mut parser, &inner.text, Some(tree))
+                } else {
+                    None
+                };
+            }
+        }
+
+        Ok(document)
+    }
+
+    /**
+        Applies text edits with UTF-8 positions to the document, where all edits
+        are relative to the current contents, just like in a [`WorkspaceEdit`].
+
+        The syntax tree, if any, is edited but not re-parsed.
+
+        # Errors
+
+        - If the ranges of any two edits overlap, in which case the document is left unchanged.
+
+        [`WorkspaceEdit`]: async_lsp::lsp_types::WorkspaceEdit
+    */
+    pub(crate) fn apply_text_edits(&mut self, edits: &[TextEdit]) -> Result<(), OverlappingEdits> {
+        let edits = sort_edits(edits, |position| self.position_to_byte(position))?;
+
+        let inner = self.inner_mut();
+        for (range, text) in edits {
+            let start_char = inner.text.byte_to_char(range.start);
+            let end_char = inner.text.byte_to_char(range.end);
+
+            #[cfg(feature = "tree-sitter")]
+            if let Some(tree) = inner.tree_sitter_tree.as_mut() {
+                let start_byte = inner.text.char_to_byte(start_char);
+                let old_end_byte = inner.text.char_to_byte(end_char);
+                let (_, start_position) = byte_offset_to_ts(&inner.text, start_byte);
+                let (_, old_end_position) = byte_offset_to_ts(&inner.text, old_end_byte);
+                let new_end_position = text.chars().fold(start_position, |point, ch| {
+                    if ch == '\n' {
+                        Point::new(point.row + 1, 0)
+                    } else {
+                        Point::new(point.row, point.column + ch.len_utf8())
+                    }
+                });
+                tree.edit(&InputEdit {
+                    start_byte,
+                    old_end_byte,
+                    new_end_byte: start_byte + text.len(),
+                    start_position,
+                    old_end_position,
+                    new_end_position,
+                });
+            }
+
+            let old_range = inner.text.char_to_byte(start_char)..inner.text.char_to_byte(end_char);
+            inner.text.remove(start_char..end_char);
+            inner.text.insert(start_char, text);
+            inner.edit_line_index(old_range, text.len());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range, TextEdit};
+
+    use crate::document::Document;
+
+    #[test]
+    fn edits_are_previewed_without_changing_the_document() {
+        let doc = Document::new_for_tests("let a = 1;\nlet b = 🙂;\n");
+        doc.extensions().insert(1_u32);
+
+        let preview = doc
+            .with_edits(&[
+                TextEdit::new(
+                    Range::new(Position::new(1, 8), Position::new(1, 12)),
+                    "2".into(),
+                ),
+                TextEdit::new(
+                    Range::new(Position::new(0, 4), Position::new(0, 5)),
+                    "x".into(),
+                ),
+            ])
+            .unwrap();
+
+        assert_eq!(preview.text_contents(), "let x = 1;\nlet b = 2;\n");
+        assert_eq!(preview.version(), doc.version());
+        assert!(preview.extensions().is_empty());
+        assert_eq!(doc.text_contents(), "let a = 1;\nlet b = 🙂;\n");
+    }
+}
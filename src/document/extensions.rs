@@ -0,0 +1,121 @@
+use std::{
+    any::{Any, TypeId},
+    fmt,
+    sync::Arc,
+};
+
+use dashmap::DashMap;
+
+use super::Document;
+
+type ExtensionValue = Arc<dyn Any + Send + Sync>;
+
+/**
+    Typed data that the server has attached to a document,
+    with at most one value stored for each type.
+
+    Data is shared between all snapshots of the same document, and is
+    removed automatically when the document is no longer tracked.
+
+    See [`ServerState::set_document_data`] for more information.
+
+    [`ServerState::set_document_data`]: crate::server::ServerState::set_document_data
+*/
+#[derive(Clone, Default)]
+pub struct DocumentExtensions {
+    values: Arc<DashMap<TypeId, ExtensionValue>>,
+}
+
+impl DocumentExtensions {
+    /**
+        Gets the value of the given type, if one has been set.
+    */
+    #[must_use]
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let value = self.values.get(&TypeId::of::<T>())?;
+        Arc::clone(value.value()).downcast().ok()
+    }
+
+    /**
+        Returns `true` if a value of the given type has been set.
+    */
+    #[must_use]
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /**
+        Returns the number of values that have been set.
+    */
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /**
+        Returns `true` if no values have been set.
+    */
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub(crate) fn insert<T: Any + Send + Sync>(&self, value: T) -> Option<Arc<T>> {
+        let previous = self.values.insert(TypeId::of::<T>(), Arc::new(value))?;
+        previous.downcast().ok()
+    }
+
+    pub(crate) fn remove<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let (_, previous) = self.values.remove(&TypeId::of::<T>())?;
+        previous.downcast().ok()
+    }
+}
+
+impl fmt::Debug for DocumentExtensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DocumentExtensions")
+            .field("len", &self.values.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Document {
+    /**
+        Returns the typed data that the server has attached to this document.
+
+        See [`DocumentExtensions`] for more information.
+    */
+    #[must_use]
+    pub fn extensions(&self) -> &DocumentExtensions {
+        &self.inner.extensions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DocumentExtensions;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Scopes(Vec<&'static str>);
+
+    #[test]
+    fn values_are_stored_per_type() {
+        let extensions = DocumentExtensions::default();
+        assert!(extensions.insert(Scopes(vec!["a"])).is_none());
+        assert!(extensions.insert(7_u32).is_none());
+
+        let snapshot = extensions.clone();
+        let previous = extensions.insert(Scopes(vec!["b"]));
+        assert_eq!(previous.as_deref(), Some(&Scopes(vec!["a"])));
+        assert_eq!(
+            snapshot.get::<Scopes>().as_deref(),
+            Some(&Scopes(vec!["b"]))
+        );
+        assert_eq!(snapshot.get::<u32>().as_deref(), Some(&7));
+        assert_eq!(snapshot.len(), 2);
+
+        assert_eq!(extensions.remove::<u32>().as_deref(), Some(&7));
+        assert!(!snapshot.contains::<u32>());
+        assert!(snapshot.get::<u64>().is_none());
+    }
+}
@@ -0,0 +1,146 @@
+use async_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::{
+    tree_sitter::{Node, Point, QueryCursor, StreamingIterator},
+    tree_sitter_utils::RopeTextProvider,
+};
+
+use super::Document;
+
+impl Document {
+    /**
+        Computes folding ranges for the document, from its tree-sitter tree.
+
+        If the document matcher for this document has a folding query, such as a
+        grammar's `folds.scm`, all of its captures are folded. Otherwise, all
+        nodes with any of the folding node kinds of the matcher are folded.
+
+        Only nodes spanning multiple lines are folded, and when several nodes
+        start on the same line, only the outermost of them is folded. Nodes
+        with a kind containing `comment` are folded as comments.
+
+        Returns an empty list if the document has no tree-sitter tree,
+        or if its matcher has no folding query or folding node kinds.
+
+        See [`DocumentMatcher::with_folding_query`] and
+        [`DocumentMatcher::with_folding_kinds`] for more information.
+
+        [`DocumentMatcher::with_folding_query`]: crate::server::DocumentMatcher::with_folding_query
+        [`DocumentMatcher::with_folding_kinds`]: crate::server::DocumentMatcher::with_folding_kinds
+    */
+    #[must_use]
+    pub fn folding_ranges(&self) -> Vec<FoldingRange> {
+        let (Some(matcher), Some(root)) = (self.inner.matcher.as_ref(), self.node_at_root()) else {
+            return Vec::new();
+        };
+
+        let mut folds = Vec::new();
+        if let Some(query) = matcher.folding_query.as_ref() {
+            let text_provider = RopeTextProvider::new(&self.inner.text);
+            let mut cursor = QueryCursor::new();
+            let mut it = cursor.captures(query, root, text_provider);
+            while let Some((matched, index)) = it.next() {
+                folds.push(fold_for_node(matched.captures[*index].node));
+            }
+        } else if !matcher.folding_kinds.is_empty() {
+            self.visit(|node, _| {
+                if matcher.folding_kinds.iter().any(|kind| kind == node.kind()) {
+                    folds.push(fold_for_node(node));
+                }
+            });
+        }
+
+        folding_ranges_from_folds(folds)
+    }
+}
+
+struct Fold {
+    start: Point,
+    end: Point,
+    is_comment: bool,
+}
+
+fn fold_for_node(node: Node) -> Fold {
+    Fold {
+        start: node.start_position(),
+        end: node.end_position(),
+        is_comment: node.kind().contains("comment"),
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn folding_ranges_from_folds(mut folds: Vec<Fold>) -> Vec<FoldingRange> {
+    // NOTE: Sorting by start, and then by end in reverse, makes sure
+    // that the outermost fold always comes first for any given line
+    folds.sort_by(|a, b| a.start.row.cmp(&b.start.row).then(b.end.cmp(&a.end)));
+
+    let mut ranges = Vec::<FoldingRange>::new();
+    for fold in folds {
+        // NOTE: Nodes that end at the very start of a line, such as ones
+        // including a trailing newline, do not include anything on that line
+        let end_row = if fold.end.column == 0 {
+            fold.end.row.saturating_sub(1)
+        } else {
+            fold.end.row
+        };
+        if end_row <= fold.start.row {
+            continue;
+        }
+        if ranges
+            .last()
+            .is_some_and(|last| last.start_line as usize == fold.start.row)
+        {
+            continue;
+        }
+        ranges.push(FoldingRange {
+            start_line: fold.start.row as u32,
+            end_line: end_row as u32,
+            kind: fold.is_comment.then_some(FoldingRangeKind::Comment),
+            ..FoldingRange::default()
+        });
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::FoldingRangeKind;
+
+    use crate::{document::Document, tree_sitter::Point};
+
+    use super::{Fold, folding_ranges_from_folds};
+
+    fn fold(start: (usize, usize), end: (usize, usize), is_comment: bool) -> Fold {
+        Fold {
+            start: Point::new(start.0, start.1),
+            end: Point::new(end.0, end.1),
+            is_comment,
+        }
+    }
+
+    #[test]
+    fn documents_without_trees_have_no_folding_ranges() {
+        assert!(
+            Document::new_for_tests("{\n}\n")
+                .folding_ranges()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn folds_are_sorted_and_deduplicated_per_line() {
+        let ranges = folding_ranges_from_folds(vec![
+            fold((4, 2), (6, 3), false),
+            fold((0, 0), (2, 0), true),
+            fold((4, 0), (8, 1), false),
+            fold((3, 0), (3, 9), false),
+        ]);
+        let lines = ranges
+            .iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(0, 1), (4, 8)]);
+        assert_eq!(ranges[0].kind, Some(FoldingRangeKind::Comment));
+        assert_eq!(ranges[1].kind, None);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{borrow::Cow, sync::Arc};
+
+use async_lsp::lsp_types::Range;
+
+use crate::text_utils::LineIndex;
+
+use super::{Document, offsets::new_position};
+
+impl Document {
+    /**
+        Returns the number of lines in the document.
+
+        This is always at least one, even for an empty document, and a
+        trailing line ending counts as starting an additional, empty, line.
+    */
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.inner.text.len_lines()
+    }
+
+    /**
+        Returns the line index of the document, building it if necessary.
+
+        The index is cached and kept up-to-date as the document is edited,
+        which makes it the preferred way to convert many positions at once.
+
+        See [`LineIndex`] for more information.
+    */
+    #[must_use]
+    pub fn line_index(&self) -> &LineIndex {
+        self.inner
+            .line_index
+            .get_or_init(|| Arc::new(LineIndex::new(&self.inner.text)))
+    }
+
+    /**
+        Returns the text of the given line, not including its line ending.
+
+        Returns `None` if the line does not exist in the document.
+    */
+    #[must_use]
+    pub fn line_text(&self, line: usize) -> Option<Cow<'_, str>> {
+        if line >= self.inner.text.len_lines() {
+            return None;
+        }
+        let len = self.line_len_bytes(line);
+        Some(self.inner.text.line(line).byte_slice(..len).into())
+    }
+
+    /**
+        Returns the range covering the given line, not including its line ending.
+
+        Lines past the end of the document are clamped to the last line.
+    */
+    #[must_use]
+    pub fn line_range(&self, line: usize) -> Range {
+        let line = self.clamp_line(line);
+        Range::new(
+            new_position(line, 0),
+            new_position(line, self.line_len_bytes(line)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range, TextEdit};
+
+    use crate::{document::Document, text_utils::LineIndex};
+
+    #[test]
+    fn lines_exclude_line_endings() {
+        let doc = Document::new_for_tests("first\r\nsecond 🙂\nlast\n");
+
+        assert_eq!(doc.line_count(), 4);
+        assert_eq!(doc.line_text(0).as_deref(), Some("first"));
+        assert_eq!(doc.line_text(1).as_deref(), Some("second 🙂"));
+        assert_eq!(doc.line_text(3).as_deref(), Some(""));
+        assert_eq!(doc.line_text(4), None);
+    }
+
+    #[test]
+    fn line_ranges_use_utf8_columns() {
+        let doc = Document::new_for_tests("first\r\nsecond 🙂");
+
+        assert_eq!(
+            doc.line_range(0),
+            Range::new(Position::new(0, 0), Position::new(0, 5))
+        );
+        assert_eq!(
+            doc.line_range(1),
+            Range::new(Position::new(1, 0), Position::new(1, 11))
+        );
+        assert_eq!(doc.line_range(99), doc.line_range(1));
+    }
+
+    #[test]
+    fn line_indexes_follow_edits() {
+        let doc = Document::new_for_tests("first\r\nsecond 🙂\nlast\n");
+        let index = doc.line_index().clone();
+
+        let edited = doc
+            .with_edits(&[TextEdit::new(
+                Range::new(Position::new(0, 5), Position::new(1, 7)),
+                "🙂\n".into(),
+            )])
+            .unwrap();
+
+        assert_eq!(edited.text_contents(), "first🙂\n🙂\nlast\n");
+        assert_eq!(edited.line_index(), &LineIndex::new(edited.text()));
+        assert_eq!(doc.line_index(), &index);
+    }
+}
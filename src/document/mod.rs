@@ -0,0 +1,750 @@
+use std::{
+    ops::Range as ByteRange,
+    sync::{Arc, OnceLock},
+};
+
+use async_lsp::lsp_types::Url;
+use ropey::Rope;
+
+#[cfg(feature = "tree-sitter")]
+use async_lsp::lsp_types::{Position, Range};
+#[cfg(feature = "tree-sitter")]
+use ropey::RopeSlice;
+
+use crate::{server::DocumentMatcher, text_utils::LineIndex};
+
+#[cfg(feature = "tree-sitter")]
+use crate::cancellation::CancellationToken;
+
+pub use self::change::DocumentChangeEvent;
+pub use self::extensions::DocumentExtensions;
+pub use self::reader::DocumentReader;
+
+#[cfg(feature = "tree-sitter")]
+pub use self::node_path::DocumentNodePathItem;
+#[cfg(feature = "tree-sitter")]
+pub use self::syntax_errors::{SyntaxErrorInfo, SyntaxErrorKind};
+
+mod change;
+mod extensions;
+#[cfg(feature = "tree-sitter")]
+mod folding;
+mod lines;
+#[cfg(feature = "tree-sitter")]
+mod node_path;
+mod offsets;
+#[cfg(feature = "tree-sitter")]
+mod query_cache;
+mod reader;
+#[cfg(feature = "tree-sitter")]
+mod selection;
+#[cfg(feature = "tree-sitter")]
+mod speculative;
+#[cfg(feature = "tree-sitter")]
+mod syntax_errors;
+mod text;
+mod words;
+
+#[cfg(feature = "tree-sitter")]
+use crate::{
+    tree_sitter::{
+        Language, Node, Query, QueryCursor, QueryMatch, QueryPredicateArg, StreamingIterator, Tree,
+        TreeCursor,
+    },
+    tree_sitter_utils::{RopeTextProvider, lsp_position_to_ts_point, ts_range_to_lsp_range},
+};
+
+#[cfg(feature = "tree-sitter")]
+pub(crate) use self::query_cache::QueryCache;
+#[cfg(feature = "tree-sitter")]
+use self::query_cache::QueryKey;
+
+/**
+    A document tracked by the language server, containing
+    the URL, text, version, and language of the document.
+
+    May be cloned cheaply to take a snapshot of the current
+    state of the document, since the contents of the document
+    are shared between snapshots until it is next updated.
+
+    Not meant to be updated by external sources, only read,
+    since the language server should be responsible for
+    always keeping the document up-to-date when edits occur.
+
+    # `tree-sitter`
+
+    With the `tree-sitter` crate feature enabled, the document
+    may also optionally store a [`tree_sitter::Language`] and
+    a parsed [`tree_sitter::Tree`] for the document's text.
+
+    If a `tree-sitter` language has been associated with the
+    document, the respective tree will be parsed using the initial
+    contents, and incrementally updated thereafter, transparently.
+*/
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub(crate) inner: Arc<DocumentInner>,
+}
+
+/**
+    The contents of a [`Document`], shared between all of its snapshots.
+
+    Cloned only when a document is updated while snapshots
+    of it are still alive, so taking a snapshot is cheap.
+*/
+#[derive(Debug, Clone)]
+pub(crate) struct DocumentInner {
+    pub(crate) uri: Url,
+    pub(crate) text: Rope,
+    pub(crate) line_index: OnceLock<Arc<LineIndex>>,
+    pub(crate) version: i32,
+    pub(crate) language: String,
+    pub(crate) matcher: Option<Arc<DocumentMatcher>>,
+    pub(crate) stale: bool,
+    pub(crate) read_only: bool,
+    pub(crate) extensions: DocumentExtensions,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) query_cache: QueryCache,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) tree_sitter_lang: Option<Language>,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) tree_sitter_tree: Option<Tree>,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) tree_sitter_pending: Option<(u64, CancellationToken)>,
+}
+
+impl DocumentInner {
+    /**
+        Replaces the entire text of the document, discarding its line index.
+    */
+    pub(crate) fn replace_text(&mut self, text: Rope) {
+        self.text = text;
+        self.line_index = OnceLock::new();
+    }
+
+    /**
+        Updates the line index of the document, if it has been built, after
+        the given byte range of the old text was replaced with `new_len` bytes.
+    */
+    pub(crate) fn edit_line_index(&mut self, old_range: ByteRange<usize>, new_len: usize) {
+        if let Some(index) = self.line_index.get_mut() {
+            Arc::make_mut(index).edit(&self.text, old_range, new_len);
+        }
+    }
+}
+
+impl From<DocumentInner> for Document {
+    fn from(inner: DocumentInner) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl Document {
+    /**
+        Gets mutable access to the contents of the document, cloning
+        them first if they are shared with any other snapshots.
+    */
+    pub(crate) fn inner_mut(&mut self) -> &mut DocumentInner {
+        let inner = Arc::make_mut(&mut self.inner);
+        // NOTE: Any cached query results may no longer be valid after
+        // the update, and must not be shared with any older snapshots
+        #[cfg(feature = "tree-sitter")]
+        {
+            inner.query_cache = QueryCache::default();
+        }
+        inner
+    }
+
+    /**
+        Returns the URL of the document.
+    */
+    #[must_use]
+    pub fn url(&self) -> &Url {
+        &self.inner.uri
+    }
+
+    /**
+        Returns the text of the document, as
+        its underlying [`Rope`] representation.
+
+        It is usually easier to use one of the several convenience
+        methods that [`Document`] provides for accessing and searching
+        through text, but this method exists as an escape hatch.
+    */
+    #[must_use]
+    pub fn text(&self) -> &Rope {
+        &self.inner.text
+    }
+
+    /**
+        Returns a reader over the full text in the document.
+
+        See [`DocumentReader`] for more information.
+    */
+    #[must_use]
+    pub fn text_reader(&self) -> DocumentReader<'_> {
+        DocumentReader::new(&self.inner.text)
+    }
+
+    /**
+        Returns the full text of the document, as a string.
+
+        When possible, prefer [`Document::text_reader`]
+        for improved performance and less allocations.
+    */
+    #[must_use]
+    pub fn text_contents(&self) -> String {
+        self.inner.text.to_string()
+    }
+
+    /**
+        Returns the full text of the document, as a string.
+
+        When possible, prefer [`Document::text_reader`]
+        for improved performance and less allocations.
+    */
+    #[must_use]
+    pub fn text_bytes(&self) -> Vec<u8> {
+        self.inner.text.bytes().collect()
+    }
+
+    /**
+        Returns the version of the document.
+
+        This number should be strictly increasing with
+        each change to the document, including undo/redo.
+    */
+    #[must_use]
+    pub fn version(&self) -> i32 {
+        self.inner.version
+    }
+
+    /**
+        Returns `true` if the contents of the document may have diverged
+        from the contents in the client, otherwise `false`.
+
+        Documents are only marked as stale when an incremental update from the
+        client could not be applied, and [`SyncRecovery::MarkStale`] or
+        [`SyncRecovery::Resync`] is used. They stay stale until the client
+        sends their full contents again.
+
+        [`SyncRecovery::MarkStale`]: crate::server::SyncRecovery::MarkStale
+        [`SyncRecovery::Resync`]: crate::server::SyncRecovery::Resync
+    */
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.inner.stale
+    }
+
+    /**
+        Returns `true` if the document was matched as read-only, otherwise `false`.
+
+        Formatting, rename, and code action edits for read-only documents
+        are rejected by the server before they are sent to the client.
+
+        See [`DocumentMatcher::with_read_only_globs`] for more information.
+
+        [`DocumentMatcher::with_read_only_globs`]: crate::server::DocumentMatcher::with_read_only_globs
+    */
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.inner.read_only
+    }
+
+    /**
+        Returns the language of the document.
+    */
+    #[must_use]
+    pub fn language(&self) -> &str {
+        &self.inner.language
+    }
+
+    /**
+        Returns the name of the document matcher that this document
+        was matched against, if one was configured, and either a
+        language or glob pattern was matched against.
+
+        See [`DocumentMatcher`] for more information.
+    */
+    #[must_use]
+    pub fn matched_name(&self) -> Option<&str> {
+        self.inner
+            .matcher
+            .as_ref()
+            .map(|matcher| matcher.name.as_str())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new_for_tests(text: &str) -> Self {
+        DocumentInner {
+            uri: Url::parse("file:///tmp/document.test").unwrap(),
+            text: text.into(),
+            line_index: OnceLock::new(),
+            version: 1,
+            language: "test".into(),
+            matcher: None,
+            stale: false,
+            read_only: false,
+            extensions: DocumentExtensions::default(),
+            #[cfg(feature = "tree-sitter")]
+            query_cache: QueryCache::default(),
+            #[cfg(feature = "tree-sitter")]
+            tree_sitter_lang: None,
+            #[cfg(feature = "tree-sitter")]
+            tree_sitter_tree: None,
+            #[cfg(feature = "tree-sitter")]
+            tree_sitter_pending: None,
+        }
+        .into()
+    }
+}
+
+#[cfg(feature = "tree-sitter")]
+impl Document {
+    /**
+        Returns `true` if the document has an assigned tree-sitter language, otherwise `false`.
+    */
+    #[must_use]
+    pub fn has_syntax_language(&self) -> bool {
+        self.inner.tree_sitter_lang.is_some()
+    }
+
+    /**
+        Returns `true` if the document has a parsed tree-sitter syntax tree, otherwise `false`.
+    */
+    #[must_use]
+    pub fn has_syntax_tree(&self) -> bool {
+        self.inner.tree_sitter_tree.is_some()
+    }
+
+    /**
+        Returns the status of the tree-sitter syntax tree for the document.
+
+        With background parsing enabled, documents that were just opened
+        or entirely replaced may not have a syntax tree until parsing
+        finishes, which is reported as [`SyntaxStatus::Pending`].
+
+        With a re-parse debounce, documents that were just edited keep their
+        edited syntax tree until the re-parse finishes, which is also
+        reported as [`SyntaxStatus::Pending`].
+
+        See [`ServerOptions::with_background_parsing`] and
+        [`ServerOptions::with_reparse_debounce`] for more information.
+
+        [`ServerOptions::with_background_parsing`]: crate::server::ServerOptions::with_background_parsing
+        [`ServerOptions::with_reparse_debounce`]: crate::server::ServerOptions::with_reparse_debounce
+    */
+    #[must_use]
+    pub fn syntax_status(&self) -> SyntaxStatus {
+        if self.inner.tree_sitter_pending.is_some() {
+            SyntaxStatus::Pending
+        } else if self.inner.tree_sitter_tree.is_some() {
+            SyntaxStatus::Ready
+        } else {
+            SyntaxStatus::Unavailable
+        }
+    }
+
+    /**
+        Returns the UTF-8 text of a [`Node`].
+
+        When possible, prefer [`Document::node_text_slice`]
+        for improved performance and less allocations.
+
+        Panics if the node is not valid for the document.
+    */
+    #[must_use]
+    pub fn node_text(&self, node: Node) -> String {
+        self.node_text_slice(node).to_string()
+    }
+
+    /**
+        Returns the UTF-8 text of a [`Node`], borrowed from the document.
+
+        Panics if the node is not valid for the document.
+    */
+    #[must_use]
+    pub fn node_text_slice(&self, node: Node) -> RopeSlice<'_> {
+        self.inner.text.byte_slice(node.byte_range())
+    }
+
+    /**
+        Returns a [`Node`] at the root of the syntax tree, if one exists.
+    */
+    #[must_use]
+    pub fn node_at_root(&self) -> Option<Node<'_>> {
+        self.inner
+            .tree_sitter_tree
+            .as_ref()
+            .map(|tree| tree.root_node())
+    }
+
+    /**
+        Returns a [`TreeCursor`] starting at the root of the syntax tree, if one exists.
+    */
+    #[must_use]
+    pub fn walk(&self) -> Option<TreeCursor<'_>> {
+        self.inner.tree_sitter_tree.as_ref().map(Tree::walk)
+    }
+
+    /**
+        Visits every node in the syntax tree, in depth-first order, calling
+        the given function with each node and its depth below the root.
+
+        Does nothing if the document has no syntax tree.
+    */
+    pub fn visit<'a>(&'a self, mut f: impl FnMut(Node<'a>, usize)) {
+        let Some(mut cursor) = self.walk() else {
+            return;
+        };
+
+        let mut depth = 0;
+        loop {
+            f(cursor.node(), depth);
+            if cursor.goto_first_child() {
+                depth += 1;
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return;
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    /**
+        Returns a [`Node`] at the given LSP position, if one exists.
+    */
+    #[must_use]
+    pub fn node_at_position(&self, position: Position) -> Option<Node<'_>> {
+        let root = self.node_at_root()?;
+        let point = lsp_position_to_ts_point(position);
+        root.descendant_for_point_range(point, point)
+    }
+
+    /**
+        Similar to [`node_at_position`], except the node must be named.
+    */
+    #[must_use]
+    pub fn node_at_position_named(&self, position: Position) -> Option<Node<'_>> {
+        let root = self.node_at_root()?;
+        let point = lsp_position_to_ts_point(position);
+        root.named_descendant_for_point_range(point, point)
+    }
+
+    /**
+        Returns the smallest named [`Node`] that fully contains the given LSP range, if one exists.
+
+        Useful as an entry point for code actions and range formatting,
+        which operate on a selected range rather than a single position.
+    */
+    #[must_use]
+    pub fn node_covering_range(&self, range: Range) -> Option<Node<'_>> {
+        let root = self.node_at_root()?;
+        let start = lsp_position_to_ts_point(range.start);
+        let end = lsp_position_to_ts_point(range.end);
+        root.named_descendant_for_point_range(start, end)
+    }
+
+    /**
+        Returns an iterator over the ancestors of a [`Node`], starting
+        with its parent, and ending with the root of the syntax tree.
+    */
+    #[allow(clippy::unused_self)]
+    pub fn node_ancestors<'a>(&'a self, node: Node<'a>) -> impl Iterator<Item = Node<'a>> + 'a {
+        std::iter::successors(node.parent(), Node::parent)
+    }
+
+    /**
+        Returns an iterator over the named children of a [`Node`].
+    */
+    #[allow(clippy::unused_self)]
+    pub fn node_children_named<'a>(
+        &'a self,
+        node: Node<'a>,
+    ) -> impl Iterator<Item = Node<'a>> + 'a {
+        std::iter::successors(node.named_child(0), Node::next_named_sibling)
+    }
+
+    /**
+        Returns an iterator over the named siblings following a [`Node`],
+        starting with its next named sibling.
+    */
+    #[allow(clippy::unused_self)]
+    pub fn next_named_siblings<'a>(
+        &'a self,
+        node: Node<'a>,
+    ) -> impl Iterator<Item = Node<'a>> + 'a {
+        std::iter::successors(node.next_named_sibling(), Node::next_named_sibling)
+    }
+
+    /**
+        Finds the closest ancestor of a [`Node`] with the given kind, if any.
+
+        The node itself is not included in the search.
+    */
+    #[must_use]
+    pub fn find_ancestor_of_kind<'a>(&'a self, node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+        self.node_ancestors(node)
+            .find(|ancestor| ancestor.kind() == kind)
+    }
+
+    /**
+        Creates and runs a query for the given query string.
+
+        Results are cached until the document is next updated, meaning
+        that running the same query again on an unchanged document,
+        or on any snapshot of it, is cheap.
+
+        Matches are filtered using the standard text predicates, such as
+        `#eq?` and `#match?`, and any custom predicates added to the matcher
+        using [`DocumentMatcher::with_query_predicate`].
+
+        Returns `Some(captures)` if the query was successful, otherwise `None`.
+    */
+    #[must_use]
+    pub fn query(&self, query: impl AsRef<str>) -> Option<Vec<DocumentQueryCapture>> {
+        let lang = self.inner.tree_sitter_lang.as_ref()?;
+        let tree = self.inner.tree_sitter_tree.as_ref()?;
+
+        let query = query.as_ref();
+        self.inner
+            .query_cache
+            .get_or_run(QueryKey::Source(query.to_string()), || {
+                let query = Query::new(lang, query).ok()?;
+                self.run_query(&query, tree, None)
+            })
+    }
+
+    /**
+        Creates and runs a query for the given query string, only
+        matching nodes that intersect the given LSP range.
+
+        Useful for handlers such as hover and completion, which only need
+        to look at the nodes near a position, instead of the entire tree.
+        Results are not cached, since ranges usually differ between uses.
+
+        Returns `Some(captures)` if the query was successful, otherwise `None`.
+    */
+    #[must_use]
+    pub fn query_in_range(
+        &self,
+        query: impl AsRef<str>,
+        range: Range,
+    ) -> Option<Vec<DocumentQueryCapture>> {
+        let lang = self.inner.tree_sitter_lang.as_ref()?;
+        let tree = self.inner.tree_sitter_tree.as_ref()?;
+
+        let query = Query::new(lang, query.as_ref()).ok()?;
+        self.run_query(&query, tree, Some(range))
+    }
+
+    /**
+        Runs the precompiled query with the given name, from the
+        document matcher that this document was matched against.
+
+        Results are cached just like for [`Document::query`].
+
+        Returns `Some(captures)` if the query was found and successful, otherwise `None`.
+
+        See [`DocumentMatcher::with_queries`] for more information.
+    */
+    #[must_use]
+    pub fn run_named_query(&self, name: &str) -> Option<Vec<DocumentQueryCapture>> {
+        let query = self.inner.matcher.as_ref()?.queries.get(name)?;
+        let tree = self.inner.tree_sitter_tree.as_ref()?;
+        self.inner
+            .query_cache
+            .get_or_run(QueryKey::Named(name.to_string()), || {
+                self.run_query(query, tree, None)
+            })
+    }
+
+    /**
+        Creates and runs a query for the given query string, returning
+        the name and [`Node`] of each capture, borrowed from the syntax tree.
+
+        Unlike [`Document::query`], this lets callers inspect the structure of
+        captured nodes, such as their parents and fields. Results are not cached.
+
+        Returns `Some(captures)` if the query was successful, otherwise `None`.
+    */
+    #[must_use]
+    pub fn query_nodes(&self, query: impl AsRef<str>) -> Option<Vec<(String, Node<'_>)>> {
+        let lang = self.inner.tree_sitter_lang.as_ref()?;
+        let tree = self.inner.tree_sitter_tree.as_ref()?;
+
+        let query = Query::new(lang, query.as_ref()).ok()?;
+        let query_names = query.capture_names();
+
+        let mut items = Vec::new();
+        self.for_each_capture(&query, tree, None, |index, node| {
+            items.push((query_names[index as usize].to_string(), node));
+        });
+        Some(items)
+    }
+
+    /**
+        Runs the given query lazily, returning an iterator over the name and [`Node`]
+        of each capture, in the order that the captured nodes appear in the document.
+
+        Unlike [`Document::query`], nothing is collected up front, meaning that callers
+        may stop early, such as after finding the first relevant capture. The query
+        must be compiled for the tree-sitter language of this document, and the given
+        cursor is used for running it, so that it can be reused between queries.
+
+        Returns `None` if the document has no syntax tree.
+    */
+    pub fn query_iter<'a>(
+        &'a self,
+        query: &'a Query,
+        cursor: &'a mut QueryCursor,
+    ) -> Option<impl Iterator<Item = (&'a str, Node<'a>)> + 'a> {
+        let root = self.node_at_root()?;
+        let query_names = query.capture_names();
+
+        let text_provider = RopeTextProvider::new(&self.inner.text);
+
+        let mut captures = cursor.captures(query, root, text_provider);
+        Some(std::iter::from_fn(move || {
+            loop {
+                let (matched, index) = captures.next()?;
+                if self.satisfies_query_predicates(query, matched) {
+                    let capture = matched.captures[*index];
+                    return Some((query_names[capture.index as usize], capture.node));
+                }
+            }
+        }))
+    }
+
+    fn run_query(
+        &self,
+        query: &Query,
+        tree: &Tree,
+        range: Option<Range>,
+    ) -> Option<Vec<DocumentQueryCapture>> {
+        let query_names = query.capture_names();
+
+        let mut items = Vec::new();
+        self.for_each_capture(query, tree, range, |index, node| {
+            let name = query_names[index as usize].to_string();
+            let text = self.node_text(node);
+            let range = ts_range_to_lsp_range(node.range());
+            items.push(DocumentQueryCapture { name, text, range });
+        });
+        Some(items)
+    }
+
+    fn for_each_capture<'a>(
+        &self,
+        query: &Query,
+        tree: &'a Tree,
+        range: Option<Range>,
+        mut f: impl FnMut(u32, Node<'a>),
+    ) {
+        let text_provider = RopeTextProvider::new(&self.inner.text);
+
+        let mut cursor = QueryCursor::new();
+        if let Some(range) = range {
+            let start = lsp_position_to_ts_point(range.start);
+            let end = lsp_position_to_ts_point(range.end);
+            cursor.set_point_range(start..end);
+        }
+        let mut it = cursor.matches(query, tree.root_node(), text_provider);
+
+        while let Some(matched) = it.next() {
+            if !self.satisfies_query_predicates(query, matched) {
+                continue;
+            }
+            for capture in matched.captures {
+                f(capture.index, capture.node);
+            }
+        }
+    }
+
+    /**
+        Checks if a query match satisfies all of the custom
+        query predicates added to the matcher of the document.
+    */
+    fn satisfies_query_predicates(&self, query: &Query, matched: &QueryMatch) -> bool {
+        let Some(matcher) = self.inner.matcher.as_ref() else {
+            return true;
+        };
+        if matcher.query_predicates.is_empty() {
+            return true;
+        }
+        query
+            .general_predicates(matched.pattern_index)
+            .iter()
+            .all(|predicate| {
+                let Some(callback) = matcher.query_predicates.get(predicate.operator.as_ref())
+                else {
+                    return true;
+                };
+                let args = predicate
+                    .args
+                    .iter()
+                    .map(|arg| match arg {
+                        QueryPredicateArg::Capture(index) => matched
+                            .nodes_for_capture_index(*index)
+                            .next()
+                            .map(|node| self.node_text(node))
+                            .unwrap_or_default(),
+                        QueryPredicateArg::String(value) => value.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                callback.call(&args)
+            })
+    }
+}
+
+/**
+    The status of the tree-sitter syntax tree for a [`Document`].
+*/
+#[cfg(feature = "tree-sitter")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxStatus {
+    /**
+        The document has a syntax tree for its current contents.
+    */
+    Ready,
+    /**
+        The document is being parsed in the background, and will have a syntax
+        tree for its current contents once parsing finishes.
+
+        Until then, the document may still have the syntax tree from before its
+        most recent edits, with the edits applied to it but not yet re-parsed.
+    */
+    Pending,
+    /**
+        The document has no syntax tree, since it has no tree-sitter
+        language, or is too large to be parsed.
+    */
+    Unavailable,
+}
+
+impl AsRef<Rope> for Document {
+    fn as_ref(&self) -> &Rope {
+        &self.inner.text
+    }
+}
+
+#[cfg(feature = "tree-sitter")]
+/**
+    A capture from a tree-sitter query on a document.
+
+    Created by calling [`Document::query`].
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentQueryCapture {
+    /// The capture name
+    pub name: String,
+    /// The textual contents of the capture
+    pub text: String,
+    /// The document range of the capture
+    pub range: Range,
+}
@@ -0,0 +1,102 @@
+use async_lsp::lsp_types::{Position, Range};
+
+use crate::tree_sitter_utils::ts_range_to_lsp_range;
+
+use super::Document;
+
+/**
+    A named node enclosing a position in a document.
+
+    Created by calling [`Document::node_path_at`].
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentNodePathItem {
+    /// The kind of the node
+    pub kind: String,
+    /// The text of the `name` field of the node, if it has one
+    pub name: Option<String>,
+    /// The document range of the node
+    pub range: Range,
+    /// The document range of the `name` field of the node, or the range of the node
+    pub selection_range: Range,
+}
+
+impl DocumentNodePathItem {
+    /**
+        Returns the text to display for the node - its name if it has one, otherwise its kind.
+    */
+    #[must_use]
+    pub fn display_text(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.kind)
+    }
+}
+
+impl Document {
+    /**
+        Returns the chain of named nodes enclosing the given LSP position,
+        ordered from outermost to innermost, ready to power breadcrumbs,
+        status bar context, or `window/showDocument` targets.
+
+        The root node of the tree is not included, since it always
+        encloses the entire document. Nodes with a `name` field, such
+        as most declarations, are usually the most useful to display,
+        and may be found by checking [`DocumentNodePathItem::name`].
+
+        Returns an empty list if the document has no tree-sitter tree.
+
+        # LSP Compatibility
+
+        Both the given position and the returned ranges use UTF-8 encoding,
+        and must be converted to the encoding negotiated with the client.
+    */
+    #[must_use]
+    pub fn node_path_at(&self, position: Position) -> Vec<DocumentNodePathItem> {
+        let Some(node) = self.node_at_position_named(position) else {
+            return Vec::new();
+        };
+
+        let mut path = std::iter::once(node)
+            .chain(self.node_ancestors(node))
+            .filter(|node| node.is_named() && node.parent().is_some())
+            .map(|node| {
+                let name = node.child_by_field_name("name");
+                DocumentNodePathItem {
+                    kind: node.kind().to_string(),
+                    name: name.map(|name| self.node_text(name)),
+                    range: ts_range_to_lsp_range(node.range()),
+                    selection_range: ts_range_to_lsp_range(name.unwrap_or(node).range()),
+                }
+            })
+            .collect::<Vec<_>>();
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use crate::document::Document;
+
+    use super::DocumentNodePathItem;
+
+    #[test]
+    fn items_display_their_name_or_kind() {
+        let mut item = DocumentNodePathItem {
+            kind: "function_declaration".into(),
+            name: None,
+            range: Range::default(),
+            selection_range: Range::default(),
+        };
+        assert_eq!(item.display_text(), "function_declaration");
+        item.name = Some("main".into());
+        assert_eq!(item.display_text(), "main");
+    }
+
+    #[test]
+    fn documents_without_trees_have_no_node_paths() {
+        let document = Document::new_for_tests("fn main() {}");
+        assert!(document.node_path_at(Position::new(0, 4)).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::ops::Range as ByteRange;
+
+use async_lsp::lsp_types::{Position, Range};
+
+use super::Document;
+
+// NOTE: All positions given to and returned from these methods use UTF-8
+// columns, just like all other positions seen by the server implementation,
+// and are clamped to the document, in the same way as the position encoding
+// conversions that are applied to positions sent by the client.
+impl Document {
+    /**
+        Converts a position in the document to an absolute byte offset.
+
+        Positions past the end of a line are clamped to the end of that line,
+        and positions past the last line are clamped to the last line.
+    */
+    #[must_use]
+    pub fn position_to_byte(&self, position: Position) -> usize {
+        let line = self.clamp_line(position.line as usize);
+        let col = (position.character as usize).min(self.line_len_bytes(line));
+        self.inner.text.line_to_byte(line) + col
+    }
+
+    /**
+        Converts an absolute byte offset in the document to a position.
+
+        Offsets past the end of the document are clamped to the end of the document.
+    */
+    #[must_use]
+    pub fn byte_to_position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.inner.text.len_bytes());
+        let line = self.inner.text.byte_to_line(offset);
+        let col = offset - self.inner.text.line_to_byte(line);
+        new_position(line, col)
+    }
+
+    /**
+        Converts a position in the document to an absolute character index.
+
+        Positions are clamped in the same way as for [`Document::position_to_byte`],
+        and positions inside of a multi-byte character refer to that character.
+    */
+    #[must_use]
+    pub fn position_to_char(&self, position: Position) -> usize {
+        self.inner
+            .text
+            .byte_to_char(self.position_to_byte(position))
+    }
+
+    /**
+        Converts an absolute character index in the document to a position.
+
+        Indices past the end of the document are clamped to the end of the document.
+    */
+    #[must_use]
+    pub fn char_to_position(&self, index: usize) -> Position {
+        let index = index.min(self.inner.text.len_chars());
+        self.byte_to_position(self.inner.text.char_to_byte(index))
+    }
+
+    /**
+        Converts a range in the document to an absolute byte range.
+
+        Both ends of the range are clamped in the same way as for [`Document::position_to_byte`].
+    */
+    #[must_use]
+    pub fn lsp_range_to_byte_range(&self, range: Range) -> ByteRange<usize> {
+        self.position_to_byte(range.start)..self.position_to_byte(range.end)
+    }
+
+    /**
+        Converts an absolute byte range in the document to a range.
+
+        Both ends of the range are clamped in the same way as for [`Document::byte_to_position`].
+    */
+    #[must_use]
+    pub fn byte_range_to_lsp_range(&self, range: ByteRange<usize>) -> Range {
+        Range::new(
+            self.byte_to_position(range.start),
+            self.byte_to_position(range.end),
+        )
+    }
+
+    pub(crate) fn clamp_line(&self, line: usize) -> usize {
+        line.min(self.inner.text.len_lines().saturating_sub(1))
+    }
+
+    /**
+        Returns the length of the given line in bytes, not including any line ending.
+    */
+    pub(crate) fn line_len_bytes(&self, line: usize) -> usize {
+        let slice = self.inner.text.line(line);
+        let mut len = slice.len_bytes();
+        if len > 0 && slice.byte(len - 1) == b'\n' {
+            len -= 1;
+            if len > 0 && slice.byte(len - 1) == b'\r' {
+                len -= 1;
+            }
+        }
+        len
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn new_position(line: usize, col: usize) -> Position {
+    Position::new(line as u32, col as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use crate::document::Document;
+
+    fn document(text: &str) -> Document {
+        Document::new_for_tests(text)
+    }
+
+    #[test]
+    fn positions_convert_to_and_from_bytes() {
+        let doc = document("a🙂b\r\nsecond\n");
+
+        assert_eq!(doc.position_to_byte(Position::new(0, 5)), 5);
+        assert_eq!(doc.position_to_byte(Position::new(1, 2)), 10);
+        assert_eq!(doc.byte_to_position(5), Position::new(0, 5));
+        assert_eq!(doc.byte_to_position(10), Position::new(1, 2));
+    }
+
+    #[test]
+    fn positions_convert_to_and_from_chars() {
+        let doc = document("a🙂b\nsecond");
+
+        assert_eq!(doc.position_to_char(Position::new(0, 5)), 2);
+        assert_eq!(doc.position_to_char(Position::new(1, 1)), 5);
+        assert_eq!(doc.char_to_position(2), Position::new(0, 5));
+        assert_eq!(doc.char_to_position(5), Position::new(1, 1));
+    }
+
+    #[test]
+    fn positions_are_clamped_to_the_document() {
+        let doc = document("first\r\nlast");
+
+        assert_eq!(doc.position_to_byte(Position::new(0, 99)), 5);
+        assert_eq!(doc.position_to_byte(Position::new(99, 99)), 11);
+        assert_eq!(doc.byte_to_position(99), Position::new(1, 4));
+        assert_eq!(doc.char_to_position(99), Position::new(1, 4));
+    }
+
+    #[test]
+    fn ranges_convert_to_and_from_byte_ranges() {
+        let doc = Document::new_for_tests("a🙂b\nsecond");
+        let range = Range::new(Position::new(0, 1), Position::new(1, 3));
+
+        assert_eq!(doc.lsp_range_to_byte_range(range), 1..10);
+        assert_eq!(doc.byte_range_to_lsp_range(1..10), range);
+        assert_eq!(
+            doc.byte_range_to_lsp_range(7..99),
+            Range::new(Position::new(1, 0), Position::new(1, 6))
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use super::DocumentQueryCapture;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum QueryKey {
+    Source(String),
+    Named(String),
+}
+
+type QueryResult = Option<Vec<DocumentQueryCapture>>;
+
+/**
+    Results of queries run on a single version of a document,
+    shared between all snapshots of that same version.
+
+    A fresh, empty cache is used whenever the document is updated.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QueryCache {
+    results: Arc<DashMap<QueryKey, QueryResult>>,
+}
+
+impl QueryCache {
+    pub(crate) fn get_or_run(
+        &self,
+        key: QueryKey,
+        run: impl FnOnce() -> QueryResult,
+    ) -> QueryResult {
+        if let Some(result) = self.results.get(&key) {
+            return result.value().clone();
+        }
+        // NOTE: The query is run without holding a lock on the cache, since
+        // walking the syntax tree may be slow, and other queries may be run
+        let result = run();
+        self.results.insert(key, result.clone());
+        result
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.results.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Document;
+
+    use super::{QueryCache, QueryKey};
+
+    #[test]
+    fn results_are_cached_per_key() {
+        let cache = QueryCache::default();
+        let mut runs = 0;
+        let mut run = |key: &str| {
+            cache.get_or_run(QueryKey::Source(key.into()), || {
+                runs += 1;
+                None
+            })
+        };
+
+        assert!(run("(a)").is_none());
+        assert!(run("(a)").is_none());
+        assert!(run("(b)").is_none());
+        assert_eq!(runs, 2);
+
+        let snapshot = cache.clone();
+        assert_eq!(snapshot.len(), 2);
+        assert!(
+            snapshot
+                .get_or_run(QueryKey::Named("(a)".into()), || Some(Vec::new()))
+                .is_some()
+        );
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn updated_documents_use_fresh_caches() {
+        let document = Document::new_for_tests("text");
+        let key = QueryKey::Source("(a)".into());
+        let _ = document.inner.query_cache.get_or_run(key, || None);
+
+        let snapshot = document.clone();
+        assert_eq!(snapshot.inner.query_cache.len(), 1);
+
+        let mut updated = document.clone();
+        updated.inner_mut().version += 1;
+        assert_eq!(updated.inner.query_cache.len(), 0);
+        assert_eq!(document.inner.query_cache.len(), 1);
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::{BufRead, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+
+use ropey::Rope;
+
+/**
+    A buffered, seekable reader over the full text contents of a document.
+
+    Reads directly from the chunks of the underlying [`Rope`], without
+    copying the full document text, meaning it is cheap to create and
+    can be used to stream documents into parsers or external processes.
+
+    # `async-read`
+
+    With the `async-read` crate feature enabled, this reader also
+    implements the [`tokio::io::AsyncRead`], [`tokio::io::AsyncBufRead`],
+    and [`tokio::io::AsyncSeek`] traits. Since the document is always
+    kept in memory, reading or seeking will never be pending.
+
+    Created by calling [`Document::text_reader`].
+
+    [`Document::text_reader`]: crate::server::Document::text_reader
+*/
+#[derive(Debug, Clone)]
+pub struct DocumentReader<'d> {
+    text: &'d Rope,
+    position: usize,
+}
+
+impl<'d> DocumentReader<'d> {
+    pub(crate) fn new(text: &'d Rope) -> Self {
+        Self { text, position: 0 }
+    }
+
+    /**
+        Returns the current byte offset of the reader in the document.
+    */
+    #[must_use]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    fn remaining_chunk(&self) -> &'d [u8] {
+        if self.position >= self.text.len_bytes() {
+            return &[];
+        }
+        let (chunk, chunk_start, _, _) = self.text.chunk_at_byte(self.position);
+        &chunk.as_bytes()[self.position - chunk_start..]
+    }
+
+    fn seek_to(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+                self.position = offset;
+                return Ok(offset as u64);
+            }
+            SeekFrom::End(offset) => (self.text.len_bytes(), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+
+        let position = isize::try_from(offset)
+            .ok()
+            .and_then(|offset| base.checked_add_signed(offset))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "invalid seek to a negative or overflowing position",
+                )
+            })?;
+
+        self.position = position;
+        Ok(position as u64)
+    }
+}
+
+impl Read for DocumentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut written = 0;
+
+        // NOTE: Chunks may be smaller than the given buffer, so we keep
+        // copying until the buffer is full, or the document has ended
+        while written < buf.len() {
+            let remaining = self.remaining_chunk();
+            if remaining.is_empty() {
+                break;
+            }
+
+            let len = remaining.len().min(buf.len() - written);
+            buf[written..written + len].copy_from_slice(&remaining[..len]);
+
+            written += len;
+            self.position += len;
+        }
+
+        Ok(written)
+    }
+}
+
+impl BufRead for DocumentReader<'_> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(self.remaining_chunk())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = self.position.saturating_add(amt);
+    }
+}
+
+impl Seek for DocumentReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.seek_to(pos)
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        Ok(self.position as u64)
+    }
+}
+
+#[cfg(feature = "async-read")]
+mod async_read {
+    use std::{
+        io::{Read, Result, SeekFrom},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, ReadBuf};
+
+    use super::DocumentReader;
+
+    impl AsyncRead for DocumentReader<'_> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<Result<()>> {
+            let this = self.get_mut();
+            let read = this.read(buf.initialize_unfilled())?;
+            buf.advance(read);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncBufRead for DocumentReader<'_> {
+        fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+            Poll::Ready(Ok(self.get_mut().remaining_chunk()))
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            std::io::BufRead::consume(self.get_mut(), amt);
+        }
+    }
+
+    impl AsyncSeek for DocumentReader<'_> {
+        fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+            self.get_mut().seek_to(position).map(|_| ())
+        }
+
+        fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+            Poll::Ready(Ok(self.position as u64))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Read, Seek, SeekFrom};
+
+    use ropey::Rope;
+
+    use super::DocumentReader;
+
+    fn large_text() -> String {
+        "some line 🙂\n".repeat(2000)
+    }
+
+    #[test]
+    fn reader_preserves_unread_chunk_bytes() {
+        let text = Rope::from_str("hello");
+        let mut reader = DocumentReader::new(&text);
+
+        let mut actual = Vec::new();
+        let mut buf = [0; 1];
+        while reader.read(&mut buf).unwrap() != 0 {
+            actual.push(buf[0]);
+        }
+
+        assert_eq!(actual, b"hello");
+    }
+
+    #[test]
+    fn reads_fill_buffers_across_chunks() {
+        let contents = large_text();
+        let text = Rope::from_str(&contents);
+        assert!(text.chunks().count() > 1);
+
+        let mut reader = DocumentReader::new(&text);
+        let mut buf = vec![0; contents.len() + 10];
+        assert_eq!(reader.read(&mut buf).unwrap(), contents.len());
+        assert_eq!(&buf[..contents.len()], contents.as_bytes());
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn buffered_lines_match_document_lines() {
+        let contents = large_text();
+        let text = Rope::from_str(&contents);
+
+        let lines: Vec<_> = DocumentReader::new(&text)
+            .lines()
+            .map(Result::unwrap)
+            .collect();
+        let expected: Vec<_> = contents.lines().map(ToString::to_string).collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn seeking_moves_the_read_position() {
+        let text = Rope::from_str("hello world");
+        let mut reader = DocumentReader::new(&text);
+        let mut actual = String::new();
+
+        assert_eq!(reader.seek(SeekFrom::Start(6)).unwrap(), 6);
+        reader.read_to_string(&mut actual).unwrap();
+        assert_eq!(actual, "world");
+
+        actual.clear();
+        assert_eq!(reader.seek(SeekFrom::End(-5)).unwrap(), 6);
+        assert_eq!(reader.seek(SeekFrom::Current(-6)).unwrap(), 0);
+        reader.read_to_string(&mut actual).unwrap();
+        assert_eq!(actual, "hello world");
+
+        assert!(reader.seek(SeekFrom::Current(-20)).is_err());
+        assert_eq!(reader.seek(SeekFrom::Start(100)).unwrap(), 100);
+        assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "async-read")]
+    fn async_reads_return_the_full_document() {
+        use tokio::io::AsyncReadExt;
+
+        let contents = large_text();
+        let text = Rope::from_str(&contents);
+
+        let mut actual = String::new();
+        let mut reader = DocumentReader::new(&text);
+        futures::executor::block_on(AsyncReadExt::read_to_string(&mut reader, &mut actual))
+            .unwrap();
+        assert_eq!(actual, contents);
+    }
+}
@@ -0,0 +1,83 @@
+use async_lsp::lsp_types::{Position, Range, SelectionRange};
+
+use crate::tree_sitter_utils::ts_range_to_lsp_range;
+
+use super::Document;
+
+impl Document {
+    /**
+        Returns the selection ranges at the given LSP position, starting with the
+        innermost named node at the position, and linked to the ranges of each of
+        its named ancestors, ready to be returned for `textDocument/selectionRange`.
+
+        Ancestors with the same range as their child are skipped, so that
+        each step outwards expands the selection.
+
+        Returns `None` if the document has no tree-sitter tree.
+
+        # LSP Compatibility
+
+        Both the given position and the returned ranges use UTF-8 encoding,
+        and must be converted to the encoding negotiated with the client.
+    */
+    #[must_use]
+    pub fn selection_range_at(&self, position: Position) -> Option<SelectionRange> {
+        let mut node = self.node_at_position_named(position)?;
+
+        let mut ranges = vec![ts_range_to_lsp_range(node.range())];
+        while let Some(parent) = node.parent() {
+            if parent.is_named() {
+                ranges.push(ts_range_to_lsp_range(parent.range()));
+            }
+            node = parent;
+        }
+
+        link_selection_ranges(ranges)
+    }
+}
+
+/**
+    Links ranges, ordered from innermost to outermost, into a selection range.
+*/
+fn link_selection_ranges(mut ranges: Vec<Range>) -> Option<SelectionRange> {
+    ranges.dedup();
+    ranges.into_iter().rev().fold(None, |parent, range| {
+        Some(SelectionRange {
+            range,
+            parent: parent.map(Box::new),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use crate::document::Document;
+
+    use super::link_selection_ranges;
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(0, start), Position::new(0, end))
+    }
+
+    #[test]
+    fn documents_without_trees_have_no_selection_ranges() {
+        let document = Document::new_for_tests("foo(bar)");
+        assert!(document.selection_range_at(Position::new(0, 5)).is_none());
+    }
+
+    #[test]
+    fn selection_ranges_are_linked_from_innermost_to_outermost() {
+        let ranges = vec![range(4, 7), range(3, 8), range(3, 8), range(0, 8)];
+        let selection = link_selection_ranges(ranges).unwrap();
+
+        let mut linked = vec![selection.range];
+        let mut parent = selection.parent;
+        while let Some(selection) = parent {
+            linked.push(selection.range);
+            parent = selection.parent;
+        }
+        assert_eq!(linked, [range(4, 7), range(3, 8), range(0, 8)]);
+    }
+}
@@ -0,0 +1,77 @@
+use async_lsp::lsp_types::Range;
+
+use crate::{
+    text_utils::byte_offset_to_ts,
+    tree_sitter::{InputEdit, Parser, Tree},
+    tree_sitter_utils::parse_rope,
+};
+
+use super::Document;
+
+impl Document {
+    /**
+        Parses the document as it would look after replacing the given range
+        with the given text, without changing the document itself.
+
+        The current tree-sitter tree of the document is cloned, edited, and
+        re-parsed incrementally, which is much cheaper than a full parse -
+        useful for completions that need to analyze the document as it would
+        look after inserting a candidate.
+
+        Note that the returned tree describes the edited text, not the text of
+        this document, so node positions after the edit will not match it.
+
+        Returns `None` if the document has no tree-sitter tree.
+
+        # LSP Compatibility
+
+        The range must use UTF-8 encoding, and is clamped to
+        the document, in the same way as [`Document::position_to_byte`].
+    */
+    #[must_use]
+    pub fn tree_with_edit(&self, range: Range, new_text: &str) -> Option<Tree> {
+        let lang = self.inner.tree_sitter_lang.as_ref()?;
+        let old_tree = self.inner.tree_sitter_tree.as_ref()?;
+
+        let text = &self.inner.text;
+        let start_char = text.byte_to_char(self.position_to_byte(range.start));
+        let end_char = text
+            .byte_to_char(self.position_to_byte(range.end))
+            .max(start_char);
+        let start_byte = text.char_to_byte(start_char);
+        let old_end_byte = text.char_to_byte(end_char);
+        let new_end_byte = start_byte + new_text.len();
+
+        let mut new_text_rope = text.clone();
+        new_text_rope.remove(start_char..end_char);
+        new_text_rope.insert(start_char, new_text);
+
+        let mut tree = old_tree.clone();
+        tree.edit(&InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+            start_position: byte_offset_to_ts(text, start_byte).1,
+            old_end_position: byte_offset_to_ts(text, old_end_byte).1,
+            new_end_position: byte_offset_to_ts(&new_text_rope, new_end_byte).1,
+        });
+
+        let mut parser = Parser::new();
+        parser.set_language(lang).ok()?;
+        parse_rope(&mut parser, &new_text_rope, Some(&tree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use crate::document::Document;
+
+    #[test]
+    fn documents_without_trees_have_no_edited_trees() {
+        let document = Document::new_for_tests("let x = 1");
+        let range = Range::new(Position::new(0, 8), Position::new(0, 9));
+        assert!(document.tree_with_edit(range, "2").is_none());
+    }
+}
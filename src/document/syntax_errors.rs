@@ -0,0 +1,136 @@
+use async_lsp::lsp_types::Range;
+
+use crate::{
+    tree_sitter::{Language, Node},
+    tree_sitter_utils::ts_range_to_lsp_range,
+};
+
+use super::Document;
+
+/**
+    The kind of a syntax error in a document.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxErrorKind {
+    /// Text that could not be parsed, from an `ERROR` node
+    Error,
+    /// A node that was expected but missing, from a `MISSING` node, with the missing kind
+    Missing(String),
+}
+
+/**
+    A syntax error in the tree-sitter tree of a document.
+
+    Created by calling [`Document::syntax_errors`].
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxErrorInfo {
+    /// The kind of the syntax error
+    pub kind: SyntaxErrorKind,
+    /// The document range of the syntax error
+    pub range: Range,
+    /// Node kinds that the grammar expected instead, if known
+    pub expected: Vec<String>,
+}
+
+impl Document {
+    /**
+        Checks if the tree-sitter tree of the document contains any syntax errors.
+
+        This is much cheaper than [`Document::syntax_errors`], since the
+        tree keeps track of errors, and no nodes need to be visited.
+
+        Returns `false` if the document has no tree-sitter tree.
+    */
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.node_at_root().is_some_and(|root| root.has_error())
+    }
+
+    /**
+        Lists all syntax errors in the tree-sitter tree of the document,
+        from its `ERROR` and `MISSING` nodes, sorted by their position.
+
+        Errors nested inside of other errors are not listed separately.
+        For `ERROR` nodes, the node kinds that the grammar would have
+        accepted at the start of the error are listed as expected.
+
+        Returns an empty list if the document has no tree-sitter tree.
+
+        # LSP Compatibility
+
+        All ranges use UTF-8 encoding, and must be
+        converted to the encoding negotiated with the client.
+    */
+    #[must_use]
+    pub fn syntax_errors(&self) -> Vec<SyntaxErrorInfo> {
+        let (Some(lang), Some(root)) = (self.inner.tree_sitter_lang.as_ref(), self.node_at_root())
+        else {
+            return Vec::new();
+        };
+        let mut errors = Vec::new();
+        collect_errors(lang, root, &mut errors);
+        errors
+    }
+}
+
+fn collect_errors(lang: &Language, node: Node, errors: &mut Vec<SyntaxErrorInfo>) {
+    // NOTE: Subtrees without any errors in them can be skipped entirely
+    if node.is_error() {
+        errors.push(SyntaxErrorInfo {
+            kind: SyntaxErrorKind::Error,
+            range: ts_range_to_lsp_range(node.range()),
+            expected: expected_kinds(lang, node),
+        });
+    } else if node.is_missing() {
+        errors.push(SyntaxErrorInfo {
+            kind: SyntaxErrorKind::Missing(node.kind().to_string()),
+            range: ts_range_to_lsp_range(node.range()),
+            expected: vec![node.kind().to_string()],
+        });
+    } else if node.has_error() {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect_errors(lang, child, errors);
+        }
+    }
+}
+
+fn expected_kinds(lang: &Language, node: Node) -> Vec<String> {
+    // NOTE: Error nodes themselves have no useful parse state, so we use
+    // the state after the node before the error, or the state at the start
+    // of the parent node when the error is the first node in its parent
+    let state = match (node.prev_sibling(), node.parent()) {
+        (Some(prev), _) => prev.next_parse_state(),
+        (None, Some(parent)) => parent.parse_state(),
+        (None, None) => return Vec::new(),
+    };
+    let Some(lookahead) = lang.lookahead_iterator(state) else {
+        return Vec::new();
+    };
+
+    let mut kinds = Vec::<String>::new();
+    for symbol in lookahead {
+        if !lang.node_kind_is_visible(symbol) {
+            continue;
+        }
+        if let Some(kind) = lang.node_kind_for_id(symbol)
+            && !kinds.iter().any(|k| k == kind)
+        {
+            kinds.push(kind.to_string());
+        }
+    }
+    kinds
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Document;
+
+    #[test]
+    fn documents_without_trees_have_no_syntax_errors() {
+        let document = Document::new_for_tests("fn (");
+        assert!(!document.has_errors());
+        assert!(document.syntax_errors().is_empty());
+    }
+}
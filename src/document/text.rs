@@ -0,0 +1,144 @@
+use std::{borrow::Cow, ops::Range as ByteRange};
+
+use async_lsp::lsp_types::Range;
+use ropey::iter::Chunks;
+
+use super::Document;
+
+impl Document {
+    /**
+        Returns an iterator over the chunks of text in the document.
+
+        Chunks are borrowed directly from the underlying [`Rope`], and
+        may be used to process the full text without any allocations.
+
+        [`Rope`]: ropey::Rope
+    */
+    #[must_use]
+    pub fn text_chunks(&self) -> Chunks<'_> {
+        self.inner.text.chunks()
+    }
+
+    /**
+        Returns a 64-bit hash of the full text contents of the document.
+
+        The hash is computed on demand using FNV-1a, and is stable across
+        runs and platforms, meaning that it may be used to key caches by
+        content instead of version, or to cheaply detect saves and
+        changes that did not actually change the contents.
+
+        Note that this is not a cryptographic hash.
+    */
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        self.inner
+            .text
+            .chunks()
+            .flat_map(str::bytes)
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /**
+        Returns the text covered by the given range, clamped to the document.
+
+        Returns `None` if the range ends before it starts.
+    */
+    #[must_use]
+    pub fn text_in_range(&self, range: Range) -> Option<Cow<'_, str>> {
+        self.text_in_byte_range(self.lsp_range_to_byte_range(range))
+    }
+
+    /**
+        Returns the text covered by the given byte range, clamped to the document.
+
+        Returns `None` if the range ends before it starts, or if either end
+        of the range is not on a character boundary in the document.
+    */
+    #[must_use]
+    pub fn text_in_byte_range(&self, range: ByteRange<usize>) -> Option<Cow<'_, str>> {
+        let len = self.inner.text.len_bytes();
+        let start = range.start.min(len);
+        let end = range.end.min(len);
+        if start > end || !self.is_char_boundary(start) || !self.is_char_boundary(end) {
+            return None;
+        }
+        Some(self.inner.text.byte_slice(start..end).into())
+    }
+
+    pub(crate) fn is_char_boundary(&self, byte: usize) -> bool {
+        byte == self.inner.text.len_bytes()
+            || self
+                .inner
+                .text
+                .char_to_byte(self.inner.text.byte_to_char(byte))
+                == byte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use crate::document::Document;
+
+    #[test]
+    fn text_chunks_contain_the_full_text() {
+        let text = "some line 🙂\n".repeat(2000);
+        let doc = Document::new_for_tests(&text);
+
+        assert!(doc.text_chunks().count() > 1);
+        assert_eq!(doc.text_chunks().collect::<String>(), text);
+    }
+
+    #[test]
+    fn content_hashes_depend_only_on_contents() {
+        let text = "some line 🙂\n".repeat(2000);
+        let doc = Document::new_for_tests(&text);
+
+        let mut other = Document::new_for_tests("");
+        let inner = other.inner_mut();
+        inner.text = ropey::Rope::from_str(&text[..100]);
+        inner.text.insert(inner.text.len_chars(), &text[100..]);
+
+        assert_eq!(doc.content_hash(), other.content_hash());
+        assert_ne!(
+            doc.content_hash(),
+            Document::new_for_tests("a").content_hash()
+        );
+        assert_eq!(
+            Document::new_for_tests("").content_hash(),
+            0xcbf2_9ce4_8422_2325
+        );
+    }
+
+    #[test]
+    fn text_in_range_is_clamped() {
+        let doc = Document::new_for_tests("first 🙂\nsecond\n");
+
+        let range = Range::new(Position::new(0, 6), Position::new(1, 3));
+        assert_eq!(doc.text_in_range(range).as_deref(), Some("🙂\nsec"));
+
+        let range = Range::new(Position::new(1, 3), Position::new(99, 0));
+        assert_eq!(doc.text_in_range(range).as_deref(), Some("ond\n"));
+
+        let range = Range::new(Position::new(1, 0), Position::new(0, 0));
+        assert_eq!(doc.text_in_range(range), None);
+    }
+
+    #[test]
+    fn text_in_byte_range_requires_char_boundaries() {
+        let doc = Document::new_for_tests("a🙂b");
+
+        assert_eq!(doc.text_in_byte_range(1..5).as_deref(), Some("🙂"));
+        assert_eq!(doc.text_in_byte_range(5..99).as_deref(), Some("b"));
+        assert_eq!(doc.text_in_byte_range(2..5), None);
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 5..1;
+        assert_eq!(doc.text_in_byte_range(reversed), None);
+    }
+}
@@ -0,0 +1,88 @@
+use async_lsp::lsp_types::{Position, Range};
+
+use crate::text_utils::{is_identifier_char, word_range_at};
+
+use super::Document;
+
+impl Document {
+    /**
+        Returns the word at the given position, and its range, if any.
+
+        Words consist of alphanumeric characters and underscores. A position
+        directly after the end of a word, such as the cursor position while
+        typing the word, is also considered to be part of that word.
+
+        See [`Document::word_at_position_with`] for using other word characters,
+        and [`text_utils::word_range_at`] for finding words in any text.
+
+        [`text_utils::word_range_at`]: crate::text_utils::word_range_at
+    */
+    #[must_use]
+    pub fn word_at_position(&self, position: Position) -> Option<(String, Range)> {
+        self.word_at_position_with(position, is_identifier_char)
+    }
+
+    /**
+        Returns the word at the given position, and its range, if any.
+
+        Same as [`Document::word_at_position`], except that the given
+        predicate decides which characters are part of a word.
+    */
+    #[must_use]
+    pub fn word_at_position_with(
+        &self,
+        position: Position,
+        is_word_char: impl Fn(char) -> bool,
+    ) -> Option<(String, Range)> {
+        let range = word_range_at(&self.inner.text, position, is_word_char)?;
+        let text = self.text_in_range(range)?;
+        Some((text.into_owned(), range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use crate::document::Document;
+
+    fn word(text: &str, col: u32) -> Option<(String, Range)> {
+        Document::new_for_tests(text).word_at_position(Position::new(0, col))
+    }
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(0, start), Position::new(0, end))
+    }
+
+    #[test]
+    fn words_are_found_around_the_position() {
+        assert_eq!(
+            word("let foo_bar = 1", 4),
+            Some(("foo_bar".into(), range(4, 11)))
+        );
+        assert_eq!(
+            word("let foo_bar = 1", 7),
+            Some(("foo_bar".into(), range(4, 11)))
+        );
+        assert_eq!(
+            word("let foo_bar = 1", 11),
+            Some(("foo_bar".into(), range(4, 11)))
+        );
+        assert_eq!(word("let foo_bar = 1", 12), None);
+    }
+
+    #[test]
+    fn words_use_utf8_columns() {
+        assert_eq!(word("🙂 héllo", 5), Some(("héllo".into(), range(5, 11))));
+        assert_eq!(word("🙂 héllo", 7), Some(("héllo".into(), range(5, 11))));
+    }
+
+    #[test]
+    fn word_characters_are_configurable() {
+        let doc = Document::new_for_tests("use foo-bar.baz");
+        let found = doc.word_at_position_with(Position::new(0, 6), |c| {
+            c.is_alphanumeric() || c == '-' || c == '.'
+        });
+        assert_eq!(found, Some(("foo-bar.baz".into(), range(4, 15))));
+    }
+}
@@ -4,7 +4,10 @@ use async_lsp::lsp_types::Url;
 use globset::{Glob, GlobSet};
 
 #[cfg(feature = "tree-sitter")]
-use tree_sitter::Language;
+use std::fmt;
+
+#[cfg(feature = "tree-sitter")]
+use tree_sitter::{Language, Query};
 
 /**
     Options for matching documents based on their URLs and
@@ -28,11 +31,58 @@ pub struct DocumentMatcher {
         Strings to match documents based on their language identifiers.
     */
     pub lang_strings: Vec<String>,
+    /**
+        Optional globs for matched documents that should be read-only,
+        such as generated files or files in build output directories.
+
+        See [`Document::is_read_only`] for more information.
+
+        [`Document::is_read_only`]: crate::server::Document::is_read_only
+    */
+    pub read_only_globs: Vec<String>,
     #[cfg(feature = "tree-sitter")]
     /**
         The tree-sitter language grammar to associate with the matched document.
     */
     pub lang_grammar: Option<Language>,
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Tree-sitter language grammars for matched documents with URLs
+        matching specific globs, such as globs for `.tsx` files, which take
+        precedence over the grammar in [`DocumentMatcher::lang_grammar`].
+
+        The first grammar with a matching glob is used.
+    */
+    pub lang_grammar_globs: Vec<(Vec<String>, Language)>,
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Named tree-sitter queries, compiled for the language grammar.
+
+        See [`Document::run_named_query`] for more information.
+
+        [`Document::run_named_query`]: crate::server::Document::run_named_query
+    */
+    pub queries: HashMap<String, Arc<Query>>,
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Tree-sitter node kinds that should be folded.
+
+        See [`Document::folding_ranges`] for more information.
+
+        [`Document::folding_ranges`]: crate::server::Document::folding_ranges
+    */
+    pub folding_kinds: Vec<String>,
+    #[cfg(feature = "tree-sitter")]
+    /**
+        A tree-sitter query for nodes that should be folded, such as a grammar's `folds.scm`.
+
+        See [`Document::folding_ranges`] for more information.
+
+        [`Document::folding_ranges`]: crate::server::Document::folding_ranges
+    */
+    pub folding_query: Option<Arc<Query>>,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) query_predicates: HashMap<String, QueryPredicate>,
 }
 
 impl DocumentMatcher {
@@ -48,8 +98,19 @@ impl DocumentMatcher {
             name: name.into(),
             url_globs: Vec::new(),
             lang_strings: Vec::new(),
+            read_only_globs: Vec::new(),
             #[cfg(feature = "tree-sitter")]
             lang_grammar: None,
+            #[cfg(feature = "tree-sitter")]
+            lang_grammar_globs: Vec::new(),
+            #[cfg(feature = "tree-sitter")]
+            queries: HashMap::new(),
+            #[cfg(feature = "tree-sitter")]
+            folding_kinds: Vec::new(),
+            #[cfg(feature = "tree-sitter")]
+            folding_query: None,
+            #[cfg(feature = "tree-sitter")]
+            query_predicates: HashMap::new(),
         }
     }
 
@@ -80,6 +141,24 @@ impl DocumentMatcher {
         self
     }
 
+    /**
+        Adds the given read-only URL globs to the matcher.
+
+        Documents matched by this matcher that also match any of
+        these globs will be flagged as read-only, and the server will
+        reject any formatting, rename, or code action edits for them.
+    */
+    #[must_use]
+    pub fn with_read_only_globs<I, U>(mut self, read_only_globs: I) -> Self
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<String>,
+    {
+        self.read_only_globs
+            .extend(read_only_globs.into_iter().map(Into::into));
+        self
+    }
+
     #[cfg(feature = "tree-sitter")]
     /**
         Sets the tree-sitter language grammar
@@ -90,6 +169,168 @@ impl DocumentMatcher {
         self.lang_grammar = Some(lang_grammar);
         self
     }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Adds a tree-sitter language grammar to use for matched
+        documents with URLs matching any of the given globs, instead of
+        the one given to [`DocumentMatcher::with_lang_grammar`].
+
+        This lets a single matcher handle closely related languages, such as
+        TypeScript and TSX, without duplicating all of its other options.
+
+        Note that named queries and folding queries are compiled for a single
+        grammar, and will not produce results for documents using other grammars.
+    */
+    #[must_use]
+    pub fn with_lang_grammar_for_globs<I, U>(mut self, url_globs: I, lang_grammar: Language) -> Self
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<String>,
+    {
+        let url_globs = url_globs.into_iter().map(Into::into).collect();
+        self.lang_grammar_globs.push((url_globs, lang_grammar));
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Adds a tree-sitter language grammar to use for matched documents with
+        any of the given file extensions, such as `tsx`, instead of the one
+        given to [`DocumentMatcher::with_lang_grammar`].
+
+        See [`DocumentMatcher::with_lang_grammar_for_globs`] for more information.
+    */
+    #[must_use]
+    pub fn with_lang_grammar_for_extensions<I, U>(
+        self,
+        extensions: I,
+        lang_grammar: Language,
+    ) -> Self
+    where
+        I: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        let url_globs = extensions
+            .into_iter()
+            .map(|ext| format!("**/*.{}", ext.as_ref().trim_start_matches('.')))
+            .collect::<Vec<_>>();
+        self.with_lang_grammar_for_globs(url_globs, lang_grammar)
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Adds the given named tree-sitter queries to the matcher.
+
+        Queries should be compiled once, for the same language grammar as the
+        one given to [`DocumentMatcher::with_lang_grammar`], and may then be run
+        for matched documents using [`Document::run_named_query`] - avoiding
+        the cost of compiling queries each time they are used.
+
+        Queries with the same name as a previously added query replace it.
+
+        [`Document::run_named_query`]: crate::server::Document::run_named_query
+    */
+    #[must_use]
+    pub fn with_queries<I, N>(mut self, queries: I) -> Self
+    where
+        I: IntoIterator<Item = (N, Query)>,
+        N: Into<String>,
+    {
+        self.queries.extend(
+            queries
+                .into_iter()
+                .map(|(name, query)| (name.into(), Arc::new(query))),
+        );
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Adds the given tree-sitter node kinds to fold, such as `block`.
+
+        Node kinds are only used if no folding query has been set using
+        [`DocumentMatcher::with_folding_query`].
+
+        See [`Document::folding_ranges`] for more information.
+
+        [`Document::folding_ranges`]: crate::server::Document::folding_ranges
+    */
+    #[must_use]
+    pub fn with_folding_kinds<I, K>(mut self, folding_kinds: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        self.folding_kinds
+            .extend(folding_kinds.into_iter().map(Into::into));
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Sets the tree-sitter query used to find nodes to fold, such as a
+        grammar's `folds.scm`, compiled for the same language grammar as
+        the one given to [`DocumentMatcher::with_lang_grammar`].
+
+        All captures in the query are folded, regardless of their names.
+
+        See [`Document::folding_ranges`] for more information.
+
+        [`Document::folding_ranges`]: crate::server::Document::folding_ranges
+    */
+    #[must_use]
+    pub fn with_folding_query(mut self, folding_query: Query) -> Self {
+        self.folding_query = Some(Arc::new(folding_query));
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Adds a custom tree-sitter query predicate, such as `is-upper?`,
+        which is then used to filter matches of all queries run for
+        matched documents, such as using [`Document::query`].
+
+        The predicate is called with its arguments for each match, where captures
+        are replaced with their text, and matches are kept only if it returns `true`.
+
+        Predicates that have not been added are ignored, except for the standard
+        text predicates, such as `eq?`, `match?`, and `any-of?`, which tree-sitter
+        always applies. Predicates with the same name as a previously added
+        predicate replace it.
+
+        [`Document::query`]: crate::server::Document::query
+    */
+    #[must_use]
+    pub fn with_query_predicate<F>(mut self, name: impl Into<String>, predicate: F) -> Self
+    where
+        F: Fn(&[String]) -> bool + Send + Sync + 'static,
+    {
+        self.query_predicates
+            .insert(name.into(), QueryPredicate(Arc::new(predicate)));
+        self
+    }
+}
+
+#[cfg(feature = "tree-sitter")]
+type QueryPredicateFn = dyn Fn(&[String]) -> bool + Send + Sync;
+
+#[cfg(feature = "tree-sitter")]
+#[derive(Clone)]
+pub(crate) struct QueryPredicate(Arc<QueryPredicateFn>);
+
+#[cfg(feature = "tree-sitter")]
+impl QueryPredicate {
+    pub(crate) fn call(&self, args: &[String]) -> bool {
+        (self.0)(args)
+    }
+}
+
+#[cfg(feature = "tree-sitter")]
+impl fmt::Debug for QueryPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("QueryPredicate")
+    }
 }
 
 /**
@@ -101,6 +342,9 @@ impl DocumentMatcher {
 pub(crate) struct DocumentMatchers {
     globsets: Arc<Vec<(GlobSet, Arc<DocumentMatcher>)>>,
     languages: Arc<HashMap<String, Arc<DocumentMatcher>>>,
+    read_only: Arc<Vec<(GlobSet, Arc<DocumentMatcher>)>>,
+    #[cfg(feature = "tree-sitter")]
+    grammars: Arc<Vec<(GlobSet, Arc<DocumentMatcher>, Language)>>,
 }
 
 #[allow(dead_code)]
@@ -108,32 +352,23 @@ impl DocumentMatchers {
     pub(crate) fn new(it: impl IntoIterator<Item = DocumentMatcher>) -> Self {
         let mut globsets = Vec::new();
         let mut languages = HashMap::new();
+        let mut read_only = Vec::new();
+        #[cfg(feature = "tree-sitter")]
+        let mut grammars = Vec::new();
 
         for matcher in it {
             let matcher = Arc::new(matcher);
 
-            let mut globset = GlobSet::builder();
-            let mut globset_any = false;
-            for glob in &matcher.url_globs {
-                if let Ok(glob) = Glob::new(glob) {
-                    globset.add(glob);
-                    globset_any = true;
-                } else {
-                    #[cfg(feature = "tracing")]
-                    tracing::warn!(
-                        "Encountered invalid glob pattern '{}' in matcher '{}'",
-                        glob,
-                        matcher.name
-                    );
-                }
+            if let Some(globset) = build_globset(&matcher, &matcher.url_globs) {
+                globsets.push((globset, Arc::clone(&matcher)));
             }
-
-            if globset_any {
-                if let Ok(globset) = globset.build() {
-                    globsets.push((globset, Arc::clone(&matcher)));
-                } else {
-                    #[cfg(feature = "tracing")]
-                    tracing::warn!("Encountered invalid globset in matcher '{}'", matcher.name);
+            if let Some(globset) = build_globset(&matcher, &matcher.read_only_globs) {
+                read_only.push((globset, Arc::clone(&matcher)));
+            }
+            #[cfg(feature = "tree-sitter")]
+            for (globs, grammar) in &matcher.lang_grammar_globs {
+                if let Some(globset) = build_globset(&matcher, globs) {
+                    grammars.push((globset, Arc::clone(&matcher), grammar.clone()));
                 }
             }
 
@@ -147,6 +382,9 @@ impl DocumentMatchers {
         Self {
             globsets: Arc::new(globsets),
             languages: Arc::new(languages),
+            read_only: Arc::new(read_only),
+            #[cfg(feature = "tree-sitter")]
+            grammars: Arc::new(grammars),
         }
     }
 
@@ -159,6 +397,13 @@ impl DocumentMatchers {
             .or_else(|| self.find_url(url))
     }
 
+    pub(crate) fn url_globs(&self) -> Vec<String> {
+        self.globsets
+            .iter()
+            .flat_map(|(_, matcher)| matcher.url_globs.iter().cloned())
+            .collect()
+    }
+
     pub(crate) fn find_url(&self, url: &Url) -> Option<Arc<DocumentMatcher>> {
         url.to_file_path().ok().and_then(|p| {
             self.globsets
@@ -167,4 +412,63 @@ impl DocumentMatchers {
                 .map(|(_, matcher)| Arc::clone(matcher))
         })
     }
+
+    pub(crate) fn is_read_only(&self, matcher: Option<&Arc<DocumentMatcher>>, url: &Url) -> bool {
+        let Some(matcher) = matcher else {
+            return false;
+        };
+        url.to_file_path().is_ok_and(|p| {
+            self.read_only
+                .iter()
+                .any(|(globset, m)| Arc::ptr_eq(m, matcher) && globset.is_match(&p))
+        })
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) fn lang_grammar(
+        &self,
+        matcher: Option<&Arc<DocumentMatcher>>,
+        url: &Url,
+    ) -> Option<Language> {
+        let matcher = matcher?;
+        url.to_file_path()
+            .ok()
+            .and_then(|p| {
+                self.grammars
+                    .iter()
+                    .find(|(globset, m, _)| Arc::ptr_eq(m, matcher) && globset.is_match(&p))
+                    .map(|(_, _, grammar)| grammar.clone())
+            })
+            .or_else(|| matcher.lang_grammar.clone())
+    }
+}
+
+#[allow(unused_variables)]
+fn build_globset(matcher: &DocumentMatcher, globs: &[String]) -> Option<GlobSet> {
+    let mut globset = GlobSet::builder();
+    let mut globset_any = false;
+    for glob in globs {
+        if let Ok(glob) = Glob::new(glob) {
+            globset.add(glob);
+            globset_any = true;
+        } else {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                "Encountered invalid glob pattern '{}' in matcher '{}'",
+                glob,
+                matcher.name
+            );
+        }
+    }
+
+    if !globset_any {
+        return None;
+    }
+    globset
+        .build()
+        .inspect_err(|_| {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Encountered invalid globset in matcher '{}'", matcher.name);
+        })
+        .ok()
 }
@@ -0,0 +1,238 @@
+use std::{future::Future, sync::Arc};
+
+use async_lsp::lsp_types::Url;
+use dashmap::DashMap;
+use futures::future::{Either, select};
+
+use crate::{cancellation::CancellationToken, document::Document, server_state::ServerState};
+
+/**
+    Cancellation tokens for background tasks spawned for
+    documents, cancelled whenever their document changes.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DocumentTasks {
+    inner: Arc<DashMap<Url, Vec<CancellationToken>>>,
+}
+
+impl DocumentTasks {
+    fn register(&self, url: &Url) -> CancellationToken {
+        let token = CancellationToken::new();
+        let mut tokens = self.inner.entry(url.clone()).or_default();
+        // NOTE: Finished tasks cancel their own tokens, so we can prune them here
+        tokens.retain(|t| !t.is_cancelled());
+        tokens.push(token.clone());
+        token
+    }
+
+    pub(crate) fn cancel(&self, url: &Url) {
+        if let Some((_, tokens)) = self.inner.remove(url) {
+            for token in tokens {
+                token.cancel();
+            }
+        }
+    }
+}
+
+pub(crate) fn spawn<F, Fut>(
+    state: ServerState,
+    url: Url,
+    restart: bool,
+    task: F,
+) -> Option<CancellationToken>
+where
+    F: Fn(Document) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let handle = tokio::runtime::Handle::try_current().ok()?;
+    state.document(&url)?;
+
+    let cancellation = CancellationToken::new();
+    let outer = cancellation.clone();
+    handle.spawn(async move {
+        loop {
+            let Some(doc) = state.document(&url) else {
+                return;
+            };
+
+            // Make sure the document did not change between taking
+            // the snapshot above and registering the task for it
+            let run = state.document_tasks().register(&url);
+            if state.document(&url).map(|d| d.version()) != Some(doc.version()) {
+                run.cancel();
+                if restart && !outer.is_cancelled() {
+                    continue;
+                }
+                return;
+            }
+
+            let fut = std::pin::pin!(task(doc));
+            let cancelled = std::pin::pin!(async {
+                select(
+                    std::pin::pin!(run.cancelled()),
+                    std::pin::pin!(outer.cancelled()),
+                )
+                .await;
+            });
+
+            match select(fut, cancelled).await {
+                Either::Left(((), _)) => {
+                    run.cancel();
+                    return;
+                }
+                Either::Right(((), _)) => {
+                    if !restart || outer.is_cancelled() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    Some(cancellation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use async_lsp::{
+        ClientSocket,
+        lsp_types::{
+            DidChangeTextDocumentParams, DidOpenTextDocumentParams, TextDocumentContentChangeEvent,
+            TextDocumentItem, Url, VersionedTextDocumentIdentifier,
+        },
+    };
+
+    use crate::server::{Server, ServerState};
+
+    struct TestServer;
+
+    impl Server for TestServer {}
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("runtime can be created")
+    }
+
+    fn open(state: &mut ServerState, url: &Url, text: &str) {
+        let _ = state.handle_document_open::<TestServer>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(url.clone(), "test".into(), 1, text.into()),
+        });
+    }
+
+    fn change(state: &mut ServerState, url: &Url, version: i32, text: &str) {
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(url.clone(), version),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.into(),
+            }],
+        });
+    }
+
+    fn recording_task(
+        seen: &Arc<Mutex<Vec<(i32, bool)>>>,
+    ) -> impl Fn(crate::server::Document) -> futures::future::BoxFuture<'static, ()>
+    + Send
+    + Sync
+    + 'static {
+        let seen = Arc::clone(seen);
+        move |doc| {
+            let seen = Arc::clone(&seen);
+            Box::pin(async move {
+                let index = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push((doc.version(), false));
+                    seen.len() - 1
+                };
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                seen.lock().unwrap()[index].1 = true;
+            })
+        }
+    }
+
+    #[test]
+    fn tasks_require_a_runtime_and_a_tracked_document() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/task.test").unwrap();
+        assert!(
+            state
+                .spawn_for_document(url.clone(), |_| async {})
+                .is_none()
+        );
+
+        runtime().block_on(async {
+            assert!(
+                state
+                    .spawn_for_document(url.clone(), |_| async {})
+                    .is_none()
+            );
+            open(&mut state, &url, "abc");
+            assert!(
+                state
+                    .spawn_for_document(url.clone(), |_| async {})
+                    .is_some()
+            );
+        });
+    }
+
+    #[test]
+    fn tasks_are_cancelled_when_the_document_changes() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/task.test").unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        runtime().block_on(async {
+            open(&mut state, &url, "abc");
+            state.spawn_for_document(url.clone(), recording_task(&seen));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            change(&mut state, &url, 2, "def");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        });
+
+        assert_eq!(*seen.lock().unwrap(), vec![(1, false)]);
+    }
+
+    #[test]
+    fn restarting_tasks_run_again_with_the_latest_document() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/task.test").unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        runtime().block_on(async {
+            open(&mut state, &url, "abc");
+            state.spawn_for_document_restarting(url.clone(), recording_task(&seen));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            change(&mut state, &url, 2, "def");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        });
+
+        assert_eq!(*seen.lock().unwrap(), vec![(1, false), (2, true)]);
+    }
+
+    #[test]
+    fn tasks_can_be_cancelled_using_the_returned_token() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let url = Url::parse("file:///tmp/task.test").unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        runtime().block_on(async {
+            open(&mut state, &url, "abc");
+            let token = state
+                .spawn_for_document_restarting(url.clone(), recording_task(&seen))
+                .expect("task can be spawned");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            token.cancel();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        });
+
+        assert_eq!(*seen.lock().unwrap(), vec![(1, false)]);
+    }
+}
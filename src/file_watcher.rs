@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use async_lsp::lsp_types::{FileChangeType, FileEvent, Url};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{ModifyKind, RenameMode},
+};
+use tokio::sync::mpsc::unbounded_channel;
+
+/**
+    A filesystem watcher for workspace folders, used when
+    the client can not watch files on behalf of the server.
+
+    Stops watching when dropped.
+*/
+pub(crate) struct FileWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    /**
+        Starts watching the given roots recursively, calling `on_change`
+        on the current tokio runtime with batches of file changes.
+
+        Returns `None` if there is no runtime, or the watcher could not be created.
+    */
+    pub(crate) fn spawn<F>(roots: &[PathBuf], on_change: F) -> Option<Self>
+    where
+        F: Fn(Vec<FileEvent>) + Send + 'static,
+    {
+        let handle = tokio::runtime::Handle::try_current().ok()?;
+        let (sender, mut receiver) = unbounded_channel::<Vec<FileEvent>>();
+
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(event) = event {
+                let changes = file_events(&event);
+                if !changes.is_empty() {
+                    let _ = sender.send(changes);
+                }
+            }
+        })
+        .ok()?;
+
+        for root in roots {
+            #[allow(unused_variables)]
+            if let Err(e) = watcher.watch(root, RecursiveMode::Recursive) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to watch '{}': {e}", root.display());
+            }
+        }
+
+        handle.spawn(async move {
+            while let Some(changes) = receiver.recv().await {
+                on_change(changes);
+            }
+        });
+
+        Some(Self { _watcher: watcher })
+    }
+}
+
+fn file_events(event: &Event) -> Vec<FileEvent> {
+    let typ = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            FileChangeType::CREATED
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            FileChangeType::DELETED
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            // NOTE: Renames with both paths are reported as a single
+            // event, with the old path first, and the new path second
+            return event
+                .paths
+                .iter()
+                .zip([FileChangeType::DELETED, FileChangeType::CREATED])
+                .filter_map(|(path, typ)| file_event(path, typ))
+                .collect();
+        }
+        EventKind::Modify(_) => FileChangeType::CHANGED,
+        _ => return Vec::new(),
+    };
+
+    event
+        .paths
+        .iter()
+        .filter_map(|path| file_event(path, typ))
+        .collect()
+}
+
+fn file_event(path: &Path, typ: FileChangeType) -> Option<FileEvent> {
+    let uri = Url::from_file_path(path).ok()?;
+    Some(FileEvent { uri, typ })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use async_lsp::lsp_types::FileChangeType;
+    use notify::{
+        Event, EventKind,
+        event::{CreateKind, ModifyKind, RenameMode},
+    };
+
+    use super::file_events;
+
+    #[test]
+    fn renames_are_reported_as_deletions_and_creations() {
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/tmp/old.test"))
+            .add_path(PathBuf::from("/tmp/new.test"));
+
+        let changes = file_events(&event);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].uri.path(), "/tmp/old.test");
+        assert_eq!(changes[0].typ, FileChangeType::DELETED);
+        assert_eq!(changes[1].uri.path(), "/tmp/new.test");
+        assert_eq!(changes[1].typ, FileChangeType::CREATED);
+    }
+
+    #[test]
+    fn creations_are_reported_for_all_paths() {
+        let event = Event::new(EventKind::Create(CreateKind::File))
+            .add_path(PathBuf::from("/tmp/a.test"))
+            .add_path(PathBuf::from("/tmp/b.test"));
+
+        let changes = file_events(&event);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|c| c.typ == FileChangeType::CREATED));
+    }
+}
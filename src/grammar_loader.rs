@@ -0,0 +1,190 @@
+use std::{
+    env::consts::DLL_EXTENSION,
+    path::{Path, PathBuf},
+};
+
+use libloading::Library;
+use tree_sitter::Language;
+use tree_sitter_language::LanguageFn;
+
+use crate::{
+    document_matcher::DocumentMatcher,
+    result::{ServerError, ServerResult},
+};
+
+type RawLanguageFn = unsafe extern "C" fn() -> *const ();
+
+/**
+    Loads tree-sitter grammars at runtime from shared libraries, such as
+    `tree-sitter-lua.so`, built using the tree-sitter CLI - letting a single
+    server binary support grammars provided by its users.
+
+    Grammars are found by name in the search paths of the loader, or may be
+    loaded from a specific path, and are kept loaded for the rest of the process.
+
+    Only native shared libraries are supported. Loading grammars compiled to
+    WASM, such as `tree-sitter-lua.wasm`, is not implemented - it would need
+    the `wasm` feature of tree-sitter, and a WASM store attached to every
+    parser that uses them. Such grammars are rejected with an error instead.
+*/
+#[derive(Debug, Default, Clone)]
+pub struct GrammarLoader {
+    search_paths: Vec<PathBuf>,
+}
+
+impl GrammarLoader {
+    /**
+        Creates a new grammar loader, without any search paths.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Adds the given directories to search for grammar libraries in.
+
+        For a grammar named `lua`, each directory is searched for a library
+        named `lua`, `liblua`, `tree-sitter-lua`, or `libtree-sitter-lua`,
+        using the shared library extension of the current platform.
+    */
+    #[must_use]
+    pub fn with_search_paths<I, P>(mut self, search_paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.search_paths
+            .extend(search_paths.into_iter().map(Into::into));
+        self
+    }
+
+    /**
+        Loads the grammar with the given name from the search paths of the loader.
+
+        # Errors
+
+        - If no library for the grammar exists in any of the search paths.
+        - If the library could not be loaded, or does not contain the grammar.
+    */
+    pub fn load(&self, name: &str) -> ServerResult<Language> {
+        let path = self
+            .search_paths
+            .iter()
+            .flat_map(|dir| library_names(name).map(move |file| dir.join(file)))
+            .find(|path| path.is_file())
+            .ok_or_else(|| ServerError::Unknown(format!("Grammar library not found: {name}")))?;
+        Self::load_from_path(path, name)
+    }
+
+    /**
+        Loads the grammar with the given name from the library at the given path.
+
+        The library must export the `tree_sitter_{name}` function generated by
+        the tree-sitter CLI, with any dashes in the name replaced by underscores.
+
+        # Errors
+
+        - If the path is a WASM grammar, which is not supported.
+        - If the library could not be loaded, or does not contain the grammar.
+    */
+    pub fn load_from_path(path: impl AsRef<Path>, name: &str) -> ServerResult<Language> {
+        let path = path.as_ref();
+        if is_wasm(path) {
+            return Err(ServerError::Unknown(format!(
+                "WASM grammars are not supported, use a shared library instead of {}",
+                path.display()
+            )));
+        }
+        let symbol = language_symbol(name);
+
+        // SAFETY: Loading a library runs its initialization code, and the
+        // grammar function is trusted to match the signature that the
+        // tree-sitter CLI generates, since that is all that we can check
+        let language = unsafe {
+            let library = Library::new(path).map_err(|e| {
+                ServerError::Unknown(format!("Failed to load {}: {e}", path.display()))
+            })?;
+            let language_fn = *library
+                .get::<RawLanguageFn>(symbol.as_bytes())
+                .map_err(|e| {
+                    ServerError::Unknown(format!("Missing {symbol} in {}: {e}", path.display()))
+                })?;
+
+            // NOTE: Languages point into the static data of the library,
+            // so it must never be unloaded while the language may be used
+            std::mem::forget(library);
+
+            Language::new(LanguageFn::from_raw(language_fn))
+        };
+
+        Ok(language)
+    }
+
+    /**
+        Loads the grammar with the given name from the search
+        paths of the loader, and attaches it to the given matcher.
+
+        See [`GrammarLoader::load`] and [`DocumentMatcher::with_lang_grammar`].
+
+        # Errors
+
+        - If the grammar could not be loaded.
+    */
+    pub fn load_for_matcher(
+        &self,
+        matcher: DocumentMatcher,
+        name: &str,
+    ) -> ServerResult<DocumentMatcher> {
+        Ok(matcher.with_lang_grammar(self.load(name)?))
+    }
+}
+
+fn language_symbol(name: &str) -> String {
+    format!("tree_sitter_{}", name.replace('-', "_"))
+}
+
+fn is_wasm(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("wasm"))
+}
+
+fn library_names(name: &str) -> impl Iterator<Item = String> + '_ {
+    ["", "lib", "tree-sitter-", "libtree-sitter-"]
+        .into_iter()
+        .map(move |prefix| format!("{prefix}{name}.{DLL_EXTENSION}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GrammarLoader, is_wasm, language_symbol, library_names};
+
+    #[test]
+    fn grammar_names_map_to_symbols_and_libraries() {
+        assert_eq!(language_symbol("c-sharp"), "tree_sitter_c_sharp");
+
+        let names = library_names("lua").collect::<Vec<_>>();
+        assert_eq!(names.len(), 4);
+        assert!(names[2].starts_with("tree-sitter-lua."));
+    }
+
+    #[test]
+    fn missing_grammars_are_errors() {
+        let loader = GrammarLoader::new().with_search_paths([std::env::temp_dir()]);
+        assert!(loader.load("definitely-not-a-grammar").is_err());
+        assert!(GrammarLoader::load_from_path("/nonexistent/grammar.so", "grammar").is_err());
+    }
+
+    #[test]
+    fn wasm_grammars_are_rejected() {
+        assert!(is_wasm("tree-sitter-lua.wasm".as_ref()));
+        assert!(!is_wasm("tree-sitter-lua.so".as_ref()));
+
+        let error = GrammarLoader::load_from_path("/nonexistent/lua.wasm", "lua").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("WASM grammars are not supported")
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use tokio::sync::Notify;
+
+/**
+    Tracks the number of requests that are currently being handled,
+    so that shutdown can wait for all of them to complete first.
+
+    Cheap to clone - all clones share the same underlying count.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InFlightRequests {
+    inner: Arc<InFlightRequestsInner>,
+}
+
+#[derive(Debug, Default)]
+struct InFlightRequestsInner {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlightRequests {
+    /**
+        Marks a request as in-flight, until the returned guard is dropped.
+    */
+    pub(crate) fn start(&self) -> InFlightGuard {
+        self.inner.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /**
+        Returns the number of requests that are currently in-flight.
+    */
+    pub(crate) fn count(&self) -> usize {
+        self.inner.count.load(Ordering::SeqCst)
+    }
+
+    /**
+        Waits until there are no requests in-flight.
+
+        Returns immediately if there are no requests in-flight.
+    */
+    pub(crate) async fn idle(&self) {
+        loop {
+            let notified = self.inner.idle.notified();
+            let mut notified = std::pin::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.count() == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
+/**
+    A guard for an in-flight request, which completes the request when dropped.
+*/
+#[derive(Debug)]
+pub(crate) struct InFlightGuard {
+    inner: Arc<InFlightRequestsInner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InFlightRequests;
+
+    #[test]
+    fn idle_waits_for_all_guards() {
+        let in_flight = InFlightRequests::default();
+        futures::executor::block_on(in_flight.idle());
+
+        let first = in_flight.start();
+        let second = in_flight.start();
+        assert_eq!(in_flight.count(), 2);
+
+        let waiter = in_flight.clone();
+        let handle = std::thread::spawn(move || futures::executor::block_on(waiter.idle()));
+
+        drop(first);
+        assert_eq!(in_flight.count(), 1);
+        drop(second);
+
+        handle.join().expect("waiter completes once idle");
+        assert_eq!(in_flight.count(), 0);
+    }
+}
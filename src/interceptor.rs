@@ -0,0 +1,55 @@
+use async_lsp::{AnyNotification, AnyRequest, ResponseError};
+use serde_json::Value;
+
+/**
+    An interceptor that wraps every request and notification sent to a [`Server`].
+
+    Interceptors are registered using [`Server::server_interceptors`], and may
+    be used for logging, authorization, rate limiting, or rewriting requests,
+    without needing to reimplement any of the managed request handling.
+
+    Interceptors run before any other processing done by the server, such as
+    document tracking and position encoding conversion, meaning that all
+    positions seen by an interceptor use the negotiated position encoding.
+
+    When multiple interceptors are registered, they run in order for
+    incoming requests and notifications, and in reverse order for responses.
+
+    [`Server`]: crate::server::Server
+    [`Server::server_interceptors`]: crate::server::Server::server_interceptors
+*/
+#[allow(unused_variables)]
+pub trait Interceptor: Send + Sync + 'static {
+    /**
+        Called before a request is handled by the server.
+
+        The request may be modified in place. Returning an error
+        will respond to the request using that error immediately,
+        without the request being handled by the server at all.
+
+        # Errors
+
+        - If the request should not be handled by the server.
+    */
+    fn on_request(&self, request: &mut AnyRequest) -> Result<(), ResponseError> {
+        Ok(())
+    }
+
+    /**
+        Called with the response to a request, before it is sent to the client.
+
+        The response may be modified in place. This is also called for
+        responses to requests that were rejected by an interceptor.
+    */
+    fn on_response(&self, method: &str, response: &mut Result<Value, ResponseError>) {}
+
+    /**
+        Called before a notification is handled by the server.
+
+        The notification may be modified in place. Returning `false`
+        will drop the notification without it being handled by the server.
+    */
+    fn on_notification(&self, notification: &mut AnyNotification) -> bool {
+        true
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_lsp::{
+    AnyEvent, AnyNotification, AnyRequest, LspService, RequestId, ResponseError, Result,
+    lsp_types::{CancelParams, notification::Cancel, notification::Notification},
+};
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::cancellation::{CancellationToken, scope_request_cancellation};
+
+/**
+    Middleware that associates each incoming request with a [`CancellationToken`].
+
+    The token is cancelled when the client sends a `$/cancelRequest`
+    notification for the request, or if the request is dropped before
+    it completes, and is made available to the request handler.
+
+    The `ConcurrencyLayer` from `async-lsp` also handles `$/cancelRequest`, but it
+    only forgets the abort handle for the request, without ever aborting it, and
+    does not pass the notification on. This layer must therefore be placed outside
+    of it, and is what actually lets handlers observe cancellation and stop early.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestCancellationLayer;
+
+impl<S> Layer<S> for RequestCancellationLayer {
+    type Service = RequestCancellation<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RequestCancellation {
+            service,
+            ongoing: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+pub(crate) struct RequestCancellation<S> {
+    service: S,
+    ongoing: Arc<DashMap<RequestId, CancellationToken>>,
+}
+
+impl<S> Service<AnyRequest> for RequestCancellation<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: AnyRequest) -> Self::Future {
+        let token = CancellationToken::new();
+        let guard = OngoingGuard {
+            ongoing: Arc::clone(&self.ongoing),
+            id: req.id.clone(),
+            token: token.clone(),
+            completed: false,
+        };
+        self.ongoing.insert(req.id.clone(), token.clone());
+
+        let fut = scope_request_cancellation(token, self.service.call(req));
+        Box::pin(async move {
+            let mut guard = guard;
+            let result = fut.await;
+            guard.completed = true;
+            result
+        })
+    }
+}
+
+impl<S> LspService for RequestCancellation<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    fn notify(&mut self, notif: AnyNotification) -> ControlFlow<async_lsp::Result<()>> {
+        if notif.method == Cancel::METHOD
+            && let Ok(params) = serde_json::from_value::<CancelParams>(notif.params.clone())
+            && let Some((_, token)) = self.ongoing.remove(&params.id)
+        {
+            token.cancel();
+        }
+        self.service.notify(notif)
+    }
+
+    fn emit(&mut self, event: AnyEvent) -> ControlFlow<async_lsp::Result<()>> {
+        self.service.emit(event)
+    }
+}
+
+/**
+    Stops tracking a request once it completes, and cancels
+    its token if the request was dropped before completing.
+*/
+struct OngoingGuard {
+    ongoing: Arc<DashMap<RequestId, CancellationToken>>,
+    id: RequestId,
+    token: CancellationToken,
+    completed: bool,
+}
+
+impl Drop for OngoingGuard {
+    fn drop(&mut self) {
+        self.ongoing.remove(&self.id);
+        if !self.completed {
+            self.token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+
+    use async_lsp::{
+        AnyNotification, AnyRequest, ErrorCode, LspService, ResponseError,
+        lsp_types::{
+            CancelParams, Hover, HoverParams, NumberOrString, Position, Url,
+            notification::Cancel,
+            request::{Request, Shutdown},
+        },
+        router::Router,
+    };
+    use tower::{Layer, Service};
+
+    use crate::{
+        cancellation::current_request_cancellation,
+        server::{Server, ServerError, ServerResult, ServerState},
+        testing::TestServer as TestHarness,
+    };
+
+    use super::RequestCancellationLayer;
+
+    fn service() -> impl LspService<Response = serde_json::Value, Error = ResponseError> {
+        let mut router = Router::new(());
+        router.request::<Shutdown, _>(|(), ()| async {
+            current_request_cancellation().cancelled().await;
+            Ok(())
+        });
+        RequestCancellationLayer.layer(router)
+    }
+
+    fn request(id: i32) -> AnyRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "method": Shutdown::METHOD,
+        }))
+        .expect("request is valid")
+    }
+
+    fn cancel_notification(id: i32) -> AnyNotification {
+        serde_json::from_value(serde_json::json!({
+            "method": "$/cancelRequest",
+            "params": { "id": id },
+        }))
+        .expect("notification is valid")
+    }
+
+    #[test]
+    fn cancel_request_notifications_cancel_the_request_token() {
+        let mut service = service();
+        let fut = service.call(request(1));
+
+        let _ = service.notify(cancel_notification(1));
+
+        let result = futures::executor::block_on(fut);
+        assert!(result.is_ok());
+    }
+
+    #[derive(Clone, Default)]
+    struct CancellableServer {
+        observed: Arc<AtomicBool>,
+    }
+
+    impl Server for CancellableServer {
+        async fn hover(&self, state: ServerState, _: HoverParams) -> ServerResult<Option<Hover>> {
+            state.current_cancellation().cancelled().await;
+            self.observed.store(true, Ordering::SeqCst);
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn handlers_observe_cancellation_when_served() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let server = CancellableServer::default();
+            let observed = Arc::clone(&server.observed);
+            let server = TestHarness::spawn(server).await.expect("server spawns");
+            let url = Url::parse("file:///tmp/cancel.test").unwrap();
+
+            let hover = server.hover(&url, Position::new(0, 0));
+            let cancel = async {
+                // NOTE: The initialize request uses id 0, so the hover request uses id 1
+                server
+                    .notify::<Cancel>(CancelParams {
+                        id: NumberOrString::Number(1),
+                    })
+                    .unwrap();
+            };
+            let (result, ()) = futures::join!(hover, cancel);
+
+            let Err(ServerError::Lsp(async_lsp::Error::Response(error))) = result else {
+                panic!("expected an error response, got {result:?}");
+            };
+            assert_eq!(error.code, ErrorCode::REQUEST_CANCELLED);
+            assert!(observed.load(Ordering::SeqCst));
+        });
+    }
+}
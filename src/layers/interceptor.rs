@@ -0,0 +1,255 @@
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_lsp::{AnyEvent, AnyNotification, AnyRequest, LspService, ResponseError, Result};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::interceptor::Interceptor;
+
+/**
+    Middleware that runs the [`Interceptor`]s registered by a server
+    for each incoming request, response, and notification.
+*/
+#[derive(Clone, Default)]
+pub(crate) struct InterceptorLayer {
+    interceptors: Arc<[Arc<dyn Interceptor>]>,
+}
+
+impl InterceptorLayer {
+    pub(crate) fn new(interceptors: Vec<Arc<dyn Interceptor>>) -> Self {
+        Self {
+            interceptors: interceptors.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for InterceptorLayer {
+    type Service = Intercepted<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        Intercepted {
+            service,
+            interceptors: Arc::clone(&self.interceptors),
+        }
+    }
+}
+
+pub(crate) struct Intercepted<S> {
+    service: S,
+    interceptors: Arc<[Arc<dyn Interceptor>]>,
+}
+
+impl<S> Service<AnyRequest> for Intercepted<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: AnyRequest) -> Self::Future {
+        let interceptors = Arc::clone(&self.interceptors);
+        let method = req.method.clone();
+
+        let rejected = interceptors
+            .iter()
+            .find_map(|interceptor| interceptor.on_request(&mut req).err());
+        let fut = if rejected.is_none() {
+            Some(self.service.call(req))
+        } else {
+            None
+        };
+
+        Box::pin(async move {
+            let mut result = match (rejected, fut) {
+                (Some(err), _) => Err(err),
+                (None, Some(fut)) => fut.await,
+                (None, None) => unreachable!(),
+            };
+            for interceptor in interceptors.iter().rev() {
+                interceptor.on_response(&method, &mut result);
+            }
+            result
+        })
+    }
+}
+
+impl<S> LspService for Intercepted<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    fn notify(&mut self, mut notif: AnyNotification) -> ControlFlow<async_lsp::Result<()>> {
+        for interceptor in self.interceptors.iter() {
+            if !interceptor.on_notification(&mut notif) {
+                return ControlFlow::Continue(());
+            }
+        }
+        self.service.notify(notif)
+    }
+
+    fn emit(&mut self, event: AnyEvent) -> ControlFlow<async_lsp::Result<()>> {
+        self.service.emit(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use async_lsp::{
+        AnyNotification, AnyRequest, ErrorCode, LspService, ResponseError,
+        lsp_types::{
+            notification::{Exit, Notification},
+            request::{Request, Shutdown},
+        },
+        router::Router,
+    };
+    use serde_json::Value;
+    use tower::{Layer, Service};
+
+    use crate::interceptor::Interceptor;
+
+    use super::InterceptorLayer;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+        name: &'static str,
+    }
+
+    impl Interceptor for Recorder {
+        fn on_request(&self, request: &mut AnyRequest) -> Result<(), ResponseError> {
+            self.record(&format!("request {}", request.method));
+            Ok(())
+        }
+
+        fn on_response(&self, method: &str, response: &mut Result<Value, ResponseError>) {
+            self.record(&format!("response {method} {}", response.is_ok()));
+        }
+
+        fn on_notification(&self, notification: &mut AnyNotification) -> bool {
+            self.record(&format!("notification {}", notification.method));
+            true
+        }
+    }
+
+    impl Recorder {
+        fn record(&self, event: &str) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{}: {event}", self.name));
+        }
+    }
+
+    struct Reject;
+
+    impl Interceptor for Reject {
+        fn on_request(&self, _: &mut AnyRequest) -> Result<(), ResponseError> {
+            Err(ResponseError::new(ErrorCode::INVALID_REQUEST, "rejected"))
+        }
+
+        fn on_notification(&self, _: &mut AnyNotification) -> bool {
+            false
+        }
+    }
+
+    fn service(
+        interceptors: Vec<Arc<dyn Interceptor>>,
+        handled: &Arc<AtomicUsize>,
+    ) -> impl LspService<Response = Value, Error = ResponseError> {
+        let mut router = Router::new(Arc::clone(handled));
+        router.request::<Shutdown, _>(|handled, ()| {
+            handled.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        });
+        router.notification::<Exit>(|handled, ()| {
+            handled.fetch_add(1, Ordering::SeqCst);
+            std::ops::ControlFlow::Continue(())
+        });
+        InterceptorLayer::new(interceptors).layer(router)
+    }
+
+    fn request() -> AnyRequest {
+        serde_json::from_value(serde_json::json!({ "id": 1, "method": Shutdown::METHOD }))
+            .expect("request is valid")
+    }
+
+    fn notification() -> AnyNotification {
+        serde_json::from_value(serde_json::json!({ "method": Exit::METHOD }))
+            .expect("notification is valid")
+    }
+
+    #[test]
+    fn interceptors_wrap_requests_and_notifications_in_order() {
+        let first = Arc::new(Recorder {
+            name: "first",
+            ..Default::default()
+        });
+        let second = Arc::new(Recorder {
+            name: "second",
+            ..Default::default()
+        });
+        let handled = Arc::new(AtomicUsize::new(0));
+        let mut service = service(vec![first.clone(), second.clone()], &handled);
+
+        futures::executor::block_on(service.call(request())).expect("request succeeds");
+        let _ = service.notify(notification());
+
+        assert_eq!(handled.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *first.events.lock().unwrap(),
+            vec![
+                "first: request shutdown",
+                "first: response shutdown true",
+                "first: notification exit",
+            ]
+        );
+        assert_eq!(
+            *second.events.lock().unwrap(),
+            vec![
+                "second: request shutdown",
+                "second: response shutdown true",
+                "second: notification exit",
+            ]
+        );
+    }
+
+    #[test]
+    fn interceptors_can_reject_requests_and_drop_notifications() {
+        let recorder = Arc::new(Recorder {
+            name: "recorder",
+            ..Default::default()
+        });
+        let handled = Arc::new(AtomicUsize::new(0));
+        let mut service = service(vec![recorder.clone(), Arc::new(Reject)], &handled);
+
+        let error = futures::executor::block_on(service.call(request()))
+            .expect_err("request should be rejected");
+        let _ = service.notify(notification());
+
+        assert_eq!(error.code, ErrorCode::INVALID_REQUEST);
+        assert_eq!(handled.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                "recorder: request shutdown",
+                "recorder: response shutdown false",
+                "recorder: notification exit",
+            ]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use async_lsp::{AnyEvent, AnyNotification, AnyRequest, LspService, ResponseError, Result};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::metrics::{Metrics, RequestOutcome};
+
+/**
+    Middleware that reports the outcome and duration of each request to [`Metrics`].
+
+    If no metrics are given, requests are passed through as-is.
+*/
+#[derive(Clone, Default)]
+pub(crate) struct MetricsLayer {
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl MetricsLayer {
+    pub(crate) fn new(metrics: Option<Arc<dyn Metrics>>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = Measured<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        Measured {
+            service,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+pub(crate) struct Measured<S> {
+    service: S,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl<S> Service<AnyRequest> for Measured<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: AnyRequest) -> Self::Future {
+        let Some(metrics) = self.metrics.clone() else {
+            return Box::pin(self.service.call(req));
+        };
+
+        let start = Instant::now();
+        let method = req.method.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let result = fut.await;
+            let outcome = RequestOutcome::from_result(&result);
+            metrics.record_request(&method, outcome, start.elapsed());
+            result
+        })
+    }
+}
+
+impl<S> LspService for Measured<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    fn notify(&mut self, notif: AnyNotification) -> ControlFlow<async_lsp::Result<()>> {
+        self.service.notify(notif)
+    }
+
+    fn emit(&mut self, event: AnyEvent) -> ControlFlow<async_lsp::Result<()>> {
+        self.service.emit(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_lsp::{
+        AnyRequest, ErrorCode, ResponseError,
+        lsp_types::request::{Request, Shutdown},
+        router::Router,
+    };
+    use tower::{Layer, Service};
+
+    use crate::metrics::{Metrics, RequestOutcome};
+
+    use super::MetricsLayer;
+
+    #[derive(Default)]
+    struct Recorder {
+        requests: Mutex<Vec<(String, RequestOutcome)>>,
+    }
+
+    impl Metrics for Recorder {
+        fn record_request(&self, method: &str, outcome: RequestOutcome, _: std::time::Duration) {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((method.to_string(), outcome));
+        }
+    }
+
+    fn request(method: &str) -> AnyRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "method": method,
+            "params": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn requests_are_recorded_with_outcomes() {
+        let recorder = Arc::new(Recorder::default());
+        let mut router = Router::new(());
+        router.request::<Shutdown, _>(|(), ()| async {
+            Err(ResponseError::new(ErrorCode::CONTENT_MODIFIED, "stale"))
+        });
+        router.unhandled_request(|(), _| async {
+            Err(ResponseError::new(ErrorCode::METHOD_NOT_FOUND, "unknown"))
+        });
+        let mut service = MetricsLayer::new(Some(recorder.clone())).layer(router);
+
+        let _ = futures::executor::block_on(service.call(request(Shutdown::METHOD)));
+        let _ = futures::executor::block_on(service.call(request("test/unknown")));
+
+        assert_eq!(
+            *recorder.requests.lock().unwrap(),
+            vec![
+                (Shutdown::METHOD.to_string(), RequestOutcome::Stale),
+                ("test/unknown".to_string(), RequestOutcome::Error),
+            ]
+        );
+    }
+}
@@ -0,0 +1,11 @@
+mod cancellation;
+mod interceptor;
+mod metrics;
+#[cfg(feature = "tracing")]
+mod tracing;
+
+pub(crate) use self::cancellation::RequestCancellationLayer;
+pub(crate) use self::interceptor::InterceptorLayer;
+pub(crate) use self::metrics::MetricsLayer;
+#[cfg(feature = "tracing")]
+pub(crate) use self::tracing::{TracingLayer, record_document_version};
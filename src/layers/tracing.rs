@@ -0,0 +1,225 @@
+use std::{
+    ops::ControlFlow,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use async_lsp::{
+    AnyEvent, AnyNotification, AnyRequest, LspService, RequestId, ResponseError, Result,
+};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+use tracing::{Instrument, Span, debug, field::Empty, info_span};
+
+/**
+    Middleware that wraps each incoming request and notification in a span.
+
+    Request spans carry the LSP method, the request id, and the URI
+    of the document that the request is for, if any. The version of
+    the document is recorded once the request has been forwarded to
+    the server, using [`record_document_version`], and the elapsed
+    time is logged once the request completes.
+*/
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TracingLayer;
+
+impl<S> Layer<S> for TracingLayer {
+    type Service = Tracing<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        Tracing { service }
+    }
+}
+
+pub(crate) struct Tracing<S> {
+    service: S,
+}
+
+impl<S> Service<AnyRequest> for Tracing<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: AnyRequest) -> Self::Future {
+        let span = info_span!(
+            "request",
+            method = %req.method,
+            id = %request_id(&req.id),
+            uri = Empty,
+            version = Empty,
+        );
+        if let Some(uri) = extract_uri(&req.params) {
+            span.record("uri", uri);
+        }
+
+        let start = Instant::now();
+        let fut = span.in_scope(|| self.service.call(req));
+        Box::pin(
+            async move {
+                let result = fut.await;
+                let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+                match &result {
+                    Ok(_) => debug!(elapsed_ms, "request completed"),
+                    Err(e) => debug!(elapsed_ms, code = e.code.0, "request failed: {}", e.message),
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
+
+impl<S> LspService for Tracing<S>
+where
+    S: LspService<Response = serde_json::Value, Error = ResponseError>,
+    S::Future: Send + 'static,
+{
+    fn notify(&mut self, notif: AnyNotification) -> ControlFlow<async_lsp::Result<()>> {
+        let span = info_span!("notification", method = %notif.method, uri = Empty);
+        if let Some(uri) = extract_uri(&notif.params) {
+            span.record("uri", uri);
+        }
+        span.in_scope(|| self.service.notify(notif))
+    }
+
+    fn emit(&mut self, event: AnyEvent) -> ControlFlow<async_lsp::Result<()>> {
+        let _guard = info_span!("event", type_name = event.type_name()).entered();
+        self.service.emit(event)
+    }
+}
+
+/**
+    Records the version of the document that the current request is for.
+
+    Does nothing if called outside of a request span.
+*/
+pub(crate) fn record_document_version(version: i32) {
+    Span::current().record("version", version);
+}
+
+fn request_id(id: &RequestId) -> String {
+    match id {
+        RequestId::Number(n) => n.to_string(),
+        RequestId::String(s) => s.clone(),
+    }
+}
+
+fn extract_uri(params: &serde_json::Value) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    };
+
+    use async_lsp::{
+        AnyRequest,
+        lsp_types::{
+            HoverParams,
+            request::{HoverRequest, Request},
+        },
+        router::Router,
+    };
+    use tower::{Layer, Service};
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+
+    use super::TracingLayer;
+
+    /**
+        A minimal subscriber that records all span fields and event messages as strings.
+    */
+    #[derive(Default, Clone)]
+    struct Recorder {
+        next_id: Arc<AtomicU64>,
+        fields: Arc<Mutex<Vec<String>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Collect<'a>(&'a mut Vec<String>);
+
+    impl Visit for Collect<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push(format!("{}={value:?}", field.name()));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push(format!("{}={value}", field.name()));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut Collect(&mut self.fields.lock().unwrap()));
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut Collect(&mut self.fields.lock().unwrap()));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut Collect(&mut self.events.lock().unwrap()));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn requests_are_traced_with_method_id_uri_and_latency() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut router = Router::new(());
+            router.request::<HoverRequest, _>(|(), _| async { Ok(None) });
+            let mut service = TracingLayer.layer(router);
+
+            let params: HoverParams = serde_json::from_value(serde_json::json!({
+                "textDocument": { "uri": "file:///tmp/traced.test" },
+                "position": { "line": 0, "character": 0 },
+            }))
+            .unwrap();
+            let request: AnyRequest = serde_json::from_value(serde_json::json!({
+                "id": 7,
+                "method": HoverRequest::METHOD,
+                "params": params,
+            }))
+            .unwrap();
+            futures::executor::block_on(service.call(request)).expect("request succeeds");
+        });
+
+        let fields = recorder.fields.lock().unwrap();
+        for expected in [
+            "method=textDocument/hover",
+            "id=7",
+            "uri=file:///tmp/traced.test",
+        ] {
+            assert!(fields.iter().any(|f| f == expected), "missing {expected}");
+        }
+
+        let events = recorder.events.lock().unwrap();
+        assert!(events.iter().any(|e| e.starts_with("elapsed_ms=")));
+        assert!(events.iter().any(|e| e == "message=request completed"));
+    }
+}
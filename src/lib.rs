@@ -1,10 +1,24 @@
 pub use async_lsp::lsp_types;
 
+#[cfg(feature = "regex")]
+pub use regex;
 #[cfg(feature = "tree-sitter")]
 pub use tree_sitter;
 
+mod cancellation;
+mod custom_notifications;
+mod custom_requests;
+mod diagnostic_results;
+mod diagnostics_publisher;
 mod document;
 mod document_matcher;
+mod document_tasks;
+mod in_flight;
+mod interceptor;
+mod layers;
+mod metrics;
+mod multi_server;
+mod progress;
 mod requests;
 mod result;
 mod serve;
@@ -13,27 +27,64 @@ mod server_state;
 mod server_trait;
 mod server_with_state;
 mod transport;
+mod watched_files;
 mod workspace_diagnostics;
+mod workspace_index;
 mod workspace_walker;
 
+#[cfg(feature = "tree-sitter")]
+mod syntax_diagnostics;
+
+#[cfg(feature = "watch")]
+mod file_watcher;
+
+#[cfg(feature = "grammar-loading")]
+mod grammar_loader;
+
+#[cfg(feature = "websocket")]
+mod websocket;
+
 pub mod oneshot;
+pub mod testing;
 pub mod text_utils;
 
 #[cfg(feature = "tree-sitter")]
 pub mod tree_sitter_utils;
 
 pub mod server {
-    pub use crate::document::{Document, DocumentReader};
+    pub use crate::cancellation::CancellationToken;
+    pub use crate::custom_notifications::{
+        CustomNotification, CustomNotificationHandler, CustomNotifications,
+    };
+    pub use crate::custom_requests::{CustomRequest, CustomRequestHandler, CustomRequests};
+    pub use crate::diagnostics_publisher::DiagnosticsPublisher;
+    pub use crate::document::{Document, DocumentChangeEvent, DocumentExtensions, DocumentReader};
     pub use crate::document_matcher::DocumentMatcher;
+    pub use crate::interceptor::Interceptor;
+    pub use crate::metrics::{Metrics, RequestOutcome};
+    pub use crate::multi_server::MultiServer;
+    pub use crate::progress::Progress;
     pub use crate::result::{ServerError, ServerErrorCode, ServerResult};
-    pub use crate::serve::serve;
+    pub use crate::serve::{serve, serve_pipe, serve_stdio};
     pub use crate::server_options::{
-        ConfigurationKey, ServerOptions, WorkspaceDiagnostics, WorkspaceDiagnosticsSetting,
+        ConfigurationKey, ServerOptions, SyncRecovery, WorkspaceDiagnostics,
+        WorkspaceDiagnosticsSetting,
     };
     pub use crate::server_state::ServerState;
     pub use crate::server_trait::Server;
     pub use crate::transport::Transport;
+    pub use crate::workspace_index::WorkspaceIndex;
 
     #[cfg(feature = "tree-sitter")]
-    pub use crate::document::DocumentQueryCapture;
+    pub use crate::document::{
+        DocumentNodePathItem, DocumentQueryCapture, SyntaxErrorInfo, SyntaxErrorKind, SyntaxStatus,
+    };
+    #[cfg(feature = "tree-sitter")]
+    pub use crate::syntax_diagnostics::SyntaxDiagnostics;
+
+    #[cfg(feature = "grammar-loading")]
+    pub use crate::grammar_loader::GrammarLoader;
+
+    #[cfg(feature = "websocket")]
+    pub use crate::websocket::serve_websocket;
 }
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use async_lsp::{ErrorCode, ResponseError};
+use serde_json::Value;
+
+/**
+    A recorder for metrics about requests handled by a [`Server`].
+
+    Metrics are registered using [`ServerOptions::with_metrics`], and are
+    called once for every request that the server responds to, including
+    custom requests, and requests rejected by an [`Interceptor`].
+
+    This may be used to export request counts and durations to Prometheus,
+    or any other metrics system, without forking the request handling.
+
+    [`Server`]: crate::server::Server
+    [`ServerOptions::with_metrics`]: crate::server::ServerOptions::with_metrics
+    [`Interceptor`]: crate::server::Interceptor
+*/
+pub trait Metrics: Send + Sync + 'static {
+    /**
+        Called after a request has been handled, with the name of the
+        LSP method, the outcome of the request, and how long it took.
+    */
+    fn record_request(&self, method: &str, outcome: RequestOutcome, duration: Duration);
+}
+
+/**
+    The outcome of a request, as reported to [`Metrics`].
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestOutcome {
+    /// The request was handled successfully.
+    Ok,
+    /// The request failed with an error.
+    Error,
+    /// The document changed while the request was being handled,
+    /// and the result was discarded since it would have been stale.
+    Stale,
+    /// The request was cancelled by the client, or timed out.
+    Cancelled,
+}
+
+impl RequestOutcome {
+    pub(crate) fn from_result(result: &Result<Value, ResponseError>) -> Self {
+        match result {
+            Ok(_) => Self::Ok,
+            Err(e) if e.code == ErrorCode::CONTENT_MODIFIED => Self::Stale,
+            Err(e) if e.code == ErrorCode::REQUEST_CANCELLED => Self::Cancelled,
+            Err(_) => Self::Error,
+        }
+    }
+
+    /**
+        Returns a short, lowercase, name for the outcome, suitable for use as a metric label.
+    */
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+            Self::Stale => "stale",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
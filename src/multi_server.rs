@@ -0,0 +1,730 @@
+use std::{collections::HashSet, sync::Arc};
+
+use async_lsp::lsp_types::{
+    ClientCapabilities, CodeAction, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    CompletionItem, CompletionParams, CompletionResponse, DocumentDiagnosticParams,
+    DocumentDiagnosticReportResult, DocumentFormattingParams, DocumentLink, DocumentLinkParams,
+    DocumentRangeFormattingParams, DocumentSymbol, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverParams, Location, PrepareRenameResponse, ReferenceParams, RenameParams,
+    ServerCapabilities, ServerInfo, TextDocumentPositionParams, TextEdit, Url, WorkspaceEdit,
+    request::{GotoDeclarationParams, GotoDeclarationResponse},
+};
+use serde_json::Value;
+
+use crate::{
+    custom_notifications::CustomNotifications,
+    custom_requests::CustomRequests,
+    document::{Document, DocumentChangeEvent},
+    document_matcher::{DocumentMatcher, DocumentMatchers},
+    interceptor::Interceptor,
+    result::ServerResult,
+    server_options::ServerOptions,
+    server_state::ServerState,
+    server_trait::Server,
+};
+
+/**
+    The keys used to wrap `data` of items created by either server, so
+    that resolve requests for them can be routed back to the same server.
+*/
+const FIRST_DATA_KEY: &str = "asyncLanguageServer.multiServer.first";
+const SECOND_DATA_KEY: &str = "asyncLanguageServer.multiServer.second";
+
+/**
+    A server composed of two other servers, routing each request to one of them
+    based on which of their [`DocumentMatcher`]s the document was matched against.
+
+    More than two servers may be composed by using [`MultiServer::with`],
+    allowing a single process to host servers for several languages:
+
+    ```rust,ignore
+    let server = MultiServer::new(LuaServer).with(TomlServer).with(JsonServer);
+    serve_stdio(server).await
+    ```
+
+    Requests for documents that were not matched by any matcher of either
+    server are routed to the first server. Requests for resolving completion
+    items, code actions and document links are routed to the server that
+    created them, and the capabilities of both servers are merged together,
+    with the capabilities of the first server taking precedence.
+
+    Options, such as the workspace diagnostics option, are always
+    taken from the first server, since they apply to the whole process.
+*/
+pub struct MultiServer<A, B> {
+    first: Arc<A>,
+    second: Arc<B>,
+    first_names: Arc<HashSet<String>>,
+    first_matchers: DocumentMatchers,
+    second_matchers: DocumentMatchers,
+}
+
+impl<A, B> MultiServer<A, B>
+where
+    A: Server + Send + Sync + 'static,
+    B: Server + Send + Sync + 'static,
+{
+    /**
+        Creates a new server composed of the two given servers.
+    */
+    #[must_use]
+    pub fn new(first: A, second: B) -> Self {
+        let first_names = A::server_document_matchers()
+            .into_iter()
+            .map(|matcher| matcher.name)
+            .collect();
+        Self {
+            first: Arc::new(first),
+            second: Arc::new(second),
+            first_names: Arc::new(first_names),
+            first_matchers: DocumentMatchers::new(A::server_document_matchers()),
+            second_matchers: DocumentMatchers::new(B::server_document_matchers()),
+        }
+    }
+
+    /**
+        Composes this server with another server, which will
+        handle documents not handled by any of the current servers.
+    */
+    #[must_use]
+    pub fn with<C: Server + Send + Sync + 'static>(self, next: C) -> MultiServer<Self, C> {
+        MultiServer::new(self, next)
+    }
+
+    fn routes_to_first(&self, state: &ServerState, url: &Url) -> bool {
+        match state.document(url) {
+            Some(doc) => self.routes_document_to_first(&doc),
+            None => self.routes_url_to_first(url),
+        }
+    }
+
+    fn routes_document_to_first(&self, doc: &Document) -> bool {
+        match doc.matched_name() {
+            Some(name) => self.first_names.contains(name),
+            None => self.routes_url_to_first(doc.url()),
+        }
+    }
+
+    fn routes_url_to_first(&self, url: &Url) -> bool {
+        self.first_matchers.find_url(url).is_some() || self.second_matchers.find_url(url).is_none()
+    }
+}
+
+impl<A, B> Clone for MultiServer<A, B> {
+    fn clone(&self) -> Self {
+        Self {
+            first: Arc::clone(&self.first),
+            second: Arc::clone(&self.second),
+            first_names: Arc::clone(&self.first_names),
+            first_matchers: self.first_matchers.clone(),
+            second_matchers: self.second_matchers.clone(),
+        }
+    }
+}
+
+macro_rules! route_by_url {
+    ($self:ident, $state:ident, $params:ident, $method:ident, $url:expr) => {{
+        let url: &Url = $url;
+        if $self.routes_to_first(&$state, url) {
+            $self.first.$method($state, $params).await
+        } else {
+            $self.second.$method($state, $params).await
+        }
+    }};
+}
+
+impl<A, B> Server for MultiServer<A, B>
+where
+    A: Server + Send + Sync + 'static,
+    B: Server + Send + Sync + 'static,
+{
+    fn server_info() -> Option<ServerInfo> {
+        A::server_info().or_else(B::server_info)
+    }
+
+    fn server_options(&self) -> ServerOptions {
+        self.first.server_options()
+    }
+
+    fn server_capabilities(client_capabilities: ClientCapabilities) -> Option<ServerCapabilities> {
+        let first = A::server_capabilities(client_capabilities.clone());
+        let second = B::server_capabilities(client_capabilities);
+        match (first, second) {
+            (Some(first), Some(second)) => Some(merge_capabilities(&first, &second)),
+            (first, second) => first.or(second),
+        }
+    }
+
+    fn server_document_matchers() -> Vec<DocumentMatcher> {
+        let mut matchers = A::server_document_matchers();
+        matchers.extend(B::server_document_matchers());
+        matchers
+    }
+
+    fn server_custom_requests() -> CustomRequests<Self> {
+        CustomRequests::new()
+            .merge_projected(A::server_custom_requests(), |s: &Self| Arc::clone(&s.first))
+            .merge_projected(B::server_custom_requests(), |s: &Self| {
+                Arc::clone(&s.second)
+            })
+    }
+
+    fn server_custom_notifications() -> CustomNotifications<Self> {
+        CustomNotifications::new()
+            .merge_projected(A::server_custom_notifications(), |s: &Self| &*s.first)
+            .merge_projected(B::server_custom_notifications(), |s: &Self| &*s.second)
+    }
+
+    fn server_interceptors() -> Vec<Arc<dyn Interceptor>> {
+        let mut interceptors = A::server_interceptors();
+        interceptors.extend(B::server_interceptors());
+        interceptors
+    }
+
+    // Document Lifecycle
+
+    async fn document_opened(&self, state: ServerState, document: Document) -> ServerResult<()> {
+        if self.routes_document_to_first(&document) {
+            self.first.document_opened(state, document).await
+        } else {
+            self.second.document_opened(state, document).await
+        }
+    }
+
+    async fn document_changed(&self, state: ServerState, document: Document) -> ServerResult<()> {
+        if self.routes_document_to_first(&document) {
+            self.first.document_changed(state, document).await
+        } else {
+            self.second.document_changed(state, document).await
+        }
+    }
+
+    async fn document_saved(&self, state: ServerState, document: Document) -> ServerResult<()> {
+        if self.routes_document_to_first(&document) {
+            self.first.document_saved(state, document).await
+        } else {
+            self.second.document_saved(state, document).await
+        }
+    }
+
+    async fn document_edited(
+        &self,
+        state: ServerState,
+        event: DocumentChangeEvent,
+    ) -> ServerResult<()> {
+        if self.routes_url_to_first(&event.url) {
+            self.first.document_edited(state, event).await
+        } else {
+            self.second.document_edited(state, event).await
+        }
+    }
+
+    async fn document_sync_failed(&self, state: ServerState, url: Url) -> ServerResult<()> {
+        if self.routes_url_to_first(&url) {
+            self.first.document_sync_failed(state, url).await
+        } else {
+            self.second.document_sync_failed(state, url).await
+        }
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    async fn document_parse_timed_out(&self, state: ServerState, url: Url) -> ServerResult<()> {
+        if self.routes_url_to_first(&url) {
+            self.first.document_parse_timed_out(state, url).await
+        } else {
+            self.second.document_parse_timed_out(state, url).await
+        }
+    }
+
+    async fn document_closed(&self, state: ServerState, document: Document) -> ServerResult<()> {
+        if self.routes_document_to_first(&document) {
+            self.first.document_closed(state, document).await
+        } else {
+            self.second.document_closed(state, document).await
+        }
+    }
+
+    // Workspace Indexing
+
+    async fn index_document(
+        &self,
+        state: ServerState,
+        document: Document,
+    ) -> ServerResult<Vec<DocumentSymbol>> {
+        if self.routes_document_to_first(&document) {
+            self.first.index_document(state, document).await
+        } else {
+            self.second.index_document(state, document).await
+        }
+    }
+
+    // Hover, Completion, Code Action, Document Link
+
+    async fn hover(&self, state: ServerState, params: HoverParams) -> ServerResult<Option<Hover>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            hover,
+            &params.text_document_position_params.text_document.uri
+        )
+    }
+
+    async fn completion(
+        &self,
+        state: ServerState,
+        params: CompletionParams,
+    ) -> ServerResult<Option<CompletionResponse>> {
+        let url = params.text_document_position.text_document.uri.clone();
+        let first = self.routes_to_first(&state, &url);
+        let mut response = if first {
+            self.first.completion(state, params).await?
+        } else {
+            self.second.completion(state, params).await?
+        };
+
+        let items = match response.as_mut() {
+            Some(CompletionResponse::Array(items)) => items,
+            Some(CompletionResponse::List(list)) => &mut list.items,
+            None => return Ok(response),
+        };
+        for item in items {
+            wrap_data(&mut item.data, first);
+        }
+        Ok(response)
+    }
+
+    async fn completion_resolve(
+        &self,
+        state: ServerState,
+        mut item: CompletionItem,
+    ) -> ServerResult<CompletionItem> {
+        let first = unwrap_data(&mut item.data);
+        let mut item = if first {
+            self.first.completion_resolve(state, item).await?
+        } else {
+            self.second.completion_resolve(state, item).await?
+        };
+        wrap_data(&mut item.data, first);
+        Ok(item)
+    }
+
+    async fn code_action(
+        &self,
+        state: ServerState,
+        params: CodeActionParams,
+    ) -> ServerResult<Option<CodeActionResponse>> {
+        let url = params.text_document.uri.clone();
+        let first = self.routes_to_first(&state, &url);
+        let mut response = if first {
+            self.first.code_action(state, params).await?
+        } else {
+            self.second.code_action(state, params).await?
+        };
+
+        for action in response.iter_mut().flatten() {
+            if let CodeActionOrCommand::CodeAction(action) = action {
+                wrap_data(&mut action.data, first);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn code_action_resolve(
+        &self,
+        state: ServerState,
+        mut action: CodeAction,
+    ) -> ServerResult<CodeAction> {
+        let first = unwrap_data(&mut action.data);
+        let mut action = if first {
+            self.first.code_action_resolve(state, action).await?
+        } else {
+            self.second.code_action_resolve(state, action).await?
+        };
+        wrap_data(&mut action.data, first);
+        Ok(action)
+    }
+
+    async fn link(
+        &self,
+        state: ServerState,
+        params: DocumentLinkParams,
+    ) -> ServerResult<Option<Vec<DocumentLink>>> {
+        let url = params.text_document.uri.clone();
+        let first = self.routes_to_first(&state, &url);
+        let mut response = if first {
+            self.first.link(state, params).await?
+        } else {
+            self.second.link(state, params).await?
+        };
+
+        for link in response.iter_mut().flatten() {
+            wrap_data(&mut link.data, first);
+        }
+        Ok(response)
+    }
+
+    async fn link_resolve(
+        &self,
+        state: ServerState,
+        mut link: DocumentLink,
+    ) -> ServerResult<DocumentLink> {
+        let first = unwrap_data(&mut link.data);
+        let mut link = if first {
+            self.first.link_resolve(state, link).await?
+        } else {
+            self.second.link_resolve(state, link).await?
+        };
+        wrap_data(&mut link.data, first);
+        Ok(link)
+    }
+
+    // Declaration, Definition, References, Rename
+
+    async fn declaration(
+        &self,
+        state: ServerState,
+        params: GotoDeclarationParams,
+    ) -> ServerResult<Option<GotoDeclarationResponse>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            declaration,
+            &params.text_document_position_params.text_document.uri
+        )
+    }
+
+    async fn definition(
+        &self,
+        state: ServerState,
+        params: GotoDefinitionParams,
+    ) -> ServerResult<Option<GotoDefinitionResponse>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            definition,
+            &params.text_document_position_params.text_document.uri
+        )
+    }
+
+    async fn references(
+        &self,
+        state: ServerState,
+        params: ReferenceParams,
+    ) -> ServerResult<Option<Vec<Location>>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            references,
+            &params.text_document_position.text_document.uri
+        )
+    }
+
+    async fn rename(
+        &self,
+        state: ServerState,
+        params: RenameParams,
+    ) -> ServerResult<Option<WorkspaceEdit>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            rename,
+            &params.text_document_position.text_document.uri
+        )
+    }
+
+    async fn rename_prepare(
+        &self,
+        state: ServerState,
+        params: TextDocumentPositionParams,
+    ) -> ServerResult<Option<PrepareRenameResponse>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            rename_prepare,
+            &params.text_document.uri
+        )
+    }
+
+    // Formatting
+
+    async fn document_format(
+        &self,
+        state: ServerState,
+        params: DocumentFormattingParams,
+    ) -> ServerResult<Option<Vec<TextEdit>>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            document_format,
+            &params.text_document.uri
+        )
+    }
+
+    async fn document_range_format(
+        &self,
+        state: ServerState,
+        params: DocumentRangeFormattingParams,
+    ) -> ServerResult<Option<Vec<TextEdit>>> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            document_range_format,
+            &params.text_document.uri
+        )
+    }
+
+    // Diagnostics
+
+    async fn document_diagnostics(
+        &self,
+        state: ServerState,
+        params: DocumentDiagnosticParams,
+    ) -> ServerResult<DocumentDiagnosticReportResult> {
+        route_by_url!(
+            self,
+            state,
+            params,
+            document_diagnostics,
+            &params.text_document.uri
+        )
+    }
+}
+
+fn merge_capabilities(
+    first: &ServerCapabilities,
+    second: &ServerCapabilities,
+) -> ServerCapabilities {
+    let mut merged = serde_json::to_value(first).unwrap_or_default();
+    merge_json(
+        &mut merged,
+        serde_json::to_value(second).unwrap_or_default(),
+    );
+    serde_json::from_value(merged).unwrap_or_else(|_| first.clone())
+}
+
+fn merge_json(target: &mut Value, source: Value) {
+    match (target, source) {
+        (Value::Object(target), Value::Object(source)) => {
+            for (key, value) in source {
+                match target.get_mut(&key) {
+                    Some(existing) if !existing.is_null() => merge_json(existing, value),
+                    _ => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target @ Value::Bool(false), source @ (Value::Bool(true) | Value::Object(_))) => {
+            *target = source;
+        }
+        _ => {}
+    }
+}
+
+fn wrap_data(data: &mut Option<Value>, first: bool) {
+    let key = if first {
+        FIRST_DATA_KEY
+    } else {
+        SECOND_DATA_KEY
+    };
+    let inner = data.take().unwrap_or(Value::Null);
+    *data = Some(serde_json::json!({ key: inner }));
+}
+
+/**
+    Unwraps data previously wrapped using [`wrap_data`], returning `true`
+    if the data belongs to the first server. Data that was not wrapped,
+    which should only happen for misbehaving clients, belongs to the first.
+*/
+fn unwrap_data(data: &mut Option<Value>) -> bool {
+    let Some(Value::Object(map)) = data.as_mut() else {
+        return true;
+    };
+    if map.len() != 1 {
+        return true;
+    }
+    let (first, inner) = if let Some(inner) = map.remove(FIRST_DATA_KEY) {
+        (true, inner)
+    } else if let Some(inner) = map.remove(SECOND_DATA_KEY) {
+        (false, inner)
+    } else {
+        return true;
+    };
+    *data = if inner.is_null() { None } else { Some(inner) };
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::{
+        ClientSocket, LanguageServer,
+        lsp_types::{
+            ClientCapabilities, CompletionItem, CompletionOptions, CompletionParams,
+            CompletionResponse, DidOpenTextDocumentParams, Hover, HoverContents, HoverParams,
+            HoverProviderCapability, InitializeParams, MarkedString, PartialResultParams, Position,
+            ServerCapabilities, TextDocumentIdentifier, TextDocumentItem,
+            TextDocumentPositionParams, Url, WorkDoneProgressParams,
+        },
+    };
+
+    use crate::{
+        server::{DocumentMatcher, MultiServer, Server, ServerResult, ServerState},
+        server_with_state::LanguageServerWithState,
+    };
+
+    macro_rules! named_server {
+        ($ty:ident, $name:literal) => {
+            struct $ty;
+
+            impl Server for $ty {
+                fn server_capabilities(_: ClientCapabilities) -> Option<ServerCapabilities> {
+                    Some(ServerCapabilities {
+                        hover_provider: Some(HoverProviderCapability::Simple(true)),
+                        completion_provider: Some(CompletionOptions {
+                            trigger_characters: Some(vec![$name.into()]),
+                            resolve_provider: Some(true),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })
+                }
+
+                fn server_document_matchers() -> Vec<DocumentMatcher> {
+                    vec![DocumentMatcher::new($name).with_lang_strings([$name])]
+                }
+
+                async fn hover(
+                    &self,
+                    _: ServerState,
+                    _: HoverParams,
+                ) -> ServerResult<Option<Hover>> {
+                    Ok(Some(Hover {
+                        contents: HoverContents::Scalar(MarkedString::String($name.into())),
+                        range: None,
+                    }))
+                }
+
+                async fn completion(
+                    &self,
+                    _: ServerState,
+                    _: CompletionParams,
+                ) -> ServerResult<Option<CompletionResponse>> {
+                    let mut item = CompletionItem::new_simple($name.into(), String::new());
+                    item.data = Some(serde_json::json!($name));
+                    Ok(Some(CompletionResponse::Array(vec![item])))
+                }
+
+                async fn completion_resolve(
+                    &self,
+                    _: ServerState,
+                    mut item: CompletionItem,
+                ) -> ServerResult<CompletionItem> {
+                    item.detail = Some(format!("{}:{}", $name, item.data.take().unwrap()));
+                    Ok(item)
+                }
+            }
+        };
+    }
+
+    named_server!(AlphaServer, "alpha");
+    named_server!(BetaServer, "beta");
+    named_server!(GammaServer, "gamma");
+
+    type TestServer =
+        LanguageServerWithState<MultiServer<MultiServer<AlphaServer, BetaServer>, GammaServer>>;
+
+    fn open(server: &mut TestServer, uri: &Url, lang: &str) {
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), lang.into(), 1, "text".into()),
+        });
+    }
+
+    fn hover_text(server: &mut TestServer, uri: &Url) -> String {
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(0, 0),
+            ),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+        let hover = futures::executor::block_on(server.hover(params))
+            .expect("hover succeeds")
+            .expect("hover is returned");
+        match hover.contents {
+            HoverContents::Scalar(MarkedString::String(s)) => s,
+            other => panic!("unexpected hover contents: {other:?}"),
+        }
+    }
+
+    fn server()
+    -> LanguageServerWithState<MultiServer<MultiServer<AlphaServer, BetaServer>, GammaServer>> {
+        let multi = MultiServer::new(AlphaServer, BetaServer).with(GammaServer);
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), multi);
+        futures::executor::block_on(server.initialize(InitializeParams::default()))
+            .expect("server can initialize");
+        server
+    }
+
+    #[test]
+    fn requests_are_routed_by_matched_document() {
+        let mut server = server();
+        for name in ["alpha", "beta", "gamma"] {
+            let uri = Url::parse(&format!("file:///tmp/file.{name}")).unwrap();
+            open(&mut server, &uri, name);
+            assert_eq!(hover_text(&mut server, &uri), name);
+        }
+
+        let unmatched = Url::parse("file:///tmp/file.txt").unwrap();
+        open(&mut server, &unmatched, "plaintext");
+        assert_eq!(hover_text(&mut server, &unmatched), "alpha");
+    }
+
+    #[test]
+    fn capabilities_are_merged() {
+        let mut server = server();
+        let result = futures::executor::block_on(server.initialize(InitializeParams::default()))
+            .expect("server can initialize");
+        let completion = result.capabilities.completion_provider.unwrap();
+        assert_eq!(completion.trigger_characters, Some(vec!["alpha".into()]));
+        assert_eq!(completion.resolve_provider, Some(true));
+        assert!(result.capabilities.hover_provider.is_some());
+    }
+
+    #[test]
+    fn resolve_requests_are_routed_to_the_originating_server() {
+        let mut server = server();
+        for name in ["alpha", "beta", "gamma"] {
+            let uri = Url::parse(&format!("file:///tmp/file.{name}")).unwrap();
+            open(&mut server, &uri, name);
+
+            let params = CompletionParams {
+                text_document_position: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(uri),
+                    Position::new(0, 0),
+                ),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+                context: None,
+            };
+            let Some(CompletionResponse::Array(mut items)) =
+                futures::executor::block_on(server.completion(params))
+                    .expect("completion succeeds")
+            else {
+                panic!("expected completion items");
+            };
+
+            let item = items.pop().expect("completion item is returned");
+            let item = futures::executor::block_on(server.completion_item_resolve(item))
+                .expect("resolve succeeds");
+            assert_eq!(item.detail, Some(format!("{name}:\"{name}\"")));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fs, path::PathBuf};
+use std::path::PathBuf;
 
 use async_lsp::lsp_types::{
     Diagnostic, DocumentDiagnosticReport, DocumentDiagnosticReportKind,
@@ -9,6 +9,7 @@ use crate::{
     document_matcher::DocumentMatchers,
     result::ServerResult,
     server_trait::Server,
+    text_utils::read_text_file,
     workspace_walker::{WorkspaceWalkConfig, WorkspaceWalker, path_to_url},
 };
 
@@ -223,7 +224,7 @@ fn workspace_document(
     Ok(Some(WorkspaceDocument {
         document: OneshotDocument {
             uri,
-            text: fs::read_to_string(&path)?,
+            text: read_text_file(&path)?,
             language_id,
             version: 1,
         },
@@ -0,0 +1,385 @@
+use async_lsp::{
+    ClientSocket,
+    lsp_types::{
+        ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+        WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport, notification,
+    },
+};
+
+use crate::{cancellation::CancellationToken, result::ServerResult, server_state::ServerState};
+
+/**
+    A handle to work done progress that is being reported to the client.
+
+    Created by calling [`ServerState::create_progress`].
+
+    The progress will automatically be ended when the handle
+    is dropped, if [`Progress::end`] was not called before that.
+
+    If the client does not support work done progress, the handle
+    is still usable, but nothing is ever reported to the client.
+*/
+#[derive(Debug)]
+pub struct Progress {
+    state: ServerState,
+    client: ClientSocket,
+    token: ProgressToken,
+    cancellation: CancellationToken,
+    reported: bool,
+    ended: bool,
+}
+
+impl Progress {
+    pub(crate) fn new(state: ServerState, token: ProgressToken) -> Self {
+        let client = state.client();
+        let cancellation = state.progress_cancellation(&token);
+        Self {
+            state,
+            client,
+            token,
+            cancellation,
+            reported: true,
+            ended: false,
+        }
+    }
+
+    pub(crate) fn unreported(state: ServerState, token: ProgressToken) -> Self {
+        let client = state.client();
+        Self {
+            state,
+            client,
+            token,
+            cancellation: CancellationToken::new(),
+            reported: false,
+            ended: false,
+        }
+    }
+
+    pub(crate) fn begin(&self, title: String) -> ServerResult<()> {
+        self.notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title,
+            cancellable: Some(true),
+            message: None,
+            percentage: Some(0),
+        }))
+    }
+
+    /**
+        Returns the token used to identify this progress.
+    */
+    #[must_use]
+    pub fn token(&self) -> &ProgressToken {
+        &self.token
+    }
+
+    /**
+        Returns `true` if this progress is reported to the client, otherwise
+        `false` if the client does not support work done progress.
+    */
+    #[must_use]
+    pub fn is_reported(&self) -> bool {
+        self.reported
+    }
+
+    /**
+        Returns a token that will be cancelled if the
+        client requests this progress to be cancelled.
+    */
+    #[must_use]
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /**
+        Returns `true` if the client has requested
+        this progress to be cancelled, otherwise `false`.
+    */
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /**
+        Reports a new message for the progress, keeping the current percentage.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn report(&self, message: impl Into<String>) -> ServerResult<()> {
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message: Some(message.into()),
+            percentage: None,
+        }))
+    }
+
+    /**
+        Reports a new percentage for the progress, keeping the current message.
+
+        The percentage is clamped to the range `0..=100`.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn report_percentage(&self, percentage: u32) -> ServerResult<()> {
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message: None,
+            percentage: Some(percentage.min(100)),
+        }))
+    }
+
+    /**
+        Reports a new percentage and a new message for the progress.
+
+        The percentage is clamped to the range `0..=100`.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn report_percentage_with_message(
+        &self,
+        percentage: u32,
+        message: impl Into<String>,
+    ) -> ServerResult<()> {
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message: Some(message.into()),
+            percentage: Some(percentage.min(100)),
+        }))
+    }
+
+    /**
+        Ends the progress, with an optional final message.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn end(mut self, message: Option<impl Into<String>>) -> ServerResult<()> {
+        self.finish(message.map(Into::into))
+    }
+
+    fn finish(&mut self, message: Option<String>) -> ServerResult<()> {
+        if self.ended {
+            return Ok(());
+        }
+        self.ended = true;
+        self.state.release_progress_cancellation(&self.token);
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd { message }))
+    }
+
+    fn notify(&self, value: WorkDoneProgress) -> ServerResult<()> {
+        if !self.reported {
+            return Ok(());
+        }
+        self.client
+            .notify::<notification::Progress>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .map_err(Into::into)
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let _ = self.finish(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_lsp::{
+        ClientSocket,
+        lsp_types::{
+            Hover, HoverParams, NumberOrString, Position, ProgressParamsValue, ProgressToken, Url,
+            WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressCancelParams,
+            WorkDoneProgressEnd, WorkDoneProgressReport, notification,
+            request::WorkDoneProgressCreate,
+        },
+    };
+
+    use crate::{
+        server::{Server, ServerResult, ServerState},
+        testing::{MockClient, TestServer as TestHarness},
+    };
+
+    use super::Progress;
+
+    struct TestServer;
+
+    impl Server for TestServer {}
+
+    #[test]
+    fn progress_is_cancelled_by_the_client() {
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let token = NumberOrString::String("progress".into());
+        let progress = Progress::new(state.clone(), token.clone());
+
+        let _ = state.handle_work_done_progress_cancel(WorkDoneProgressCancelParams { token });
+
+        assert!(progress.is_cancelled());
+        assert!(progress.cancellation().is_cancelled());
+    }
+
+    #[test]
+    fn ending_progress_releases_cancellation() {
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let token = NumberOrString::String("progress".into());
+        let progress = Progress::new(state.clone(), token.clone());
+        let cancellation = progress.cancellation();
+
+        let _ = progress.end(None::<String>);
+        let _ = state.handle_work_done_progress_cancel(WorkDoneProgressCancelParams { token });
+
+        assert!(!cancellation.is_cancelled());
+    }
+
+    #[derive(Clone)]
+    struct ProgressServer;
+
+    impl Server for ProgressServer {
+        async fn hover(&self, state: ServerState, _: HoverParams) -> ServerResult<Option<Hover>> {
+            let progress = state.create_progress("Indexing").await?;
+            progress.report("halfway")?;
+            progress.report_percentage_with_message(150, "almost")?;
+            progress.report_percentage(50)?;
+            progress.end(Some("done"))?;
+
+            let dropped = state.create_progress("Dropped").await?;
+            drop(dropped);
+            Ok(None)
+        }
+    }
+
+    async fn expect_progress(client: &MockClient, token: &ProgressToken) -> WorkDoneProgress {
+        let params = client
+            .expect_notification::<notification::Progress>()
+            .await
+            .unwrap();
+        assert_eq!(&params.token, token);
+        let ProgressParamsValue::WorkDone(value) = params.value;
+        value
+    }
+
+    #[test]
+    fn progress_is_created_reported_and_ended() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let capabilities = serde_json::from_value(serde_json::json!({
+                "window": { "workDoneProgress": true },
+            }))
+            .unwrap();
+            let server = TestHarness::spawn_with_capabilities(ProgressServer, capabilities)
+                .await
+                .expect("server spawns");
+            let url = Url::parse("file:///tmp/progress.test").unwrap();
+            assert!(
+                server
+                    .hover(&url, Position::new(0, 0))
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+
+            let client = server.client();
+            let token = client
+                .expect_request::<WorkDoneProgressCreate>()
+                .await
+                .unwrap()
+                .token;
+            assert_eq!(
+                expect_progress(client, &token).await,
+                WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: "Indexing".into(),
+                    cancellable: Some(true),
+                    message: None,
+                    percentage: Some(0),
+                })
+            );
+            assert_eq!(
+                expect_progress(client, &token).await,
+                WorkDoneProgress::Report(WorkDoneProgressReport {
+                    cancellable: None,
+                    message: Some("halfway".into()),
+                    percentage: None,
+                })
+            );
+            assert_eq!(
+                expect_progress(client, &token).await,
+                WorkDoneProgress::Report(WorkDoneProgressReport {
+                    cancellable: None,
+                    message: Some("almost".into()),
+                    percentage: Some(100),
+                })
+            );
+            assert_eq!(
+                expect_progress(client, &token).await,
+                WorkDoneProgress::Report(WorkDoneProgressReport {
+                    cancellable: None,
+                    message: None,
+                    percentage: Some(50),
+                })
+            );
+            assert_eq!(
+                expect_progress(client, &token).await,
+                WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: Some("done".into()),
+                })
+            );
+
+            let dropped = client
+                .expect_request::<WorkDoneProgressCreate>()
+                .await
+                .unwrap()
+                .token;
+            assert_ne!(dropped, token);
+            assert!(matches!(
+                expect_progress(client, &dropped).await,
+                WorkDoneProgress::Begin(_)
+            ));
+            assert_eq!(
+                expect_progress(client, &dropped).await,
+                WorkDoneProgress::End(WorkDoneProgressEnd { message: None })
+            );
+        });
+    }
+
+    #[test]
+    fn progress_is_not_reported_without_client_support() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let server = TestHarness::spawn(ProgressServer)
+                .await
+                .expect("server spawns");
+            let url = Url::parse("file:///tmp/progress.test").unwrap();
+            assert!(
+                server
+                    .hover(&url, Position::new(0, 0))
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+
+            server
+                .client()
+                .expect_silence(Duration::from_millis(20))
+                .await
+                .unwrap();
+        });
+    }
+}
@@ -1,11 +1,12 @@
 use async_lsp::lsp_types::{
-    CodeAction as LspCodeAction, CodeActionOrCommand as LspCodeActionOrCommand,
-    CodeActionParams as LspCodeActionParams, CompletionItem as LspCompletionItem,
-    CompletionParams as LspCompletionParams, CompletionResponse as LspCompletionResponse,
-    CompletionTextEdit as LspCompletionTextEdit, Diagnostic as LspDiagnostic,
-    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportKind,
-    DocumentDiagnosticReportResult, DocumentFormattingParams as LspDocumentFormattingParams,
-    DocumentLink as LspDocumentLink, DocumentLinkParams as LspDocumentLinkParams,
+    CodeAction as LspCodeAction, CodeActionDisabled as LspCodeActionDisabled,
+    CodeActionOrCommand as LspCodeActionOrCommand, CodeActionParams as LspCodeActionParams,
+    CompletionItem as LspCompletionItem, CompletionParams as LspCompletionParams,
+    CompletionResponse as LspCompletionResponse, CompletionTextEdit as LspCompletionTextEdit,
+    Diagnostic as LspDiagnostic, DocumentDiagnosticParams, DocumentDiagnosticReport,
+    DocumentDiagnosticReportKind, DocumentDiagnosticReportResult,
+    DocumentFormattingParams as LspDocumentFormattingParams, DocumentLink as LspDocumentLink,
+    DocumentLinkParams as LspDocumentLinkParams,
     DocumentRangeFormattingParams as LspDocumentRangeFormattingParams,
     GotoDefinitionParams as LspGotoDefinitionParams,
     GotoDefinitionResponse as LspGotoDefinitionResponse, Hover as LspHover,
@@ -22,7 +23,7 @@ use async_lsp::lsp_types::{
 
 use crate::{
     server::{Document, ServerState},
-    text_utils::{Encoding, position_to_encoding},
+    text_utils::{Encoding, PositionOutOfRange},
 };
 
 // ════════════════════════════════
@@ -35,21 +36,78 @@ pub trait Request {
     type Params;
     type Response;
 
+    /**
+        Whether the request should be rejected for read-only documents.
+    */
+    const REJECTS_READ_ONLY: bool = false;
+
     fn extract_url(params: &Self::Params) -> Option<Url> {
         None
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {}
+    fn extract_previous_result_id(params: &Self::Params) -> Option<String> {
+        None
+    }
+
+    /**
+        Converts positions in the params to UTF-8, rejecting positions outside of the document.
+    */
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        Ok(())
+    }
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {}
+
+    /**
+        Returns a response for the document without calling the handler, if the
+        result from the previous request, given by the client, is still current.
+    */
+    fn unchanged_response(
+        state: &ServerState,
+        document: &Document,
+        previous_result_id: Option<&str>,
+    ) -> Option<Self::Response> {
+        None
+    }
+
+    /**
+        Remembers the result for the document at the given version, and lets
+        the client know if it did not change since its previous request.
+    */
+    fn apply_result_id(
+        state: &ServerState,
+        url: &Url,
+        version: Option<i32>,
+        previous_result_id: Option<&str>,
+        response: &mut Self::Response,
+    ) {
+    }
+
+    /**
+        Removes or disables any edits to read-only documents in the response.
+
+        Returns the URL of a read-only document if the response as a whole
+        must be rejected, since its edits can not be removed individually.
+    */
+    fn reject_read_only_edits(state: &ServerState, response: &mut Self::Response) -> Option<Url> {
+        None
+    }
 }
 
-fn modify_incoming_position(state: &ServerState, document: &Document, position: &mut LspPosition) {
-    *position = position_to_encoding(
-        &document.text,
+pub(crate) fn modify_incoming_position(
+    state: &ServerState,
+    document: &Document,
+    position: &mut LspPosition,
+) -> Result<(), PositionOutOfRange> {
+    *position = document.line_index().try_position_to_encoding(
         *position,
         state.get_position_encoding(),
         Encoding::UTF8,
-    );
+    )?;
+    Ok(())
 }
 
 fn modify_incoming_position_at_url(
@@ -57,19 +115,30 @@ fn modify_incoming_position_at_url(
     fallback: &Document,
     url: &Url,
     position: &mut LspPosition,
-) {
+) -> Result<(), PositionOutOfRange> {
     if url == fallback.url() {
-        modify_incoming_position(state, fallback, position);
+        modify_incoming_position(state, fallback, position)
     } else if let Some(document) = state.document(url) {
-        modify_incoming_position(state, &document, position);
+        modify_incoming_position(state, &document, position)
     } else {
-        modify_incoming_position(state, fallback, position);
+        // NOTE: Positions in documents that we do not know about can
+        // not be validated, so they are converted on a best-effort basis
+        *position = fallback.line_index().position_to_encoding(
+            *position,
+            state.get_position_encoding(),
+            Encoding::UTF8,
+        );
+        Ok(())
     }
 }
 
-fn modify_incoming_range(state: &ServerState, document: &Document, range: &mut LspRange) {
-    modify_incoming_position(state, document, &mut range.start);
-    modify_incoming_position(state, document, &mut range.end);
+fn modify_incoming_range(
+    state: &ServerState,
+    document: &Document,
+    range: &mut LspRange,
+) -> Result<(), PositionOutOfRange> {
+    modify_incoming_position(state, document, &mut range.start)?;
+    modify_incoming_position(state, document, &mut range.end)
 }
 
 fn modify_incoming_range_at_url(
@@ -77,19 +146,26 @@ fn modify_incoming_range_at_url(
     fallback: &Document,
     url: &Url,
     range: &mut LspRange,
-) {
-    modify_incoming_position_at_url(state, fallback, url, &mut range.start);
-    modify_incoming_position_at_url(state, fallback, url, &mut range.end);
+) -> Result<(), PositionOutOfRange> {
+    modify_incoming_position_at_url(state, fallback, url, &mut range.start)?;
+    modify_incoming_position_at_url(state, fallback, url, &mut range.end)
 }
 
-fn modify_incoming_location(state: &ServerState, document: &Document, loc: &mut LspLocation) {
+fn modify_incoming_location(
+    state: &ServerState,
+    document: &Document,
+    loc: &mut LspLocation,
+) -> Result<(), PositionOutOfRange> {
     let uri = loc.uri.clone();
-    modify_incoming_range_at_url(state, document, &uri, &mut loc.range);
+    modify_incoming_range_at_url(state, document, &uri, &mut loc.range)
 }
 
-fn modify_outgoing_position(state: &ServerState, document: &Document, position: &mut LspPosition) {
-    *position = position_to_encoding(
-        &document.text,
+pub(crate) fn modify_outgoing_position(
+    state: &ServerState,
+    document: &Document,
+    position: &mut LspPosition,
+) {
+    *position = document.line_index().position_to_encoding(
         *position,
         Encoding::UTF8,
         state.get_position_encoding(),
@@ -135,16 +211,25 @@ fn modify_outgoing_text_edit(state: &ServerState, document: &Document, edit: &mu
     modify_outgoing_range(state, document, &mut edit.range);
 }
 
-fn modify_incoming_diagnostic(state: &ServerState, document: &Document, diag: &mut LspDiagnostic) {
-    modify_incoming_range(state, document, &mut diag.range);
+fn modify_incoming_diagnostic(
+    state: &ServerState,
+    document: &Document,
+    diag: &mut LspDiagnostic,
+) -> Result<(), PositionOutOfRange> {
+    modify_incoming_range(state, document, &mut diag.range)?;
     if let Some(related) = diag.related_information.as_mut() {
         for info in related {
-            modify_incoming_location(state, document, &mut info.location);
+            modify_incoming_location(state, document, &mut info.location)?;
         }
     }
+    Ok(())
 }
 
-fn modify_outgoing_diagnostic(state: &ServerState, document: &Document, diag: &mut LspDiagnostic) {
+pub(crate) fn modify_outgoing_diagnostic(
+    state: &ServerState,
+    document: &Document,
+    diag: &mut LspDiagnostic,
+) {
     let url = document.url().clone();
     modify_outgoing_diagnostic_at_url(state, document, &url, diag);
 }
@@ -208,6 +293,46 @@ fn modify_outgoing_location_link(
     );
 }
 
+pub(crate) fn find_read_only_edit(state: &ServerState, edit: &LspWorkspaceEdit) -> Option<Url> {
+    use async_lsp::lsp_types::{DocumentChangeOperation, DocumentChanges, ResourceOp};
+
+    let mut urls = Vec::new();
+    if let Some(changes) = edit.changes.as_ref() {
+        urls.extend(changes.keys());
+    }
+    match edit.document_changes.as_ref() {
+        Some(DocumentChanges::Edits(edits)) => {
+            urls.extend(edits.iter().map(|edit| &edit.text_document.uri));
+        }
+        Some(DocumentChanges::Operations(ops)) => {
+            for op in ops {
+                match op {
+                    DocumentChangeOperation::Edit(edit) => urls.push(&edit.text_document.uri),
+                    DocumentChangeOperation::Op(ResourceOp::Rename(rename)) => {
+                        urls.push(&rename.old_uri);
+                    }
+                    DocumentChangeOperation::Op(ResourceOp::Delete(delete)) => {
+                        urls.push(&delete.uri);
+                    }
+                    // NOTE: Creating a file that already exists only changes it if
+                    // it is overwritten, which clobbers any read-only document there
+                    DocumentChangeOperation::Op(ResourceOp::Create(create)) => {
+                        let overwrite = create.options.as_ref().and_then(|o| o.overwrite);
+                        if overwrite == Some(true) {
+                            urls.push(&create.uri);
+                        }
+                    }
+                }
+            }
+        }
+        None => {}
+    }
+
+    urls.into_iter()
+        .find(|url| state.url_is_read_only(url))
+        .cloned()
+}
+
 fn modify_outgoing_workspace_edit(
     state: &ServerState,
     document: &Document,
@@ -301,12 +426,16 @@ impl Request for Hover {
         )
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
         modify_incoming_position(
             state,
             document,
             &mut params.text_document_position_params.position,
-        );
+        )
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -328,8 +457,12 @@ impl Request for Completion {
         Some(params.text_document_position.text_document.uri.clone())
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_position(state, document, &mut params.text_document_position.position);
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_position(state, document, &mut params.text_document_position.position)
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -387,11 +520,35 @@ impl Request for CodeAction {
         Some(params.text_document.uri.clone())
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_range(state, document, &mut params.range);
+    fn reject_read_only_edits(state: &ServerState, response: &mut Self::Response) -> Option<Url> {
+        // NOTE: Other code actions may still be useful, so instead of rejecting
+        // the entire response we disable only the actions that edit read-only docs
+        for action in response.iter_mut().flatten() {
+            if let LspCodeActionOrCommand::CodeAction(action) = action {
+                let read_only = action
+                    .edit
+                    .as_ref()
+                    .and_then(|e| find_read_only_edit(state, e));
+                if let Some(url) = read_only {
+                    action.disabled = Some(LspCodeActionDisabled {
+                        reason: format!("document is read-only: {url}"),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_range(state, document, &mut params.range)?;
         for diag in &mut params.context.diagnostics {
-            modify_incoming_diagnostic(state, document, diag);
+            modify_incoming_diagnostic(state, document, diag)?;
         }
+        Ok(())
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -420,6 +577,13 @@ impl Request for CodeActionResolve {
 
     // CodeAction doesn't contain a document URI
 
+    fn reject_read_only_edits(state: &ServerState, response: &mut Self::Response) -> Option<Url> {
+        response
+            .edit
+            .as_ref()
+            .and_then(|edit| find_read_only_edit(state, edit))
+    }
+
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
         if let Some(diagnostics) = response.diagnostics.as_mut() {
             for diag in diagnostics {
@@ -465,8 +629,12 @@ impl Request for DocumentLinkResolve {
         params.target.clone()
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_range(state, document, &mut params.range);
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_range(state, document, &mut params.range)
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -494,12 +662,16 @@ impl Request for Definition {
         )
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
         modify_incoming_position(
             state,
             document,
             &mut params.text_document_position_params.position,
-        );
+        )
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -539,12 +711,16 @@ impl Request for Declaration {
         )
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
         modify_incoming_position(
             state,
             document,
             &mut params.text_document_position_params.position,
-        );
+        )
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -582,8 +758,12 @@ impl Request for References {
         Some(params.text_document_position.text_document.uri.clone())
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_position(state, document, &mut params.text_document_position.position);
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_position(state, document, &mut params.text_document_position.position)
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -601,12 +781,18 @@ impl Request for Rename {
     type Params = LspRenameParams;
     type Response = Option<LspWorkspaceEdit>;
 
+    const REJECTS_READ_ONLY: bool = true;
+
     fn extract_url(params: &Self::Params) -> Option<Url> {
         Some(params.text_document_position.text_document.uri.clone())
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_position(state, document, &mut params.text_document_position.position);
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_position(state, document, &mut params.text_document_position.position)
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -614,6 +800,12 @@ impl Request for Rename {
             modify_outgoing_workspace_edit(state, document, response);
         }
     }
+
+    fn reject_read_only_edits(state: &ServerState, response: &mut Self::Response) -> Option<Url> {
+        response
+            .as_ref()
+            .and_then(|edit| find_read_only_edit(state, edit))
+    }
 }
 
 pub struct RenamePrepare;
@@ -622,12 +814,18 @@ impl Request for RenamePrepare {
     type Params = LspTextDocumentPositionParams;
     type Response = Option<LspPrepareRenameResponse>;
 
+    const REJECTS_READ_ONLY: bool = true;
+
     fn extract_url(params: &Self::Params) -> Option<Url> {
         Some(params.text_document.uri.clone())
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_position(state, document, &mut params.position);
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_position(state, document, &mut params.position)
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -653,6 +851,8 @@ impl Request for DocumentFormat {
     type Params = LspDocumentFormattingParams;
     type Response = Option<Vec<LspTextEdit>>;
 
+    const REJECTS_READ_ONLY: bool = true;
+
     fn extract_url(params: &Self::Params) -> Option<Url> {
         Some(params.text_document.uri.clone())
     }
@@ -672,12 +872,18 @@ impl Request for DocumentRangeFormat {
     type Params = LspDocumentRangeFormattingParams;
     type Response = Option<Vec<LspTextEdit>>;
 
+    const REJECTS_READ_ONLY: bool = true;
+
     fn extract_url(params: &Self::Params) -> Option<Url> {
         Some(params.text_document.uri.clone())
     }
 
-    fn modify_params(state: &ServerState, document: &Document, params: &mut Self::Params) {
-        modify_incoming_range(state, document, &mut params.range);
+    fn modify_params(
+        state: &ServerState,
+        document: &Document,
+        params: &mut Self::Params,
+    ) -> Result<(), PositionOutOfRange> {
+        modify_incoming_range(state, document, &mut params.range)
     }
 
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
@@ -703,6 +909,32 @@ impl Request for DocumentDiagnostics {
         Some(params.text_document.uri.clone())
     }
 
+    fn extract_previous_result_id(params: &Self::Params) -> Option<String> {
+        params.previous_result_id.clone()
+    }
+
+    fn unchanged_response(
+        state: &ServerState,
+        document: &Document,
+        previous_result_id: Option<&str>,
+    ) -> Option<Self::Response> {
+        state
+            .diagnostic_results()
+            .unchanged(document.url(), document.version(), previous_result_id)
+    }
+
+    fn apply_result_id(
+        state: &ServerState,
+        url: &Url,
+        version: Option<i32>,
+        previous_result_id: Option<&str>,
+        response: &mut Self::Response,
+    ) {
+        state
+            .diagnostic_results()
+            .record(url, version, previous_result_id, response);
+    }
+
     fn modify_response(state: &ServerState, document: &Document, response: &mut Self::Response) {
         match response {
             DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) => {
@@ -740,18 +972,26 @@ mod tests {
     use async_lsp::{
         ClientSocket,
         lsp_types::{
-            CodeActionContext, CodeActionParams, CompletionItem, CompletionResponse, Diagnostic,
-            DidOpenTextDocumentParams, DocumentDiagnosticReport, DocumentDiagnosticReportKind,
+            CodeActionContext, CodeActionParams, CompletionItem, CompletionResponse, CreateFile,
+            CreateFileOptions, Diagnostic, DidOpenTextDocumentParams, DocumentChangeOperation,
+            DocumentChanges, DocumentDiagnosticReport, DocumentDiagnosticReportKind,
             DocumentDiagnosticReportResult, FullDocumentDiagnosticReport, GotoDefinitionResponse,
             Location, PartialResultParams, Position, Range, RelatedFullDocumentDiagnosticReport,
-            TextDocumentIdentifier, TextDocumentItem, TextEdit, Url, WorkDoneProgressParams,
-            WorkspaceEdit,
+            ResourceOp, TextDocumentIdentifier, TextDocumentItem, TextEdit, Url,
+            WorkDoneProgressParams, WorkspaceEdit,
         },
     };
 
-    use crate::{server::Server, server_state::ServerState, text_utils::Encoding};
+    use crate::{
+        server::{DocumentMatcher, Server},
+        server_state::ServerState,
+        text_utils::Encoding,
+    };
 
-    use super::{CodeAction, Completion, Definition, DocumentDiagnostics, Rename, Request};
+    use super::{
+        CodeAction, Completion, Definition, DocumentDiagnostics, Rename, Request,
+        find_read_only_edit,
+    };
 
     struct TestServer;
 
@@ -866,7 +1106,7 @@ mod tests {
             partial_result_params: PartialResultParams::default(),
         };
 
-        <CodeAction as Request>::modify_params(&state, &document, &mut params);
+        <CodeAction as Request>::modify_params(&state, &document, &mut params).unwrap();
 
         assert_eq!(params.range, r(0, 0, 4));
         assert_eq!(params.context.diagnostics[0].range, r(0, 4, 4));
@@ -930,4 +1170,42 @@ mod tests {
         let edit = edit.changes.unwrap().into_values().next().unwrap();
         assert_eq!(edit[0].range, r(0, 2, 2));
     }
+
+    struct ReadOnlyServer;
+
+    impl Server for ReadOnlyServer {
+        fn server_document_matchers() -> Vec<DocumentMatcher> {
+            vec![
+                DocumentMatcher::new("Test")
+                    .with_url_globs(["**/*.test"])
+                    .with_read_only_globs(["**/generated/**"]),
+            ]
+        }
+    }
+
+    #[test]
+    fn overwriting_creates_are_read_only_edits() {
+        let state = ServerState::new::<ReadOnlyServer>(ClientSocket::new_closed());
+        let generated = url("generated/a.test");
+        let create = |overwrite: Option<bool>| WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                    uri: generated.clone(),
+                    options: Some(CreateFileOptions {
+                        overwrite,
+                        ignore_if_exists: None,
+                    }),
+                    annotation_id: None,
+                })),
+            ])),
+            ..WorkspaceEdit::default()
+        };
+
+        assert_eq!(find_read_only_edit(&state, &create(None)), None);
+        assert_eq!(find_read_only_edit(&state, &create(Some(false))), None);
+        assert_eq!(
+            find_read_only_edit(&state, &create(Some(true))),
+            Some(generated.clone())
+        );
+    }
 }
@@ -1,5 +1,7 @@
 #![allow(clippy::needless_pass_by_value)]
 
+use std::path::PathBuf;
+
 use async_lsp::ResponseError;
 use thiserror::Error;
 
@@ -13,6 +15,8 @@ pub type ServerResult<T> = Result<T, ServerError>;
 pub enum ServerError {
     #[error("Failed to connect to port {0}")]
     TcpConnect(u16),
+    #[error("Failed to connect to pipe {}", .0.display())]
+    PipeConnect(PathBuf),
     #[error("Uncategorized error: {0}")]
     Unknown(String),
     #[error("JSON RPC error: {0}")]
@@ -21,6 +25,8 @@ pub enum ServerError {
     Lsp(#[from] async_lsp::Error),
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
 }
 
 impl ServerError {
@@ -1,6 +1,6 @@
 #![allow(clippy::missing_panics_doc)]
 
-use std::num::NonZeroUsize;
+use std::{num::NonZeroUsize, path::Path};
 
 use async_lsp::{
     client_monitor::ClientProcessMonitorLayer, concurrency::ConcurrencyLayer,
@@ -9,11 +9,14 @@ use async_lsp::{
 use tower::ServiceBuilder;
 
 #[cfg(feature = "tracing")]
-use async_lsp::tracing::TracingLayer;
+use crate::layers::TracingLayer;
 
 use crate::{
-    result::ServerResult, server_trait::Server, server_with_state::LanguageServerWithState,
-    transport::Transport,
+    layers::{InterceptorLayer, MetricsLayer, RequestCancellationLayer},
+    result::ServerResult,
+    server_trait::Server,
+    server_with_state::LanguageServerWithState,
+    transport::{LspTransportRead, LspTransportWrite, Transport, pipe_read_write},
 };
 
 /**
@@ -23,7 +26,10 @@ use crate::{
 
     This will automatically attach middleware for:
 
-    - Tracing metadata for each request
+    - Tracing spans for each request, with method, id, document and latency
+    - Request metrics, if registered using `ServerOptions::with_metrics`
+    - Interceptors registered using `Server::server_interceptors`
+    - Cancellation tokens for each request, see `ServerState::current_cancellation`
     - Maximum concurrency of 8 in-flight LSP requests at a time
     - Catching panics and safely returning internal server error statuses
     - Client process monitoring and automatic server shutdown when client exits
@@ -39,21 +45,43 @@ where
     S: Send + Sync + 'static,
 {
     let (reader, writer) = transport.into_read_write().await?;
+    serve_read_write(reader, writer, server).await
+}
 
+/**
+    Serves a language server over an already connected reader and writer.
+*/
+pub(crate) async fn serve_read_write<S>(
+    reader: LspTransportRead,
+    writer: LspTransportWrite,
+    server: S,
+) -> ServerResult<()>
+where
+    S: Server + Clone,
+    S: Send + Sync + 'static,
+{
     let (server, _) = async_lsp::MainLoop::new_server(|client| {
         let builder = ServiceBuilder::new().layer(LifecycleLayer::default());
 
         #[cfg(feature = "tracing")]
-        let builder = builder.layer(TracingLayer::default());
+        let builder = builder.layer(TracingLayer);
+
+        let mut router = Router::from_language_server(LanguageServerWithState::new(
+            client.clone(),
+            server.clone(),
+        ));
+        router.unhandled_request(LanguageServerWithState::custom_request);
+        router.unhandled_notification(LanguageServerWithState::custom_notification);
 
         builder
+            .layer(MetricsLayer::new(server.server_options().metrics))
+            .layer(InterceptorLayer::new(S::server_interceptors()))
+            // NOTE: Must come before the concurrency layer, which consumes `$/cancelRequest`
+            .layer(RequestCancellationLayer)
             .layer(ConcurrencyLayer::new(NonZeroUsize::new(8).unwrap()))
             .layer(CatchUnwindLayer::default())
-            .layer(ClientProcessMonitorLayer::new(client.clone()))
-            .service(Router::from_language_server(LanguageServerWithState::new(
-                client,
-                server.clone(),
-            )))
+            .layer(ClientProcessMonitorLayer::new(client))
+            .service(router)
     });
 
     server
@@ -61,3 +89,126 @@ where
         .await
         .map_err(Into::into)
 }
+
+/**
+    Serves a language server over stdin and stdout.
+
+    This is a shorthand for [`serve`] using [`Transport::Stdio`], which is
+    the transport used by most language clients, and includes all of the
+    same middleware, meaning a complete server `main` can be as short as:
+
+    ```rust,ignore
+    #[tokio::main]
+    async fn main() -> ServerResult<()> {
+        serve_stdio(MyServer::default()).await
+    }
+    ```
+
+    # Errors
+
+    - If the server encounters an I/O error while running
+*/
+pub async fn serve_stdio<S>(server: S) -> ServerResult<()>
+where
+    S: Server + Clone,
+    S: Send + Sync + 'static,
+{
+    serve(Transport::Stdio, server).await
+}
+
+/**
+    Serves a language server over a Unix domain socket, or a Windows named pipe.
+
+    Just like [`Transport::Socket`], this connects to a socket or pipe that
+    has been created by the client, which is how the `--pipe` option given
+    to language servers by clients such as VS Code is expected to be used.
+
+    # Errors
+
+    - If the socket or pipe at the given path could not be connected to
+    - If the server encounters an I/O error while running
+*/
+pub async fn serve_pipe<S>(server: S, path: impl AsRef<Path>) -> ServerResult<()>
+where
+    S: Server + Clone,
+    S: Send + Sync + 'static,
+{
+    let (reader, writer) = pipe_read_write(path.as_ref()).await?;
+    serve_read_write(reader, writer, server).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::server::{Server, ServerError};
+
+    use super::serve_pipe;
+
+    #[derive(Clone)]
+    struct TestServer;
+
+    impl Server for TestServer {}
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created")
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is after epoch")
+            .as_millis();
+        std::env::temp_dir().join(format!("async-language-server-{name}-{millis}.sock"))
+    }
+
+    #[test]
+    fn serve_pipe_fails_for_missing_pipes() {
+        let path = temp_path("missing-pipe");
+        let result = runtime().block_on(serve_pipe(TestServer, &path));
+        assert!(matches!(result, Err(ServerError::PipeConnect(p)) if p == path));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serve_pipe_serves_requests_over_unix_sockets() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let path = temp_path("unix-pipe");
+        runtime().block_on(async {
+            let listener = tokio::net::UnixListener::bind(&path).unwrap();
+            tokio::spawn(serve_pipe(TestServer, path.clone()));
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+
+            let request =
+                r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#;
+            write
+                .write_all(format!("Content-Length: {}\r\n\r\n{request}", request.len()).as_bytes())
+                .await
+                .unwrap();
+
+            let mut read = BufReader::new(read);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                read.read_line(&mut line).await.unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                } else if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            read.read_exact(&mut body).await.unwrap();
+
+            let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(response["id"], 1);
+            assert!(response["result"]["capabilities"].is_object());
+        });
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,11 +1,40 @@
-use async_lsp::lsp_types::{ConfigurationItem, LSPAny};
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
+
+use async_lsp::lsp_types::{ConfigurationItem, LSPAny, Url};
+#[cfg(feature = "tree-sitter")]
+use ropey::Rope;
+
+use crate::metrics::Metrics;
+
+#[cfg(feature = "tree-sitter")]
+use crate::syntax_diagnostics::SyntaxDiagnostics;
 
 /**
     Options for the language server wrapper.
 */
-#[derive(Debug, Default, Clone)]
+#[derive(Default, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ServerOptions {
     pub(crate) workspace_diagnostics: WorkspaceDiagnostics,
+    pub(crate) request_timeout: Option<Duration>,
+    pub(crate) content_modified_retries: usize,
+    pub(crate) shutdown_timeout: Option<Duration>,
+    pub(crate) metrics: Option<Arc<dyn Metrics>>,
+    pub(crate) workspace_indexing: bool,
+    pub(crate) document_history: usize,
+    pub(crate) full_text_sync: bool,
+    pub(crate) exclude_save_text: bool,
+    pub(crate) save_reads_from_disk: bool,
+    pub(crate) track_watched_files: bool,
+    pub(crate) sync_recovery: SyncRecovery,
+    pub(crate) path_resolver: Option<PathResolver>,
+    pub(crate) parse_limit: ParseLimit,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) syntax_diagnostics: Option<SyntaxDiagnostics>,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) background_parsing: bool,
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) reparse_debounce: Option<Duration>,
 }
 
 impl ServerOptions {
@@ -20,6 +49,411 @@ impl ServerOptions {
         self.workspace_diagnostics = workspace_diagnostics.into();
         self
     }
+
+    /**
+        Sets the maximum duration that a request handler may run for.
+
+        Handlers exceeding this duration are aborted, their cancellation
+        token is cancelled, and the client receives a `RequestCancelled` error.
+
+        By default, request handlers may run for any amount of time.
+
+        Timeouts require the server to run within a tokio runtime with timers
+        enabled, such as when using [`serve`]. Without a runtime, the timeout is
+        ignored and an error is emitted, and without timers, tokio will panic.
+
+        [`serve`]: crate::server::serve
+    */
+    #[must_use]
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /**
+        Sets how many times a request may be retried if its document
+        is modified while the request is being handled by the server.
+
+        Each retry re-invokes the handler using the latest version of the
+        document, with the original request parameters converted against it.
+        Once all retries have been used up, or if retries are not enabled,
+        the client receives a `ContentModified` error.
+
+        By default, requests are not retried.
+    */
+    #[must_use]
+    pub fn with_content_modified_retries(mut self, retries: usize) -> Self {
+        self.content_modified_retries = retries;
+        self
+    }
+
+    /**
+        Sets the maximum duration to wait for in-flight requests to
+        complete when the client requests the server to shut down.
+
+        Requests still running after this duration will not be waited
+        for, and the shutdown request will be responded to regardless.
+
+        By default, shutdown waits for up to 5 seconds.
+
+        The timeout requires the server to run within a tokio runtime with timers
+        enabled, such as when using [`serve`]. Without a runtime, the timeout is
+        ignored and an error is emitted, and without timers, tokio will panic.
+
+        [`serve`]: crate::server::serve
+    */
+    #[must_use]
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /**
+        Sets the metrics recorder that is called with the
+        outcome and duration of every request to the server.
+
+        By default, no metrics are recorded.
+    */
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: impl Metrics) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /**
+        Sets whether documents in the workspace folders of the client
+        should be indexed in the background, using [`Server::index_document`].
+
+        See [`WorkspaceIndex`] for more information.
+
+        By default, the workspace is not indexed.
+
+        [`Server::index_document`]: crate::server::Server::index_document
+        [`WorkspaceIndex`]: crate::server::WorkspaceIndex
+    */
+    #[must_use]
+    pub fn with_workspace_indexing(mut self, yes: bool) -> Self {
+        self.workspace_indexing = yes;
+        self
+    }
+
+    /**
+        Sets how many previous versions of each document should be kept
+        around, to be retrieved using [`ServerState::document_at_version`].
+
+        By default, no previous versions are kept.
+
+        [`ServerState::document_at_version`]: crate::server::ServerState::document_at_version
+    */
+    #[must_use]
+    pub fn with_document_history(mut self, versions: usize) -> Self {
+        self.document_history = versions;
+        self
+    }
+
+    /**
+        Sets whether the client should send the full contents of documents
+        on every change, instead of only the incremental changes made.
+
+        This may be preferable for servers that always re-read entire documents,
+        and where incremental tracking is not worth the overhead - note however
+        that it increases the amount of data sent by the client for large documents.
+
+        By default, documents are synchronized incrementally.
+    */
+    #[must_use]
+    pub fn with_full_text_sync(mut self, yes: bool) -> Self {
+        self.full_text_sync = yes;
+        self
+    }
+
+    /**
+        Sets whether the client should send the full contents of documents
+        when they are saved, which lets the server verify that its contents
+        are in sync with the client after every save.
+
+        By default, the full contents are sent.
+    */
+    #[must_use]
+    pub fn with_save_text(mut self, yes: bool) -> Self {
+        self.exclude_save_text = !yes;
+        self
+    }
+
+    /**
+        Sets whether documents should be re-read from disk when they are saved
+        by the client, and the client did not send their full contents.
+
+        Note that the contents on disk may not match the contents in the client,
+        if further edits were made while saving, so this is usually not needed -
+        documents are always re-read from disk when saved if they are stale.
+
+        By default, the contents that were synchronized with the client are kept.
+    */
+    #[must_use]
+    pub fn with_save_reads_from_disk(mut self, yes: bool) -> Self {
+        self.save_reads_from_disk = yes;
+        self
+    }
+
+    /**
+        Sets whether files in the workspace that are created or changed on disk
+        should start being tracked by the server, if they match any of the
+        document matchers, without waiting for the client to open them.
+
+        This lets workspace features, such as finding references, see new files
+        right away - the files are tracked just like any other files that have
+        not been opened by the client, and are read from disk when they change.
+
+        By default, only files that are already tracked are refreshed.
+    */
+    #[must_use]
+    pub fn with_track_watched_files(mut self, yes: bool) -> Self {
+        self.track_watched_files = yes;
+        self
+    }
+
+    /**
+        Sets how documents should be recovered when an incremental
+        update from the client could not be applied to them.
+
+        Regardless of the strategy used, [`Server::document_sync_failed`]
+        is called whenever this happens.
+
+        By default, documents are marked as stale.
+
+        [`Server::document_sync_failed`]: crate::server::Server::document_sync_failed
+    */
+    #[must_use]
+    pub fn with_sync_recovery(mut self, recovery: SyncRecovery) -> Self {
+        self.sync_recovery = recovery;
+        self
+    }
+
+    /**
+        Sets the maximum size of documents, in bytes, that will be parsed
+        using tree-sitter. Larger documents are still tracked, but only
+        as text, and will not have a syntax tree.
+
+        This prevents large generated files from stalling the server,
+        since documents are parsed while handling client notifications.
+
+        By default, documents of any size are parsed.
+    */
+    #[must_use]
+    pub fn with_max_parse_bytes(mut self, bytes: usize) -> Self {
+        self.parse_limit.max_bytes = Some(bytes);
+        self
+    }
+
+    /**
+        Sets the maximum number of lines in documents that will be parsed using tree-sitter.
+
+        See [`ServerOptions::with_max_parse_bytes`] for more information.
+    */
+    #[must_use]
+    pub fn with_max_parse_lines(mut self, lines: usize) -> Self {
+        self.parse_limit.max_lines = Some(lines);
+        self
+    }
+
+    /**
+        Sets the maximum duration that parsing a single document using tree-sitter may take.
+
+        Documents that take longer to parse, such as adversarial or very large inputs,
+        are still tracked, but only as text, and will not have a syntax tree. When this
+        happens, a warning is emitted, and [`Server::document_parse_timed_out`] is called.
+
+        By default, parsing may take any amount of time.
+
+        [`Server::document_parse_timed_out`]: crate::server::Server::document_parse_timed_out
+    */
+    #[must_use]
+    pub fn with_parse_timeout(mut self, timeout: Duration) -> Self {
+        self.parse_limit.timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Enables diagnostics for syntax errors in the tree-sitter trees of documents,
+        which are published automatically whenever documents are opened or changed.
+
+        See [`SyntaxDiagnostics`] for more information.
+
+        By default, no syntax diagnostics are published.
+    */
+    #[must_use]
+    pub fn with_syntax_diagnostics(mut self, diagnostics: SyntaxDiagnostics) -> Self {
+        self.syntax_diagnostics = Some(diagnostics);
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Sets whether full tree-sitter parses should run on a background
+        thread, instead of while handling client notifications.
+
+        Full parses happen whenever a document is opened, re-read, or
+        replaced entirely, and may take a long time for large documents.
+        Incremental re-parses, after small edits, are always done immediately.
+
+        While a document is being parsed in the background, it has no syntax
+        tree, which can be detected using [`Document::syntax_status`]. Syntax
+        diagnostics, if enabled, are published again once parsing finishes.
+
+        By default, documents are parsed while handling notifications.
+
+        [`Document::syntax_status`]: crate::server::Document::syntax_status
+    */
+    #[must_use]
+    pub fn with_background_parsing(mut self, background_parsing: bool) -> Self {
+        self.background_parsing = background_parsing;
+        self
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    /**
+        Sets a quiet period to wait for after incremental edits to a
+        document, before re-parsing it using tree-sitter, so that a burst
+        of small edits, such as while typing, results in a single re-parse.
+
+        Edits made during the quiet period are applied to the previous syntax
+        tree, and the quiet period restarts. Until the re-parse finishes, the
+        document keeps this edited syntax tree, and reports its status as
+        pending, which can be detected using [`Document::syntax_status`].
+        Re-parses run on a background thread.
+
+        By default, documents are re-parsed immediately after every change.
+
+        [`Document::syntax_status`]: crate::server::Document::syntax_status
+    */
+    #[must_use]
+    pub fn with_reparse_debounce(mut self, debounce: Duration) -> Self {
+        self.reparse_debounce = Some(debounce);
+        self
+    }
+
+    /**
+        Sets the function used to resolve document URLs to paths on disk,
+        whenever the server needs to read the contents of a document itself.
+
+        Returning `None` means that the document is not backed by a file, and
+        that it should only ever be kept in memory. This may be used to support
+        remote or virtual workspace schemes that map to local files.
+
+        By default, only `file` URLs are resolved, using [`Url::to_file_path`].
+    */
+    #[must_use]
+    pub fn with_path_resolver<F>(mut self, resolver: F) -> Self
+    where
+        F: Fn(&Url) -> Option<PathBuf> + Send + Sync + 'static,
+    {
+        self.path_resolver = Some(PathResolver(Arc::new(resolver)));
+        self
+    }
+}
+
+impl fmt::Debug for ServerOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("ServerOptions");
+        f.field("workspace_diagnostics", &self.workspace_diagnostics)
+            .field("request_timeout", &self.request_timeout)
+            .field("content_modified_retries", &self.content_modified_retries)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("metrics", &self.metrics.is_some())
+            .field("workspace_indexing", &self.workspace_indexing)
+            .field("document_history", &self.document_history)
+            .field("full_text_sync", &self.full_text_sync)
+            .field("exclude_save_text", &self.exclude_save_text)
+            .field("save_reads_from_disk", &self.save_reads_from_disk)
+            .field("track_watched_files", &self.track_watched_files)
+            .field("sync_recovery", &self.sync_recovery)
+            .field("path_resolver", &self.path_resolver.is_some())
+            .field("parse_limit", &self.parse_limit);
+        #[cfg(feature = "tree-sitter")]
+        f.field("syntax_diagnostics", &self.syntax_diagnostics)
+            .field("background_parsing", &self.background_parsing)
+            .field("reparse_debounce", &self.reparse_debounce);
+        f.finish()
+    }
+}
+
+type PathResolverFn = dyn Fn(&Url) -> Option<PathBuf> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct PathResolver(Arc<PathResolverFn>);
+
+impl PathResolver {
+    pub(crate) fn resolve(&self, url: &Url) -> Option<PathBuf> {
+        (self.0)(url)
+    }
+}
+
+impl fmt::Debug for PathResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PathResolver")
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ParseLimit {
+    max_bytes: Option<usize>,
+    max_lines: Option<usize>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl ParseLimit {
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) fn is_exceeded_by(self, text: &Rope) -> bool {
+        self.max_bytes.is_some_and(|max| text.len_bytes() > max)
+            || self.max_lines.is_some_and(|max| text.len_lines() > max)
+    }
+}
+
+/**
+    Controls how documents are recovered when an incremental
+    update from the client could not be applied to them.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SyncRecovery {
+    /**
+        Keep the changes that could be applied, and mark the document as stale.
+
+        See [`Document::is_stale`] for more information.
+
+        [`Document::is_stale`]: crate::server::Document::is_stale
+    */
+    #[default]
+    MarkStale,
+    /**
+        Mark the document as stale, just like [`SyncRecovery::MarkStale`], and ask
+        the client to send the full contents of the document on its next change.
+
+        The client is asked by registering full document sync for only this
+        document, which requires the client to support dynamic registration.
+        Until the full contents arrive, every change to the document is reported
+        using [`Server::document_sync_failed`], since it can not be trusted.
+
+        [`Server::document_sync_failed`]: crate::server::Server::document_sync_failed
+    */
+    Resync,
+    /**
+        Re-read the document from disk, falling back to [`SyncRecovery::MarkStale`]
+        for documents that are not backed by a file.
+
+        Note that this may diverge from the contents in the client,
+        if the document has unsaved changes.
+    */
+    ReadFromDisk,
+    /**
+        Stop tracking the document, until the client opens it again
+        and sends its full contents as part of the open notification.
+
+        Note that clients usually have no reason to open a document
+        again, until the user closes and re-opens it in the editor.
+    */
+    Discard,
 }
 
 /**
@@ -195,6 +629,31 @@ mod tests {
         assert_eq!(key.value(&serde_json::json!(true)), Some(true));
     }
 
+    #[test]
+    #[cfg(feature = "tree-sitter")]
+    fn parse_limits_apply_to_bytes_and_lines() {
+        use super::ServerOptions;
+
+        let text = ropey::Rope::from_str("one\ntwo\nthree");
+
+        let limit = ServerOptions::default().parse_limit;
+        assert!(!limit.is_exceeded_by(&text));
+
+        let limit = ServerOptions::default()
+            .with_max_parse_bytes(13)
+            .parse_limit;
+        assert!(!limit.is_exceeded_by(&text));
+        let limit = ServerOptions::default()
+            .with_max_parse_bytes(12)
+            .parse_limit;
+        assert!(limit.is_exceeded_by(&text));
+
+        let limit = ServerOptions::default().with_max_parse_lines(3).parse_limit;
+        assert!(!limit.is_exceeded_by(&text));
+        let limit = ServerOptions::default().with_max_parse_lines(2).parse_limit;
+        assert!(limit.is_exceeded_by(&text));
+    }
+
     #[test]
     fn configuration_key_reads_section_path_settings() {
         let key = ConfigurationKey::new("test").with_path(["workspaceDiagnostics", "enabled"]);
@@ -1,32 +1,79 @@
 #![allow(clippy::needless_pass_by_value)]
 #![allow(clippy::too_many_lines)]
 
-use std::{collections::HashSet, ops::ControlFlow, path::PathBuf, sync::Arc};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use async_lsp::{
     ClientSocket, Result,
     lsp_types::{
-        DidChangeTextDocumentParams, DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams,
-        DidOpenTextDocumentParams, DidSaveTextDocumentParams, Url, WorkspaceFolder,
+        ApplyWorkspaceEditParams, ApplyWorkspaceEditResponse, ClientCapabilities, ClientInfo,
+        ConfigurationItem, ConfigurationParams, Diagnostic, DidChangeTextDocumentParams,
+        DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+        DidSaveTextDocumentParams, DocumentChangeOperation, DocumentChanges, DocumentFilter,
+        FileChangeType, LogMessageParams, MessageActionItem, MessageType, NumberOrString, OneOf,
+        ProgressToken, Range, Registration, RegistrationParams, ShowDocumentParams,
+        ShowMessageParams, ShowMessageRequestParams, TextDocumentChangeRegistrationOptions,
+        TextDocumentEdit, TextEdit, Unregistration, UnregistrationParams, Url,
+        WorkDoneProgressCancelParams, WorkDoneProgressCreateParams, WorkspaceClientCapabilities,
+        WorkspaceEdit, WorkspaceFolder,
+        notification::{LogMessage, ShowMessage},
+        request::{
+            ApplyWorkspaceEdit, InlayHintRefreshRequest, InlineValueRefreshRequest,
+            RegisterCapability, Request as LspRequest, SemanticTokensRefresh, ShowDocument,
+            ShowMessageRequest, UnregisterCapability, WorkDoneProgressCreate,
+            WorkspaceConfiguration, WorkspaceDiagnosticRefresh,
+        },
     },
 };
 use dashmap::DashMap;
 use ropey::Rope;
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "tree-sitter")]
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
 
 #[cfg(feature = "tree-sitter")]
-use tree_sitter::{InputEdit, Parser, Point};
+use futures::future::BoxFuture;
+#[cfg(feature = "tree-sitter")]
+use tree_sitter::{InputEdit, Language, ParseOptions, ParseState, Parser, Point, Tree};
 
 use crate::{
-    document::Document,
-    document_matcher::DocumentMatchers,
-    result::ServerResult,
+    cancellation::CancellationToken,
+    custom_notifications::CustomNotification,
+    diagnostic_results::DiagnosticResults,
+    diagnostics_publisher::{DiagnosticsPublisher, PublishedDiagnostics},
+    document::{Document, DocumentChangeEvent, DocumentExtensions, DocumentInner},
+    document_matcher::{DocumentMatcher, DocumentMatchers},
+    document_tasks::DocumentTasks,
+    progress::Progress,
+    result::{ServerError, ServerResult},
     server::Server,
-    server_options::ServerOptions,
-    text_utils::{Encoding, position_to_encoding},
+    server_options::{PathResolver, ServerOptions, SyncRecovery},
+    text_utils::{Encoding, position_to_encoding, read_text_file},
     workspace_diagnostics::WorkspaceDiagnosticsState,
+    workspace_index::WorkspaceIndex,
     workspace_walker::{WorkspaceWalkConfig, WorkspaceWalker, path_to_url},
 };
 
+#[cfg(feature = "tree-sitter")]
+use crate::{
+    document::QueryCache, server_options::ParseLimit, syntax_diagnostics::SyntaxDiagnostics,
+    tree_sitter_utils::parse_rope_with_options,
+};
+
 /**
     Managed state for an LSP server.
 
@@ -39,15 +86,74 @@ pub struct ServerState {
     documents: Arc<DashMap<Url, DocumentEntry>>,
     workspace_roots: Arc<DashMap<Url, PathBuf>>,
     workspace_diagnostics: WorkspaceDiagnosticsState,
+    progress_cancellations: Arc<DashMap<ProgressToken, CancellationToken>>,
+    progress_counter: Arc<AtomicU64>,
+    published_diagnostics: PublishedDiagnostics,
+    diagnostic_results: DiagnosticResults,
+    document_tasks: DocumentTasks,
+    request_cancellation: Option<CancellationToken>,
     #[allow(dead_code)]
     matchers: DocumentMatchers,
     encoding: Arc<Encoding>,
+    client_capabilities: Arc<ClientCapabilities>,
+    client_info: Option<Arc<ClientInfo>>,
+    document_history: usize,
+    sync_recovery: SyncRecovery,
+    resyncing: Arc<DashMap<Url, bool>>,
+    save_reads_from_disk: bool,
+    track_watched_files: bool,
+    workspace_index: WorkspaceIndex,
+    path_resolver: Option<PathResolver>,
+    #[cfg(feature = "tree-sitter")]
+    parse_limit: ParseLimit,
+    #[cfg(feature = "tree-sitter")]
+    syntax_diagnostics: Option<Arc<SyntaxDiagnostics>>,
+    #[cfg(feature = "tree-sitter")]
+    background_parsing: bool,
+    #[cfg(feature = "tree-sitter")]
+    reparse_debounce: Option<Duration>,
+    #[cfg(feature = "tree-sitter")]
+    parse_counter: Arc<AtomicU64>,
+    #[cfg(feature = "tree-sitter")]
+    parse_timeout_hook: Option<ParseTimeoutHook>,
+}
+
+#[cfg(feature = "tree-sitter")]
+type ParseTimeoutHookFn = dyn Fn(ServerState, Url) -> BoxFuture<'static, ()> + Send + Sync;
+
+/**
+    Runs [`Server::document_parse_timed_out`] for the server, type-erased
+    so that it can be stored in the state and used from any thread.
+*/
+#[cfg(feature = "tree-sitter")]
+#[derive(Clone)]
+struct ParseTimeoutHook(Arc<ParseTimeoutHookFn>);
+
+#[cfg(feature = "tree-sitter")]
+impl fmt::Debug for ParseTimeoutHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ParseTimeoutHook")
+    }
+}
+
+/**
+    The outcome of applying a change from the client to a tracked document.
+*/
+#[derive(Debug, Clone)]
+pub(crate) struct DocumentChange {
+    pub(crate) event: Option<DocumentChangeEvent>,
+    pub(crate) sync_failed: bool,
 }
 
 #[derive(Debug, Clone)]
 struct DocumentEntry {
     document: Document,
     origin: DocumentOrigin,
+    history: VecDeque<Document>,
+    // NOTE: The document as it was before any edits were applied locally,
+    // which is restored as soon as the client sends its next change, since
+    // any changes made by the client are relative to its own contents
+    local_edit_base: Option<Document>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -67,6 +173,39 @@ impl ServerState {
         self.client.clone()
     }
 
+    /**
+        Gets the capabilities of the connected client, as sent during initialization.
+
+        Can be used to check if the client supports snippets, markdown,
+        and other optional features, before using them in responses.
+
+        Returns empty capabilities if the server has not yet been initialized.
+    */
+    #[must_use]
+    pub fn client_capabilities(&self) -> &ClientCapabilities {
+        &self.client_capabilities
+    }
+
+    /**
+        Gets the name and version of the connected client, if it was sent during initialization.
+    */
+    #[must_use]
+    pub fn client_info(&self) -> Option<&ClientInfo> {
+        self.client_info.as_deref()
+    }
+
+    /**
+        Gets the position encoding negotiated with the connected client.
+
+        Positions in requests and responses handled by this library are converted
+        automatically, but any positions sent in custom messages, or in responses
+        that are not converted, such as semantic tokens, must use this encoding.
+    */
+    #[must_use]
+    pub fn position_encoding(&self) -> Encoding {
+        *self.encoding
+    }
+
     /**
         Gets a snapshot of a document by its URL.
 
@@ -83,6 +222,94 @@ impl ServerState {
         Some(entry.document.clone())
     }
 
+    /**
+        Gets a snapshot of a document by its URL, at the given version.
+
+        Previous versions of documents are only kept if enabled using
+        [`ServerOptions::with_document_history`], and only for as many
+        versions as configured - this may be used to compare against the
+        contents of a document that a request was originally issued for.
+
+        Returns `None` if the document is not found, or
+        if the given version is no longer available.
+    */
+    #[must_use]
+    pub fn document_at_version(&self, url: &Url, version: i32) -> Option<Document> {
+        let entry = self.documents.get(url)?;
+        if entry.document.version() == version {
+            return Some(entry.document.clone());
+        }
+        entry
+            .history
+            .iter()
+            .rev()
+            .find(|document| document.version() == version)
+            .cloned()
+    }
+
+    /**
+        Attaches a value to a tracked document, replacing and returning
+        any previous value of the same type that was attached to it.
+
+        This may be used to store the results of analyzing a document, such
+        as scopes or imports, and have them removed automatically whenever
+        the document is closed or otherwise stops being tracked. Attached
+        values are kept while the document is edited, and are shared with
+        all of its snapshots, which includes snapshots taken previously.
+
+        Does nothing and returns `None` if the document is not tracked.
+    */
+    pub fn set_document_data<T: Any + Send + Sync>(&self, url: &Url, value: T) -> Option<Arc<T>> {
+        let entry = self.documents.get(url)?;
+        entry.document.inner.extensions.insert(value)
+    }
+
+    /**
+        Gets a value of the given type that was attached to a tracked document.
+
+        See [`ServerState::set_document_data`] for more information.
+    */
+    #[must_use]
+    pub fn document_data<T: Any + Send + Sync>(&self, url: &Url) -> Option<Arc<T>> {
+        let entry = self.documents.get(url)?;
+        entry.document.inner.extensions.get()
+    }
+
+    /**
+        Removes and returns a value of the given type that was attached to a tracked document.
+
+        See [`ServerState::set_document_data`] for more information.
+    */
+    #[allow(clippy::must_use_candidate)]
+    pub fn remove_document_data<T: Any + Send + Sync>(&self, url: &Url) -> Option<Arc<T>> {
+        let entry = self.documents.get(url)?;
+        entry.document.inner.extensions.remove()
+    }
+
+    /**
+        Changes the language of a tracked document, and returns the updated document.
+
+        The document is matched again using its new language, and is fully
+        re-parsed using the tree-sitter grammar of the new matcher, if any.
+        This is useful for clients that let users change the language mode
+        of a document after it was opened, which does not notify the server.
+
+        Does nothing and returns `None` if the document is not tracked.
+    */
+    pub fn set_document_language(
+        &self,
+        url: &Url,
+        language_id: impl Into<String>,
+    ) -> Option<Document> {
+        let mut entry = self.documents.get_mut(url)?;
+        let language = language_id.into();
+        if entry.document.inner.language != language {
+            entry.document.inner_mut().language = language;
+            self.rematch_document(entry.document.inner_mut());
+        }
+        Some(entry.document.clone())
+    }
+
     /**
         Gets snapshots of all documents currently tracked by the server.
 
@@ -96,587 +323,2747 @@ impl ServerState {
             .map(|entry| entry.document.clone())
             .collect()
     }
-}
 
-// Private implementation
+    /**
+        Gets snapshots of all documents currently tracked by the server,
+        which were matched by the [`DocumentMatcher`] with the given name.
 
-impl ServerState {
-    #[allow(dead_code)]
-    pub(crate) fn new<T: Server>(client: ClientSocket) -> Self {
-        Self::with_options::<T>(client, ServerOptions::default())
-    }
+        See [`Document::matched_name`] for more information.
 
-    pub(crate) fn with_options<T: Server>(client: ClientSocket, options: ServerOptions) -> Self {
-        let documents = Arc::new(DashMap::new());
-        let workspace_roots = Arc::new(DashMap::new());
-        let workspace_diagnostics = WorkspaceDiagnosticsState::new(&options);
-        let matchers = DocumentMatchers::new(T::server_document_matchers());
-        let encoding = Arc::new(Encoding::default());
-        Self {
-            client,
-            documents,
-            workspace_roots,
-            workspace_diagnostics,
-            matchers,
-            encoding,
-        }
+        [`DocumentMatcher`]: crate::server::DocumentMatcher
+    */
+    #[must_use]
+    pub fn documents_matching(&self, matcher_name: &str) -> Vec<Document> {
+        self.documents
+            .iter()
+            .filter(|entry| entry.document.matched_name() == Some(matcher_name))
+            .map(|entry| entry.document.clone())
+            .collect()
     }
 
-    #[allow(clippy::extra_unused_type_parameters)]
-    fn insert_document<T: Server>(
-        &self,
-        url: Url,
-        text: String,
-        version: i32,
-        language: String,
-        origin: DocumentOrigin,
-    ) {
-        #[cfg(feature = "tree-sitter")]
-        let mut tree_sitter_lang = self
-            .matchers
-            .find(&url, language.as_str())
-            .and_then(|m| m.lang_grammar.clone());
+    /**
+        Resolves the path on disk for a document URL.
 
-        #[cfg(feature = "tree-sitter")]
-        let tree_sitter_tree = if let Some(lang) = tree_sitter_lang.as_ref() {
-            let mut parser = Parser::new();
-            if parser.set_language(lang).is_ok() {
-                parser.parse(&text, None)
-            } else {
-                tree_sitter_lang.take();
-                None
-            }
+        Uses the path resolver set using [`ServerOptions::with_path_resolver`],
+        or [`Url::to_file_path`] for `file` URLs if no resolver was set.
+
+        Returns `None` if the document is not backed by a file.
+    */
+    #[must_use]
+    pub fn file_path(&self, url: &Url) -> Option<PathBuf> {
+        if let Some(resolver) = &self.path_resolver {
+            resolver.resolve(url)
+        } else if url.scheme() == "file" {
+            url.to_file_path().ok()
         } else {
             None
-        };
+        }
+    }
 
-        let matcher = self.matchers.find(&url, &language);
+    /**
+        Gets the path of a document URL relative to the workspace folder that contains it.
 
-        self.documents.insert(
-            url.clone(),
-            DocumentEntry {
-                document: Document {
-                    uri: url,
-                    text: Rope::from(text),
-                    version,
-                    language,
-                    matcher,
-                    #[cfg(feature = "tree-sitter")]
-                    tree_sitter_lang,
-                    #[cfg(feature = "tree-sitter")]
-                    tree_sitter_tree,
-                },
-                origin,
-            },
-        );
-    }
+        When workspace folders are nested, the innermost folder is used.
 
-    pub(crate) fn set_workspace_folders(&self, folders: impl IntoIterator<Item = WorkspaceFolder>) {
-        self.workspace_roots.clear();
+        Returns `None` if the document is not backed by a file,
+        or if it is not inside of any of the workspace folders.
+    */
+    #[must_use]
+    pub fn relativize(&self, url: &Url) -> Option<PathBuf> {
+        let path = self.file_path(url)?;
+        let roots = self.workspace_roots();
 
-        for folder in folders {
-            if let Some(path) = workspace_folder_path(&folder) {
-                self.workspace_roots.insert(folder.uri, path);
-            }
-        }
+        let strip = |path: &Path| {
+            roots
+                .iter()
+                .filter_map(|root| path.strip_prefix(root).ok())
+                .min_by_key(|relative| relative.components().count())
+                .map(Path::to_path_buf)
+        };
+
+        // NOTE: Workspace folders are canonicalized, but document URLs
+        // may not be, so we also try again using the canonical path
+        strip(&path).or_else(|| strip(&std::fs::canonicalize(&path).ok()?))
     }
 
-    pub(crate) fn handle_workspace_folders_change(
-        &self,
-        params: DidChangeWorkspaceFoldersParams,
-    ) -> ControlFlow<Result<()>> {
-        let removed_roots: Vec<_> = params
-            .event
-            .removed
-            .iter()
-            .filter_map(workspace_folder_path)
-            .collect();
+    /**
+        Resolves a path relative to the workspace folders into a file URL.
 
-        for folder in params.event.removed {
-            self.workspace_roots.remove(&folder.uri);
-        }
-        self.remove_workspace_documents_in_roots(&removed_roots);
+        When there are multiple workspace folders, the first folder where
+        the path exists on disk is used, falling back to the first folder.
+        Absolute paths are converted directly, without using any folders.
 
-        for folder in params.event.added {
-            if let Some(path) = workspace_folder_path(&folder) {
-                self.workspace_roots.insert(folder.uri, path);
-            }
+        Returns `None` if there are no workspace folders to resolve against.
+    */
+    #[must_use]
+    pub fn resolve(&self, relative_path: impl AsRef<Path>) -> Option<Url> {
+        let relative_path = relative_path.as_ref();
+        if relative_path.is_absolute() {
+            return Url::from_file_path(relative_path).ok();
         }
 
-        ControlFlow::Continue(())
-    }
-
-    pub(crate) fn workspace_roots(&self) -> Vec<PathBuf> {
-        let mut roots: Vec<_> = self
-            .workspace_roots
-            .iter()
-            .map(|root| root.value().clone())
+        let candidates: Vec<_> = self
+            .workspace_roots()
+            .into_iter()
+            .map(|root| root.join(relative_path))
             .collect();
-        roots.sort();
-        roots
-    }
-
-    pub(crate) fn document_urls(&self) -> Vec<Url> {
-        let mut urls: Vec<_> = self
-            .documents
+        let path = candidates
             .iter()
-            .map(|entry| entry.document.uri.clone())
-            .collect();
-        urls.sort();
-        urls
-    }
-
-    pub(crate) fn document_workspace_version(&self, url: &Url) -> Option<i64> {
-        let entry = self.documents.get(url)?;
-        match entry.origin {
-            DocumentOrigin::Open => Some(i64::from(entry.document.version())),
-            DocumentOrigin::Workspace => None,
-        }
-    }
+            .find(|path| path.exists())
+            .or_else(|| candidates.first())?;
 
-    pub(crate) fn workspace_diagnostics(&self) -> WorkspaceDiagnosticsState {
-        self.workspace_diagnostics.clone()
+        Url::from_file_path(path).ok()
     }
 
-    pub(crate) fn set_workspace_diagnostics_enabled(&self, enabled: bool) -> bool {
-        let changed = self.workspace_diagnostics.set_enabled(enabled);
-        if changed && !enabled {
-            self.remove_workspace_documents();
-        }
-        changed
-    }
+    /**
+        Gets a snapshot of a document by its URL, reading it from disk
+        if it is not already tracked by the server.
 
-    pub(crate) fn refresh_workspace_documents<T: Server>(&self) -> ServerResult<Vec<Url>> {
-        if !self.workspace_diagnostics.enabled() {
-            return Ok(self.document_urls());
-        }
+        Documents read from disk are matched and parsed just like documents
+        opened by the client, and are tracked until the client opens them,
+        at which point the contents sent by the client take precedence.
 
-        let roots = self.workspace_roots();
-        if roots.is_empty() {
-            return Ok(self.document_urls());
-        }
+        This is useful for features such as goto definition, which
+        may need to look into files the client has not yet opened.
 
-        let walker = WorkspaceWalker::new(&roots, WorkspaceWalkConfig::default())?;
-        let mut urls = Vec::new();
+        # Errors
 
-        for path in walker.files()? {
-            let uri = path_to_url(&path)?;
-            let Some(matcher) = self.matchers.find_url(&uri) else {
-                continue;
-            };
+        - If the URL is not a valid file path.
+        - If the file could not be read.
+    */
+    pub async fn open_from_disk(&self, url: &Url) -> ServerResult<Document> {
+        if let Some(document) = self.document(url) {
+            return Ok(document);
+        }
 
-            urls.push(uri.clone());
-            if self
-                .documents
-                .get(&uri)
-                .is_some_and(|entry| entry.origin == DocumentOrigin::Open)
-            {
-                continue;
-            }
+        let path = self
+            .file_path(url)
+            .ok_or_else(|| ServerError::unknown(format!("URL is not a file path: {url}")))?;
+        let text = if tokio::runtime::Handle::try_current().is_ok() {
+            tokio::task::spawn_blocking(move || read_text_file(path))
+                .await
+                .map_err(ServerError::unknown)??
+        } else {
+            read_text_file(path)?
+        };
 
-            let language = matcher
-                .lang_strings
-                .first()
-                .cloned()
-                .unwrap_or_else(|| matcher.name.to_ascii_lowercase());
-            let text = std::fs::read_to_string(&path)?;
-            self.insert_document::<T>(uri, text, 0, language, DocumentOrigin::Workspace);
+        // NOTE: The client may have opened the document while we were
+        // reading it, in which case its contents should be used instead
+        if let Some(document) = self.document(url) {
+            return Ok(document);
         }
 
-        let urls: HashSet<_> = urls.into_iter().collect();
-        self.documents.retain(|url, entry| {
-            entry.origin == DocumentOrigin::Open
-                || !url_is_in_roots(url, &roots)
-                || urls.contains(url)
-        });
+        let language = self.url_language(url).unwrap_or_default();
+        self.insert_document(url.clone(), text, 0, language, DocumentOrigin::Workspace);
 
-        let mut urls: Vec<_> = urls.into_iter().collect();
-        urls.sort();
-        Ok(urls)
+        self.document(url)
+            .ok_or_else(|| ServerError::unknown(format!("Document was removed: {url}")))
     }
 
-    fn remove_workspace_documents(&self) {
-        self.documents
-            .retain(|_, entry| entry.origin == DocumentOrigin::Open);
+    /**
+        Gets the index of all documents in the workspace folders of the client.
+
+        See [`WorkspaceIndex`] for more information.
+    */
+    #[must_use]
+    pub fn workspace_index(&self) -> &WorkspaceIndex {
+        &self.workspace_index
     }
 
-    fn remove_workspace_documents_in_roots(&self, roots: &[PathBuf]) {
-        if roots.is_empty() {
-            return;
-        }
+    /**
+        Gets the cancellation token for the request currently being handled.
 
-        self.documents.retain(|url, entry| {
-            entry.origin == DocumentOrigin::Open || !url_is_in_roots(url, roots)
-        });
+        The token is cancelled when the client sends a `$/cancelRequest`
+        notification for the request, and may be used to stop expensive
+        work early - any result returned after the request has been
+        cancelled will be discarded and replaced with an error.
+
+        Returns a token that is never cancelled if this state
+        was not passed to a request handler by the server.
+    */
+    #[must_use]
+    pub fn current_cancellation(&self) -> CancellationToken {
+        self.request_cancellation.clone().unwrap_or_default()
     }
 
-    pub(crate) fn get_position_encoding(&self) -> Encoding {
-        *self.encoding
+    /**
+        Gets a cancellation handle for the given work done progress token.
+
+        The returned token is cancelled when the client sends a
+        `window/workDoneProgress/cancel` notification for the progress
+        token, and may be used to abort long-running operations early.
+
+        Calling this method several times with the same progress
+        token returns handles sharing the same cancellation state.
+        Once the work has finished, the progress token should be
+        released using [`ServerState::release_progress_cancellation`].
+    */
+    #[must_use]
+    pub fn progress_cancellation(&self, token: &ProgressToken) -> CancellationToken {
+        self.progress_cancellations
+            .entry(token.clone())
+            .or_default()
+            .clone()
     }
 
-    pub(crate) fn set_position_encoding(&mut self, kind: impl Into<Encoding>) {
-        self.encoding = Arc::new(kind.into());
+    /**
+        Spawns a background task for the document at the given URL.
+
+        The task is given a snapshot of the document, and will be cancelled
+        automatically if the document changes or is closed before it finishes.
+
+        Returns a token that may be used to cancel the task manually, or `None` if
+        the document is not tracked, or if there is no tokio runtime to spawn on.
+    */
+    pub fn spawn_for_document<F, Fut>(&self, url: Url, task: F) -> Option<CancellationToken>
+    where
+        F: Fn(Document) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        crate::document_tasks::spawn(self.clone(), url, false, task)
     }
 
-    pub(crate) fn handle_document_open<T: Server>(
-        &mut self,
-        params: DidOpenTextDocumentParams,
-    ) -> ControlFlow<Result<()>> {
-        self.insert_document::<T>(
-            params.text_document.uri,
-            params.text_document.text,
-            params.text_document.version,
-            params.text_document.language_id,
-            DocumentOrigin::Open,
-        );
+    /**
+        Spawns a background task for the document at the given URL, just like
+        [`ServerState::spawn_for_document`], but instead of only cancelling the task
+        when the document changes, it is also restarted using the latest snapshot.
 
-        ControlFlow::Continue(())
+        The task stops restarting once it finishes, once the document is no
+        longer tracked, or once the returned token has been cancelled.
+    */
+    pub fn spawn_for_document_restarting<F, Fut>(
+        &self,
+        url: Url,
+        task: F,
+    ) -> Option<CancellationToken>
+    where
+        F: Fn(Document) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        crate::document_tasks::spawn(self.clone(), url, true, task)
     }
 
-    pub(crate) fn handle_document_close<T: Server>(
+    /**
+        Stops tracking cancellation for the given work done progress token.
+
+        Any handles previously returned by [`ServerState::progress_cancellation`]
+        will no longer be cancelled by the client for this progress token.
+    */
+    pub fn release_progress_cancellation(&self, token: &ProgressToken) {
+        self.progress_cancellations.remove(token);
+    }
+}
+
+// Client requests & notifications
+
+impl ServerState {
+    /**
+        Gets a publisher for push diagnostics.
+
+        See [`DiagnosticsPublisher`] for more information.
+    */
+    #[must_use]
+    pub fn diagnostics(&self) -> DiagnosticsPublisher {
+        DiagnosticsPublisher::new(self.clone())
+    }
+
+    /**
+        Shows an informational message to the user.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn show_info(&self, message: impl Into<String>) -> ServerResult<()> {
+        self.show_message(MessageType::INFO, message.into())
+    }
+
+    /**
+        Shows a warning message to the user.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn show_warning(&self, message: impl Into<String>) -> ServerResult<()> {
+        self.show_message(MessageType::WARNING, message.into())
+    }
+
+    /**
+        Shows an error message to the user.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn show_error(&self, message: impl Into<String>) -> ServerResult<()> {
+        self.show_message(MessageType::ERROR, message.into())
+    }
+
+    /**
+        Asks the client to log a message, typically to an output
+        panel, without explicitly showing it to the user.
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn log_message(&self, typ: MessageType, message: impl Into<String>) -> ServerResult<()> {
+        self.client.notify::<LogMessage>(LogMessageParams {
+            typ,
+            message: message.into(),
+        })?;
+        Ok(())
+    }
+
+    /**
+        Shows a message to the user, along with the given actions to choose from.
+
+        Returns the title of the action that the user picked,
+        or `None` if the message was dismissed without picking one.
+
+        # Errors
+
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn show_message_request<I, A>(
         &self,
-        params: DidCloseTextDocumentParams,
-    ) -> ControlFlow<Result<()>> {
-        let url = params.text_document.uri;
-        let Some(entry) = self.documents.get(&url) else {
-            return ControlFlow::Continue(());
-        };
+        typ: MessageType,
+        message: impl Into<String>,
+        actions: I,
+    ) -> ServerResult<Option<String>>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        let actions = actions
+            .into_iter()
+            .map(|title| MessageActionItem {
+                title: title.into(),
+                properties: HashMap::new(),
+            })
+            .collect::<Vec<_>>();
+        let picked = self
+            .client
+            .request::<ShowMessageRequest>(ShowMessageRequestParams {
+                typ,
+                message: message.into(),
+                actions: Some(actions),
+            })
+            .await?;
+        Ok(picked.map(|action| action.title))
+    }
 
-        let language = entry.document.language.clone();
-        let roots = self.workspace_roots();
-        let keep_as_workspace = self.workspace_diagnostics.enabled()
-            && self.matchers.find_url(&url).is_some()
-            && url_is_in_roots(&url, &roots);
-        drop(entry);
+    /**
+        Asks the client to show the document at the given URL,
+        optionally selecting a range and taking focus.
 
-        if !keep_as_workspace {
-            self.documents.remove(&url);
-            return ControlFlow::Continue(());
-        }
+        The range should use UTF-8 offsets, just like all other positions
+        given to the server, and is converted to the negotiated position
+        encoding automatically if the document is tracked by the server.
 
-        if let Ok(text) = std::fs::read_to_string(url.path()) {
-            self.insert_document::<T>(url, text, 0, language, DocumentOrigin::Workspace);
-        } else {
-            self.documents.remove(&url);
-        }
+        Returns `true` if the client reported that it showed the document.
 
-        ControlFlow::Continue(())
+        # Errors
+
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn show_document(
+        &self,
+        url: Url,
+        range: Option<Range>,
+        take_focus: bool,
+    ) -> ServerResult<bool> {
+        let selection = range.map(|range| self.range_to_client_encoding(&url, range));
+        let result = self
+            .client
+            .request::<ShowDocument>(ShowDocumentParams {
+                uri: url,
+                external: None,
+                take_focus: Some(take_focus),
+                selection,
+            })
+            .await?;
+        Ok(result.success)
     }
 
-    pub(crate) fn handle_document_change<T: Server>(
-        &mut self,
-        params: DidChangeTextDocumentParams,
-    ) -> ControlFlow<Result<()>> {
-        let Some(mut entry) = self.documents.get_mut(&params.text_document.uri) else {
-            return ControlFlow::Continue(());
-        };
+    /**
+        Asks the client to refresh all semantic tokens it currently shows.
 
-        entry.origin = DocumentOrigin::Open;
-        let doc = &mut entry.document;
-        doc.version = params.text_document.version;
+        Returns `false` without sending anything if the client does not support
+        refreshing semantic tokens, otherwise `true` once the client responds.
 
-        let encoding = self.encoding.as_ref();
+        # Errors
 
-        // Try to perform an incremental update on the document contents, using the changes
-        let mut incremental_update_failed = false;
-        #[cfg(feature = "tree-sitter")]
-        let mut tree_sitter_incrementally_edited = false;
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn request_semantic_tokens_refresh(&self) -> ServerResult<bool> {
+        let supported = self
+            .workspace_client_capabilities()
+            .and_then(|w| w.semantic_tokens.as_ref())
+            .and_then(|s| s.refresh_support);
+        self.request_refresh::<SemanticTokensRefresh>(supported)
+            .await
+    }
 
-        for change in params.content_changes {
-            let Some(range) = change.range else {
-                doc.text = Rope::from_str(&change.text);
+    /**
+        Asks the client to refresh all inlay hints it currently shows.
 
-                #[cfg(feature = "tree-sitter")]
-                {
-                    let mut parser = doc_parser(doc);
-                    doc.tree_sitter_tree = parser
-                        .as_mut()
-                        .and_then(|parser| parser.parse(doc.text_contents(), None));
-                }
+        Returns `false` without sending anything if the client does not support
+        refreshing inlay hints, otherwise `true` once the client responds.
 
-                continue;
-            };
+        # Errors
 
-            // 1. Convert the LSP positions, using their arbitrary encoding,
-            //    to what Ropey expects to use for its incremental updates
-            let start_char_absolute = if let Ok(line_start_char_offset) =
-                doc.text.try_line_to_char(range.start.line as usize)
-            {
-                let start =
-                    position_to_encoding(doc.text(), range.start, encoding, Encoding::UTF32);
-                line_start_char_offset + start.character as usize
-            } else {
-                incremental_update_failed = true;
-                break;
-            };
-            let end_char_absolute = if let Ok(line_start_char_offset) =
-                doc.text.try_line_to_char(range.end.line as usize)
-            {
-                let end = position_to_encoding(doc.text(), range.end, encoding, Encoding::UTF32);
-                (line_start_char_offset + end.character as usize)
-                    .max(start_char_absolute)
-                    .min(doc.text.len_chars())
-            } else {
-                incremental_update_failed = true;
-                break;
-            };
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn request_inlay_hint_refresh(&self) -> ServerResult<bool> {
+        let supported = self
+            .workspace_client_capabilities()
+            .and_then(|w| w.inlay_hint.as_ref())
+            .and_then(|i| i.refresh_support);
+        self.request_refresh::<InlayHintRefreshRequest>(supported)
+            .await
+    }
 
-            // 3. Perform incremental edit on the syntax tree as well, if enabled
-            //    Note that we need to do this before updating the document contents
-            #[cfg(feature = "tree-sitter")]
-            if let Some(tree) = doc.tree_sitter_tree.as_mut() {
-                // Compute some byte offsets based on the yet-to-be-changed rope
-                let start_byte = doc.text.char_to_byte(start_char_absolute);
-                let old_end_byte = doc.text.char_to_byte(end_char_absolute);
-                let new_end_byte = start_byte + change.text.len();
+    /**
+        Asks the client to refresh all inline values it currently shows.
 
-                // Convert the start and old end positions to the correct encoding
-                let start_position =
-                    position_to_encoding(&doc.text, range.start, encoding, Encoding::UTF8);
-                let old_end_position =
-                    position_to_encoding(&doc.text, range.end, encoding, Encoding::UTF8);
+        Returns `false` without sending anything if the client does not support
+        refreshing inline values, otherwise `true` once the client responds.
 
-                // Compute the new end point based on the contents of the edit
-                let (new_end_row, new_end_col_bytes) = change.text.chars().fold(
-                    (
-                        start_position.line as usize,
-                        start_position.character as usize,
-                    ),
-                    |(row, col_bytes), ch| {
-                        if ch == '\n' {
-                            (row + 1, 0)
-                        } else {
-                            (row, col_bytes + ch.len_utf8())
-                        }
-                    },
-                );
+        # Errors
 
-                // Finally, apply the edit to incrementally update the syntax tree
-                tree.edit(&InputEdit {
-                    start_byte,
-                    old_end_byte,
-                    new_end_byte,
-                    start_position: Point {
-                        row: start_position.line as usize,
-                        column: start_position.character as usize,
-                    },
-                    old_end_position: Point {
-                        row: old_end_position.line as usize,
-                        column: old_end_position.character as usize,
-                    },
-                    new_end_position: Point {
-                        row: new_end_row,
-                        column: new_end_col_bytes,
-                    },
-                });
-                tree_sitter_incrementally_edited = true;
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn request_inline_value_refresh(&self) -> ServerResult<bool> {
+        let supported = self
+            .workspace_client_capabilities()
+            .and_then(|w| w.inline_value.as_ref())
+            .and_then(|i| i.refresh_support);
+        self.request_refresh::<InlineValueRefreshRequest>(supported)
+            .await
+    }
+
+    /**
+        Asks the client to pull diagnostics again, for both documents and the workspace.
+
+        Pulled diagnostics are reused for documents that have not changed since
+        they were last pulled, so this should be called whenever diagnostics may
+        change for other reasons, such as changes to configuration or to other files.
+
+        Returns `false` without sending anything if the client does not support
+        refreshing diagnostics, otherwise `true` once the client responds.
+
+        # Errors
+
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn request_diagnostics_refresh(&self) -> ServerResult<bool> {
+        self.diagnostic_results.clear();
+        let supported = self
+            .workspace_client_capabilities()
+            .and_then(|w| w.diagnostic.as_ref())
+            .and_then(|d| d.refresh_support);
+        self.request_refresh::<WorkspaceDiagnosticRefresh>(supported)
+            .await
+    }
+
+    fn workspace_client_capabilities(&self) -> Option<&WorkspaceClientCapabilities> {
+        self.client_capabilities.workspace.as_ref()
+    }
+
+    async fn request_refresh<R>(&self, supported: Option<bool>) -> ServerResult<bool>
+    where
+        R: LspRequest<Params = (), Result = ()>,
+    {
+        if !supported.unwrap_or(false) {
+            return Ok(false);
+        }
+        self.client.request::<R>(()).await?;
+        Ok(true)
+    }
+
+    /**
+        Asks the client to apply the given workspace edit.
+
+        All ranges in the edit should use UTF-8 offsets, just like all other
+        positions given to the server, and are converted to the negotiated
+        position encoding automatically for documents tracked by the server.
+
+        # Errors
+
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn apply_workspace_edit(
+        &self,
+        mut edit: WorkspaceEdit,
+    ) -> ServerResult<ApplyWorkspaceEditResponse> {
+        self.workspace_edit_to_client_encoding(&mut edit);
+        let response = self
+            .client
+            .request::<ApplyWorkspaceEdit>(ApplyWorkspaceEditParams { label: None, edit })
+            .await?;
+        Ok(response)
+    }
+
+    /**
+        Applies the text edits in the given workspace edit to the documents
+        tracked by the server, without involving the client.
+
+        This may be used to keep the state of the server consistent with
+        a workspace edit that it asked the client to apply, before the client
+        has applied it and sent its own changes back. Local edits are undone
+        when the client sends its next change for a document, since changes
+        from the client are always relative to the contents in the client.
+
+        All ranges in the edit should use UTF-8 offsets, just like for
+        [`ServerState::apply_workspace_edit`]. Edits for documents that are
+        not tracked, edits for other versions of documents, and any resource
+        operations such as creating or renaming files, are ignored.
+        Edits for a document are also ignored if any two of them overlap.
+    */
+    pub fn apply_edit_locally(&self, edit: WorkspaceEdit) {
+        let mut document_edits = HashMap::<Url, Vec<TextEdit>>::new();
+        let mut add_document_edit = |doc_edit: TextDocumentEdit| {
+            let url = doc_edit.text_document.uri;
+            let version = doc_edit.text_document.version;
+            if version.is_some() && self.document(&url).map(|d| d.version()) != version {
+                return;
             }
+            let edits = doc_edit.edits.into_iter().map(|edit| match edit {
+                OneOf::Left(edit) => edit,
+                OneOf::Right(edit) => edit.text_edit,
+            });
+            document_edits.entry(url).or_default().extend(edits);
+        };
 
-            // 4. Finally, try to incrementally update the document contents
-            if doc
-                .text
-                .try_remove(start_char_absolute..end_char_absolute)
-                .is_err()
-                || doc
-                    .text
-                    .try_insert(start_char_absolute, &change.text)
-                    .is_err()
-            {
-                incremental_update_failed = true;
-                break;
+        match edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits.into_iter().for_each(add_document_edit),
+            Some(DocumentChanges::Operations(ops)) => {
+                for op in ops {
+                    if let DocumentChangeOperation::Edit(doc_edit) = op {
+                        add_document_edit(doc_edit);
+                    }
+                }
+            }
+            None => {
+                for (url, edits) in edit.changes.unwrap_or_default() {
+                    document_edits.entry(url).or_default().extend(edits);
+                }
             }
         }
 
-        // If the incremental update was successful, and we applied edits to the syntax
-        // tree, we must finalize those changes by parsing using tree-sitter once again
-        #[cfg(feature = "tree-sitter")]
-        if !incremental_update_failed
-            && tree_sitter_incrementally_edited
-            && let Some(tree) = doc.tree_sitter_tree.as_ref()
-        {
-            let mut parser = doc_parser(doc).expect("has tree - must have parser");
-            let updated_tree = parser.parse(doc.text_contents(), Some(tree));
-            doc.tree_sitter_tree = updated_tree;
+        for (url, edits) in document_edits {
+            let Some(mut entry) = self.documents.get_mut(&url) else {
+                continue;
+            };
+            self.document_tasks.cancel(&url);
+
+            let base = entry.document.clone();
+            #[allow(unused_variables)]
+            if let Err(e) = entry.document.apply_text_edits(&edits) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Ignoring local edits for document {url}: {e}");
+                continue;
+            }
+            if entry.local_edit_base.is_none() {
+                entry.local_edit_base = Some(base);
+            }
+
+            #[cfg(feature = "tree-sitter")]
+            {
+                let doc = entry.document.inner_mut();
+                if doc.tree_sitter_tree.is_some() {
+                    if let Some((_, cancellation)) = doc.tree_sitter_pending.take() {
+                        cancellation.cancel();
+                    }
+                    doc.tree_sitter_tree = self.doc_tree(doc, doc.tree_sitter_tree.as_ref());
+                } else {
+                    self.reparse_document(doc);
+                }
+            }
         }
+    }
+
+    /**
+        Fetches the configuration section with the given name from the client,
+        optionally scoped to a specific resource, such as a workspace folder.
 
-        // If the incremental update failed, we will re-insert the entire file instead
-        // Note that we must first drop the document reference to prevent a deadlock
-        if incremental_update_failed {
-            let uri = doc.uri.clone();
-            let version = doc.version();
-            let language = doc.language.clone();
+        The configuration is deserialized into the given type `T` - if the
+        client has no value for the section, `T` is deserialized from `null`,
+        meaning `Option<T>` may be used for sections that are not always set.
+
+        # Errors
+
+        - If the client could not be reached, or responded with an error.
+        - If the configuration could not be deserialized into `T`.
+    */
+    pub async fn fetch_configuration<T: DeserializeOwned>(
+        &self,
+        section: impl Into<String>,
+        scope_uri: Option<Url>,
+    ) -> ServerResult<T> {
+        let mut values = self
+            .client
+            .request::<WorkspaceConfiguration>(ConfigurationParams {
+                items: vec![ConfigurationItem {
+                    scope_uri,
+                    section: Some(section.into()),
+                }],
+            })
+            .await?;
+        let value = if values.is_empty() {
+            serde_json::Value::Null
+        } else {
+            values.swap_remove(0)
+        };
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /**
+        Sends a custom notification to the client.
+
+        Positions in the notification should use UTF-8 offsets, and are converted
+        to the negotiated position encoding, as described by [`CustomNotification`].
+
+        # Errors
+
+        - If the client could not be reached.
+    */
+    pub fn notify_custom<N: CustomNotification>(&self, mut params: N::Params) -> ServerResult<()> {
+        crate::custom_notifications::convert_outgoing::<N>(self, &mut params);
+        self.client.notify::<N>(params)?;
+        Ok(())
+    }
+
+    /**
+        Asks the client to create a new work done progress with
+        the given title, and begins reporting progress using it.
+
+        The returned [`Progress`] handle may be used to report messages
+        and percentages, and to check if the client requested cancellation.
+
+        If the client does not support work done progress, nothing is sent, and
+        the returned handle does not report anything - see [`Progress::is_reported`].
+
+        # Errors
+
+        - If the client could not be reached, or responded with an error.
+    */
+    pub async fn create_progress(&self, title: impl Into<String>) -> ServerResult<Progress> {
+        let id = self.progress_counter.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("async-language-server/progress/{id}"));
+
+        let supported = self
+            .client_capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        if !supported {
+            return Ok(Progress::unreported(self.clone(), token));
+        }
+
+        self.client
+            .request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await?;
+
+        let progress = Progress::new(self.clone(), token);
+        progress.begin(title.into())?;
+        Ok(progress)
+    }
+}
+
+// Private implementation
+
+impl ServerState {
+    #[allow(dead_code)]
+    pub(crate) fn new<T: Server>(client: ClientSocket) -> Self {
+        Self::with_options::<T>(client, ServerOptions::default())
+    }
+
+    pub(crate) fn with_options<T: Server>(client: ClientSocket, options: ServerOptions) -> Self {
+        let documents = Arc::new(DashMap::new());
+        let workspace_roots = Arc::new(DashMap::new());
+        let workspace_diagnostics = WorkspaceDiagnosticsState::new(&options);
+        let progress_cancellations = Arc::new(DashMap::new());
+        let progress_counter = Arc::new(AtomicU64::new(0));
+        let matchers = DocumentMatchers::new(T::server_document_matchers());
+        let encoding = Arc::new(Encoding::default());
+        Self {
+            client,
+            documents,
+            workspace_roots,
+            workspace_diagnostics,
+            progress_cancellations,
+            progress_counter,
+            published_diagnostics: PublishedDiagnostics::default(),
+            diagnostic_results: DiagnosticResults::default(),
+            document_tasks: DocumentTasks::default(),
+            request_cancellation: None,
+            matchers,
+            encoding,
+            client_capabilities: Arc::new(ClientCapabilities::default()),
+            client_info: None,
+            document_history: options.document_history,
+            sync_recovery: options.sync_recovery,
+            resyncing: Arc::new(DashMap::new()),
+            save_reads_from_disk: options.save_reads_from_disk,
+            track_watched_files: options.track_watched_files,
+            workspace_index: WorkspaceIndex::default(),
+            path_resolver: options.path_resolver.clone(),
+            #[cfg(feature = "tree-sitter")]
+            parse_limit: options.parse_limit,
+            #[cfg(feature = "tree-sitter")]
+            syntax_diagnostics: options.syntax_diagnostics.clone().map(Arc::new),
+            #[cfg(feature = "tree-sitter")]
+            background_parsing: options.background_parsing,
+            #[cfg(feature = "tree-sitter")]
+            reparse_debounce: options.reparse_debounce,
+            #[cfg(feature = "tree-sitter")]
+            parse_counter: Arc::new(AtomicU64::new(0)),
+            #[cfg(feature = "tree-sitter")]
+            parse_timeout_hook: None,
+        }
+    }
+
+    fn insert_document(
+        &self,
+        url: Url,
+        text: String,
+        version: i32,
+        language: String,
+        origin: DocumentOrigin,
+    ) {
+        #[cfg_attr(not(feature = "tree-sitter"), allow(unused_mut))]
+        let mut document = self.match_document(url.clone(), text, version, language);
+
+        // NOTE: Documents parsed in the background must be tracked before
+        // parsing starts, so that the parsed tree can be attached to them
+        #[cfg(feature = "tree-sitter")]
+        if !self.background_parsing {
+            document.tree_sitter_tree = self.doc_tree(&document, None);
+        }
+
+        self.documents.insert(
+            url.clone(),
+            DocumentEntry {
+                document: document.into(),
+                origin,
+                history: VecDeque::new(),
+                local_edit_base: None,
+            },
+        );
+
+        #[cfg(feature = "tree-sitter")]
+        if self.background_parsing
+            && let Some(mut entry) = self.documents.get_mut(&url)
+        {
+            self.reparse_document(entry.document.inner_mut());
+        }
+    }
+
+    /**
+        Creates a new matched and parsed document, without tracking it.
+    */
+    pub(crate) fn create_document(
+        &self,
+        url: Url,
+        text: String,
+        version: i32,
+        language: String,
+    ) -> Document {
+        #[cfg_attr(not(feature = "tree-sitter"), allow(unused_mut))]
+        let mut document = self.match_document(url, text, version, language);
+        #[cfg(feature = "tree-sitter")]
+        {
+            document.tree_sitter_tree = self.doc_tree(&document, None);
+        }
+        document.into()
+    }
+
+    /**
+        Creates a new matched document, without parsing or tracking it.
+    */
+    fn match_document(
+        &self,
+        url: Url,
+        text: String,
+        version: i32,
+        language: String,
+    ) -> DocumentInner {
+        let matcher = self.matchers.find(&url, &language);
+        let read_only = self.matchers.is_read_only(matcher.as_ref(), &url);
+
+        #[cfg(feature = "tree-sitter")]
+        let tree_sitter_lang = self
+            .matchers
+            .lang_grammar(matcher.as_ref(), &url)
+            .filter(|lang| lang_parser(lang).is_some());
+
+        DocumentInner {
+            uri: url,
+            text: Rope::from_str(&text),
+            line_index: OnceLock::new(),
+            version,
+            language,
+            matcher,
+            stale: false,
+            read_only,
+            extensions: DocumentExtensions::default(),
+            #[cfg(feature = "tree-sitter")]
+            query_cache: QueryCache::default(),
+            #[cfg(feature = "tree-sitter")]
+            tree_sitter_lang,
+            #[cfg(feature = "tree-sitter")]
+            tree_sitter_tree: None,
+            #[cfg(feature = "tree-sitter")]
+            tree_sitter_pending: None,
+        }
+    }
+
+    /**
+        Returns `true` if the document at the given URL is read-only,
+        either as a tracked document or through its URL matcher.
+    */
+    pub(crate) fn url_is_read_only(&self, url: &Url) -> bool {
+        if let Some(entry) = self.documents.get(url) {
+            return entry.document.inner.read_only;
+        }
+        let matcher = self.matchers.find_url(url);
+        self.matchers.is_read_only(matcher.as_ref(), url)
+    }
+
+    /**
+        Gets the language for a document, if it has a matcher for its URL.
+    */
+    pub(crate) fn url_language(&self, url: &Url) -> Option<String> {
+        self.matchers
+            .find_url(url)
+            .map(|matcher| matcher_language(&matcher))
+    }
+
+    pub(crate) fn set_workspace_folders(&self, folders: impl IntoIterator<Item = WorkspaceFolder>) {
+        self.workspace_roots.clear();
+
+        for folder in folders {
+            if let Some(path) = workspace_folder_path(&folder) {
+                self.workspace_roots.insert(folder.uri, path);
+            }
+        }
+    }
+
+    pub(crate) fn handle_workspace_folders_change(
+        &self,
+        params: DidChangeWorkspaceFoldersParams,
+    ) -> ControlFlow<Result<()>> {
+        let removed_roots: Vec<_> = params
+            .event
+            .removed
+            .iter()
+            .filter_map(workspace_folder_path)
+            .collect();
+
+        for folder in params.event.removed {
+            self.workspace_roots.remove(&folder.uri);
+        }
+        self.remove_workspace_documents_in_roots(&removed_roots);
+
+        for folder in params.event.added {
+            if let Some(path) = workspace_folder_path(&folder) {
+                self.workspace_roots.insert(folder.uri, path);
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    pub(crate) fn workspace_roots(&self) -> Vec<PathBuf> {
+        let mut roots: Vec<_> = self
+            .workspace_roots
+            .iter()
+            .map(|root| root.value().clone())
+            .collect();
+        roots.sort();
+        roots
+    }
+
+    pub(crate) fn document_urls(&self) -> Vec<Url> {
+        let mut urls: Vec<_> = self
+            .documents
+            .iter()
+            .map(|entry| entry.document.inner.uri.clone())
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    /**
+        Reads the contents of the file backing a document.
+
+        Returns `None` for documents that are not backed by a file, such as
+        `untitled:` documents, which are only ever kept in memory.
+    */
+    fn read_document_file(&self, url: &Url) -> Option<String> {
+        let path = self.file_path(url)?;
+        read_text_file(path).ok()
+    }
+
+    pub(crate) fn document_is_open(&self, url: &Url) -> bool {
+        self.documents
+            .get(url)
+            .is_some_and(|entry| entry.origin == DocumentOrigin::Open)
+    }
+
+    pub(crate) fn watched_url_globs(&self) -> Vec<String> {
+        self.matchers.url_globs()
+    }
+
+    /**
+        Refreshes a document that is tracked by the server, but not opened
+        by the client, after the file for it was changed on disk.
+
+        Documents opened by the client are left as-is, since
+        the client is the source of truth for their contents.
+
+        Untracked files that were created or changed start being tracked
+        if [`ServerOptions::with_track_watched_files`] is enabled, and
+        they match a document matcher and are inside of the workspace.
+    */
+    pub(crate) fn handle_watched_file_change(&self, url: &Url, typ: FileChangeType) {
+        let language = if let Some(entry) = self.documents.get(url) {
+            if entry.origin == DocumentOrigin::Open {
+                return;
+            }
+            entry.document.inner.language.clone()
+        } else if self.track_watched_files
+            && typ != FileChangeType::DELETED
+            && url_is_in_roots(url, &self.workspace_roots())
+        {
+            let Some(language) = self.url_language(url) else {
+                return;
+            };
+            language
+        } else {
+            return;
+        };
+
+        let text = if typ == FileChangeType::DELETED {
+            None
+        } else {
+            self.read_document_file(url)
+        };
+
+        self.document_tasks.cancel(url);
+        if let Some(text) = text {
+            self.insert_document(url.clone(), text, 0, language, DocumentOrigin::Workspace);
+        } else {
+            self.documents.remove(url);
+        }
+    }
+
+    pub(crate) fn document_workspace_version(&self, url: &Url) -> Option<i64> {
+        let entry = self.documents.get(url)?;
+        match entry.origin {
+            DocumentOrigin::Open => Some(i64::from(entry.document.version())),
+            DocumentOrigin::Workspace => None,
+        }
+    }
+
+    pub(crate) fn workspace_diagnostics(&self) -> WorkspaceDiagnosticsState {
+        self.workspace_diagnostics.clone()
+    }
+
+    pub(crate) fn set_workspace_diagnostics_enabled(&self, enabled: bool) -> bool {
+        let changed = self.workspace_diagnostics.set_enabled(enabled);
+        if changed && !enabled {
+            self.remove_workspace_documents();
+        }
+        changed
+    }
+
+    #[allow(clippy::extra_unused_type_parameters)]
+    pub(crate) fn refresh_workspace_documents<T: Server>(&self) -> ServerResult<Vec<Url>> {
+        if !self.workspace_diagnostics.enabled() {
+            return Ok(self.document_urls());
+        }
+
+        let roots = self.workspace_roots();
+        if roots.is_empty() {
+            return Ok(self.document_urls());
+        }
+
+        let walker = WorkspaceWalker::new(&roots, WorkspaceWalkConfig::default())?;
+        let mut urls = Vec::new();
+
+        for path in walker.files()? {
+            let uri = path_to_url(&path)?;
+            let Some(matcher) = self.matchers.find_url(&uri) else {
+                continue;
+            };
+
+            urls.push(uri.clone());
+            if self
+                .documents
+                .get(&uri)
+                .is_some_and(|entry| entry.origin == DocumentOrigin::Open)
+            {
+                continue;
+            }
+
+            let language = matcher_language(&matcher);
+            let text = read_text_file(&path)?;
+            self.insert_document(uri, text, 0, language, DocumentOrigin::Workspace);
+        }
+
+        let urls: HashSet<_> = urls.into_iter().collect();
+        self.documents.retain(|url, entry| {
+            entry.origin == DocumentOrigin::Open
+                || !url_is_in_roots(url, &roots)
+                || urls.contains(url)
+        });
+
+        let mut urls: Vec<_> = urls.into_iter().collect();
+        urls.sort();
+        Ok(urls)
+    }
+
+    fn remove_workspace_documents(&self) {
+        self.documents
+            .retain(|_, entry| entry.origin == DocumentOrigin::Open);
+    }
+
+    fn remove_workspace_documents_in_roots(&self, roots: &[PathBuf]) {
+        if roots.is_empty() {
+            return;
+        }
+
+        self.documents.retain(|url, entry| {
+            entry.origin == DocumentOrigin::Open || !url_is_in_roots(url, roots)
+        });
+    }
+
+    pub(crate) fn handle_work_done_progress_cancel(
+        &self,
+        params: WorkDoneProgressCancelParams,
+    ) -> ControlFlow<Result<()>> {
+        if let Some((_, cancellation)) = self.progress_cancellations.remove(&params.token) {
+            cancellation.cancel();
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    pub(crate) fn get_position_encoding(&self) -> Encoding {
+        *self.encoding
+    }
+
+    pub(crate) fn set_position_encoding(&mut self, kind: impl Into<Encoding>) {
+        self.encoding = Arc::new(kind.into());
+    }
+
+    pub(crate) fn set_client_details(
+        &mut self,
+        capabilities: ClientCapabilities,
+        info: Option<ClientInfo>,
+    ) {
+        self.client_capabilities = Arc::new(capabilities);
+        self.client_info = info.map(Arc::new);
+    }
+
+    pub(crate) fn with_request_cancellation(mut self, token: CancellationToken) -> Self {
+        self.request_cancellation = Some(token);
+        self
+    }
+
+    pub(crate) fn range_to_client_encoding(&self, url: &Url, range: Range) -> Range {
+        let Some(entry) = self.documents.get(url) else {
+            return range;
+        };
+        let index = entry.document.line_index();
+        Range {
+            start: index.position_to_encoding(range.start, Encoding::UTF8, *self.encoding),
+            end: index.position_to_encoding(range.end, Encoding::UTF8, *self.encoding),
+        }
+    }
+
+    pub(crate) fn document_tasks(&self) -> &DocumentTasks {
+        &self.document_tasks
+    }
+
+    pub(crate) fn published_diagnostics(&self) -> &PublishedDiagnostics {
+        &self.published_diagnostics
+    }
+
+    pub(crate) fn diagnostic_results(&self) -> &DiagnosticResults {
+        &self.diagnostic_results
+    }
+
+    /**
+        Gets the syntax diagnostics for a document, or `None` if they are not enabled.
+    */
+    #[allow(clippy::unused_self, unused_variables)]
+    pub(crate) fn syntax_diagnostics(
+        &self,
+        document: Option<&Document>,
+    ) -> Option<Vec<Diagnostic>> {
+        #[cfg(feature = "tree-sitter")]
+        if let Some(options) = self.syntax_diagnostics.as_ref() {
+            return Some(document.map(|d| options.collect(d)).unwrap_or_default());
+        }
+        None
+    }
+
+    fn show_message(&self, typ: MessageType, message: String) -> ServerResult<()> {
+        self.client
+            .notify::<ShowMessage>(ShowMessageParams { typ, message })?;
+        Ok(())
+    }
+
+    pub(crate) fn workspace_edit_to_client_encoding(&self, edit: &mut WorkspaceEdit) {
+        if let Some(changes) = edit.changes.as_mut() {
+            for (url, edits) in changes.iter_mut() {
+                for edit in edits {
+                    edit.range = self.range_to_client_encoding(url, edit.range);
+                }
+            }
+        }
+
+        let convert_document_edit = |doc_edit: &mut TextDocumentEdit| {
+            let url = &doc_edit.text_document.uri;
+            for edit in &mut doc_edit.edits {
+                let range = match edit {
+                    OneOf::Left(edit) => &mut edit.range,
+                    OneOf::Right(edit) => &mut edit.text_edit.range,
+                };
+                *range = self.range_to_client_encoding(url, *range);
+            }
+        };
+
+        match edit.document_changes.as_mut() {
+            Some(DocumentChanges::Edits(edits)) => edits.iter_mut().for_each(convert_document_edit),
+            Some(DocumentChanges::Operations(ops)) => {
+                for op in ops {
+                    if let DocumentChangeOperation::Edit(doc_edit) = op {
+                        convert_document_edit(doc_edit);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    #[allow(clippy::extra_unused_type_parameters)]
+    pub(crate) fn handle_document_open<T: Server>(
+        &mut self,
+        params: DidOpenTextDocumentParams,
+    ) -> ControlFlow<Result<()>> {
+        self.document_tasks.cancel(&params.text_document.uri);
+        self.finish_resync(&params.text_document.uri);
+        self.insert_document(
+            params.text_document.uri,
+            params.text_document.text,
+            params.text_document.version,
+            params.text_document.language_id,
+            DocumentOrigin::Open,
+        );
+
+        ControlFlow::Continue(())
+    }
+
+    #[allow(clippy::extra_unused_type_parameters)]
+    pub(crate) fn handle_document_close<T: Server>(
+        &self,
+        params: DidCloseTextDocumentParams,
+    ) -> ControlFlow<Result<()>> {
+        let url = params.text_document.uri;
+        let _ = self.diagnostics().clear(&url);
+        self.diagnostic_results.remove(&url);
+        self.document_tasks.cancel(&url);
+        self.finish_resync(&url);
+
+        let Some(entry) = self.documents.get(&url) else {
+            return ControlFlow::Continue(());
+        };
+
+        let language = entry.document.inner.language.clone();
+        let roots = self.workspace_roots();
+        let keep_as_workspace = self.workspace_diagnostics.enabled()
+            && self.matchers.find_url(&url).is_some()
+            && url_is_in_roots(&url, &roots);
+        drop(entry);
+
+        if !keep_as_workspace {
+            self.documents.remove(&url);
+            return ControlFlow::Continue(());
+        }
+
+        if let Some(text) = self.read_document_file(&url) {
+            self.insert_document(url, text, 0, language, DocumentOrigin::Workspace);
+        } else {
+            self.documents.remove(&url);
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /**
+        Applies a change to a tracked document, returning a structured description
+        of the edits that were made, or `None` if the document is not tracked.
+    */
+    pub(crate) fn apply_document_change(
+        &mut self,
+        params: DidChangeTextDocumentParams,
+    ) -> Option<DocumentChange> {
+        let mut entry = self.documents.get_mut(&params.text_document.uri)?;
+        // NOTE: The document is locked until we drop the entry, so any background
+        // tasks for the document can not see the new version before it is ready
+        self.document_tasks.cancel(&params.text_document.uri);
+
+        entry.origin = DocumentOrigin::Open;
+        if let Some(base) = entry.local_edit_base.take() {
+            entry.document = base;
+        }
+        if self.document_history > 0 {
+            let previous = entry.document.clone();
+            entry.history.push_back(previous);
+            while entry.history.len() > self.document_history {
+                entry.history.pop_front();
+            }
+        }
+        let doc = entry.document.inner_mut();
+        let old_version = doc.version;
+        let was_stale = doc.stale;
+        doc.version = params.text_document.version;
+        let mut edits = Vec::with_capacity(params.content_changes.len());
+
+        let encoding = self.encoding.as_ref();
+
+        // Try to perform an incremental update on the document contents, using the changes
+        let mut incremental_update_failed = false;
+        #[cfg(feature = "tree-sitter")]
+        let mut tree_sitter_incrementally_edited = false;
+
+        for change in params.content_changes {
+            let Some(range) = change.range else {
+                let old_len = doc.text.len_bytes();
+                doc.replace_text(Rope::from_str(&change.text));
+                doc.stale = false;
+                edits.push((0..old_len, change.text));
+
+                #[cfg(feature = "tree-sitter")]
+                self.reparse_document(doc);
+
+                continue;
+            };
+
+            // 1. Convert the LSP positions, using their arbitrary encoding,
+            //    to what Ropey expects to use for its incremental updates
+            let start_char_absolute = if let Ok(line_start_char_offset) =
+                doc.text.try_line_to_char(range.start.line as usize)
+            {
+                let start = position_to_encoding(&doc.text, range.start, encoding, Encoding::UTF32);
+                line_start_char_offset + start.character as usize
+            } else {
+                incremental_update_failed = true;
+                break;
+            };
+            let end_char_absolute = if let Ok(line_start_char_offset) =
+                doc.text.try_line_to_char(range.end.line as usize)
+            {
+                let end = position_to_encoding(&doc.text, range.end, encoding, Encoding::UTF32);
+                (line_start_char_offset + end.character as usize)
+                    .max(start_char_absolute)
+                    .min(doc.text.len_chars())
+            } else {
+                incremental_update_failed = true;
+                break;
+            };
+
+            // 2. Compute some byte offsets based on the yet-to-be-changed rope
+            let start_byte = doc.text.char_to_byte(start_char_absolute);
+            let old_end_byte = doc.text.char_to_byte(end_char_absolute);
+
+            // 3. Perform incremental edit on the syntax tree as well, if enabled
+            //    Note that we need to do this before updating the document contents
+            #[cfg(feature = "tree-sitter")]
+            if let Some(tree) = doc.tree_sitter_tree.as_mut() {
+                let new_end_byte = start_byte + change.text.len();
+
+                // Convert the start and old end positions to the correct encoding
+                let start_position =
+                    position_to_encoding(&doc.text, range.start, encoding, Encoding::UTF8);
+                let old_end_position =
+                    position_to_encoding(&doc.text, range.end, encoding, Encoding::UTF8);
+
+                // Compute the new end point based on the contents of the edit
+                let (new_end_row, new_end_col_bytes) = change.text.chars().fold(
+                    (
+                        start_position.line as usize,
+                        start_position.character as usize,
+                    ),
+                    |(row, col_bytes), ch| {
+                        if ch == '\n' {
+                            (row + 1, 0)
+                        } else {
+                            (row, col_bytes + ch.len_utf8())
+                        }
+                    },
+                );
+
+                // Finally, apply the edit to incrementally update the syntax tree
+                tree.edit(&InputEdit {
+                    start_byte,
+                    old_end_byte,
+                    new_end_byte,
+                    start_position: Point {
+                        row: start_position.line as usize,
+                        column: start_position.character as usize,
+                    },
+                    old_end_position: Point {
+                        row: old_end_position.line as usize,
+                        column: old_end_position.character as usize,
+                    },
+                    new_end_position: Point {
+                        row: new_end_row,
+                        column: new_end_col_bytes,
+                    },
+                });
+                tree_sitter_incrementally_edited = true;
+            }
+
+            // 4. Finally, try to incrementally update the document contents
+            if doc
+                .text
+                .try_remove(start_char_absolute..end_char_absolute)
+                .is_err()
+                || doc
+                    .text
+                    .try_insert(start_char_absolute, &change.text)
+                    .is_err()
+            {
+                incremental_update_failed = true;
+                break;
+            }
+            doc.edit_line_index(start_byte..old_end_byte, change.text.len());
+            edits.push((start_byte..old_end_byte, change.text));
+        }
+
+        // If the incremental update was successful, and we applied edits to the syntax
+        // tree, we must finalize those changes by parsing using tree-sitter once again,
+        // and documents that were previously too large to parse may now be parsed too
+        #[cfg(feature = "tree-sitter")]
+        if !incremental_update_failed {
+            if tree_sitter_incrementally_edited {
+                self.reparse_edited_document(doc);
+            } else if doc.tree_sitter_tree.is_none() {
+                self.reparse_document(doc);
+            }
+        }
+
+        if !incremental_update_failed {
+            if was_stale && !doc.stale {
+                self.finish_resync(&doc.uri);
+            }
+            return Some(DocumentChange {
+                event: Some(DocumentChangeEvent {
+                    url: doc.uri.clone(),
+                    old_version,
+                    new_version: doc.version,
+                    edits,
+                }),
+                sync_failed: doc.stale && self.resyncing.contains_key(&doc.uri),
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Incremental update failed for document {}", doc.uri);
+
+        // NOTE: The failed change may have been partially applied
+        // to the text, so the line index can no longer be trusted
+        doc.line_index = OnceLock::new();
+
+        // NOTE: Documents that are not backed by a file, such as untitled
+        // ones, can not be re-read from disk, and are marked as stale instead
+        let recovery = match self.sync_recovery {
+            SyncRecovery::ReadFromDisk if self.file_path(&doc.uri).is_none() => {
+                SyncRecovery::MarkStale
+            }
+            recovery => recovery,
+        };
+
+        match recovery {
+            SyncRecovery::MarkStale | SyncRecovery::Resync => {
+                doc.stale = true;
+                if recovery == SyncRecovery::Resync {
+                    self.request_resync(&doc.uri);
+                }
+                #[cfg(feature = "tree-sitter")]
+                self.reparse_document(doc);
+                Some(DocumentChange {
+                    event: Some(DocumentChangeEvent {
+                        url: doc.uri.clone(),
+                        old_version,
+                        new_version: doc.version,
+                        edits,
+                    }),
+                    sync_failed: true,
+                })
+            }
+            SyncRecovery::Discard => {
+                let uri = doc.uri.clone();
+                drop(entry);
+                self.documents.remove(&uri);
+                Some(DocumentChange {
+                    event: None,
+                    sync_failed: true,
+                })
+            }
+            SyncRecovery::ReadFromDisk => {
+                // We will re-insert the entire file instead - note that we
+                // must first drop the document reference to prevent a deadlock
+                let uri = doc.uri.clone();
+                let version = doc.version;
+                let language = doc.language.clone();
+                let len = doc.text.len_bytes();
+                let extensions = doc.extensions.clone();
+                let history = std::mem::take(&mut entry.history);
+
+                drop(entry);
+
+                // NOTE: We must read the contents of the file synchronously
+                // as the fallback here, since notification handlers are actually
+                // synchronous both according to LSP spec and the async-lsp crate
+                let Some(text) = self.read_document_file(&uri) else {
+                    self.documents.remove(&uri);
+                    return Some(DocumentChange {
+                        event: None,
+                        sync_failed: true,
+                    });
+                };
+                edits.push((0..len, text.clone()));
+                self.insert_document(uri.clone(), text, version, language, DocumentOrigin::Open);
+                if let Some(mut entry) = self.documents.get_mut(&uri) {
+                    entry.history = history;
+                    entry.document.inner_mut().extensions = extensions;
+                }
+                Some(DocumentChange {
+                    event: Some(DocumentChangeEvent {
+                        url: uri,
+                        old_version,
+                        new_version: version,
+                        edits,
+                    }),
+                    sync_failed: true,
+                })
+            }
+        }
+    }
+
+    #[allow(clippy::extra_unused_type_parameters)]
+    pub(crate) fn handle_document_save<T: Server>(
+        &self,
+        params: DidSaveTextDocumentParams,
+    ) -> ControlFlow<Result<()>> {
+        let url = params.text_document.uri;
+        let Some(mut entry) = self.documents.get_mut(&url) else {
+            return ControlFlow::Continue(());
+        };
+        self.document_tasks.cancel(&url);
+
+        // NOTE: The client has saved its own contents, which include any
+        // local edits, so those must not be undone by its next change
+        entry.local_edit_base = None;
+
+        // NOTE: Without the full contents from the client, we keep the contents
+        // that were already synchronized, unless those are known to be stale,
+        // since the file on disk may be older or newer than the contents in
+        // the client if any further edits were made while it was being saved
+        let read_from_disk = entry.document.inner.stale || self.save_reads_from_disk;
+        let text = if let Some(text) = &params.text {
+            Rope::from_str(text)
+        } else if !read_from_disk || self.file_path(&url).is_none() {
+            return ControlFlow::Continue(());
+        } else if let Some(text) = self.read_document_file(&url) {
+            // NOTE: We must read the contents of the file synchronously
+            // as the fallback here, since notification handlers are actually
+            // synchronous both according to LSP spec and the async-lsp crate
+            Rope::from_str(&text)
+        } else {
+            drop(entry);
+            self.documents.remove(&url);
+            return ControlFlow::Continue(());
+        };
+
+        // Saving a document without changing its contents is common,
+        // and we can skip re-creating its syntax tree when that happens
+        let doc = entry.document.inner_mut();
+        if !doc.stale && doc.text == text {
+            return ControlFlow::Continue(());
+        }
+        doc.stale = false;
+        doc.replace_text(text);
+        self.finish_resync(&url);
+
+        // Since we just read the entire file contents, we will also
+        // re-create the entire tree-sitter tree using those new contents
+        self.rematch_document(doc);
+
+        ControlFlow::Continue(())
+    }
+
+    /**
+        Asks the client to send the full contents of the document on its next
+        change, by registering full document sync for only that document.
+    */
+    fn request_resync(&self, url: &Url) {
+        if self.resyncing.contains_key(url) {
+            return;
+        }
+        let supported = self
+            .client_capabilities
+            .text_document
+            .as_ref()
+            .and_then(|t| t.synchronization.as_ref())
+            .and_then(|s| s.dynamic_registration)
+            .unwrap_or(false);
+        let pattern = self.file_path(url).map(|path| glob_escape(&path));
+        let runtime = tokio::runtime::Handle::try_current().ok();
+
+        // NOTE: The document is tracked even if we can not register anything,
+        // so that changes are still reported as failed until it is fully synced
+        let (Some(pattern), Some(runtime), true) = (pattern, runtime, supported) else {
+            self.resyncing.insert(url.clone(), false);
+            return;
+        };
+        self.resyncing.insert(url.clone(), true);
+
+        let options = TextDocumentChangeRegistrationOptions {
+            document_selector: Some(vec![DocumentFilter {
+                language: None,
+                scheme: Some(url.scheme().to_string()),
+                pattern: Some(pattern),
+            }]),
+            // NOTE: TextDocumentSyncKind does not expose its inner value, 1 is FULL
+            sync_kind: 1,
+        };
+        let client = self.client.clone();
+        let id = resync_registration_id(url);
+        runtime.spawn(async move {
+            let _ = client
+                .request::<RegisterCapability>(RegistrationParams {
+                    registrations: vec![Registration {
+                        id,
+                        method: "textDocument/didChange".into(),
+                        register_options: serde_json::to_value(options).ok(),
+                    }],
+                })
+                .await;
+        });
+    }
+
+    /**
+        Removes the full document sync registered by [`ServerState::request_resync`],
+        once the full contents of the document have been received from the client.
+    */
+    fn finish_resync(&self, url: &Url) {
+        let Some((_, true)) = self.resyncing.remove(url) else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let client = self.client.clone();
+        let id = resync_registration_id(url);
+        runtime.spawn(async move {
+            let _ = client
+                .request::<UnregisterCapability>(UnregistrationParams {
+                    unregisterations: vec![Unregistration {
+                        id,
+                        method: "textDocument/didChange".into(),
+                    }],
+                })
+                .await;
+        });
+    }
+
+    /**
+        Matches the document again, and re-creates its entire tree-sitter tree.
+    */
+    fn rematch_document(&self, doc: &mut DocumentInner) {
+        // The implementor may want to know what, if any, document
+        // matcher we may have matched against - so let's save that
+        let matcher = self.matchers.find(&doc.uri, &doc.language);
+        doc.read_only = self.matchers.is_read_only(matcher.as_ref(), &doc.uri);
+        doc.matcher.clone_from(&matcher);
+
+        #[cfg(feature = "tree-sitter")]
+        {
+            doc.tree_sitter_lang = self
+                .matchers
+                .lang_grammar(matcher.as_ref(), &doc.uri)
+                .filter(|lang| lang_parser(lang).is_some());
+            self.reparse_document(doc);
+        }
+    }
+
+    /**
+        Re-creates the entire tree-sitter tree of the document, either
+        immediately, or in the background if background parsing is enabled.
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn reparse_document(&self, doc: &mut DocumentInner) {
+        // NOTE: Any parse still running in the background is for
+        // contents that are now outdated, so there is no point in finishing it
+        if let Some((_, cancellation)) = doc.tree_sitter_pending.take() {
+            cancellation.cancel();
+        }
+        doc.tree_sitter_tree = None;
+
+        if self.background_parsing
+            && !self.parse_limit.is_exceeded_by(&doc.text)
+            && let Some(lang) = doc.tree_sitter_lang.clone()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            self.spawn_parse(doc, lang, None, None, &runtime);
+            return;
+        }
+
+        doc.tree_sitter_tree = self.doc_tree(doc, None);
+    }
+
+    /**
+        Re-parses the tree-sitter tree of the document after incremental edits, either
+        immediately, or once no more edits have been made for the debounce duration.
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn reparse_edited_document(&self, doc: &mut DocumentInner) {
+        if let Some((_, cancellation)) = doc.tree_sitter_pending.take() {
+            cancellation.cancel();
+        }
+        let old_tree = doc.tree_sitter_tree.take();
+
+        // NOTE: The edited tree stays readable until the re-parse finishes, so
+        // that tree-based features keep working while the user is still typing,
+        // and so that any further edits during the debounce can be applied to it
+        if let Some(debounce) = self.reparse_debounce
+            && !self.parse_limit.is_exceeded_by(&doc.text)
+            && let Some(lang) = doc.tree_sitter_lang.clone()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            doc.tree_sitter_tree.clone_from(&old_tree);
+            self.spawn_parse(doc, lang, old_tree, Some(debounce), &runtime);
+            return;
+        }
+
+        doc.tree_sitter_tree = self.doc_tree(doc, old_tree.as_ref());
+    }
+
+    /**
+        Parses the document on a background thread, optionally after a delay,
+        and attaches the tree to the document once parsing finishes.
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn spawn_parse(
+        &self,
+        doc: &mut DocumentInner,
+        lang: Language,
+        old_tree: Option<Tree>,
+        delay: Option<Duration>,
+        runtime: &tokio::runtime::Handle,
+    ) {
+        let id = self.parse_counter.fetch_add(1, Ordering::Relaxed);
+        let cancellation = CancellationToken::new();
+        doc.tree_sitter_pending = Some((id, cancellation.clone()));
+
+        let state = self.clone();
+        let url = doc.uri.clone();
+        let text = doc.text.clone();
+        let delayed_cancellation = cancellation.clone();
+        let parse = move || {
+            let timeout = state.parse_limit.timeout;
+            let tree = parse_text(
+                &lang,
+                &text,
+                old_tree.as_ref(),
+                timeout,
+                Some(&cancellation),
+            )
+            .unwrap_or_else(|ParseTimedOut| {
+                state.report_parse_timeout(&url);
+                None
+            });
+            if !cancellation.is_cancelled() {
+                state.attach_parsed_tree(&url, id, tree);
+            }
+        };
+
+        let Some(delay) = delay else {
+            runtime.spawn_blocking(parse);
+            return;
+        };
+        let blocking = runtime.clone();
+        runtime.spawn(async move {
+            tokio::time::sleep(delay).await;
+            if !delayed_cancellation.is_cancelled() {
+                blocking.spawn_blocking(parse);
+            }
+        });
+    }
+
+    /**
+        Attaches a tree parsed in the background to its document, unless
+        the document has changed and been scheduled for parsing again since.
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn attach_parsed_tree(&self, url: &Url, id: u64, tree: Option<Tree>) {
+        {
+            let Some(mut entry) = self.documents.get_mut(url) else {
+                return;
+            };
+            if entry
+                .document
+                .inner
+                .tree_sitter_pending
+                .as_ref()
+                .is_none_or(|(pending, _)| *pending != id)
+            {
+                return;
+            }
+            let doc = entry.document.inner_mut();
+            doc.tree_sitter_tree = tree;
+            doc.tree_sitter_pending = None;
+        }
+        let _ = self.diagnostics().publish_syntax(url);
+    }
+
+    /**
+        Parses the document using its tree-sitter language, if it has one,
+        and if the document is within the configured parse limits.
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn doc_tree(&self, doc: &DocumentInner, old_tree: Option<&Tree>) -> Option<Tree> {
+        // NOTE: Parsing very large documents, such as generated files, may take
+        // several seconds and block all other notifications, so we skip them
+        if self.parse_limit.is_exceeded_by(&doc.text) {
+            return None;
+        }
+        let lang = doc.tree_sitter_lang.as_ref()?;
+        parse_text(lang, &doc.text, old_tree, self.parse_limit.timeout, None).unwrap_or_else(
+            |ParseTimedOut| {
+                self.report_parse_timeout(&doc.uri);
+                None
+            },
+        )
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    pub(crate) fn set_parse_timeout_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(ServerState, Url) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    {
+        self.parse_timeout_hook = Some(ParseTimeoutHook(Arc::new(hook)));
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    fn report_parse_timeout(&self, url: &Url) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Parsing timed out for document {url}, it will only be available as text");
+
+        // NOTE: The hook is spawned instead of awaited, since parsing happens
+        // while the document is being updated, and the hook may want to read it
+        if let Some(hook) = self.parse_timeout_hook.as_ref()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn((hook.0)(self.clone(), url.clone()));
+        }
+    }
+}
+
+fn resync_registration_id(url: &Url) -> String {
+    format!("async-language-server.resync:{url}")
+}
+
+fn glob_escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for ch in path.to_string_lossy().chars() {
+        if matches!(ch, '*' | '?' | '[' | ']' | '{' | '}') {
+            escaped.push('[');
+            escaped.push(ch);
+            escaped.push(']');
+        } else {
+            escaped.push(ch);
+        }
+    }
+    escaped
+}
+
+#[cfg(feature = "tree-sitter")]
+fn lang_parser(lang: &Language) -> Option<Parser> {
+    let mut parser = Parser::new();
+    if parser.set_language(lang).is_ok() {
+        Some(parser)
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "tree-sitter")]
+struct ParseTimedOut;
+
+/**
+    Parses the given text, giving up once the timeout has elapsed,
+    or once the given cancellation token has been cancelled.
+*/
+#[cfg(feature = "tree-sitter")]
+fn parse_text(
+    lang: &Language,
+    text: &Rope,
+    old_tree: Option<&Tree>,
+    timeout: Option<Duration>,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Option<Tree>, ParseTimedOut> {
+    let Some(mut parser) = lang_parser(lang) else {
+        return Ok(None);
+    };
+
+    let started = Instant::now();
+    let mut timed_out = false;
+    let mut progress = |_: &ParseState| {
+        timed_out = timeout.is_some_and(|timeout| started.elapsed() > timeout);
+        timed_out || cancellation.is_some_and(CancellationToken::is_cancelled)
+    };
+
+    let options = ParseOptions::new().progress_callback(&mut progress);
+    let tree = parse_rope_with_options(&mut parser, text, old_tree, Some(options));
+    if timed_out {
+        Err(ParseTimedOut)
+    } else {
+        Ok(tree)
+    }
+}
+
+fn matcher_language(matcher: &DocumentMatcher) -> String {
+    matcher
+        .lang_strings
+        .first()
+        .cloned()
+        .unwrap_or_else(|| matcher.name.to_ascii_lowercase())
+}
+
+pub(crate) fn url_is_in_roots(url: &Url, roots: &[PathBuf]) -> bool {
+    url.to_file_path()
+        .is_ok_and(|path| roots.iter().any(|root| path.starts_with(root)))
+}
+
+fn workspace_folder_path(folder: &WorkspaceFolder) -> Option<PathBuf> {
+    let path = folder.uri.to_file_path().ok()?;
+    Some(std::fs::canonicalize(&path).unwrap_or(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::PathBuf,
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use async_lsp::{
+        ClientSocket,
+        lsp_types::{
+            AnnotatedTextEdit, ClientCapabilities, ConfigurationItem, DidChangeTextDocumentParams,
+            DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+            DocumentChangeOperation, DocumentChanges, FileChangeType, Hover, HoverContents,
+            HoverParams, MarkedString, MessageType, NumberOrString, OneOf,
+            OptionalVersionedTextDocumentIdentifier, Position, Range, ShowDocumentResult,
+            TextDocumentContentChangeEvent, TextDocumentEdit, TextDocumentIdentifier,
+            TextDocumentItem, TextEdit, Url, VersionedTextDocumentIdentifier,
+            WorkDoneProgressCancelParams, WorkspaceEdit, WorkspaceFolder,
+            notification::{DidChangeTextDocument, LogMessage, ShowMessage},
+            request::{
+                RegisterCapability, SemanticTokensRefresh, ShowDocument, ShowMessageRequest,
+                UnregisterCapability, WorkspaceConfiguration, WorkspaceDiagnosticRefresh,
+            },
+        },
+    };
+
+    use crate::{
+        server::{
+            DocumentMatcher, Server, ServerOptions, ServerResult, SyncRecovery,
+            WorkspaceDiagnostics,
+        },
+        testing::{MockClient, TestServer as TestHarness},
+        text_utils::Encoding,
+    };
+
+    use super::ServerState;
+
+    struct TestServer;
+
+    impl Server for TestServer {
+        fn server_document_matchers() -> Vec<DocumentMatcher> {
+            vec![
+                DocumentMatcher::new("Test")
+                    .with_url_globs(["**/*.test", "*.test"])
+                    .with_lang_strings(["test"]),
+            ]
+        }
+    }
+
+    fn url(path: &str) -> Url {
+        Url::parse(&format!("file:///tmp/{path}")).unwrap()
+    }
+
+    fn open_document(state: &mut ServerState, uri: Url, text: impl Into<String>) {
+        let _ = state.handle_document_open::<TestServer>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri, "test".into(), 1, text.into()),
+        });
+    }
+
+    fn temp_workspace(name: &str) -> PathBuf {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is after epoch")
+            .as_millis();
+        let root =
+            std::env::temp_dir().join(format!("async-language-server-state-{name}-{millis}"));
+        fs::create_dir_all(&root).expect("temp workspace can be created");
+        root
+    }
+
+    fn workspace_folder(path: &PathBuf) -> WorkspaceFolder {
+        let uri = Url::from_file_path(path).expect("path can be converted to a URL");
+        WorkspaceFolder {
+            uri,
+            name: "test".into(),
+        }
+    }
+
+    #[test]
+    fn documents_can_be_filtered_by_matcher_name() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        open_document(&mut state, url("a.test"), "a");
+        let _ = state.handle_document_open::<TestServer>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(url("b.txt"), "plain".into(), 1, "b".into()),
+        });
+
+        assert_eq!(state.documents().len(), 2);
+
+        let matching = state.documents_matching("Test");
+        assert_eq!(matching.len(), 1);
+        assert_eq!(matching[0].url(), &url("a.test"));
+        assert!(state.documents_matching("Other").is_empty());
+    }
+
+    #[test]
+    fn documents_are_matched_again_when_their_language_changes() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("language.txt");
+        let _ = state.handle_document_open::<TestServer>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "plain".into(), 1, "a".into()),
+        });
+        assert_eq!(state.document(&uri).unwrap().matched_name(), None);
+
+        let document = state.set_document_language(&uri, "test").unwrap();
+        assert_eq!(document.language(), "test");
+        assert_eq!(document.matched_name(), Some("Test"));
+        assert_eq!(state.documents_matching("Test").len(), 1);
+
+        let document = state.set_document_language(&uri, "plain").unwrap();
+        assert_eq!(document.matched_name(), None);
+        assert!(
+            state
+                .set_document_language(&url("missing.txt"), "test")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn snapshots_share_contents_until_documents_change() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("snapshot.test");
+        open_document(&mut state, uri.clone(), "old");
+
+        let first = state.document(&uri).unwrap();
+        let second = state.document(&uri).unwrap();
+        assert!(Arc::ptr_eq(&first.inner, &second.inner));
+
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "new".into(),
+            }],
+        });
+
+        let current = state.document(&uri).unwrap();
+        assert!(!Arc::ptr_eq(&first.inner, &current.inner));
+        assert_eq!(first.text_contents(), "old");
+        assert_eq!(current.text_contents(), "new");
+    }
+
+    #[test]
+    #[cfg(feature = "tree-sitter")]
+    fn trees_parsed_in_the_background_are_only_attached_if_still_pending() {
+        use crate::server::{CancellationToken, SyntaxStatus};
+
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_background_parsing(true),
+        );
+        let uri = url("background.test");
+        open_document(&mut state, uri.clone(), "text");
+
+        let document = state.document(&uri).unwrap();
+        assert_eq!(document.syntax_status(), SyntaxStatus::Unavailable);
+
+        if let Some(mut entry) = state.documents.get_mut(&uri) {
+            entry.document.inner_mut().tree_sitter_pending = Some((2, CancellationToken::new()));
+        }
+        let status = || state.document(&uri).unwrap().syntax_status();
+        assert_eq!(status(), SyntaxStatus::Pending);
+
+        state.attach_parsed_tree(&uri, 1, None);
+        assert_eq!(status(), SyntaxStatus::Pending);
+
+        state.attach_parsed_tree(&uri, 2, None);
+        assert_eq!(status(), SyntaxStatus::Unavailable);
+    }
+
+    #[test]
+    fn full_content_change_replaces_document_text() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("full-change.txt");
+        open_document(&mut state, uri.clone(), "old");
+
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "new".into(),
+            }],
+        });
+
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "new");
+        assert_eq!(state.document(&uri).unwrap().version(), 2);
+    }
+
+    #[test]
+    fn document_changes_are_described_using_byte_ranges() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("edits.test");
+        open_document(&mut state, uri.clone(), "🙂 hello\nworld");
+
+        let event = state
+            .apply_document_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                content_changes: vec![
+                    TextDocumentContentChangeEvent {
+                        range: Some(Range::new(Position::new(0, 3), Position::new(0, 8))),
+                        range_length: None,
+                        text: "there".into(),
+                    },
+                    TextDocumentContentChangeEvent {
+                        range: Some(Range::new(Position::new(1, 0), Position::new(1, 0))),
+                        range_length: None,
+                        text: "big ".into(),
+                    },
+                    TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: "replaced".into(),
+                    },
+                ],
+            })
+            .and_then(|change| change.event)
+            .expect("document is tracked");
+
+        assert_eq!(event.url, uri);
+        assert_eq!(event.old_version, 1);
+        assert_eq!(event.new_version, 2);
+        assert_eq!(
+            event.edits,
+            vec![
+                (5..10, "there".to_string()),
+                (11..11, "big ".to_string()),
+                (0..20, "replaced".to_string()),
+            ]
+        );
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "replaced");
+    }
+
+    #[test]
+    fn failed_incremental_updates_use_the_configured_recovery() {
+        let bad_change = |state: &mut ServerState, uri: &Url| {
+            state
+                .apply_document_change(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: Some(Range::new(Position::new(9, 0), Position::new(9, 1))),
+                        range_length: None,
+                        text: "x".into(),
+                    }],
+                })
+                .expect("document is tracked")
+        };
+        let uri = url("recovery.test");
+
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_sync_recovery(SyncRecovery::MarkStale),
+        );
+        open_document(&mut state, uri.clone(), "hello");
+        let change = bad_change(&mut state, &uri);
+        assert!(change.sync_failed);
+        assert!(change.event.is_some());
+        let document = state.document(&uri).expect("document is kept");
+        assert!(document.is_stale());
+        assert_eq!(document.text_contents(), "hello");
+
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_sync_recovery(SyncRecovery::Discard),
+        );
+        open_document(&mut state, uri.clone(), "hello");
+        let change = bad_change(&mut state, &uri);
+        assert!(change.sync_failed);
+        assert!(change.event.is_none());
+        assert!(state.document(&uri).is_none());
+
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_sync_recovery(SyncRecovery::Resync),
+        );
+        open_document(&mut state, uri.clone(), "hello");
+        assert!(bad_change(&mut state, &uri).sync_failed);
+        let change = state
+            .apply_document_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 3),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: Some(Range::new(Position::new(0, 0), Position::new(0, 0))),
+                    range_length: None,
+                    text: "x".into(),
+                }],
+            })
+            .expect("document is tracked");
+        assert!(change.sync_failed, "changes to stale documents fail");
+        let change = state
+            .apply_document_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 4),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "synced".into(),
+                }],
+            })
+            .expect("document is tracked");
+        assert!(!change.sync_failed);
+        assert!(!state.document(&uri).unwrap().is_stale());
+    }
+
+    #[test]
+    fn documents_are_marked_stale_by_default() {
+        assert_eq!(
+            ServerOptions::default().sync_recovery,
+            SyncRecovery::MarkStale
+        );
+    }
+
+    #[derive(Clone)]
+    struct ResyncServer;
+
+    impl Server for ResyncServer {
+        fn server_options(&self) -> ServerOptions {
+            ServerOptions::default().with_sync_recovery(SyncRecovery::Resync)
+        }
+    }
+
+    #[test]
+    fn resync_registers_full_sync_until_contents_arrive() {
+        runtime().block_on(async {
+            let capabilities = serde_json::from_value(serde_json::json!({
+                "textDocument": { "synchronization": { "dynamicRegistration": true } },
+            }))
+            .unwrap();
+            let server = TestHarness::spawn_with_capabilities(ResyncServer, capabilities)
+                .await
+                .expect("server spawns");
+
+            let url = Url::parse("file:///tmp/resync[1].test").unwrap();
+            server.open_document(&url, "test", "hello").unwrap();
+            server
+                .notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier::new(url.clone(), 2),
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: Some(Range::new(Position::new(9, 0), Position::new(9, 1))),
+                        range_length: None,
+                        text: "x".into(),
+                    }],
+                })
+                .unwrap();
+
+            let client = server.client();
+            let params = client.expect_request::<RegisterCapability>().await.unwrap();
+            let registration = &params.registrations[0];
+            assert_eq!(registration.method, "textDocument/didChange");
+            let options = registration.register_options.as_ref().unwrap();
+            assert_eq!(options["syncKind"], 1);
+            assert_eq!(
+                options["documentSelector"][0]["pattern"],
+                "/tmp/resync[[]1[]].test"
+            );
+
+            server.change_document(&url, "synced").unwrap();
+            let params = client
+                .expect_request::<UnregisterCapability>()
+                .await
+                .unwrap();
+            assert_eq!(params.unregisterations[0].id, registration.id);
+        });
+    }
+
+    #[test]
+    fn document_data_is_kept_until_documents_are_closed() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("data.test");
+        assert!(state.set_document_data(&uri, 1_u32).is_none());
+
+        open_document(&mut state, uri.clone(), "a");
+        let snapshot = state.document(&uri).expect("document is tracked");
+        assert!(state.set_document_data(&uri, 1_u32).is_none());
+        assert_eq!(state.set_document_data(&uri, 2_u32).as_deref(), Some(&1));
+        assert_eq!(snapshot.extensions().get::<u32>().as_deref(), Some(&2));
+
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "b".into(),
+            }],
+        });
+        assert_eq!(state.document_data::<u32>(&uri).as_deref(), Some(&2));
+
+        let _ = state.handle_document_close::<TestServer>(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+        });
+        open_document(&mut state, uri.clone(), "c");
+        assert!(state.document_data::<u32>(&uri).is_none());
+    }
+
+    #[test]
+    fn local_edits_are_undone_by_client_changes() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("local.test");
+        open_document(&mut state, uri.clone(), "hello world");
+
+        let edits = vec![
+            TextEdit::new(
+                Range::new(Position::new(0, 6), Position::new(0, 11)),
+                "there".into(),
+            ),
+            TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                "oh ".into(),
+            ),
+            TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                "well, ".into(),
+            ),
+        ];
+        state.apply_edit_locally(WorkspaceEdit::new(HashMap::from([(uri.clone(), edits)])));
+
+        let document = state.document(&uri).expect("document is tracked");
+        assert_eq!(document.text_contents(), "oh well, hello there");
+        assert_eq!(document.version(), 1);
+
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, 6), Position::new(0, 11))),
+                range_length: None,
+                text: "there".into(),
+            }],
+        });
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "hello there");
+    }
+
+    #[test]
+    fn local_edits_are_kept_after_saving_without_text() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("saved-local.test");
+        open_document(&mut state, uri.clone(), "hello world");
+
+        let edits = vec![TextEdit::new(
+            Range::new(Position::new(0, 0), Position::new(0, 5)),
+            "howdy".into(),
+        )];
+        state.apply_edit_locally(WorkspaceEdit::new(HashMap::from([(uri.clone(), edits)])));
+        let _ = state.handle_document_save::<TestServer>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            text: None,
+        });
+
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, 6), Position::new(0, 11))),
+                range_length: None,
+                text: "there".into(),
+            }],
+        });
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "howdy there");
+    }
+
+    #[test]
+    fn overlapping_local_edits_are_ignored() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = url("overlapping.test");
+        open_document(&mut state, uri.clone(), "hello world");
+
+        let edits = vec![
+            TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 7)),
+                "bye".into(),
+            ),
+            TextEdit::new(
+                Range::new(Position::new(0, 6), Position::new(0, 11)),
+                "there".into(),
+            ),
+        ];
+        state.apply_edit_locally(WorkspaceEdit::new(HashMap::from([(uri.clone(), edits)])));
+
+        let document = state.document(&uri).expect("document is tracked");
+        assert_eq!(document.text_contents(), "hello world");
+    }
+
+    #[test]
+    fn previous_document_versions_are_kept_when_enabled() {
+        let change = |state: &mut ServerState, uri: &Url, version: i32, text: &str| {
+            let _ = state.apply_document_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: text.into(),
+                }],
+            });
+        };
+        let text_at = |state: &ServerState, uri: &Url, version: i32| {
+            state
+                .document_at_version(uri, version)
+                .map(|document| document.text_contents())
+        };
+
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_document_history(2),
+        );
+        let uri = url("history.test");
+        open_document(&mut state, uri.clone(), "a");
+        change(&mut state, &uri, 2, "b");
+        change(&mut state, &uri, 3, "c");
+        change(&mut state, &uri, 4, "d");
 
-            drop(entry);
+        assert_eq!(text_at(&state, &uri, 4).as_deref(), Some("d"));
+        assert_eq!(text_at(&state, &uri, 3).as_deref(), Some("c"));
+        assert_eq!(text_at(&state, &uri, 2).as_deref(), Some("b"));
+        assert_eq!(text_at(&state, &uri, 1), None);
 
-            // NOTE: We must read the contents of the file synchronously
-            // as the fallback here, since notification handlers are actually
-            // synchronous both according to LSP spec and the async-lsp crate
-            if let Ok(text) = std::fs::read_to_string(uri.path()) {
-                self.insert_document::<T>(uri, text, version, language, DocumentOrigin::Open);
-            } else {
-                self.documents.remove(&uri);
-            }
-        }
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        open_document(&mut state, uri.clone(), "a");
+        change(&mut state, &uri, 2, "b");
 
-        ControlFlow::Continue(())
+        assert_eq!(text_at(&state, &uri, 2).as_deref(), Some("b"));
+        assert_eq!(text_at(&state, &uri, 1), None);
     }
 
-    #[allow(clippy::extra_unused_type_parameters)]
-    pub(crate) fn handle_document_save<T: Server>(
-        &self,
-        params: DidSaveTextDocumentParams,
-    ) -> ControlFlow<Result<()>> {
-        let url = params.text_document.uri;
-        let Some(mut entry) = self.documents.get_mut(&url) else {
-            return ControlFlow::Continue(());
+    #[test]
+    fn saving_without_text_keeps_synchronized_contents_unless_stale() {
+        let root = temp_workspace("save-without-text");
+        let file = root.join("a.test");
+        fs::write(&file, "disk").expect("test file can be written");
+        let uri = Url::from_file_path(&file).expect("path can be converted to a URL");
+        let save = |state: &ServerState| {
+            let _ = state.handle_document_save::<TestServer>(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                text: None,
+            });
         };
 
-        // NOTE: We must read the contents of the file synchronously
-        // as the fallback here, since notification handlers are actually
-        // synchronous both according to LSP spec and the async-lsp crate
-        let text = if let Some(text) = &params.text {
-            Rope::from_str(text)
-        } else if let Ok(text) = std::fs::read_to_string(url.path()) {
-            Rope::from_str(&text)
-        } else {
-            drop(entry);
-            self.documents.remove(&url);
-            return ControlFlow::Continue(());
-        };
-        let doc = &mut entry.document;
-        doc.text = text;
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_sync_recovery(SyncRecovery::MarkStale),
+        );
+        open_document(&mut state, uri.clone(), "open");
+        save(&state);
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "open");
 
-        // The implementor may want to know what, if any, document
-        // matcher we may have matched against - so let's save that
-        let matcher = self.matchers.find(doc.url(), doc.language());
-        doc.matcher.clone_from(&matcher);
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(9, 0), Position::new(9, 1))),
+                range_length: None,
+                text: "x".into(),
+            }],
+        });
+        assert!(state.document(&uri).unwrap().is_stale());
+        save(&state);
 
-        // Since we just read the entire file contents, we will also
-        // re-create the entire tree-sitter tree using those new contents
-        #[cfg(feature = "tree-sitter")]
-        {
-            let mut tree_sitter_lang = matcher.and_then(|m| m.lang_grammar.clone());
+        let document = state.document(&uri).unwrap();
+        assert!(!document.is_stale());
+        assert_eq!(document.text_contents(), "disk");
 
-            let tree_sitter_tree = if let Some(lang) = tree_sitter_lang.as_ref() {
-                let mut parser = Parser::new();
-                if parser.set_language(lang).is_ok() {
-                    parser.parse(doc.text_contents(), None)
-                } else {
-                    tree_sitter_lang.take();
-                    None
-                }
-            } else {
-                None
-            };
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
 
-            doc.tree_sitter_lang = tree_sitter_lang;
-            doc.tree_sitter_tree = tree_sitter_tree;
-        }
+    #[test]
+    fn untitled_documents_are_kept_in_memory() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let uri = Url::parse("untitled:Untitled-1").unwrap();
+        open_document(&mut state, uri.clone(), "hello");
 
-        ControlFlow::Continue(())
-    }
-}
+        let _ = state.handle_document_save::<TestServer>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            text: None,
+        });
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "hello");
 
-#[cfg(feature = "tree-sitter")]
-fn doc_parser(doc: &Document) -> Option<Parser> {
-    let lang = doc.tree_sitter_lang.as_ref()?;
-    let mut parser = Parser::new();
-    if parser.set_language(lang).is_ok() {
-        Some(parser)
-    } else {
-        None
-    }
-}
+        let _ = state.apply_document_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(9, 0), Position::new(9, 1))),
+                range_length: None,
+                text: "x".into(),
+            }],
+        });
+        let document = state.document(&uri).expect("untitled document is kept");
+        assert_eq!(document.text_contents(), "hello");
+        assert_eq!(document.version(), 2);
 
-fn url_is_in_roots(url: &Url, roots: &[PathBuf]) -> bool {
-    url.to_file_path()
-        .is_ok_and(|path| roots.iter().any(|root| path.starts_with(root)))
-}
+        let _ = state.handle_document_close::<TestServer>(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+        });
+        assert!(state.document(&uri).is_none());
+    }
 
-fn workspace_folder_path(folder: &WorkspaceFolder) -> Option<PathBuf> {
-    let path = folder.uri.to_file_path().ok()?;
-    Some(std::fs::canonicalize(&path).unwrap_or(path))
-}
+    #[test]
+    fn client_ranges_are_converted_using_the_negotiated_encoding() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        state.set_position_encoding(Encoding::UTF16);
+        let uri = url("client-range.txt");
+        open_document(&mut state, uri.clone(), "🙂abc");
+
+        let range = Range::new(Position::new(0, 4), Position::new(0, 6));
+        assert_eq!(
+            state.range_to_client_encoding(&uri, range),
+            Range::new(Position::new(0, 2), Position::new(0, 4)),
+        );
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        fs,
-        path::PathBuf,
-        time::{SystemTime, UNIX_EPOCH},
-    };
+        let missing = url("missing.txt");
+        assert_eq!(state.range_to_client_encoding(&missing, range), range);
+    }
 
-    use async_lsp::{
-        ClientSocket,
-        lsp_types::{
-            DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-            TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem, Url,
-            VersionedTextDocumentIdentifier, WorkspaceFolder,
-        },
-    };
+    #[test]
+    fn workspace_edits_are_converted_using_the_negotiated_encoding() {
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        state.set_position_encoding(Encoding::UTF16);
+        let uri = url("workspace-edit.txt");
+        open_document(&mut state, uri.clone(), "🙂abc");
+
+        let range = Range::new(Position::new(0, 4), Position::new(0, 6));
+        let expected = Range::new(Position::new(0, 2), Position::new(0, 4));
+        let mut edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri.clone(),
+                vec![TextEdit::new(range, "x".into())],
+            )])),
+            document_changes: Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: Some(1),
+                    },
+                    edits: vec![
+                        OneOf::Left(TextEdit::new(range, "y".into())),
+                        OneOf::Right(AnnotatedTextEdit {
+                            text_edit: TextEdit::new(range, "z".into()),
+                            annotation_id: "annotation".into(),
+                        }),
+                    ],
+                }),
+            ])),
+            change_annotations: None,
+        };
 
-    use crate::server::{DocumentMatcher, Server, ServerOptions, WorkspaceDiagnostics};
+        state.workspace_edit_to_client_encoding(&mut edit);
 
-    use super::ServerState;
+        assert_eq!(edit.changes.unwrap()[&uri][0].range, expected);
+        let Some(DocumentChanges::Operations(ops)) = edit.document_changes else {
+            panic!("document changes should be operations");
+        };
+        let DocumentChangeOperation::Edit(doc_edit) = &ops[0] else {
+            panic!("operation should be an edit");
+        };
+        for edit in &doc_edit.edits {
+            let range = match edit {
+                OneOf::Left(edit) => edit.range,
+                OneOf::Right(edit) => edit.text_edit.range,
+            };
+            assert_eq!(range, expected);
+        }
+    }
 
-    struct TestServer;
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created")
+    }
 
-    impl Server for TestServer {
-        fn server_document_matchers() -> Vec<DocumentMatcher> {
-            vec![
-                DocumentMatcher::new("Test")
-                    .with_url_globs(["**/*.test", "*.test"])
-                    .with_lang_strings(["test"]),
-            ]
+    fn hover_string(hover: Option<Hover>) -> String {
+        match hover.map(|h| h.contents) {
+            Some(HoverContents::Scalar(MarkedString::String(s))) => s,
+            other => panic!("expected plain string hover, got {other:?}"),
         }
     }
 
-    fn url(path: &str) -> Url {
-        Url::parse(&format!("file:///tmp/{path}")).unwrap()
+    #[derive(serde::Deserialize)]
+    struct TestSettings {
+        enabled: bool,
+        name: String,
     }
 
-    fn open_document(state: &mut ServerState, uri: Url, text: impl Into<String>) {
-        let _ = state.handle_document_open::<TestServer>(DidOpenTextDocumentParams {
-            text_document: TextDocumentItem::new(uri, "test".into(), 1, text.into()),
+    #[derive(Clone)]
+    struct ConfiguredServer;
+
+    impl Server for ConfiguredServer {
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            let scope = params.text_document_position_params.text_document.uri;
+            let settings = state
+                .fetch_configuration::<TestSettings>("test", Some(scope))
+                .await?;
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(format!(
+                    "{}: {}",
+                    settings.name, settings.enabled
+                ))),
+                range: None,
+            }))
+        }
+    }
+
+    #[test]
+    fn fetch_configuration_deserializes_the_requested_section() {
+        runtime().block_on(async {
+            let client = MockClient::new();
+            client.respond_to::<WorkspaceConfiguration, _>(|_| {
+                vec![serde_json::json!({ "enabled": true, "name": "configured" })]
+            });
+            let server = TestHarness::spawn_with_client(
+                ConfiguredServer,
+                ClientCapabilities::default(),
+                client,
+            )
+            .await
+            .expect("server spawns");
+
+            let uri = url("config.test");
+            let hover = server.hover(&uri, Position::new(0, 0)).await.unwrap();
+            assert_eq!(hover_string(hover), "configured: true");
+
+            let params = server
+                .client()
+                .expect_request::<WorkspaceConfiguration>()
+                .await
+                .unwrap();
+            assert_eq!(
+                params.items,
+                vec![ConfigurationItem {
+                    scope_uri: Some(uri),
+                    section: Some("test".into()),
+                }]
+            );
         });
     }
 
-    fn temp_workspace(name: &str) -> PathBuf {
-        let millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("system time is after epoch")
-            .as_millis();
-        let root =
-            std::env::temp_dir().join(format!("async-language-server-state-{name}-{millis}"));
-        fs::create_dir_all(&root).expect("temp workspace can be created");
-        root
+    #[test]
+    fn fetch_configuration_fails_for_mismatched_settings() {
+        runtime().block_on(async {
+            let client = MockClient::new();
+            client.respond_to::<WorkspaceConfiguration, _>(|_| {
+                vec![serde_json::json!({ "enabled": "yes" })]
+            });
+            let server = TestHarness::spawn_with_client(
+                ConfiguredServer,
+                ClientCapabilities::default(),
+                client,
+            )
+            .await
+            .expect("server spawns");
+
+            let uri = url("config.test");
+            assert!(server.hover(&uri, Position::new(0, 0)).await.is_err());
+        });
     }
 
-    fn workspace_folder(path: &PathBuf) -> WorkspaceFolder {
-        let uri = Url::from_file_path(path).expect("path can be converted to a URL");
-        WorkspaceFolder {
-            uri,
-            name: "test".into(),
+    #[derive(Clone)]
+    struct MessagingServer;
+
+    impl Server for MessagingServer {
+        async fn hover(&self, state: ServerState, _: HoverParams) -> ServerResult<Option<Hover>> {
+            state.show_info("info")?;
+            state.show_warning("warning")?;
+            state.show_error("error")?;
+            state.log_message(MessageType::LOG, "log")?;
+            let picked = state
+                .show_message_request(MessageType::INFO, "pick one", ["first", "second"])
+                .await?;
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(format!("{picked:?}"))),
+                range: None,
+            }))
         }
     }
 
     #[test]
-    fn full_content_change_replaces_document_text() {
-        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
-        let uri = url("full-change.txt");
-        open_document(&mut state, uri.clone(), "old");
+    fn messages_are_sent_to_the_client() {
+        runtime().block_on(async {
+            let client = MockClient::new();
+            client.respond_to::<ShowMessageRequest, _>(|params| {
+                params.actions.and_then(|mut actions| actions.pop())
+            });
+            let server = TestHarness::spawn_with_client(
+                MessagingServer,
+                ClientCapabilities::default(),
+                client,
+            )
+            .await
+            .expect("server spawns");
+
+            let hover = server.hover(&url("a.test"), Position::new(0, 0)).await;
+            assert_eq!(hover_string(hover.unwrap()), r#"Some("second")"#);
+
+            let client = server.client();
+            for (typ, message) in [
+                (MessageType::INFO, "info"),
+                (MessageType::WARNING, "warning"),
+                (MessageType::ERROR, "error"),
+            ] {
+                let params = client.expect_notification::<ShowMessage>().await.unwrap();
+                assert_eq!((params.typ, params.message.as_str()), (typ, message));
+            }
 
-        let _ = state.handle_document_change::<TestServer>(DidChangeTextDocumentParams {
-            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
-            content_changes: vec![TextDocumentContentChangeEvent {
+            let params = client.expect_notification::<LogMessage>().await.unwrap();
+            assert_eq!(
+                (params.typ, params.message.as_str()),
+                (MessageType::LOG, "log")
+            );
+
+            let params = client.expect_request::<ShowMessageRequest>().await.unwrap();
+            assert_eq!(params.typ, MessageType::INFO);
+            assert_eq!(params.message, "pick one");
+            let titles = params
+                .actions
+                .unwrap()
+                .into_iter()
+                .map(|action| action.title)
+                .collect::<Vec<_>>();
+            assert_eq!(titles, ["first", "second"]);
+        });
+    }
+
+    #[test]
+    fn dismissed_message_requests_pick_nothing() {
+        runtime().block_on(async {
+            let server = TestHarness::spawn(MessagingServer)
+                .await
+                .expect("server spawns");
+            let hover = server.hover(&url("a.test"), Position::new(0, 0)).await;
+            assert_eq!(hover_string(hover.unwrap()), "None");
+        });
+    }
+
+    #[derive(Clone)]
+    struct ShowingServer;
+
+    impl Server for ShowingServer {
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            let url = params.text_document_position_params.text_document.uri;
+            let range = Range::new(Position::new(0, 4), Position::new(0, 6));
+            let shown = state.show_document(url, Some(range), true).await?;
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(shown.to_string())),
                 range: None,
-                range_length: None,
-                text: "new".into(),
-            }],
+            }))
+        }
+    }
+
+    #[test]
+    fn show_document_returns_the_client_result() {
+        runtime().block_on(async {
+            let client = MockClient::new();
+            client.respond_to::<ShowDocument, _>(|params| ShowDocumentResult {
+                success: params.take_focus == Some(true),
+            });
+            let server = TestHarness::spawn_with_client(
+                ShowingServer,
+                ClientCapabilities::default(),
+                client,
+            )
+            .await
+            .expect("server spawns");
+
+            let uri = url("show.test");
+            server.open_document(&uri, "test", "🙂abc").unwrap();
+            let hover = server.hover(&uri, Position::new(0, 0)).await;
+            assert_eq!(hover_string(hover.unwrap()), "true");
+
+            let params = server
+                .client()
+                .expect_request::<ShowDocument>()
+                .await
+                .unwrap();
+            assert_eq!(params.uri, uri);
+            assert_eq!(params.external, None);
+            assert_eq!(params.take_focus, Some(true));
+            assert_eq!(
+                params.selection,
+                Some(Range::new(Position::new(0, 2), Position::new(0, 4)))
+            );
         });
+    }
 
-        assert_eq!(state.document(&uri).unwrap().text_contents(), "new");
-        assert_eq!(state.document(&uri).unwrap().version(), 2);
+    #[test]
+    fn work_done_progress_cancel_cancels_matching_tokens() {
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let first = NumberOrString::String("first".into());
+        let second = NumberOrString::Number(2);
+        let first_cancellation = state.progress_cancellation(&first);
+        let second_cancellation = state.progress_cancellation(&second);
+
+        let _ = state.handle_work_done_progress_cancel(WorkDoneProgressCancelParams {
+            token: first.clone(),
+        });
+
+        assert!(first_cancellation.is_cancelled());
+        assert!(!second_cancellation.is_cancelled());
+        assert!(!state.progress_cancellation(&first).is_cancelled());
     }
 
     #[test]
@@ -698,6 +3085,33 @@ mod tests {
         fs::remove_dir_all(root).expect("temp workspace can be removed");
     }
 
+    #[test]
+    fn unopened_documents_can_be_read_from_disk() {
+        let root = temp_workspace("open-from-disk");
+        let manifest = root.join("a.test");
+        fs::write(&manifest, "disk").expect("test file can be written");
+        let uri = Url::from_file_path(&manifest).expect("path can be converted to a URL");
+
+        let mut state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        let document = futures::executor::block_on(state.open_from_disk(&uri))
+            .expect("document can be read from disk");
+
+        assert_eq!(document.text_contents(), "disk");
+        assert_eq!(document.matched_name(), Some("Test"));
+        assert_eq!(state.document_workspace_version(&uri), None);
+
+        open_document(&mut state, uri.clone(), "open");
+        let document = futures::executor::block_on(state.open_from_disk(&uri))
+            .expect("open document is returned as-is");
+        assert_eq!(document.text_contents(), "open");
+
+        let missing = Url::from_file_path(root.join("missing.test")).unwrap();
+        assert!(futures::executor::block_on(state.open_from_disk(&missing)).is_err());
+        assert!(state.document(&missing).is_none());
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
     #[test]
     fn workspace_refresh_preserves_open_documents() {
         let root = temp_workspace("open-document");
@@ -721,6 +3135,77 @@ mod tests {
         fs::remove_dir_all(root).expect("temp workspace can be removed");
     }
 
+    #[test]
+    fn paths_are_relative_to_the_innermost_workspace_folder() {
+        let root = temp_workspace("relative-paths");
+        let nested = root.join("nested");
+        fs::create_dir_all(&nested).expect("nested folder can be created");
+        fs::write(root.join("a.test"), "a").expect("test file can be written");
+        fs::write(nested.join("b.test"), "b").expect("test file can be written");
+        let root = fs::canonicalize(root).expect("root can be canonicalized");
+        let nested = root.join("nested");
+
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        state.set_workspace_folders([workspace_folder(&root), workspace_folder(&nested)]);
+
+        let file_url = |path: PathBuf| Url::from_file_path(path).unwrap();
+        assert_eq!(
+            state.relativize(&file_url(root.join("a.test"))),
+            Some(PathBuf::from("a.test"))
+        );
+        assert_eq!(
+            state.relativize(&file_url(nested.join("b.test"))),
+            Some(PathBuf::from("b.test"))
+        );
+        assert_eq!(state.relativize(&url("outside.test")), None);
+
+        assert_eq!(
+            state.resolve("b.test"),
+            Some(file_url(nested.join("b.test")))
+        );
+        assert_eq!(
+            state.resolve("missing.test"),
+            Some(file_url(root.join("missing.test")))
+        );
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
+    #[test]
+    fn created_files_are_tracked_when_enabled() {
+        let root = temp_workspace("track-watched-files");
+        fs::write(root.join("a.test"), "created").expect("test file can be written");
+        fs::write(root.join("b.txt"), "created").expect("test file can be written");
+        let root = fs::canonicalize(root).expect("root can be canonicalized");
+        let matched = Url::from_file_path(root.join("a.test")).unwrap();
+        let unmatched = Url::from_file_path(root.join("b.txt")).unwrap();
+
+        let state = ServerState::new::<TestServer>(ClientSocket::new_closed());
+        state.set_workspace_folders([workspace_folder(&root)]);
+        state.handle_watched_file_change(&matched, FileChangeType::CREATED);
+        assert!(state.document(&matched).is_none());
+
+        let state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_track_watched_files(true),
+        );
+        state.set_workspace_folders([workspace_folder(&root)]);
+        state.handle_watched_file_change(&matched, FileChangeType::CREATED);
+        state.handle_watched_file_change(&unmatched, FileChangeType::CREATED);
+        state.handle_watched_file_change(&url("outside.test"), FileChangeType::CHANGED);
+
+        let document = state.document(&matched).expect("created file is tracked");
+        assert_eq!(document.text_contents(), "created");
+        assert_eq!(document.matched_name(), Some("Test"));
+        assert!(!state.document_is_open(&matched));
+        assert_eq!(state.document_urls(), vec![matched.clone()]);
+
+        state.handle_watched_file_change(&matched, FileChangeType::DELETED);
+        assert!(state.document(&matched).is_none());
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
     #[test]
     fn closing_workspace_documents_keeps_disk_snapshot() {
         let root = temp_workspace("close-workspace-document");
@@ -743,6 +3228,58 @@ mod tests {
         fs::remove_dir_all(root).expect("temp workspace can be removed");
     }
 
+    #[test]
+    fn saved_documents_are_read_from_percent_decoded_paths() {
+        let root = temp_workspace("percent-decoded");
+        let file = root.join("a b.test");
+        fs::write(&file, "disk").expect("test file can be written");
+        let uri = Url::from_file_path(&file).expect("path can be converted to a URL");
+        assert!(uri.path().contains("%20"));
+
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default().with_save_reads_from_disk(true),
+        );
+        open_document(&mut state, uri.clone(), "open");
+        let _ = state.handle_document_save::<TestServer>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            text: None,
+        });
+
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "disk");
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
+    #[test]
+    fn custom_path_resolvers_are_used_for_disk_reads() {
+        let root = temp_workspace("path-resolver");
+        fs::write(root.join("a.test"), "disk").expect("test file can be written");
+        let uri = Url::parse("remote://host/a.test").unwrap();
+
+        let resolver_root = root.clone();
+        let mut state = ServerState::with_options::<TestServer>(
+            ClientSocket::new_closed(),
+            ServerOptions::default()
+                .with_save_reads_from_disk(true)
+                .with_path_resolver(move |url: &Url| {
+                    (url.scheme() == "remote").then(|| resolver_root.join(&url.path()[1..]))
+                }),
+        );
+        assert_eq!(state.file_path(&uri), Some(root.join("a.test")));
+        assert_eq!(state.file_path(&url("a.test")), None);
+
+        open_document(&mut state, uri.clone(), "open");
+        let _ = state.handle_document_save::<TestServer>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            text: None,
+        });
+
+        assert_eq!(state.document(&uri).unwrap().text_contents(), "disk");
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
     #[test]
     fn closing_workspace_documents_removes_them_when_workspace_diagnostics_are_disabled() {
         let root = temp_workspace("close-disabled-workspace-document");
@@ -786,4 +3323,62 @@ mod tests {
 
         fs::remove_dir_all(root).expect("temp workspace can be removed");
     }
+
+    #[derive(Clone)]
+    struct RefreshingServer;
+
+    impl Server for RefreshingServer {
+        async fn hover(&self, state: ServerState, _: HoverParams) -> ServerResult<Option<Hover>> {
+            let refreshed = [
+                state.request_semantic_tokens_refresh().await?,
+                state.request_inlay_hint_refresh().await?,
+                state.request_inline_value_refresh().await?,
+                state.request_diagnostics_refresh().await?,
+            ];
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(format!("{refreshed:?}"))),
+                range: None,
+            }))
+        }
+    }
+
+    #[test]
+    fn refresh_requests_are_only_sent_when_supported() {
+        runtime().block_on(async {
+            let capabilities = serde_json::from_value(serde_json::json!({
+                "workspace": {
+                    "semanticTokens": { "refreshSupport": true },
+                    "inlayHint": { "refreshSupport": false },
+                    "diagnostic": { "refreshSupport": true },
+                },
+            }))
+            .unwrap();
+            let server = TestHarness::spawn_with_capabilities(RefreshingServer, capabilities)
+                .await
+                .expect("server spawns");
+
+            let url = Url::parse("file:///tmp/refresh.test").unwrap();
+            let hover = server.hover(&url, Position::new(0, 0)).await.unwrap();
+            assert_eq!(
+                hover.map(|h| h.contents),
+                Some(HoverContents::Scalar(MarkedString::String(
+                    "[true, false, false, true]".into()
+                )))
+            );
+
+            let client = server.client();
+            client
+                .expect_request::<SemanticTokensRefresh>()
+                .await
+                .unwrap();
+            client
+                .expect_request::<WorkspaceDiagnosticRefresh>()
+                .await
+                .unwrap();
+            client
+                .expect_silence(Duration::from_millis(20))
+                .await
+                .unwrap();
+        });
+    }
 }
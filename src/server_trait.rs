@@ -3,21 +3,28 @@
 #![allow(clippy::unused_async)]
 #![allow(clippy::must_use_candidate)]
 
+use std::sync::Arc;
+
 use async_lsp::{
     ErrorCode,
     lsp_types::{
         ClientCapabilities, CodeAction, CodeActionParams, CodeActionResponse, CompletionItem,
         CompletionParams, CompletionResponse, Diagnostic, DocumentDiagnosticParams,
         DocumentDiagnosticReportResult, DocumentFormattingParams, DocumentLink, DocumentLinkParams,
-        DocumentRangeFormattingParams, GotoDefinitionParams, GotoDefinitionResponse, Hover,
-        HoverParams, Location, PrepareRenameResponse, ReferenceParams, RenameParams,
-        ServerCapabilities, ServerInfo, TextDocumentPositionParams, TextEdit, Url, WorkspaceEdit,
+        DocumentRangeFormattingParams, DocumentSymbol, GotoDefinitionParams,
+        GotoDefinitionResponse, Hover, HoverParams, Location, PrepareRenameResponse,
+        ReferenceParams, RenameParams, ServerCapabilities, ServerInfo, TextDocumentPositionParams,
+        TextEdit, Url, WorkspaceEdit,
         request::{GotoDeclarationParams, GotoDeclarationResponse},
     },
 };
 
 use crate::{
+    custom_notifications::CustomNotifications,
+    custom_requests::CustomRequests,
+    document::{Document, DocumentChangeEvent},
     document_matcher::DocumentMatcher,
+    interceptor::Interceptor,
     result::{ServerError, ServerResult},
     server_options::ServerOptions,
     server_state::ServerState,
@@ -50,6 +57,161 @@ pub trait Server {
         vec![]
     }
 
+    fn server_custom_requests() -> CustomRequests<Self>
+    where
+        Self: Sized,
+    {
+        CustomRequests::new()
+    }
+
+    fn server_custom_notifications() -> CustomNotifications<Self>
+    where
+        Self: Sized,
+    {
+        CustomNotifications::new()
+    }
+
+    fn server_interceptors() -> Vec<Arc<dyn Interceptor>> {
+        vec![]
+    }
+
+    // Document Lifecycle
+
+    /**
+        Called with a snapshot of a document after it was opened by the client.
+
+        Document lifecycle hooks run in the background, in the order that
+        the respective notifications were received, and do not block the
+        handling of any other notifications or requests. They require a
+        tokio runtime, and are not called when one is not available.
+    */
+    fn document_opened(
+        &self,
+        state: ServerState,
+        document: Document,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /**
+        Called with a snapshot of a document after it was changed by the client.
+
+        See [`Server::document_opened`] for more information.
+    */
+    fn document_changed(
+        &self,
+        state: ServerState,
+        document: Document,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /**
+        Called with a structured description of the edits made to a document
+        after it was changed by the client, right before [`Server::document_changed`].
+
+        This may be used by incremental analyzers to update their
+        own data structures, without re-reading the whole document.
+
+        See [`Server::document_opened`] for more information.
+    */
+    fn document_edited(
+        &self,
+        state: ServerState,
+        event: DocumentChangeEvent,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /**
+        Called with the URL of a document after an incremental update from the
+        client could not be applied to it, and the document was recovered.
+
+        When using [`SyncRecovery::Resync`], this is also called for every
+        change to the document until its full contents have been received.
+
+        See [`SyncRecovery`] for the available recovery strategies, and
+        [`Server::document_opened`] for more information about hooks.
+
+        [`SyncRecovery`]: crate::server::SyncRecovery
+        [`SyncRecovery::Resync`]: crate::server::SyncRecovery::Resync
+    */
+    fn document_sync_failed(
+        &self,
+        state: ServerState,
+        url: Url,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /**
+        Called with the URL of a document after parsing it using tree-sitter took
+        longer than the parse timeout, and the document was kept as text only.
+
+        Unlike other document hooks, this may be called at any time, since
+        documents may also be parsed in the background, and is not
+        ordered relative to any other hooks for the same document.
+
+        See [`ServerOptions::with_parse_timeout`] for more information.
+
+        [`ServerOptions::with_parse_timeout`]: crate::server::ServerOptions::with_parse_timeout
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn document_parse_timed_out(
+        &self,
+        state: ServerState,
+        url: Url,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /**
+        Called with a snapshot of a document after it was saved by the client.
+
+        See [`Server::document_opened`] for more information.
+    */
+    fn document_saved(
+        &self,
+        state: ServerState,
+        document: Document,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /**
+        Called with the last snapshot of a document after it was closed by the client.
+
+        See [`Server::document_opened`] for more information.
+    */
+    fn document_closed(
+        &self,
+        state: ServerState,
+        document: Document,
+    ) -> impl Future<Output = ServerResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    // Workspace Indexing
+
+    /**
+        Called with a snapshot of a document in the workspace folders of
+        the client, returning the symbols that should be stored for it in
+        the [`WorkspaceIndex`].
+
+        Only called if workspace indexing has been enabled using
+        [`ServerOptions::with_workspace_indexing`]. Documents that fail
+        to be indexed are left out of the index.
+
+        [`WorkspaceIndex`]: crate::server::WorkspaceIndex
+    */
+    fn index_document(
+        &self,
+        state: ServerState,
+        document: Document,
+    ) -> impl Future<Output = ServerResult<Vec<DocumentSymbol>>> + Send {
+        async { Ok(Vec::new()) }
+    }
+
     // Hover, Completion, Code Action, Document Link
 
     fn hover(
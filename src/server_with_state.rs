@@ -1,22 +1,35 @@
-use std::{ops::ControlFlow, sync::Arc};
+use std::{
+    any::Any, future::Future, ops::ControlFlow, panic::AssertUnwindSafe, sync::Arc, time::Duration,
+};
 
 use async_lsp::{
-    ClientSocket, ErrorCode, LanguageServer, ResponseError, Result,
+    AnyNotification, AnyRequest, ClientSocket, ErrorCode, LanguageServer, ResponseError, Result,
     lsp_types::{
-        DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWorkspaceFoldersParams,
-        DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
-        InitializeParams, InitializeResult, InitializedParams, SaveOptions,
-        TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
-        TextDocumentSyncSaveOptions, Url, WorkspaceDiagnosticParams,
+        DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+        DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+        DidSaveTextDocumentParams, InitializeParams, InitializeResult, InitializedParams,
+        SaveOptions, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+        TextDocumentSyncSaveOptions, Url, WorkDoneProgressCancelParams, WorkspaceDiagnosticParams,
         WorkspaceDiagnosticReportResult, WorkspaceFolder,
     },
 };
-use futures::future::BoxFuture;
+use futures::{FutureExt, future::BoxFuture};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
 
 #[cfg(feature = "tracing")]
 use tracing::{debug, info};
 
-use crate::{server_state::ServerState, server_trait::Server, text_utils::Encoding};
+use crate::{
+    cancellation::CancellationToken, custom_notifications::CustomNotifications,
+    custom_requests::CustomRequests, document::DocumentChangeEvent, in_flight::InFlightRequests,
+    server_state::ServerState, server_trait::Server, text_utils::Encoding, watched_files,
+    workspace_index,
+};
+
+#[cfg(feature = "watch")]
+use crate::file_watcher::FileWatcher;
+
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 const POSITION_ENCODING_PREFERRED_ORDER: [Encoding; 3] = [
     // First, prefer to use UTF-8 encoding, since this will make all of
@@ -32,55 +45,139 @@ const POSITION_ENCODING_PREFERRED_ORDER: [Encoding; 3] = [
 
 macro_rules! implement_method {
     ($async_lsp_method:ident => $our_server_trait_method:ident @ $request_type:ty) => {
+        #[allow(clippy::too_many_lines)]
         fn $async_lsp_method(
             &mut self,
-            mut params: <$request_type as crate::requests::Request>::Params,
+            params: <$request_type as crate::requests::Request>::Params,
         ) -> BoxFuture<
             'static,
             Result<<$request_type as crate::requests::Request>::Response, Self::Error>,
         > {
             let server = Arc::clone(&self.server);
             let state = self.state.clone();
+            let timeout = self.request_timeout;
+            let retries = self.content_modified_retries;
+            let in_flight = self.in_flight.start();
             Box::pin(async move {
+                let _in_flight = in_flight;
+
+                // 0. Associate the state with the cancellation token for this request
+                let cancellation = crate::cancellation::current_request_cancellation();
+                let state = state.with_request_cancellation(cancellation.clone());
+
                 // 1. Try to extract the URL from the params for document tracking
                 let url: Option<Url> =
                     <$request_type as crate::requests::Request>::extract_url(&params);
-                let mut ver: Option<i32> = None;
-
-                // 2. If we got an URL, track the document version & call the "modify params" callback
-                if let Some(url) = url.as_ref() {
-                    if let Some(doc) = state.document(url) {
-                        ver.replace(doc.version());
-                        <$request_type as crate::requests::Request>::modify_params(
-                            &state,
-                            &doc,
-                            &mut params,
-                        );
+                let previous_result_id =
+                    <$request_type as crate::requests::Request>::extract_previous_result_id(
+                        &params,
+                    );
+
+                // 1a. Reject requests that would edit read-only documents up front
+                if <$request_type as crate::requests::Request>::REJECTS_READ_ONLY {
+                    if let Some(url) = url.as_ref().filter(|url| state.url_is_read_only(url)) {
+                        return Err(read_only_error(url));
                     }
                 }
 
-                // 3. Call the user-defined language server function
-                let mut result = server
-                    .$our_server_trait_method(state.clone(), params)
-                    .await?;
+                // 1b. Skip the handler if the client already has the current result
+                if let Some(result) = unchanged_response::<$request_type>(
+                    &state,
+                    url.as_ref(),
+                    previous_result_id.as_deref(),
+                ) {
+                    return Ok(result);
+                }
 
-                // 4. Check our document again, if we had one originally
-                if let Some(url) = url.as_ref() {
-                    if let Some(doc) = state.document(url) {
-                        // 4a. If the version changed, our result is stale, and we should try again
-                        if ver.is_some_and(|v| v != doc.version()) {
-                            return Err(ResponseError::new(
-                                ErrorCode::CONTENT_MODIFIED,
-                                "document was modified during processing",
-                            ));
+                // 2. Handle the request, retrying with a fresh document snapshot if the
+                //    document is modified during processing and retries are enabled
+                let mut original_params = Some(params);
+                let mut attempt = 0;
+                let (mut result, version) = loop {
+                    let mut params = if attempt < retries {
+                        original_params.clone()
+                    } else {
+                        original_params.take()
+                    }
+                    .expect("params are kept until the last attempt");
+                    let mut ver: Option<i32> = None;
+
+                    // 2a. If we got an URL, track the document version & call the "modify params" callback
+                    if let Some(url) = url.as_ref() {
+                        if let Some(doc) = state.document(url) {
+                            ver.replace(doc.version());
+                            #[cfg(feature = "tracing")]
+                            crate::layers::record_document_version(doc.version());
+                            <$request_type as crate::requests::Request>::modify_params(
+                                &state,
+                                &doc,
+                                &mut params,
+                            )
+                            .map_err(|e| {
+                                ResponseError::new(ErrorCode::INVALID_PARAMS, e.to_string())
+                            })?;
+                        }
+                    }
+
+                    // 3. Call the user-defined language server function
+                    let mut result = with_timeout(
+                        server.$our_server_trait_method(state.clone(), params),
+                        timeout,
+                        &cancellation,
+                    )
+                    .await??;
+                    if cancellation.is_cancelled() {
+                        return Err(ResponseError::new(
+                            ErrorCode::REQUEST_CANCELLED,
+                            "request was cancelled during processing",
+                        ));
+                    }
+
+                    // 4. Check our document again, if we had one originally
+                    if let Some(url) = url.as_ref() {
+                        if let Some(doc) = state.document(url) {
+                            // 4a. If the version changed, our result is stale, and we should try again
+                            if ver.is_some_and(|v| v != doc.version()) {
+                                if attempt < retries {
+                                    attempt += 1;
+                                    continue;
+                                }
+                                return Err(ResponseError::new(
+                                    ErrorCode::CONTENT_MODIFIED,
+                                    "document was modified during processing",
+                                ));
+                            }
+                            // 4b. Version is not stale, run the final "modify response" callback
+                            <$request_type as crate::requests::Request>::modify_response(
+                                &state,
+                                &doc,
+                                &mut result,
+                            );
                         }
-                        // 4b. Version is not stale, run the final "modify response" callback
-                        <$request_type as crate::requests::Request>::modify_response(
-                            &state,
-                            &doc,
-                            &mut result,
-                        );
                     }
+
+                    break (result, ver);
+                };
+
+                // 5. Make sure that no edits for read-only documents reach the client
+                if let Some(url) =
+                    <$request_type as crate::requests::Request>::reject_read_only_edits(
+                        &state,
+                        &mut result,
+                    )
+                {
+                    return Err(read_only_error(&url));
+                }
+
+                // 6. Let the client know if the result did not change since its previous request
+                if let Some(url) = url.as_ref() {
+                    <$request_type as crate::requests::Request>::apply_result_id(
+                        &state,
+                        url,
+                        version,
+                        previous_result_id.as_deref(),
+                        &mut result,
+                    );
                 }
 
                 Ok(result)
@@ -97,6 +194,95 @@ macro_rules! implement_methods {
     };
 }
 
+fn unchanged_response<R: crate::requests::Request>(
+    state: &ServerState,
+    url: Option<&Url>,
+    previous_result_id: Option<&str>,
+) -> Option<R::Response> {
+    let doc = state.document(url?)?;
+    R::unchanged_response(state, &doc, previous_result_id)
+}
+
+pub(crate) fn read_only_error(url: &Url) -> ResponseError {
+    ResponseError::new(
+        ErrorCode::REQUEST_FAILED,
+        format!("document is read-only: {url}"),
+    )
+}
+
+pub(crate) async fn with_timeout<F: Future>(
+    future: F,
+    timeout: Option<Duration>,
+    cancellation: &CancellationToken,
+) -> Result<F::Output, ResponseError> {
+    let Some(timeout) = timeout.filter(|_| timers_available("request timeout")) else {
+        return Ok(future.await);
+    };
+
+    if let Ok(output) = tokio::time::timeout(timeout, future).await {
+        Ok(output)
+    } else {
+        cancellation.cancel();
+        Err(ResponseError::new(
+            ErrorCode::REQUEST_CANCELLED,
+            format!("request timed out after {}ms", timeout.as_millis()),
+        ))
+    }
+}
+
+/**
+    Checks if there is a tokio runtime that timeouts can be driven by.
+
+    Timeouts are ignored, and an error is emitted, when there is no runtime.
+    Runtimes without timers enabled are not detected, and will panic
+    when a timeout is used, same as any other use of `tokio::time`.
+*/
+fn timers_available(kind: &str) -> bool {
+    let available = tokio::runtime::Handle::try_current().is_ok();
+    #[cfg(feature = "tracing")]
+    if !available {
+        tracing::error!("Ignoring {kind} since the server is not running within a tokio runtime");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = kind;
+    available
+}
+
+/**
+    Catches any panic in the given background hook future, such as a
+    document hook, emitting an error instead of unwinding further.
+
+    Panics in request handlers are not caught here, since those are already
+    turned into `INTERNAL_ERROR` responses by the `CatchUnwindLayer` used in
+    [`serve`], which only covers requests, and not hooks or notifications.
+
+    [`serve`]: crate::server::serve
+*/
+#[allow(unused_variables)]
+pub(crate) async fn catch_panic<F: Future>(hook: &str, future: F) -> Option<F::Output> {
+    AssertUnwindSafe(future)
+        .catch_unwind()
+        .await
+        .inspect_err(|payload| {
+            #[cfg(feature = "tracing")]
+            tracing::error!(
+                "Hook '{hook}' panicked: {}",
+                panic_message(payload.as_ref())
+            );
+        })
+        .ok()
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
 fn workspace_folders(params: &InitializeParams) -> Vec<WorkspaceFolder> {
     if let Some(folders) = params.workspace_folders.clone() {
         return folders;
@@ -131,14 +317,211 @@ fn workspace_folders(params: &InitializeParams) -> Vec<WorkspaceFolder> {
 pub(crate) struct LanguageServerWithState<T: Server> {
     server: Arc<T>,
     state: ServerState,
+    request_timeout: Option<Duration>,
+    content_modified_retries: usize,
+    shutdown_timeout: Duration,
+    workspace_indexing: bool,
+    full_text_sync: bool,
+    save_text: bool,
+    in_flight: InFlightRequests,
+    document_hooks: Option<UnboundedSender<BoxFuture<'static, ()>>>,
+    #[cfg(feature = "watch")]
+    file_watcher: Option<FileWatcher>,
+    custom_requests: Arc<CustomRequests<T>>,
+    custom_notifications: Arc<CustomNotifications<T>>,
 }
 
 impl<T: Server> LanguageServerWithState<T> {
     pub(crate) fn new(client: ClientSocket, server: T) -> Self {
         let options = server.server_options();
+        let request_timeout = options.request_timeout;
+        let content_modified_retries = options.content_modified_retries;
+        let shutdown_timeout = options.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+        let workspace_indexing = options.workspace_indexing;
+        let full_text_sync = options.full_text_sync;
+        let save_text = !options.exclude_save_text;
         let server = Arc::new(server);
         let state = ServerState::with_options::<T>(client, options);
-        Self { server, state }
+        let custom_requests = Arc::new(T::server_custom_requests());
+        let custom_notifications = Arc::new(T::server_custom_notifications());
+        Self {
+            server,
+            state,
+            request_timeout,
+            content_modified_retries,
+            shutdown_timeout,
+            workspace_indexing,
+            full_text_sync,
+            save_text,
+            in_flight: InFlightRequests::default(),
+            document_hooks: None,
+            #[cfg(feature = "watch")]
+            file_watcher: None,
+            custom_requests,
+            custom_notifications,
+        }
+    }
+
+    /**
+        Handles a request that is not part of the builtin
+        LSP methods, using the registered custom requests.
+    */
+    pub(crate) fn custom_request(
+        &mut self,
+        req: AnyRequest,
+    ) -> BoxFuture<'static, Result<serde_json::Value, ResponseError>> {
+        let in_flight = self.in_flight.start();
+        let fut = self.custom_requests.handle(
+            Arc::clone(&self.server),
+            self.state.clone(),
+            &req.method,
+            req.params,
+            self.request_timeout,
+        );
+        Box::pin(async move {
+            let _in_flight = in_flight;
+            fut.await
+        })
+    }
+
+    /**
+        Handles a notification that is not part of the builtin
+        LSP methods, using the registered custom notifications.
+    */
+    pub(crate) fn custom_notification(
+        &mut self,
+        notif: AnyNotification,
+    ) -> ControlFlow<async_lsp::Result<()>> {
+        self.custom_notifications.handle(
+            &self.server,
+            self.state.clone(),
+            &notif.method,
+            notif.params,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+enum DocumentHook {
+    Opened,
+    Changed,
+    Edited(DocumentChangeEvent),
+    SyncFailed,
+    Saved,
+    Closed,
+}
+
+impl DocumentHook {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Opened => "document_opened",
+            Self::Changed => "document_changed",
+            Self::Edited(_) => "document_edited",
+            Self::SyncFailed => "document_sync_failed",
+            Self::Saved => "document_saved",
+            Self::Closed => "document_closed",
+        }
+    }
+}
+
+impl<T: Server + Send + Sync + 'static> LanguageServerWithState<T> {
+    /**
+        Lets the state run [`Server::document_parse_timed_out`] whenever
+        parsing a document times out, even when parsing in the background.
+    */
+    #[cfg(feature = "tree-sitter")]
+    fn install_parse_timeout_hook(&mut self) {
+        let server = Arc::clone(&self.server);
+        self.state.set_parse_timeout_hook(move |state, url| {
+            let server = Arc::clone(&server);
+            Box::pin(async move {
+                let name = "document_parse_timed_out";
+                let result = catch_panic(name, server.document_parse_timed_out(state, url));
+                #[allow(unused_variables)]
+                if let Some(Err(e)) = result.await {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!("Document hook {name} failed: {e}");
+                }
+            })
+        });
+    }
+
+    /**
+        Queues the given document lifecycle hook to run in the background, using a
+        snapshot of the document, making sure that hooks run in the order received.
+    */
+    fn run_document_hook(&mut self, url: &Url, hook: DocumentHook) {
+        let document = self.state.document(url);
+        if document.is_none() && !matches!(hook, DocumentHook::SyncFailed) {
+            return;
+        }
+
+        if self
+            .document_hooks
+            .as_ref()
+            .is_none_or(UnboundedSender::is_closed)
+        {
+            let Ok(handle) = tokio::runtime::Handle::try_current() else {
+                return;
+            };
+            let (sender, mut receiver) = unbounded_channel::<BoxFuture<'static, ()>>();
+            handle.spawn(async move {
+                while let Some(hook) = receiver.recv().await {
+                    hook.await;
+                }
+            });
+            self.document_hooks = Some(sender);
+        }
+
+        let server = Arc::clone(&self.server);
+        let state = self.state.clone();
+        let url = url.clone();
+        let fut: BoxFuture<'static, ()> = Box::pin(async move {
+            let name = hook.name();
+            let result = match (hook, document) {
+                (DocumentHook::Edited(event), _) => {
+                    catch_panic(name, server.document_edited(state, event)).await
+                }
+                (DocumentHook::SyncFailed, _) => {
+                    catch_panic(name, server.document_sync_failed(state, url)).await
+                }
+                (_, None) => return,
+                (DocumentHook::Opened, Some(document)) => {
+                    catch_panic(name, server.document_opened(state, document)).await
+                }
+                (DocumentHook::Changed, Some(document)) => {
+                    catch_panic(name, server.document_changed(state, document)).await
+                }
+                (DocumentHook::Saved, Some(document)) => {
+                    catch_panic(name, server.document_saved(state, document)).await
+                }
+                (DocumentHook::Closed, Some(document)) => {
+                    catch_panic(name, server.document_closed(state, document)).await
+                }
+            };
+            #[allow(unused_variables)]
+            if let Some(Err(e)) = result {
+                #[cfg(feature = "tracing")]
+                tracing::error!("Document hook {name} failed: {e}");
+            }
+        });
+
+        if let Some(sender) = &self.document_hooks {
+            let _ = sender.send(fut);
+        }
+    }
+
+    /**
+        (Re)starts watching the current workspace folders for file changes.
+    */
+    #[cfg(feature = "watch")]
+    fn watch_files(&mut self) {
+        let server = Arc::clone(&self.server);
+        let state = self.state.clone();
+        let indexing = self.workspace_indexing;
+        self.file_watcher = FileWatcher::spawn(&self.state.workspace_roots(), move |changes| {
+            watched_files::apply_changes(&server, &state, indexing, changes);
+        });
     }
 }
 
@@ -176,6 +559,9 @@ impl<T: Server + Send + Sync + 'static> LanguageServer for LanguageServerWithSta
             &self.state,
             initialization_options.as_ref(),
         );
+        if self.workspace_indexing {
+            crate::workspace_diagnostics::enable_workspace_folders(&mut result);
+        }
 
         // 3. Try to figure out what position encoding best matches what
         //    both our server + the connected client prefers / supports
@@ -194,13 +580,18 @@ impl<T: Server + Send + Sync + 'static> LanguageServer for LanguageServerWithSta
         }
 
         // 4. Insert capabilities for our automatic handling of encodings & documents
+        let sync_kind = if self.full_text_sync {
+            TextDocumentSyncKind::FULL
+        } else {
+            TextDocumentSyncKind::INCREMENTAL
+        };
         result.capabilities.position_encoding = Some(negotiated_position_encoding.into_lsp());
         result.capabilities.text_document_sync = Some(TextDocumentSyncCapability::Options(
             TextDocumentSyncOptions {
-                change: Some(TextDocumentSyncKind::INCREMENTAL),
+                change: Some(sync_kind),
                 open_close: Some(true),
                 save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
-                    include_text: Some(true),
+                    include_text: Some(self.save_text),
                 })),
                 ..Default::default()
             },
@@ -210,6 +601,10 @@ impl<T: Server + Send + Sync + 'static> LanguageServer for LanguageServerWithSta
         self.state
             .set_position_encoding(negotiated_position_encoding);
         self.state.set_workspace_folders(workspace_folders.clone());
+        self.state
+            .set_client_details(client_capabilities, params.client_info.clone());
+        #[cfg(feature = "tree-sitter")]
+        self.install_parse_timeout_hook();
 
         // 6. Emit a useful message about the negotiation, if enabled
         #[cfg(feature = "tracing")]
@@ -250,8 +645,38 @@ impl<T: Server + Send + Sync + 'static> LanguageServer for LanguageServerWithSta
 
     // Document notification callbacks & content updating
 
+    fn shutdown(&mut self, (): ()) -> BoxFuture<'static, Result<(), Self::Error>> {
+        let in_flight = self.in_flight.clone();
+        let timeout = self.shutdown_timeout;
+        Box::pin(async move {
+            if !timers_available("shutdown timeout") {
+                in_flight.idle().await;
+            } else if tokio::time::timeout(timeout, in_flight.idle())
+                .await
+                .is_err()
+            {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    "Shutting down with {} request(s) still in-flight after {}ms",
+                    in_flight.count(),
+                    timeout.as_millis()
+                );
+            }
+            Ok(())
+        })
+    }
+
     fn initialized(&mut self, _params: InitializedParams) -> ControlFlow<Result<()>> {
         crate::workspace_diagnostics::initialized(self.state.clone());
+        if self.workspace_indexing {
+            workspace_index::index_workspace(Arc::clone(&self.server), self.state.clone());
+        }
+        if watched_files::client_can_watch(&self.state) {
+            watched_files::register(self.state.clone());
+        } else {
+            #[cfg(feature = "watch")]
+            self.watch_files();
+        }
         ControlFlow::Continue(())
     }
 
@@ -270,30 +695,86 @@ impl<T: Server + Send + Sync + 'static> LanguageServer for LanguageServerWithSta
         &mut self,
         params: DidChangeWorkspaceFoldersParams,
     ) -> ControlFlow<Result<()>> {
-        self.state.handle_workspace_folders_change(params)
+        let flow = self.state.handle_workspace_folders_change(params);
+        if self.workspace_indexing {
+            workspace_index::index_workspace(Arc::clone(&self.server), self.state.clone());
+        }
+        #[cfg(feature = "watch")]
+        if self.file_watcher.is_some() {
+            self.watch_files();
+        }
+        flow
+    }
+
+    fn did_change_watched_files(
+        &mut self,
+        params: DidChangeWatchedFilesParams,
+    ) -> ControlFlow<Result<()>> {
+        watched_files::apply_changes(
+            &self.server,
+            &self.state,
+            self.workspace_indexing,
+            params.changes,
+        );
+        ControlFlow::Continue(())
+    }
+
+    fn work_done_progress_cancel(
+        &mut self,
+        params: WorkDoneProgressCancelParams,
+    ) -> ControlFlow<Result<()>> {
+        self.state.handle_work_done_progress_cancel(params)
     }
 
     fn did_open(&mut self, params: DidOpenTextDocumentParams) -> ControlFlow<Result<()>> {
         #[cfg(feature = "tracing")]
         debug!("did_open: {}", params.text_document.uri);
-        self.state.handle_document_open::<T>(params)
+        let url = params.text_document.uri.clone();
+        let flow = self.state.handle_document_open::<T>(params);
+        let _ = self.state.diagnostics().publish_syntax(&url);
+        self.run_document_hook(&url, DocumentHook::Opened);
+        flow
     }
 
     #[allow(unused_variables)]
     fn did_close(&mut self, params: DidCloseTextDocumentParams) -> ControlFlow<Result<()>> {
         #[cfg(feature = "tracing")]
         debug!("did_close: {}", params.text_document.uri);
+        let url = params.text_document.uri.clone();
+        self.run_document_hook(&url, DocumentHook::Closed);
         self.state.handle_document_close::<T>(params)
     }
 
     fn did_change(&mut self, params: DidChangeTextDocumentParams) -> ControlFlow<Result<()>> {
-        self.state.handle_document_change::<T>(params)
+        let url = params.text_document.uri.clone();
+        if let Some(change) = self.state.apply_document_change(params) {
+            if change.sync_failed {
+                self.run_document_hook(&url, DocumentHook::SyncFailed);
+            }
+            if let Some(event) = change.event {
+                self.run_document_hook(&url, DocumentHook::Edited(event));
+            }
+            let _ = self.state.diagnostics().publish_syntax(&url);
+        }
+        self.run_document_hook(&url, DocumentHook::Changed);
+        ControlFlow::Continue(())
     }
 
     fn did_save(&mut self, params: DidSaveTextDocumentParams) -> ControlFlow<Result<()>> {
         #[cfg(feature = "tracing")]
         debug!("did_save: {}", params.text_document.uri);
-        self.state.handle_document_save::<T>(params)
+        let url = params.text_document.uri.clone();
+        let flow = self.state.handle_document_save::<T>(params);
+        let _ = self.state.diagnostics().publish_syntax(&url);
+        self.run_document_hook(&url, DocumentHook::Saved);
+        if self.workspace_indexing {
+            workspace_index::index_tracked_document(
+                Arc::clone(&self.server),
+                self.state.clone(),
+                &url,
+            );
+        }
+        flow
     }
 
     fn workspace_diagnostic(
@@ -334,29 +815,45 @@ mod tests {
         collections::HashMap,
         fs,
         path::PathBuf,
-        time::{SystemTime, UNIX_EPOCH},
+        sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
     };
 
     use async_lsp::{
-        ClientSocket, ErrorCode, LanguageServer,
+        ClientSocket, ErrorCode, LanguageServer, ResponseError,
         lsp_types::{
-            ClientCapabilities, Diagnostic, DiagnosticOptions, DiagnosticServerCapabilities,
-            DidChangeConfigurationParams, DidChangeWorkspaceFoldersParams,
-            DidOpenTextDocumentParams, DocumentDiagnosticParams, DocumentDiagnosticReport,
-            DocumentDiagnosticReportKind, DocumentDiagnosticReportResult,
-            FullDocumentDiagnosticReport, InitializeParams, OneOf, PartialResultParams, Position,
-            PreviousResultId, Range, RelatedFullDocumentDiagnosticReport, ServerCapabilities,
-            TextDocumentItem, Url, WorkDoneProgressParams, WorkspaceDiagnosticParams,
-            WorkspaceDiagnosticReportResult, WorkspaceDocumentDiagnosticReport, WorkspaceFolder,
+            ClientCapabilities, CodeAction, CodeActionContext, CodeActionOrCommand,
+            CodeActionParams, CodeActionResponse, Diagnostic, DiagnosticOptions,
+            DiagnosticRelatedInformation, DiagnosticServerCapabilities,
+            DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+            DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+            DidSaveTextDocumentParams, DocumentDiagnosticParams, DocumentDiagnosticReport,
+            DocumentDiagnosticReportKind, DocumentDiagnosticReportResult, DocumentFormattingParams,
+            DocumentSymbol, FileChangeType, FileEvent, FormattingOptions,
+            FullDocumentDiagnosticReport, Hover, HoverContents, HoverParams, InitializeParams,
+            InitializeResult, InitializedParams, Location, MarkedString, OneOf,
+            PartialResultParams, Position, PreviousResultId, Range,
+            RelatedFullDocumentDiagnosticReport, RenameParams, ServerCapabilities, SymbolKind,
+            TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+            TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+            TextDocumentSyncSaveOptions, TextEdit, Url, VersionedTextDocumentIdentifier,
+            WorkDoneProgressParams, WorkspaceDiagnosticParams, WorkspaceDiagnosticReportResult,
+            WorkspaceDocumentDiagnosticReport, WorkspaceEdit, WorkspaceFolder,
             WorkspaceFoldersChangeEvent,
         },
     };
 
     use crate::{
+        cancellation::scope_request_cancellation,
         server::{
-            DocumentMatcher, Server, ServerOptions, ServerResult, ServerState, WorkspaceDiagnostics,
+            CancellationToken, Document, DocumentChangeEvent, DocumentMatcher, Server, ServerError,
+            ServerOptions, ServerResult, ServerState, WorkspaceDiagnostics,
         },
         server_with_state::LanguageServerWithState,
+        testing::TestServer as TestHarness,
     };
 
     struct TestServer;
@@ -403,6 +900,303 @@ mod tests {
         }
     }
 
+    struct SlowServer;
+
+    impl Server for SlowServer {
+        fn server_options(&self) -> ServerOptions {
+            ServerOptions::default().with_request_timeout(Duration::from_millis(10))
+        }
+
+        async fn hover(&self, _: ServerState, _: HoverParams) -> ServerResult<Option<Hover>> {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(None)
+        }
+    }
+
+    struct DrainingServer {
+        delay: Duration,
+        completed: Arc<AtomicBool>,
+    }
+
+    impl Server for DrainingServer {
+        fn server_options(&self) -> ServerOptions {
+            ServerOptions::default().with_shutdown_timeout(Duration::from_millis(100))
+        }
+
+        async fn hover(&self, _: ServerState, _: HoverParams) -> ServerResult<Option<Hover>> {
+            tokio::time::sleep(self.delay).await;
+            self.completed.store(true, Ordering::SeqCst);
+            Ok(None)
+        }
+    }
+
+    struct FullSyncServer;
+
+    impl Server for FullSyncServer {
+        fn server_options(&self) -> ServerOptions {
+            ServerOptions::default()
+                .with_full_text_sync(true)
+                .with_save_text(false)
+        }
+    }
+
+    struct IndexingServer;
+
+    impl Server for IndexingServer {
+        fn server_document_matchers() -> Vec<DocumentMatcher> {
+            test_document_matchers()
+        }
+
+        fn server_options(&self) -> ServerOptions {
+            ServerOptions::default().with_workspace_indexing(true)
+        }
+
+        #[allow(deprecated)]
+        async fn index_document(
+            &self,
+            _: ServerState,
+            document: Document,
+        ) -> ServerResult<Vec<DocumentSymbol>> {
+            let range = Range::default();
+            Ok(vec![DocumentSymbol {
+                name: document.text_contents(),
+                detail: None,
+                kind: SymbolKind::VARIABLE,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range: range,
+                children: None,
+            }])
+        }
+    }
+
+    #[derive(Default)]
+    struct LifecycleServer {
+        events: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl LifecycleServer {
+        fn record(&self, hook: &str, document: &Document) {
+            self.events.lock().unwrap().push(format!(
+                "{hook} v{} {}",
+                document.version(),
+                document.text_contents()
+            ));
+        }
+    }
+
+    impl Server for LifecycleServer {
+        async fn document_opened(&self, _: ServerState, document: Document) -> ServerResult<()> {
+            self.record("opened", &document);
+            Ok(())
+        }
+
+        async fn document_changed(&self, _: ServerState, document: Document) -> ServerResult<()> {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.record("changed", &document);
+            Ok(())
+        }
+
+        async fn document_edited(
+            &self,
+            _: ServerState,
+            event: DocumentChangeEvent,
+        ) -> ServerResult<()> {
+            self.events.lock().unwrap().push(format!(
+                "edited v{}-v{} {:?}",
+                event.old_version, event.new_version, event.edits
+            ));
+            Ok(())
+        }
+
+        async fn document_saved(&self, _: ServerState, document: Document) -> ServerResult<()> {
+            self.record("saved", &document);
+            Ok(())
+        }
+
+        async fn document_closed(&self, _: ServerState, document: Document) -> ServerResult<()> {
+            self.record("closed", &document);
+            Ok(())
+        }
+    }
+
+    #[derive(Clone)]
+    struct PanickingServer;
+
+    impl Server for PanickingServer {
+        async fn hover(&self, _: ServerState, params: HoverParams) -> ServerResult<Option<Hover>> {
+            assert!(
+                params.text_document_position_params.position.line > 0,
+                "hover on the first line"
+            );
+            Ok(None)
+        }
+    }
+
+    struct ReadOnlyServer;
+
+    impl Server for ReadOnlyServer {
+        fn server_document_matchers() -> Vec<DocumentMatcher> {
+            vec![
+                DocumentMatcher::new("Test")
+                    .with_url_globs(["**/*.test"])
+                    .with_lang_strings(["test"])
+                    .with_read_only_globs(["**/generated/**"]),
+            ]
+        }
+
+        async fn document_format(
+            &self,
+            _: ServerState,
+            _: DocumentFormattingParams,
+        ) -> ServerResult<Option<Vec<TextEdit>>> {
+            Ok(Some(Vec::new()))
+        }
+
+        async fn rename(
+            &self,
+            _: ServerState,
+            params: RenameParams,
+        ) -> ServerResult<Option<WorkspaceEdit>> {
+            let target = Url::parse(&params.new_name).unwrap();
+            let edit = TextEdit::new(Range::default(), "renamed".into());
+            Ok(Some(WorkspaceEdit::new(HashMap::from([(
+                target,
+                vec![edit],
+            )]))))
+        }
+
+        async fn code_action(
+            &self,
+            _: ServerState,
+            _: CodeActionParams,
+        ) -> ServerResult<Option<CodeActionResponse>> {
+            let action = |target: &str| {
+                let edit = TextEdit::new(Range::default(), "fixed".into());
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: target.into(),
+                    edit: Some(WorkspaceEdit::new(HashMap::from([(
+                        Url::parse(target).unwrap(),
+                        vec![edit],
+                    )]))),
+                    ..CodeAction::default()
+                })
+            };
+            Ok(Some(vec![
+                action("file:///tmp/generated/fix.test"),
+                action("file:///tmp/fix.test"),
+            ]))
+        }
+    }
+
+    struct RetryingServer {
+        retries: usize,
+        modifications: AtomicUsize,
+    }
+
+    impl Server for RetryingServer {
+        fn server_options(&self) -> ServerOptions {
+            ServerOptions::default().with_content_modified_retries(self.retries)
+        }
+
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            let uri = params.text_document_position_params.text_document.uri;
+            let doc = state.document(&uri).expect("document is open");
+            if self.modifications.load(Ordering::SeqCst) > 0 {
+                self.modifications.fetch_sub(1, Ordering::SeqCst);
+                let version = doc.version() + 1;
+                let mut state = state;
+                let _ = state.apply_document_change(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier::new(uri, version),
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: format!("v{version}"),
+                    }],
+                });
+            }
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(doc.text_contents())),
+                range: None,
+            }))
+        }
+    }
+
+    struct RelatedInformationServer;
+
+    impl Server for RelatedInformationServer {
+        async fn document_diagnostics(
+            &self,
+            state: ServerState,
+            params: DocumentDiagnosticParams,
+        ) -> ServerResult<DocumentDiagnosticReportResult> {
+            let uri = params.text_document.uri;
+            let text = state
+                .document(&uri)
+                .map_or_else(String::new, |doc| doc.text_contents());
+            if text.contains("modified") {
+                let mut state = state;
+                let _ = state.apply_document_change(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: "changed".into(),
+                    }],
+                });
+            }
+
+            let utf8_range = Range::new(Position::new(0, 4), Position::new(0, 6));
+            Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: vec![Diagnostic {
+                            related_information: Some(vec![DiagnosticRelatedInformation {
+                                location: Location::new(related_uri(&uri).unwrap(), utf8_range),
+                                message: "related".into(),
+                            }]),
+                            ..Diagnostic::new_simple(utf8_range, "diagnostic".into())
+                        }],
+                    },
+                }),
+            ))
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingDiagnosticsServer {
+        pulls: AtomicUsize,
+    }
+
+    impl Server for CountingDiagnosticsServer {
+        async fn document_diagnostics(
+            &self,
+            _: ServerState,
+            _: DocumentDiagnosticParams,
+        ) -> ServerResult<DocumentDiagnosticReportResult> {
+            self.pulls.fetch_add(1, Ordering::SeqCst);
+            Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: vec![Diagnostic::new_simple(
+                            Range::new(Position::new(0, 0), Position::new(0, 1)),
+                            "diagnostic".into(),
+                        )],
+                    },
+                }),
+            ))
+        }
+    }
+
     struct ConfigurableServer;
 
     impl Server for ConfigurableServer {
@@ -486,6 +1280,67 @@ mod tests {
         ))
     }
 
+    #[test]
+    fn initialize_advertises_the_configured_text_sync_options() {
+        let sync_options = |result: InitializeResult| match result.capabilities.text_document_sync {
+            Some(TextDocumentSyncCapability::Options(options)) => {
+                let include_text = match options.save {
+                    Some(TextDocumentSyncSaveOptions::SaveOptions(save)) => save.include_text,
+                    _ => None,
+                };
+                (options.change, include_text)
+            }
+            _ => (None, None),
+        };
+
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+        let result = futures::executor::block_on(server.initialize(InitializeParams::default()))
+            .expect("server can initialize");
+        assert_eq!(
+            sync_options(result),
+            (Some(TextDocumentSyncKind::INCREMENTAL), Some(true))
+        );
+
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), FullSyncServer);
+        let result = futures::executor::block_on(server.initialize(InitializeParams::default()))
+            .expect("server can initialize");
+        assert_eq!(
+            sync_options(result),
+            (Some(TextDocumentSyncKind::FULL), Some(false))
+        );
+    }
+
+    #[test]
+    fn initialize_stores_client_capabilities_and_info() {
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+        assert!(server.state.client_info().is_none());
+
+        let params: InitializeParams = serde_json::from_value(serde_json::json!({
+            "capabilities": {
+                "textDocument": {
+                    "completion": { "completionItem": { "snippetSupport": true } },
+                },
+            },
+            "clientInfo": { "name": "test-client", "version": "1.0.0" },
+        }))
+        .unwrap();
+        futures::executor::block_on(server.initialize(params)).expect("server can initialize");
+
+        let state = server.state.clone();
+        let snippets = state
+            .client_capabilities()
+            .text_document
+            .as_ref()
+            .and_then(|t| t.completion.as_ref())
+            .and_then(|c| c.completion_item.as_ref())
+            .and_then(|i| i.snippet_support);
+        assert_eq!(snippets, Some(true));
+
+        let info = state.client_info().expect("client info is stored");
+        assert_eq!(info.name, "test-client");
+        assert_eq!(info.version.as_deref(), Some("1.0.0"));
+    }
+
     #[test]
     fn initialize_enables_workspace_diagnostics() {
         let root = temp_workspace("capabilities");
@@ -688,6 +1543,59 @@ mod tests {
         fs::remove_dir_all(root).expect("temp workspace can be removed");
     }
 
+    #[test]
+    fn watched_file_changes_refresh_unopened_documents() {
+        let root = temp_workspace("watched-files");
+        let file = root.join("a.test");
+        fs::write(&file, "disk").expect("test file can be written");
+        let opened = root.join("b.test");
+        fs::write(&opened, "disk").expect("test file can be written");
+
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+        futures::executor::block_on(server.initialize(initialize_params(&root)))
+            .expect("server can initialize");
+        futures::executor::block_on(server.workspace_diagnostic(workspace_diagnostic_params()))
+            .expect("workspace diagnostics can be fetched");
+
+        let urls = server.state.document_urls();
+        let [uri, opened_uri] = urls.as_slice() else {
+            panic!("expected two workspace documents");
+        };
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                opened_uri.clone(),
+                "test".into(),
+                1,
+                "open".into(),
+            ),
+        });
+
+        fs::write(&file, "changed").expect("test file can be written");
+        fs::write(&opened, "changed").expect("test file can be written");
+        let _ = server.did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![
+                FileEvent::new(uri.clone(), FileChangeType::CHANGED),
+                FileEvent::new(opened_uri.clone(), FileChangeType::CHANGED),
+            ],
+        });
+
+        assert_eq!(
+            server.state.document(uri).unwrap().text_contents(),
+            "changed"
+        );
+        assert_eq!(
+            server.state.document(opened_uri).unwrap().text_contents(),
+            "open"
+        );
+
+        let _ = server.did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent::new(uri.clone(), FileChangeType::DELETED)],
+        });
+        assert!(server.state.document(uri).is_none());
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
     #[test]
     fn workspace_diagnostics_use_open_document_versions() {
         let root = temp_workspace("open-workspace-diagnostics");
@@ -760,6 +1668,495 @@ mod tests {
         fs::remove_dir_all(root).expect("temp workspace can be removed");
     }
 
+    #[test]
+    fn document_diagnostics_are_converted_including_related_information() {
+        let source = Url::parse("file:///tmp/a.test").unwrap();
+        let related = related_uri(&source).unwrap();
+
+        let mut server =
+            LanguageServerWithState::new(ClientSocket::new_closed(), RelatedInformationServer);
+        for (uri, text) in [(&source, "🙂abc"), (&related, "éabcd")] {
+            let _ = server.did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "test".into(), 1, text.into()),
+            });
+        }
+
+        let result = futures::executor::block_on(
+            server.document_diagnostic(document_diagnostic_params(source)),
+        )
+        .expect("document diagnostics can be fetched");
+
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = result
+        else {
+            panic!("expected full diagnostic report");
+        };
+        let diag = &report.full_document_diagnostic_report.items[0];
+        assert_eq!(
+            diag.range,
+            Range::new(Position::new(0, 2), Position::new(0, 4))
+        );
+        assert_eq!(
+            diag.related_information.as_ref().unwrap()[0].location.range,
+            Range::new(Position::new(0, 3), Position::new(0, 5)),
+        );
+    }
+
+    #[test]
+    fn document_diagnostics_are_unchanged_for_matching_result_ids() {
+        let source = Url::parse("file:///tmp/result-id.test").unwrap();
+
+        let mut server =
+            LanguageServerWithState::new(ClientSocket::new_closed(), RelatedInformationServer);
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(source.clone(), "test".into(), 1, "abc".into()),
+        });
+
+        let first = futures::executor::block_on(
+            server.document_diagnostic(document_diagnostic_params(source.clone())),
+        )
+        .expect("document diagnostics can be fetched");
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(first)) = first
+        else {
+            panic!("expected full diagnostic report");
+        };
+        let result_id = first
+            .full_document_diagnostic_report
+            .result_id
+            .expect("full reports have a result id");
+
+        let second =
+            futures::executor::block_on(server.document_diagnostic(DocumentDiagnosticParams {
+                previous_result_id: Some(result_id.clone()),
+                ..document_diagnostic_params(source.clone())
+            }))
+            .expect("document diagnostics can be fetched");
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(second)) =
+            second
+        else {
+            panic!("expected unchanged diagnostic report");
+        };
+        assert_eq!(
+            second.unchanged_document_diagnostic_report.result_id,
+            result_id
+        );
+
+        let third =
+            futures::executor::block_on(server.document_diagnostic(DocumentDiagnosticParams {
+                previous_result_id: Some("outdated".into()),
+                ..document_diagnostic_params(source)
+            }))
+            .expect("document diagnostics can be fetched");
+        assert!(matches!(
+            third,
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(_))
+        ));
+    }
+
+    fn pull_diagnostics(
+        server: &mut LanguageServerWithState<CountingDiagnosticsServer>,
+        uri: &Url,
+        previous_result_id: Option<String>,
+    ) -> DocumentDiagnosticReportResult {
+        futures::executor::block_on(server.document_diagnostic(DocumentDiagnosticParams {
+            previous_result_id,
+            ..document_diagnostic_params(uri.clone())
+        }))
+        .expect("document diagnostics can be fetched")
+    }
+
+    #[test]
+    fn unchanged_documents_do_not_pull_diagnostics_again() {
+        let source = Url::parse("file:///tmp/pulls.test").unwrap();
+        let mut server = LanguageServerWithState::new(
+            ClientSocket::new_closed(),
+            CountingDiagnosticsServer::default(),
+        );
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(source.clone(), "test".into(), 1, "abc".into()),
+        });
+
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(first)) =
+            pull_diagnostics(&mut server, &source, None)
+        else {
+            panic!("expected full diagnostic report");
+        };
+        let result_id = first.full_document_diagnostic_report.result_id;
+        assert!(matches!(
+            pull_diagnostics(&mut server, &source, result_id.clone()),
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_))
+        ));
+        assert_eq!(server.server.pulls.load(Ordering::SeqCst), 1);
+
+        let _ = server.did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(source.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "abcd".into(),
+            }],
+        });
+        assert!(matches!(
+            pull_diagnostics(&mut server, &source, result_id),
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_))
+        ));
+        assert_eq!(server.server.pulls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn document_diagnostics_for_modified_documents_are_rejected() {
+        let source = Url::parse("file:///tmp/modified.test").unwrap();
+
+        let mut server =
+            LanguageServerWithState::new(ClientSocket::new_closed(), RelatedInformationServer);
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                source.clone(),
+                "test".into(),
+                1,
+                "modified".into(),
+            ),
+        });
+
+        let error = futures::executor::block_on(
+            server.document_diagnostic(document_diagnostic_params(source)),
+        )
+        .expect_err("stale diagnostics should be rejected");
+        assert_eq!(error.code, ErrorCode::CONTENT_MODIFIED);
+    }
+
+    fn hover_with_retries(
+        retries: usize,
+        modifications: usize,
+    ) -> Result<Option<Hover>, ResponseError> {
+        let uri = Url::parse("file:///tmp/retry.test").unwrap();
+        let server = RetryingServer {
+            retries,
+            modifications: AtomicUsize::new(modifications),
+        };
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), server);
+        let _ = server.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "test".into(), 1, "v1".into()),
+        });
+
+        futures::executor::block_on(server.hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri),
+                Position::new(0, 0),
+            ),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        }))
+    }
+
+    #[test]
+    fn modified_documents_are_retried_with_fresh_snapshots() {
+        let hover = hover_with_retries(2, 2)
+            .expect("request succeeds after retrying")
+            .expect("hover is returned");
+        assert_eq!(
+            hover.contents,
+            HoverContents::Scalar(MarkedString::String("v3".into()))
+        );
+    }
+
+    #[test]
+    fn modified_documents_are_rejected_once_retries_run_out() {
+        let error = hover_with_retries(1, 2).expect_err("retries should run out");
+        assert_eq!(error.code, ErrorCode::CONTENT_MODIFIED);
+
+        let error = hover_with_retries(0, 1).expect_err("retries are disabled by default");
+        assert_eq!(error.code, ErrorCode::CONTENT_MODIFIED);
+    }
+
+    #[test]
+    fn requests_exceeding_the_timeout_are_cancelled() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("runtime can be created");
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), SlowServer);
+
+        let error = runtime
+            .block_on(server.hover(HoverParams {
+                text_document_position_params: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(Url::parse("file:///tmp/slow.test").unwrap()),
+                    Position::new(0, 0),
+                ),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            }))
+            .expect_err("request should time out");
+        assert_eq!(error.code, ErrorCode::REQUEST_CANCELLED);
+    }
+
+    fn shutdown_with_in_flight_hover(delay: Duration) -> bool {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("runtime can be created");
+        let completed = Arc::new(AtomicBool::new(false));
+        let server = DrainingServer {
+            delay,
+            completed: Arc::clone(&completed),
+        };
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), server);
+
+        runtime.block_on(async {
+            let hover = tokio::spawn(server.hover(HoverParams {
+                text_document_position_params: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(Url::parse("file:///tmp/drain.test").unwrap()),
+                    Position::new(0, 0),
+                ),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            }));
+            server.shutdown(()).await.expect("shutdown succeeds");
+            hover.abort();
+        });
+
+        completed.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn shutdown_waits_for_in_flight_requests() {
+        assert!(shutdown_with_in_flight_hover(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn shutdown_stops_waiting_after_the_timeout() {
+        assert!(!shutdown_with_in_flight_hover(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn document_lifecycle_hooks_run_in_order() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("runtime can be created");
+        let server = LifecycleServer::default();
+        let events = Arc::clone(&server.events);
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), server);
+        let uri = Url::parse("file:///tmp/lifecycle.test").unwrap();
+
+        runtime.block_on(async {
+            let _ = server.did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "test".into(), 1, "a".into()),
+            });
+            let _ = server.did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "b".into(),
+                }],
+            });
+            let _ = server.did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                text: Some("b".into()),
+            });
+            let _ = server.did_close(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+            });
+
+            for _ in 0..100 {
+                if events.lock().unwrap().len() == 5 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "opened v1 a",
+                "edited v1-v2 [(0..1, \"b\")]",
+                "changed v2 b",
+                "saved v2 b",
+                "closed v2 b"
+            ]
+        );
+    }
+
+    #[test]
+    fn workspace_documents_are_indexed_in_the_background() {
+        let root = temp_workspace("indexing");
+        fs::write(root.join("a.test"), "alpha").expect("test file can be written");
+        fs::write(root.join("b.test"), "beta").expect("test file can be written");
+        fs::write(root.join("c.txt"), "ignored").expect("test file can be written");
+        let root = fs::canonicalize(root).expect("workspace can be canonicalized");
+        let uri = Url::from_file_path(root.join("a.test")).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("runtime can be created");
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), IndexingServer);
+        let index = server.state.workspace_index().clone();
+        let symbol_name = |url: &Url| {
+            index
+                .symbols(url)
+                .and_then(|symbols| symbols.first().map(|s| s.name.clone()))
+        };
+
+        runtime.block_on(async {
+            server
+                .initialize(initialize_params(&root))
+                .await
+                .expect("server can initialize");
+            let _ = server.initialized(InitializedParams {});
+            for _ in 0..100 {
+                if !index.is_indexing() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(symbol_name(&uri).as_deref(), Some("alpha"));
+        assert_eq!(
+            symbol_name(&Url::from_file_path(root.join("b.test")).unwrap()).as_deref(),
+            Some("beta")
+        );
+
+        runtime.block_on(async {
+            let _ = server.did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "test".into(), 1, "gamma".into()),
+            });
+            let _ = server.did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                text: Some("gamma".into()),
+            });
+            for _ in 0..100 {
+                if symbol_name(&uri).as_deref() == Some("gamma") {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        assert_eq!(symbol_name(&uri).as_deref(), Some("gamma"));
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
+    #[test]
+    fn panicking_handlers_respond_with_internal_error() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let server = TestHarness::spawn(PanickingServer)
+                .await
+                .expect("server spawns");
+            let url = Url::parse("file:///tmp/panic.test").unwrap();
+
+            let error = server
+                .hover(&url, Position::new(0, 0))
+                .await
+                .expect_err("handler should panic");
+            let ServerError::Lsp(async_lsp::Error::Response(error)) = error else {
+                panic!("expected an error response, got {error:?}");
+            };
+            assert_eq!(error.code, ErrorCode::INTERNAL_ERROR);
+            assert!(error.message.contains("hover on the first line"));
+
+            server
+                .hover(&url, Position::new(1, 0))
+                .await
+                .expect("server keeps handling requests after a panic");
+        });
+    }
+
+    #[test]
+    fn edits_to_read_only_documents_are_rejected() {
+        let generated = Url::parse("file:///tmp/generated/out.test").unwrap();
+        let source = Url::parse("file:///tmp/source.test").unwrap();
+
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), ReadOnlyServer);
+        for url in [&generated, &source] {
+            let _ = server.did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(url.clone(), "test".into(), 1, "x".into()),
+            });
+        }
+        assert!(server.state.document(&generated).unwrap().is_read_only());
+        assert!(!server.state.document(&source).unwrap().is_read_only());
+
+        let format = |server: &mut LanguageServerWithState<ReadOnlyServer>, url: &Url| {
+            futures::executor::block_on(server.formatting(DocumentFormattingParams {
+                text_document: TextDocumentIdentifier::new(url.clone()),
+                options: FormattingOptions::default(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            }))
+        };
+        let error = format(&mut server, &generated).expect_err("read-only docs are not formatted");
+        assert_eq!(error.code, ErrorCode::REQUEST_FAILED);
+        assert!(error.message.contains("read-only"));
+        format(&mut server, &source).expect("writable docs are formatted");
+
+        let rename = |server: &mut LanguageServerWithState<ReadOnlyServer>, target: &str| {
+            futures::executor::block_on(server.rename(RenameParams {
+                text_document_position: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(source.clone()),
+                    Position::new(0, 0),
+                ),
+                new_name: target.into(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            }))
+        };
+        let error = rename(&mut server, "file:///tmp/generated/other.test")
+            .expect_err("renames touching untracked read-only docs are rejected");
+        assert_eq!(error.code, ErrorCode::REQUEST_FAILED);
+        rename(&mut server, "file:///tmp/other.test").expect("writable renames are kept");
+
+        let actions = futures::executor::block_on(server.code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier::new(source.clone()),
+            range: Range::default(),
+            context: CodeActionContext::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        }))
+        .unwrap()
+        .unwrap();
+        let disabled: Vec<_> = actions
+            .iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.disabled.is_some(),
+                CodeActionOrCommand::Command(_) => false,
+            })
+            .collect();
+        assert_eq!(disabled, [true, false]);
+    }
+
+    #[test]
+    fn cancelled_requests_respond_with_request_cancelled() {
+        let root = temp_workspace("cancelled-request");
+        let file = root.join("a.test");
+        fs::write(&file, "disk").expect("test file can be written");
+        let uri = Url::from_file_path(&file).expect("path can be converted to a URL");
+
+        let mut server = LanguageServerWithState::new(ClientSocket::new_closed(), TestServer);
+        futures::executor::block_on(server.initialize(initialize_params(&root)))
+            .expect("server can initialize");
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let error = futures::executor::block_on(scope_request_cancellation(
+            token,
+            server.document_diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier::new(uri),
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            }),
+        ))
+        .expect_err("request should be cancelled");
+        assert_eq!(error.code, ErrorCode::REQUEST_CANCELLED);
+
+        fs::remove_dir_all(root).expect("temp workspace can be removed");
+    }
+
     #[test]
     fn workspace_folder_changes_are_used_by_workspace_diagnostics() {
         let first = temp_workspace("workspace-folder-change-first");
@@ -856,6 +2253,16 @@ mod tests {
         }
     }
 
+    fn document_diagnostic_params(uri: Url) -> DocumentDiagnosticParams {
+        DocumentDiagnosticParams {
+            text_document: TextDocumentIdentifier::new(uri),
+            identifier: None,
+            previous_result_id: None,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        }
+    }
+
     fn related_uri(uri: &Url) -> Option<Url> {
         let path = uri.to_file_path().ok()?.with_file_name("b.test");
         Url::from_file_path(path).ok()
@@ -0,0 +1,129 @@
+use async_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Range};
+
+use crate::document::{Document, SyntaxErrorKind};
+
+/**
+    Options for diagnostics that are published automatically
+    for syntax errors in the tree-sitter trees of documents.
+
+    Diagnostics are published for all `ERROR` and `MISSING` nodes, using
+    the [`DiagnosticsPublisher`], together with any other diagnostics
+    published for the same document, whenever a document is opened,
+    changed, or saved.
+
+    See [`ServerOptions::with_syntax_diagnostics`] for more information.
+
+    [`DiagnosticsPublisher`]: crate::server::DiagnosticsPublisher
+    [`ServerOptions::with_syntax_diagnostics`]: crate::server::ServerOptions::with_syntax_diagnostics
+*/
+#[derive(Debug, Clone)]
+pub struct SyntaxDiagnostics {
+    pub(crate) severity: DiagnosticSeverity,
+    pub(crate) error_message: String,
+    pub(crate) missing_message: String,
+    pub(crate) source: Option<String>,
+}
+
+impl SyntaxDiagnostics {
+    /**
+        Creates new syntax diagnostics options, using the default
+        error severity and messages, and without any source.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Sets the severity of syntax diagnostics.
+
+        By default, syntax errors are reported as errors.
+    */
+    #[must_use]
+    pub fn with_severity(mut self, severity: DiagnosticSeverity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /**
+        Sets the message used for `ERROR` nodes.
+
+        By default, the message is `Syntax error`.
+    */
+    #[must_use]
+    pub fn with_error_message(mut self, message: impl Into<String>) -> Self {
+        self.error_message = message.into();
+        self
+    }
+
+    /**
+        Sets the message used for `MISSING` nodes, where any `{kind}`
+        in the message is replaced with the kind of the missing node.
+
+        By default, the message is `Missing {kind}`.
+    */
+    #[must_use]
+    pub fn with_missing_message(mut self, message: impl Into<String>) -> Self {
+        self.missing_message = message.into();
+        self
+    }
+
+    /**
+        Sets the source of syntax diagnostics, such as the name of the language.
+
+        By default, no source is set.
+    */
+    #[must_use]
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub(crate) fn collect(&self, document: &Document) -> Vec<Diagnostic> {
+        document
+            .syntax_errors()
+            .into_iter()
+            .map(|error| {
+                let message = match &error.kind {
+                    SyntaxErrorKind::Error => self.error_message.clone(),
+                    SyntaxErrorKind::Missing(kind) => self.missing_message.replace("{kind}", kind),
+                };
+                self.diagnostic(error.range, message)
+            })
+            .collect()
+    }
+
+    fn diagnostic(&self, range: Range, message: String) -> Diagnostic {
+        Diagnostic {
+            range,
+            severity: Some(self.severity),
+            source: self.source.clone(),
+            message,
+            ..Diagnostic::default()
+        }
+    }
+}
+
+impl Default for SyntaxDiagnostics {
+    fn default() -> Self {
+        Self {
+            severity: DiagnosticSeverity::ERROR,
+            error_message: "Syntax error".into(),
+            missing_message: "Missing {kind}".into(),
+            source: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Document;
+
+    use super::SyntaxDiagnostics;
+
+    #[test]
+    fn documents_without_trees_have_no_syntax_diagnostics() {
+        let options = SyntaxDiagnostics::new().with_source("test");
+        assert!(options.collect(&Document::new_for_tests("(")).is_empty());
+    }
+}
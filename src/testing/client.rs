@@ -0,0 +1,474 @@
+#![allow(clippy::missing_panics_doc)]
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_lsp::{
+    AnyNotification, AnyRequest, ResponseError,
+    lsp_types::{notification::Notification, request::Request},
+};
+use dashmap::DashMap;
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::result::{ServerError, ServerResult};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Responder = Box<dyn Fn(Value) -> Result<Value, ResponseError> + Send + Sync>;
+
+/**
+    A message sent from the server to the client.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Notification { method: String, params: Value },
+    Request { method: String, params: Value },
+}
+
+impl ClientMessage {
+    /**
+        Returns the method of the message.
+    */
+    #[must_use]
+    pub fn method(&self) -> &str {
+        match self {
+            Self::Notification { method, .. } | Self::Request { method, .. } => method,
+        }
+    }
+
+    /**
+        Returns the parameters of the message.
+    */
+    #[must_use]
+    pub fn params(&self) -> &Value {
+        match self {
+            Self::Notification { params, .. } | Self::Request { params, .. } => params,
+        }
+    }
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Notification { method, .. } => write!(f, "notification '{method}'"),
+            Self::Request { method, .. } => write!(f, "request '{method}'"),
+        }
+    }
+}
+
+/**
+    A scripted, in-memory, client, used together with a [`TestServer`].
+
+    Records all notifications and requests sent by the server, in the order
+    they were sent, and responds to requests from the server using responses
+    registered with [`MockClient::respond_to`], or an empty result by default.
+
+    Messages are consumed in order by the `expect_*` and `wait_for_*` methods,
+    which fail if no matching message arrives within the configured timeout.
+
+    [`TestServer`]: super::TestServer
+*/
+#[derive(Clone)]
+pub struct MockClient {
+    inner: Arc<MockClientInner>,
+}
+
+struct MockClientInner {
+    messages: Mutex<VecDeque<ClientMessage>>,
+    received: Notify,
+    responders: DashMap<String, Responder>,
+    timeout: Mutex<Duration>,
+}
+
+impl MockClient {
+    /**
+        Creates a new mock client, with no registered responses,
+        and a default timeout of 5 seconds for receiving messages.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(MockClientInner {
+                messages: Mutex::new(VecDeque::new()),
+                received: Notify::new(),
+                responders: DashMap::new(),
+                timeout: Mutex::new(DEFAULT_TIMEOUT),
+            }),
+        }
+    }
+
+    /**
+        Sets the timeout used when waiting for messages from the server.
+    */
+    pub fn set_timeout(&self, timeout: Duration) {
+        *self.inner.timeout.lock().unwrap() = timeout;
+    }
+
+    /**
+        Registers a response for requests of type `R` sent by the server,
+        replacing any previously registered response for the same request.
+    */
+    pub fn respond_to<R, F>(&self, responder: F)
+    where
+        R: Request,
+        F: Fn(R::Params) -> R::Result + Send + Sync + 'static,
+    {
+        let responder = move |params: Value| {
+            let params = serde_json::from_value::<R::Params>(params).map_err(|e| {
+                ResponseError::new(async_lsp::ErrorCode::INVALID_PARAMS, e.to_string())
+            })?;
+            serde_json::to_value(responder(params)).map_err(|e| {
+                ResponseError::new(async_lsp::ErrorCode::INTERNAL_ERROR, e.to_string())
+            })
+        };
+        self.inner
+            .responders
+            .insert(R::METHOD.to_string(), Box::new(responder));
+    }
+
+    /**
+        Takes all messages that have been received, but not yet consumed.
+    */
+    #[must_use]
+    pub fn take_messages(&self) -> Vec<ClientMessage> {
+        self.inner.messages.lock().unwrap().drain(..).collect()
+    }
+
+    /**
+        Waits for the next message sent by the server.
+
+        # Errors
+
+        - If no message was received within the timeout.
+    */
+    pub async fn next_message(&self) -> ServerResult<ClientMessage> {
+        let timeout = *self.inner.timeout.lock().unwrap();
+        let next = async {
+            loop {
+                let received = self.inner.received.notified();
+                if let Some(message) = self.inner.messages.lock().unwrap().pop_front() {
+                    return message;
+                }
+                received.await;
+            }
+        };
+        tokio::time::timeout(timeout, next).await.map_err(|_| {
+            ServerError::from(format!(
+                "no message was received within {}ms",
+                timeout.as_millis()
+            ))
+        })
+    }
+
+    /**
+        Expects the next message sent by the server to be a notification of type `N`.
+
+        # Errors
+
+        - If no message was received within the timeout.
+        - If the next message was not a notification of type `N`.
+    */
+    pub async fn expect_notification<N: Notification>(&self) -> ServerResult<N::Params> {
+        let message = self.next_message().await?;
+        decode_notification::<N>(message)
+    }
+
+    /**
+        Waits for a notification of type `N`, skipping any other messages sent before it.
+
+        # Errors
+
+        - If no matching notification was received within the timeout.
+    */
+    pub async fn wait_for_notification<N: Notification>(&self) -> ServerResult<N::Params> {
+        loop {
+            let message = self.next_message().await?;
+            if matches!(&message, ClientMessage::Notification { method, .. } if method == N::METHOD)
+            {
+                return decode_notification::<N>(message);
+            }
+        }
+    }
+
+    /**
+        Expects the next message sent by the server to be a request of type `R`.
+
+        # Errors
+
+        - If no message was received within the timeout.
+        - If the next message was not a request of type `R`.
+    */
+    pub async fn expect_request<R: Request>(&self) -> ServerResult<R::Params> {
+        let message = self.next_message().await?;
+        decode_request::<R>(message)
+    }
+
+    /**
+        Waits for a request of type `R`, skipping any other messages sent before it.
+
+        # Errors
+
+        - If no matching request was received within the timeout.
+    */
+    pub async fn wait_for_request<R: Request>(&self) -> ServerResult<R::Params> {
+        loop {
+            let message = self.next_message().await?;
+            if matches!(&message, ClientMessage::Request { method, .. } if method == R::METHOD) {
+                return decode_request::<R>(message);
+            }
+        }
+    }
+
+    /**
+        Expects the server to not send any messages for the given duration.
+
+        # Errors
+
+        - If a message was received within the given duration.
+    */
+    pub async fn expect_silence(&self, duration: Duration) -> ServerResult<()> {
+        tokio::time::sleep(duration).await;
+        match self.inner.messages.lock().unwrap().front() {
+            Some(message) => Err(format!("expected no messages, but got {message}").into()),
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn handle_notification(&self, notif: AnyNotification) {
+        self.push(ClientMessage::Notification {
+            method: notif.method,
+            params: notif.params,
+        });
+    }
+
+    pub(super) fn handle_request(&self, req: AnyRequest) -> Result<Value, ResponseError> {
+        let response = if let Some(responder) = self.inner.responders.get(&req.method) {
+            responder(req.params.clone())
+        } else {
+            Ok(default_response(&req))
+        };
+        self.push(ClientMessage::Request {
+            method: req.method,
+            params: req.params,
+        });
+        response
+    }
+
+    fn push(&self, message: ClientMessage) {
+        self.inner.messages.lock().unwrap().push_back(message);
+        self.inner.received.notify_waiters();
+    }
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MockClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockClient")
+            .field("messages", &self.inner.messages.lock().unwrap())
+            .finish_non_exhaustive()
+    }
+}
+
+/**
+    Creates an empty result, which is valid for most requests - except for
+    `workspace/configuration`, which expects one value per requested item.
+*/
+fn default_response(req: &AnyRequest) -> Value {
+    if req.method == "workspace/configuration" {
+        let items = req.params["items"].as_array().map_or(0, Vec::len);
+        Value::Array(vec![Value::Null; items])
+    } else {
+        Value::Null
+    }
+}
+
+fn decode_notification<N: Notification>(message: ClientMessage) -> ServerResult<N::Params> {
+    match message {
+        ClientMessage::Notification { method, params } if method == N::METHOD => {
+            Ok(serde_json::from_value(params)?)
+        }
+        other => Err(format!("expected notification '{}', but got {other}", N::METHOD).into()),
+    }
+}
+
+fn decode_request<R: Request>(message: ClientMessage) -> ServerResult<R::Params> {
+    match message {
+        ClientMessage::Request { method, params } if method == R::METHOD => {
+            Ok(serde_json::from_value(params)?)
+        }
+        other => Err(format!("expected request '{}', but got {other}", R::METHOD).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use async_lsp::lsp_types::{
+        ClientCapabilities, Diagnostic, Hover, HoverContents, HoverParams, MarkedString,
+        MessageActionItem, MessageType, NumberOrString, Position, ProgressParamsValue, Range, Url,
+        WindowClientCapabilities, WorkDoneProgress,
+        notification::{LogMessage, Progress, PublishDiagnostics, ShowMessage},
+        request::{ShowMessageRequest, WorkDoneProgressCreate},
+    };
+
+    use crate::{
+        server::{Server, ServerResult, ServerState},
+        testing::TestServer,
+    };
+
+    use super::MockClient;
+
+    #[derive(Clone)]
+    struct ChattyServer;
+
+    impl Server for ChattyServer {
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            let url = params.text_document_position_params.text_document.uri;
+
+            state.show_info("hovering")?;
+            state.diagnostics().publish(
+                url,
+                vec![Diagnostic::new_simple(
+                    Range::new(Position::new(0, 4), Position::new(0, 5)),
+                    "diagnostic".into(),
+                )],
+            )?;
+
+            let progress = state.create_progress("Working").await?;
+            progress.end(Some("done"))?;
+
+            let picked = state
+                .show_message_request(MessageType::INFO, "pick", ["a", "b"])
+                .await?;
+            Ok(picked.map(|picked| Hover {
+                contents: HoverContents::Scalar(MarkedString::String(picked)),
+                range: None,
+            }))
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created")
+    }
+
+    #[test]
+    fn mock_client_records_server_messages_in_order() {
+        runtime().block_on(async {
+            let client = MockClient::new();
+            client.respond_to::<ShowMessageRequest, _>(|params| {
+                params.actions.and_then(|mut actions| actions.pop())
+            });
+
+            let capabilities = ClientCapabilities {
+                window: Some(WindowClientCapabilities {
+                    work_done_progress: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let server = TestServer::spawn_with_client(ChattyServer, capabilities, client)
+                .await
+                .expect("server spawns");
+            let url = Url::parse("file:///tmp/mock.test").unwrap();
+            server.open_document(&url, "test", "🙂abc").unwrap();
+
+            let hover = server.hover(&url, Position::new(0, 0)).await.unwrap();
+            assert!(matches!(
+                hover.map(|h| h.contents),
+                Some(HoverContents::Scalar(MarkedString::String(s))) if s == "b"
+            ));
+
+            let client = server.client();
+            let message = client.expect_notification::<ShowMessage>().await.unwrap();
+            assert_eq!(message.message, "hovering");
+
+            let diagnostics = client
+                .expect_notification::<PublishDiagnostics>()
+                .await
+                .unwrap();
+            assert_eq!(diagnostics.version, Some(1));
+            assert_eq!(
+                diagnostics.diagnostics[0].range,
+                Range::new(Position::new(0, 2), Position::new(0, 3))
+            );
+
+            let create = client
+                .expect_request::<WorkDoneProgressCreate>()
+                .await
+                .unwrap();
+            assert!(matches!(create.token, NumberOrString::String(_)));
+
+            let begin = client.expect_notification::<Progress>().await.unwrap();
+            assert!(matches!(
+                begin.value,
+                ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(_))
+            ));
+
+            let request = client
+                .wait_for_request::<ShowMessageRequest>()
+                .await
+                .unwrap();
+            assert_eq!(
+                request.actions,
+                Some(vec![
+                    MessageActionItem {
+                        title: "a".into(),
+                        properties: HashMap::new(),
+                    },
+                    MessageActionItem {
+                        title: "b".into(),
+                        properties: HashMap::new(),
+                    },
+                ])
+            );
+
+            client
+                .expect_silence(Duration::from_millis(20))
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn mock_client_fails_for_unexpected_or_missing_messages() {
+        runtime().block_on(async {
+            let client = MockClient::new();
+            client.set_timeout(Duration::from_millis(10));
+            assert!(client.next_message().await.is_err());
+
+            client.handle_notification(
+                serde_json::from_value(serde_json::json!({
+                    "method": "window/showMessage",
+                    "params": { "type": 3, "message": "hello" },
+                }))
+                .unwrap(),
+            );
+            assert!(
+                client
+                    .expect_silence(Duration::from_millis(1))
+                    .await
+                    .is_err()
+            );
+            assert!(client.expect_notification::<LogMessage>().await.is_err());
+            assert!(client.take_messages().is_empty());
+        });
+    }
+}
@@ -0,0 +1,5 @@
+mod client;
+mod server;
+
+pub use client::{ClientMessage, MockClient};
+pub use server::TestServer;
@@ -0,0 +1,365 @@
+use std::ops::ControlFlow;
+
+use async_lsp::{
+    MainLoop, ServerSocket,
+    lsp_types::{
+        ClientCapabilities, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+        DidOpenTextDocumentParams, Hover, HoverParams, InitializeParams, InitializeResult,
+        InitializedParams, Position, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+        TextDocumentItem, TextDocumentPositionParams, Url, VersionedTextDocumentIdentifier,
+        WorkDoneProgressParams, notification, request,
+    },
+    router::Router,
+};
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use tokio::task::JoinHandle;
+
+use crate::{
+    result::{ServerError, ServerResult},
+    serve::serve_read_write,
+    server_trait::Server,
+    transport::duplex_read_write,
+};
+
+use super::MockClient;
+
+const DUPLEX_BUFFER_SIZE: usize = 64 * 1024;
+
+/**
+    A language server running in-process, connected to an in-memory client.
+
+    Useful for end-to-end tests of servers, without needing a real editor.
+    The server is served exactly like it would be using [`serve`], meaning that
+    all requests go through the same middleware, document tracking, and position
+    encoding conversion - positions sent and received using this test server
+    use the position encoding negotiated during initialization.
+
+    Must be spawned from within a tokio runtime.
+
+    [`serve`]: crate::server::serve
+*/
+pub struct TestServer {
+    socket: ServerSocket,
+    client: MockClient,
+    initialize_result: InitializeResult,
+    versions: DashMap<Url, i32>,
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl TestServer {
+    /**
+        Spawns the given server, and initializes it using default client capabilities.
+
+        # Errors
+
+        - If the server fails to initialize.
+    */
+    pub async fn spawn<S>(server: S) -> ServerResult<Self>
+    where
+        S: Server + Clone,
+        S: Send + Sync + 'static,
+    {
+        Self::spawn_with_client(server, ClientCapabilities::default(), MockClient::new()).await
+    }
+
+    /**
+        Spawns the given server, and initializes it using the given client capabilities.
+
+        # Errors
+
+        - If the server fails to initialize.
+    */
+    pub async fn spawn_with_capabilities<S>(
+        server: S,
+        capabilities: ClientCapabilities,
+    ) -> ServerResult<Self>
+    where
+        S: Server + Clone,
+        S: Send + Sync + 'static,
+    {
+        Self::spawn_with_client(server, capabilities, MockClient::new()).await
+    }
+
+    /**
+        Spawns the given server, and initializes it using the given client
+        capabilities, using the given mock client to respond to the server.
+
+        This may be used to register responses to requests sent by
+        the server during initialization, before the server is spawned.
+
+        # Errors
+
+        - If the server fails to initialize.
+    */
+    pub async fn spawn_with_client<S>(
+        server: S,
+        capabilities: ClientCapabilities,
+        client: MockClient,
+    ) -> ServerResult<Self>
+    where
+        S: Server + Clone,
+        S: Send + Sync + 'static,
+    {
+        let (client_stream, server_stream) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+
+        let (server_read, server_write) = duplex_read_write(server_stream);
+        let server_task = tokio::spawn(async move {
+            let _ = serve_read_write(server_read, server_write, server).await;
+        });
+
+        let router = client_router(client.clone());
+        let (main_loop, socket) = MainLoop::new_client(|_| router);
+        let (client_read, client_write) = duplex_read_write(client_stream);
+        let client_task = tokio::spawn(async move {
+            let _ = main_loop.run_buffered(client_read, client_write).await;
+        });
+
+        let initialize_result = socket
+            .request::<request::Initialize>(InitializeParams {
+                capabilities,
+                ..Default::default()
+            })
+            .await?;
+        socket.notify::<notification::Initialized>(InitializedParams {})?;
+
+        Ok(Self {
+            socket,
+            client,
+            initialize_result,
+            versions: DashMap::new(),
+            tasks: [server_task, client_task],
+        })
+    }
+
+    /**
+        Returns the mock client that receives all messages sent by the server.
+    */
+    #[must_use]
+    pub fn client(&self) -> &MockClient {
+        &self.client
+    }
+
+    /**
+        Returns the result the server responded with during initialization.
+    */
+    #[must_use]
+    pub fn initialize_result(&self) -> &InitializeResult {
+        &self.initialize_result
+    }
+
+    /**
+        Sends a request to the server, and waits for its response.
+
+        # Errors
+
+        - If the server responded with an error, or could not be reached.
+    */
+    pub async fn request<R: request::Request>(&self, params: R::Params) -> ServerResult<R::Result> {
+        Ok(self.socket.request::<R>(params).await?)
+    }
+
+    /**
+        Sends a notification to the server.
+
+        # Errors
+
+        - If the server could not be reached.
+    */
+    pub fn notify<N: notification::Notification>(&self, params: N::Params) -> ServerResult<()> {
+        Ok(self.socket.notify::<N>(params)?)
+    }
+
+    /**
+        Opens a document with the given language and contents.
+
+        # Errors
+
+        - If the server could not be reached.
+    */
+    pub fn open_document(
+        &self,
+        url: &Url,
+        language: impl Into<String>,
+        text: impl Into<String>,
+    ) -> ServerResult<()> {
+        self.versions.insert(url.clone(), 1);
+        self.notify::<notification::DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(url.clone(), language.into(), 1, text.into()),
+        })
+    }
+
+    /**
+        Replaces the full contents of an opened document, incrementing its version.
+
+        # Errors
+
+        - If the document has not been opened.
+        - If the server could not be reached.
+    */
+    pub fn change_document(&self, url: &Url, text: impl Into<String>) -> ServerResult<()> {
+        let version = {
+            let mut version = self
+                .versions
+                .get_mut(url)
+                .ok_or_else(|| ServerError::from(format!("document {url} is not open")))?;
+            *version += 1;
+            *version
+        };
+        self.notify::<notification::DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(url.clone(), version),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.into(),
+            }],
+        })
+    }
+
+    /**
+        Closes an opened document.
+
+        # Errors
+
+        - If the server could not be reached.
+    */
+    pub fn close_document(&self, url: &Url) -> ServerResult<()> {
+        self.versions.remove(url);
+        self.notify::<notification::DidCloseTextDocument>(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(url.clone()),
+        })
+    }
+
+    /**
+        Requests hover information at the given position in a document.
+
+        # Errors
+
+        - If the server responded with an error, or could not be reached.
+    */
+    pub async fn hover(&self, url: &Url, position: Position) -> ServerResult<Option<Hover>> {
+        self.request::<request::HoverRequest>(HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(url.clone()),
+                position,
+            ),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        })
+        .await
+    }
+
+    /**
+        Shuts down the server gracefully, using the `shutdown` request and `exit` notification.
+
+        # Errors
+
+        - If the server responded with an error, or could not be reached.
+    */
+    pub async fn shutdown(self) -> ServerResult<()> {
+        self.request::<request::Shutdown>(()).await?;
+        self.notify::<notification::Exit>(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn client_router(client: MockClient) -> Router<MockClient> {
+    let mut router = Router::new(client);
+    router.unhandled_notification(|client, notif| {
+        client.handle_notification(notif);
+        ControlFlow::Continue(())
+    });
+    router.unhandled_request(|client, req| -> BoxFuture<'static, _> {
+        let response = client.handle_request(req);
+        Box::pin(async move { response })
+    });
+    router
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{
+        ClientCapabilities, Hover, HoverContents, HoverParams, HoverProviderCapability,
+        MarkedString, Position, ServerCapabilities, Url,
+    };
+
+    use crate::server::{Server, ServerResult, ServerState};
+
+    use super::TestServer;
+
+    #[derive(Clone)]
+    struct HoverServer;
+
+    impl Server for HoverServer {
+        fn server_capabilities(_: ClientCapabilities) -> Option<ServerCapabilities> {
+            Some(ServerCapabilities {
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                ..Default::default()
+            })
+        }
+
+        async fn hover(
+            &self,
+            state: ServerState,
+            params: HoverParams,
+        ) -> ServerResult<Option<Hover>> {
+            let params = params.text_document_position_params;
+            let Some(doc) = state.document(&params.text_document.uri) else {
+                return Ok(None);
+            };
+            let text = doc.text_contents();
+            let offset = params.position.character as usize;
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(text[offset..].to_string())),
+                range: None,
+            }))
+        }
+    }
+
+    fn hover_text(hover: Option<Hover>) -> String {
+        match hover.expect("hover exists").contents {
+            HoverContents::Scalar(MarkedString::String(s)) => s,
+            _ => panic!("expected plain string hover"),
+        }
+    }
+
+    #[test]
+    fn test_server_handles_documents_end_to_end() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created");
+
+        runtime.block_on(async {
+            let server = TestServer::spawn(HoverServer).await.expect("server spawns");
+            assert!(
+                server
+                    .initialize_result()
+                    .capabilities
+                    .hover_provider
+                    .is_some()
+            );
+
+            let url = Url::parse("file:///tmp/harness.test").unwrap();
+            server.open_document(&url, "test", "🙂abc").unwrap();
+            let hover = server.hover(&url, Position::new(0, 2)).await.unwrap();
+            assert_eq!(hover_text(hover), "abc");
+
+            server.change_document(&url, "🙂xyz").unwrap();
+            let hover = server.hover(&url, Position::new(0, 3)).await.unwrap();
+            assert_eq!(hover_text(hover), "yz");
+
+            server.close_document(&url).unwrap();
+            let hover = server.hover(&url, Position::new(0, 0)).await.unwrap();
+            assert!(hover.is_none());
+
+            server.shutdown().await.expect("server shuts down");
+        });
+    }
+}
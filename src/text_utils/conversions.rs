@@ -1,9 +1,57 @@
 use ropey::Rope;
+use thiserror::Error;
 
 use super::{encoding::Encoding, position::Position};
 
+/**
+    Error returned when a position refers to a line past the end of the text.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("position is on line {line}, but the text only has {lines} lines")]
+pub struct PositionOutOfRange {
+    /// The zero-based line of the position
+    pub line: usize,
+    /// The number of lines in the text
+    pub lines: usize,
+}
+
+/**
+    Converts a position from using one encoding to another, without
+    clamping positions on lines past the end of the text.
+
+    Columns past the end of a line are still clamped to the end of that
+    line, since clients may send those according to the LSP specification.
+
+    # Errors
+
+    - If the position is on a line past the end of the text.
+*/
+pub fn try_position_to_encoding<P>(
+    contents: &Rope,
+    position: P,
+    encoding_source: impl Into<Encoding>,
+    encoding_target: impl Into<Encoding>,
+) -> Result<P, PositionOutOfRange>
+where
+    P: Into<Position>,
+    P: From<Position>,
+{
+    let position = position.into();
+    let lines = contents.len_lines();
+    if position.line >= lines {
+        return Err(PositionOutOfRange {
+            line: position.line,
+            lines,
+        });
+    }
+    Ok(position_to_encoding(contents, position, encoding_source, encoding_target).into())
+}
+
 /**
     Converts a position from using one encoding to another.
+
+    Positions on lines past the end of the text are clamped to the last line,
+    use [`try_position_to_encoding`] to reject those positions instead.
 */
 pub fn position_to_encoding<P>(
     contents: &Rope,
@@ -67,11 +115,69 @@ where
     pos.into()
 }
 
+/**
+    Converts a position, using the given encoding, to an absolute byte offset.
+
+    Positions past the end of a line are clamped to the end of that line,
+    and positions past the last line are clamped to the last line.
+*/
+pub fn position_to_byte_offset<P>(
+    contents: &Rope,
+    position: P,
+    encoding: impl Into<Encoding>,
+) -> usize
+where
+    P: Into<Position>,
+{
+    let position = position_to_encoding(contents, position.into(), encoding, Encoding::UTF8);
+    let line = position.line.min(contents.len_lines().saturating_sub(1));
+    let line_start = contents.line_to_byte(line);
+    let line_end = if line + 1 < contents.len_lines() {
+        let slice = contents.line(line);
+        let mut len = slice.len_bytes();
+        while len > 0 && matches!(slice.byte(len - 1), b'\n' | b'\r') {
+            len -= 1;
+        }
+        line_start + len
+    } else {
+        contents.len_bytes()
+    };
+    (line_start + position.col).min(line_end)
+}
+
+/**
+    Converts an absolute byte offset to a position, using the given encoding.
+
+    This is the inverse of [`position_to_byte_offset`], useful for turning
+    search hits or parser spans into positions. Offsets past the end of the
+    contents are clamped to the end, and offsets inside of a multi-byte
+    character refer to the start of that character.
+*/
+pub fn byte_offset_to_position<P>(
+    contents: &Rope,
+    offset: usize,
+    encoding: impl Into<Encoding>,
+) -> P
+where
+    P: From<Position>,
+{
+    let offset = contents.char_to_byte(contents.byte_to_char(offset.min(contents.len_bytes())));
+    let line = contents.byte_to_line(offset);
+    let position = Position {
+        line,
+        col: offset - contents.line_to_byte(line),
+    };
+    position_to_encoding(contents, position, Encoding::UTF8, encoding).into()
+}
+
 #[cfg(test)]
 mod tests {
     use ropey::Rope;
 
-    use super::{Encoding, Position, position_to_encoding};
+    use super::{
+        Encoding, Position, PositionOutOfRange, byte_offset_to_position, position_to_byte_offset,
+        position_to_encoding, try_position_to_encoding,
+    };
 
     #[test]
     fn converts_utf8_columns_to_utf16() {
@@ -92,4 +198,40 @@ mod tests {
 
         assert_eq!(converted, Position { line: 1, col: 2 });
     }
+
+    #[test]
+    fn converts_byte_offsets_to_positions_and_back() {
+        let text = Rope::from_str("ab\r\na🙂b\n");
+
+        let position: Position = byte_offset_to_position(&text, 9, Encoding::UTF16);
+        assert_eq!(position, Position { line: 1, col: 3 });
+        assert_eq!(position_to_byte_offset(&text, position, Encoding::UTF16), 9);
+
+        let inside: Position = byte_offset_to_position(&text, 7, Encoding::UTF8);
+        assert_eq!(inside, Position { line: 1, col: 1 });
+    }
+
+    #[test]
+    fn clamps_positions_to_line_ends() {
+        let text = Rope::from_str("ab\r\ncd");
+        let position = Position { line: 0, col: 10 };
+        assert_eq!(position_to_byte_offset(&text, position, Encoding::UTF8), 2);
+        let position = Position { line: 5, col: 10 };
+        assert_eq!(position_to_byte_offset(&text, position, Encoding::UTF32), 6);
+    }
+
+    #[test]
+    fn rejects_positions_past_the_last_line() {
+        let text = Rope::from_str("first\nsecond");
+        let position = Position { line: 1, col: 99 };
+        assert_eq!(
+            try_position_to_encoding(&text, position, Encoding::UTF16, Encoding::UTF8),
+            Ok(Position { line: 1, col: 6 })
+        );
+        let position = Position { line: 2, col: 0 };
+        assert_eq!(
+            try_position_to_encoding(&text, position, Encoding::UTF16, Encoding::UTF8),
+            Err(PositionOutOfRange { line: 2, lines: 2 })
+        );
+    }
 }
@@ -0,0 +1,81 @@
+use std::{io::Result, path::Path};
+
+/**
+    Reads the full contents of a file on disk as text.
+
+    Without the `encoding` crate feature, files must be valid UTF-8,
+    just like when using [`std::fs::read_to_string`].
+
+    # `encoding`
+
+    With the `encoding` crate feature enabled, files starting with a
+    byte order mark are decoded as UTF-8 or UTF-16 based on the mark,
+    and files that are not valid UTF-8 are decoded as Latin-1, meaning
+    that reading a file only fails if the file itself can not be read.
+*/
+pub(crate) fn read_text_file(path: impl AsRef<Path>) -> Result<String> {
+    decode_text(std::fs::read(path)?)
+}
+
+#[cfg(not(feature = "encoding"))]
+fn decode_text(bytes: Vec<u8>) -> Result<String> {
+    use std::io::{Error, ErrorKind};
+
+    String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(feature = "encoding")]
+fn decode_text(bytes: Vec<u8>) -> Result<String> {
+    use encoding_rs::{Encoding, WINDOWS_1252};
+
+    if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return Ok(text.into_owned());
+    }
+
+    // NOTE: Windows-1252 is a superset of the printable characters in Latin-1,
+    // and maps every single byte to a character, so decoding can never fail
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => {
+            let (text, _) = WINDOWS_1252.decode_without_bom_handling(e.as_bytes());
+            Ok(text.into_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_text;
+
+    #[test]
+    fn utf8_files_are_decoded_as_is() {
+        let text = "hello 🙂\nworld";
+        assert_eq!(decode_text(text.as_bytes().to_vec()).unwrap(), text);
+    }
+
+    #[test]
+    #[cfg(not(feature = "encoding"))]
+    fn invalid_utf8_files_are_rejected() {
+        assert!(decode_text(vec![b'c', b'a', b'f', 0xE9]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn files_are_decoded_using_byte_order_marks() {
+        let utf16_le = [0xFF, 0xFE, b'h', 0, b'i', 0, 0x3D, 0xD8, 0x42, 0xDE];
+        assert_eq!(decode_text(utf16_le.to_vec()).unwrap(), "hi🙂");
+
+        let utf16_be = [0xFE, 0xFF, 0, b'h', 0, b'i'];
+        assert_eq!(decode_text(utf16_be.to_vec()).unwrap(), "hi");
+
+        let utf8 = [0xEF, 0xBB, 0xBF, b'h', b'i'];
+        assert_eq!(decode_text(utf8.to_vec()).unwrap(), "hi");
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn invalid_utf8_files_are_decoded_as_latin1() {
+        assert_eq!(decode_text(vec![b'c', b'a', b'f', 0xE9]).unwrap(), "café");
+    }
+}
@@ -0,0 +1,334 @@
+use std::ops::Range;
+
+use async_lsp::lsp_types::TextEdit;
+use ropey::Rope;
+
+use super::{encoding::Encoding, range_conversions::byte_range_to_lsp};
+
+/**
+    The maximum number of insertions and deletions that are searched for
+    when diffing lines or words, before falling back to replacing all of
+    the differing lines or words at once - this bounds the time and
+    memory used when diffing documents that are completely different.
+*/
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hunk {
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+/**
+    Computes a minimal set of edits that turns the `old` text into the `new` text.
+
+    Lines are diffed first, and each block of changed lines is then diffed
+    word-by-word, meaning that small changes to a document, such as the ones
+    usually made by a formatter, result in similarly small edits.
+
+    All edits are relative to the `old` text, use UTF-8 columns just like all
+    other positions seen by the server, and may be given directly to the client
+    as the result of a formatting request or as part of a workspace edit.
+*/
+#[must_use]
+pub fn diff(old: &Rope, new: &str) -> Vec<TextEdit> {
+    let old_text = old.to_string();
+
+    let old_lines = split_inclusive_offsets(&old_text, |s| s.split_inclusive('\n'));
+    let new_lines = split_inclusive_offsets(new, |s| s.split_inclusive('\n'));
+
+    let mut edits = Vec::new();
+    for line_hunk in diff_hunks(&old_lines.tokens, &new_lines.tokens) {
+        let old_start = old_lines.offset(line_hunk.old.start);
+        let old_end = old_lines.offset(line_hunk.old.end);
+        let new_start = new_lines.offset(line_hunk.new.start);
+        let new_end = new_lines.offset(line_hunk.new.end);
+
+        let old_words = split_inclusive_offsets(&old_text[old_start..old_end], split_words);
+        let new_words = split_inclusive_offsets(&new[new_start..new_end], split_words);
+
+        for word_hunk in diff_hunks(&old_words.tokens, &new_words.tokens) {
+            let start = old_start + old_words.offset(word_hunk.old.start);
+            let end = old_start + old_words.offset(word_hunk.old.end);
+            let text_start = new_start + new_words.offset(word_hunk.new.start);
+            let text_end = new_start + new_words.offset(word_hunk.new.end);
+            edits.push(TextEdit {
+                range: byte_range_to_lsp(old, start..end, Encoding::UTF8),
+                new_text: new[text_start..text_end].to_string(),
+            });
+        }
+    }
+    edits
+}
+
+struct Tokens<'a> {
+    tokens: Vec<&'a str>,
+    offsets: Vec<usize>,
+}
+
+impl Tokens<'_> {
+    fn offset(&self, index: usize) -> usize {
+        self.offsets[index]
+    }
+}
+
+fn split_inclusive_offsets<'a, I>(text: &'a str, split: impl FnOnce(&'a str) -> I) -> Tokens<'a>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let tokens: Vec<_> = split(text).collect();
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    let mut offset = 0;
+    offsets.push(0);
+    for token in &tokens {
+        offset += token.len();
+        offsets.push(offset);
+    }
+    Tokens { tokens, offsets }
+}
+
+fn split_words(text: &str) -> impl Iterator<Item = &str> {
+    #[derive(PartialEq, Eq)]
+    enum Class {
+        Word,
+        Space,
+        Other,
+    }
+
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            Class::Word
+        } else if c.is_whitespace() && c != '\r' && c != '\n' {
+            Class::Space
+        } else {
+            Class::Other
+        }
+    };
+
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let first_class = class(first);
+        // NOTE: Line endings are never split, since positions
+        // in between a CR and LF are not valid positions in LSP
+        let len = if rest.starts_with("\r\n") {
+            2
+        } else if first_class == Class::Other {
+            first.len_utf8()
+        } else {
+            rest.find(|c| class(c) != first_class).unwrap_or(rest.len())
+        };
+        let (token, remaining) = rest.split_at(len);
+        rest = remaining;
+        Some(token)
+    })
+}
+
+/**
+    Finds the ranges of tokens that differ between `old` and `new`, using
+    the Myers diff algorithm, after trimming any common prefix and suffix.
+*/
+fn diff_hunks<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+
+    let hunks = myers_hunks(old_middle, new_middle).unwrap_or_else(|| {
+        vec![Hunk {
+            old: 0..old_middle.len(),
+            new: 0..new_middle.len(),
+        }]
+    });
+
+    hunks
+        .into_iter()
+        .map(|hunk| Hunk {
+            old: hunk.old.start + prefix..hunk.old.end + prefix,
+            new: hunk.new.start + prefix..hunk.new.end + prefix,
+        })
+        .collect()
+}
+
+#[allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss,
+    clippy::many_single_char_names
+)]
+fn myers_hunks<T: PartialEq>(old: &[T], new: &[T]) -> Option<Vec<Hunk>> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (old.len() + new.len()).min(MAX_EDIT_DISTANCE) as isize;
+
+    // NOTE: Only the diagonals that can be reached with d edits are kept
+    // for each d, meaning we use O(d^2) memory instead of O(d * (n + m))
+    let offset = max + 1;
+    let mut v = vec![0_isize; (2 * max + 3) as usize];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut found = false;
+    'outer: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'outer;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    // Walk backwards through the trace to find all of the matching tokens
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    matches.reverse();
+
+    // Any gaps between matching tokens are the hunks that differ
+    let mut hunks = Vec::new();
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for (old_match, new_match) in matches.into_iter().chain([(old.len(), new.len())]) {
+        if old_match > old_pos || new_match > new_pos {
+            hunks.push(Hunk {
+                old: old_pos..old_match,
+                new: new_pos..new_match,
+            });
+        }
+        old_pos = old_match + 1;
+        new_pos = new_match + 1;
+    }
+    Some(hunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write as _;
+
+    use async_lsp::lsp_types::{Position, Range};
+    use ropey::Rope;
+
+    use crate::document::Document;
+
+    use super::{Hunk, diff, diff_hunks};
+
+    fn apply(old: &str, new: &str) -> String {
+        let edits = diff(&Rope::from_str(old), new);
+        Document::new_for_tests(old)
+            .with_edits(&edits)
+            .unwrap()
+            .text_contents()
+    }
+
+    #[test]
+    fn hunks_contain_only_differing_tokens() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "e", "f"];
+        assert_eq!(
+            diff_hunks(&old, &new),
+            vec![
+                Hunk {
+                    old: 1..2,
+                    new: 1..2
+                },
+                Hunk {
+                    old: 3..4,
+                    new: 3..3
+                },
+                Hunk {
+                    old: 5..5,
+                    new: 4..5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_words_produce_small_edits() {
+        let old = "fn main() {\n    let  a = 1;\n}\n";
+        let new = "fn main() {\n    let a = 1;\n}\n";
+
+        let edits = diff(&Rope::from_str(old), new);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 7), Position::new(1, 9))
+        );
+        assert_eq!(edits[0].new_text, " ");
+    }
+
+    #[test]
+    fn identical_texts_produce_no_edits() {
+        assert!(diff(&Rope::from_str("same\ntext"), "same\ntext").is_empty());
+    }
+
+    #[test]
+    fn edits_turn_old_texts_into_new_texts() {
+        let cases = [
+            ("", "hello\n"),
+            ("hello\n", ""),
+            ("a\nb\nc\n", "c\nb\na\n"),
+            (
+                "let 🙂 = 1;\nfoo(bar)\n",
+                "let 🙂 = 2;\r\nfoo( bar )\nbaz\n",
+            ),
+            ("one two three\nfour\n", "zero one three\nfour five"),
+            ("a\r\nb\r\n", "a\nb\r\n"),
+            ("a\nb", "a\r\nb"),
+        ];
+        for (old, new) in cases {
+            assert_eq!(apply(old, new), new, "diffing {old:?} into {new:?}");
+        }
+    }
+
+    #[test]
+    fn large_differences_fall_back_to_replacements() {
+        let mut old = String::new();
+        let mut new = String::new();
+        for i in 0..3000 {
+            writeln!(old, "{i}").unwrap();
+            writeln!(new, "x{i}").unwrap();
+        }
+        assert_eq!(apply(&old, &new), new);
+    }
+}
@@ -0,0 +1,152 @@
+use std::ops::Range;
+
+use async_lsp::lsp_types::{Position as LspPosition, Range as LspRange, TextEdit};
+use ropey::Rope;
+use thiserror::Error;
+
+use super::{conversions::position_to_byte_offset, encoding::Encoding};
+
+/**
+    Error returned when applying text edits with overlapping ranges.
+
+    Both ranges are given exactly as they were in the edits.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error(
+    "text edits overlap, the edit starting at {}:{} overlaps the edit ending at {}:{}",
+    .second.start.line,
+    .second.start.character,
+    .first.end.line,
+    .first.end.character
+)]
+pub struct OverlappingEdits {
+    /// The range of the edit that starts first
+    pub first: LspRange,
+    /// The range of the edit that starts inside of the first one
+    pub second: LspRange,
+}
+
+/**
+    Applies text edits, just like the ones in a [`WorkspaceEdit`], to the given text.
+
+    All edits must be relative to the given text, and use the given encoding.
+    Edits are applied from the end of the text to the start, so that the
+    ranges of the remaining edits stay valid, and multiple edits inserting
+    text at the same position have their text inserted in the given order.
+
+    Positions are clamped to the text, in the same way as for [`position_to_byte_offset`].
+
+    # Errors
+
+    - If the ranges of any two edits overlap, in which case the text is left unchanged.
+
+    [`WorkspaceEdit`]: async_lsp::lsp_types::WorkspaceEdit
+*/
+pub fn apply_edits(
+    rope: &mut Rope,
+    edits: &[TextEdit],
+    encoding: impl Into<Encoding>,
+) -> Result<(), OverlappingEdits> {
+    let encoding = encoding.into();
+    let edits = sort_edits(edits, |position| {
+        position_to_byte_offset(rope, position, encoding)
+    })?;
+    for (range, text) in edits {
+        let start = rope.byte_to_char(range.start);
+        let end = rope.byte_to_char(range.end);
+        rope.remove(start..end);
+        rope.insert(start, text);
+    }
+    Ok(())
+}
+
+/**
+    Converts the ranges of the given edits to byte ranges, and
+    sorts the edits in the order that they should be applied in.
+
+    # Errors
+
+    - If the ranges of any two edits overlap.
+*/
+pub(crate) fn sort_edits(
+    edits: &[TextEdit],
+    to_byte: impl Fn(LspPosition) -> usize,
+) -> Result<Vec<(Range<usize>, &str)>, OverlappingEdits> {
+    let mut sorted = edits
+        .iter()
+        .map(|edit| {
+            let start = to_byte(edit.range.start);
+            let end = to_byte(edit.range.end).max(start);
+            (start..end, edit)
+        })
+        .collect::<Vec<_>>();
+
+    // NOTE: Sorting is stable, so edits with identical ranges, such as several
+    // insertions at the same position, keep their order, and insertions are
+    // sorted before any other edit that starts at the same position
+    sorted.sort_by_key(|(range, _)| (range.start, range.end));
+
+    if let Some(pair) = sorted
+        .windows(2)
+        .find(|pair| pair[1].0.start < pair[0].0.end)
+    {
+        return Err(OverlappingEdits {
+            first: pair[0].1.range,
+            second: pair[1].1.range,
+        });
+    }
+
+    Ok(sorted
+        .into_iter()
+        .rev()
+        .map(|(range, edit)| (range, edit.new_text.as_str()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range, TextEdit};
+    use ropey::Rope;
+
+    use crate::text_utils::Encoding;
+
+    use super::{OverlappingEdits, apply_edits};
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextEdit {
+        TextEdit::new(
+            Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            text.into(),
+        )
+    }
+
+    #[test]
+    fn edits_are_applied_in_reverse_order() {
+        let mut rope = Rope::from_str("a🙂b\nhello world");
+        let edits = [
+            edit((1, 6), (1, 11), "there"),
+            edit((0, 1), (0, 3), "x"),
+            edit((1, 0), (1, 0), "oh "),
+            edit((1, 0), (1, 5), "hi"),
+            edit((1, 0), (1, 0), "well, "),
+        ];
+        apply_edits(&mut rope, &edits, Encoding::UTF16).unwrap();
+        assert_eq!(rope.to_string(), "axb\noh well, hi there");
+    }
+
+    #[test]
+    fn overlapping_edits_are_rejected() {
+        let mut rope = Rope::from_str("hello world");
+        let edits = [edit((0, 6), (0, 11), "there"), edit((0, 0), (0, 7), "bye")];
+        assert_eq!(
+            apply_edits(&mut rope, &edits, Encoding::UTF8),
+            Err(OverlappingEdits {
+                first: edits[1].range,
+                second: edits[0].range,
+            })
+        );
+        assert_eq!(rope.to_string(), "hello world");
+
+        let inside = [edit((0, 0), (0, 5), "bye"), edit((0, 2), (0, 2), "x")];
+        assert!(apply_edits(&mut rope, &inside, Encoding::UTF8).is_err());
+    }
+}
@@ -53,6 +53,45 @@ impl Encoding {
         }
     }
 
+    /**
+        Measures the length of the given text in the code units of this encoding -
+        UTF-8 bytes, UTF-16 code units, or Unicode code points, respectively.
+
+        Useful for computing position deltas, such as for the lengths of
+        semantic tokens or completion edits, without a rope in hand.
+    */
+    #[must_use]
+    pub fn measure(self, text: &str) -> usize {
+        match self {
+            Self::UTF8 => text.len(),
+            Self::UTF16 => text.chars().map(char::len_utf16).sum(),
+            Self::UTF32 => text.chars().count(),
+        }
+    }
+
+    /**
+        Returns the longest prefix of the given text that is at most
+        `len` code units long, when measured using this encoding.
+
+        Characters are never split, so the returned prefix may be shorter than
+        `len` if it would otherwise end inside of a multi-byte character.
+    */
+    #[must_use]
+    pub fn slice_up_to(self, text: &str, len: usize) -> &str {
+        let mut units = 0;
+        for (offset, c) in text.char_indices() {
+            units += match self {
+                Self::UTF8 => c.len_utf8(),
+                Self::UTF16 => c.len_utf16(),
+                Self::UTF32 => 1,
+            };
+            if units > len {
+                return &text[..offset];
+            }
+        }
+        text
+    }
+
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn from_lsp(encoding: &LspPositionEncoding) -> Self {
@@ -103,3 +142,29 @@ impl Default for Encoding {
         Self::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Encoding;
+
+    const TEXT: &str = "a🙂é";
+
+    #[test]
+    fn text_is_measured_in_code_units() {
+        assert_eq!(Encoding::UTF8.measure(TEXT), 7);
+        assert_eq!(Encoding::UTF16.measure(TEXT), 4);
+        assert_eq!(Encoding::UTF32.measure(TEXT), 3);
+        assert_eq!(Encoding::UTF16.measure(""), 0);
+    }
+
+    #[test]
+    fn text_is_sliced_without_splitting_characters() {
+        assert_eq!(Encoding::UTF8.slice_up_to(TEXT, 3), "a");
+        assert_eq!(Encoding::UTF8.slice_up_to(TEXT, 5), "a🙂");
+        assert_eq!(Encoding::UTF16.slice_up_to(TEXT, 2), "a");
+        assert_eq!(Encoding::UTF16.slice_up_to(TEXT, 3), "a🙂");
+        assert_eq!(Encoding::UTF32.slice_up_to(TEXT, 2), "a🙂");
+        assert_eq!(Encoding::UTF32.slice_up_to(TEXT, 99), TEXT);
+        assert_eq!(Encoding::UTF32.slice_up_to(TEXT, 0), "");
+    }
+}
@@ -0,0 +1,407 @@
+use std::ops::Range;
+
+use ropey::{Rope, RopeSlice};
+
+use super::{conversions::PositionOutOfRange, encoding::Encoding, position::Position};
+
+/**
+    An index of the lines in a text, and the positions of all non-ASCII
+    characters in them, for converting positions between encodings
+    without traversing the text each time.
+
+    Building the index visits the entire text once, after which any
+    conversion only needs to look at the multi-byte characters on a
+    single line, making it well suited for converting many positions
+    at once, such as when encoding semantic tokens for a document.
+
+    Every conversion behaves exactly like its counterpart in
+    [`text_utils`](crate::text_utils), such as [`position_to_encoding`].
+
+    [`position_to_encoding`]: crate::text_utils::position_to_encoding
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    lines: Vec<LineInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineInfo {
+    start: usize,
+    len: usize,
+    content_len: usize,
+    wide_chars: Vec<WideChar>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    col: usize,
+    len_utf8: usize,
+    len_utf16: usize,
+}
+
+impl LineIndex {
+    /**
+        Builds a new line index for the given text.
+    */
+    #[must_use]
+    pub fn new(text: &Rope) -> Self {
+        let lines = text
+            .lines()
+            .scan(0, |start, line| {
+                let info = LineInfo::new(*start, line);
+                *start += info.len;
+                Some(info)
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /**
+        Returns the number of lines in the indexed text.
+
+        This is always at least one, just like [`Rope::len_lines`].
+    */
+    #[must_use]
+    pub fn len_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    /**
+        Returns the byte offset of the start of the given line.
+
+        Returns `None` if the line does not exist in the indexed text.
+    */
+    #[must_use]
+    pub fn line_to_byte(&self, line: usize) -> Option<usize> {
+        self.lines.get(line).map(|info| info.start)
+    }
+
+    /**
+        Updates the index after the given byte range of the indexed
+        text was replaced with `new_len` bytes of new text.
+
+        The given text must be the full text after the edit. Only the lines
+        touched by the edit are visited again, and any following lines are moved.
+    */
+    pub fn edit(&mut self, text: &Rope, old_range: Range<usize>, new_len: usize) {
+        let old_end = old_range.end.max(old_range.start);
+        let new_end = old_range.start + new_len;
+
+        // NOTE: A carriage return right before the edit may be joined with a
+        // line feed inserted by it, so we start one line earlier, and lines
+        // starting right after the edit may similarly have been joined
+        let first = self.line_of(old_range.start).saturating_sub(1);
+        let kept = self
+            .lines
+            .partition_point(|info| info.start < old_end.saturating_add(2));
+        let rebuild_until = self
+            .lines
+            .get(kept)
+            .map(|info| info.start - old_end + new_end);
+
+        let mut rebuilt = Vec::new();
+        let mut line = first;
+        while line < text.len_lines() {
+            let start = text.line_to_byte(line);
+            if rebuild_until.is_some_and(|until| start >= until) {
+                break;
+            }
+            rebuilt.push(LineInfo::new(start, text.line(line)));
+            line += 1;
+        }
+
+        for info in &mut self.lines[kept..] {
+            info.start = info.start - old_end + new_end;
+        }
+        self.lines.splice(first..kept, rebuilt);
+    }
+
+    /**
+        Converts a position from using one encoding to another.
+
+        See [`position_to_encoding`](crate::text_utils::position_to_encoding).
+    */
+    pub fn position_to_encoding<P>(
+        &self,
+        position: P,
+        encoding_source: impl Into<Encoding>,
+        encoding_target: impl Into<Encoding>,
+    ) -> P
+    where
+        P: Into<Position>,
+        P: From<Position>,
+    {
+        let encoding_source = encoding_source.into();
+        let encoding_target = encoding_target.into();
+        if encoding_target == encoding_source {
+            return position;
+        }
+
+        let position = position.into();
+        let line = position.line.min(self.lines.len() - 1);
+        let info = &self.lines[line];
+        let col = info.utf8_to_col(
+            info.col_to_utf8(position.col, encoding_source),
+            encoding_target,
+        );
+
+        Position { line, col }.into()
+    }
+
+    /**
+        Converts a position from using one encoding to another, without
+        clamping positions on lines past the end of the text.
+
+        See [`try_position_to_encoding`](crate::text_utils::try_position_to_encoding).
+
+        # Errors
+
+        - If the position is on a line past the end of the text.
+    */
+    pub fn try_position_to_encoding<P>(
+        &self,
+        position: P,
+        encoding_source: impl Into<Encoding>,
+        encoding_target: impl Into<Encoding>,
+    ) -> Result<P, PositionOutOfRange>
+    where
+        P: Into<Position>,
+        P: From<Position>,
+    {
+        let position = position.into();
+        let lines = self.lines.len();
+        if position.line >= lines {
+            return Err(PositionOutOfRange {
+                line: position.line,
+                lines,
+            });
+        }
+        Ok(self
+            .position_to_encoding(position, encoding_source, encoding_target)
+            .into())
+    }
+
+    /**
+        Converts a position, using the given encoding, to an absolute byte offset.
+
+        See [`position_to_byte_offset`](crate::text_utils::position_to_byte_offset).
+    */
+    pub fn position_to_byte_offset<P>(&self, position: P, encoding: impl Into<Encoding>) -> usize
+    where
+        P: Into<Position>,
+    {
+        let position = position.into();
+        let info = &self.lines[position.line.min(self.lines.len() - 1)];
+        info.start
+            + info
+                .col_to_utf8(position.col, encoding.into())
+                .min(info.content_len)
+    }
+
+    /**
+        Converts an absolute byte offset to a position, using the given encoding.
+
+        See [`byte_offset_to_position`](crate::text_utils::byte_offset_to_position).
+    */
+    pub fn byte_offset_to_position<P>(&self, offset: usize, encoding: impl Into<Encoding>) -> P
+    where
+        P: From<Position>,
+    {
+        let last = &self.lines[self.lines.len() - 1];
+        let offset = offset.min(last.start + last.len);
+        let line = self.line_of(offset);
+        let info = &self.lines[line];
+        let col = info.utf8_to_col(offset - info.start, encoding.into());
+        Position { line, col }.into()
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.lines
+            .partition_point(|info| info.start <= offset)
+            .saturating_sub(1)
+    }
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self::new(&Rope::new())
+    }
+}
+
+impl From<&Rope> for LineIndex {
+    fn from(text: &Rope) -> Self {
+        Self::new(text)
+    }
+}
+
+impl LineInfo {
+    fn new(start: usize, line: RopeSlice) -> Self {
+        let mut wide_chars = Vec::new();
+        let mut col = 0;
+        for c in line.chars() {
+            if !c.is_ascii() {
+                wide_chars.push(WideChar {
+                    col,
+                    len_utf8: c.len_utf8(),
+                    len_utf16: c.len_utf16(),
+                });
+            }
+            col += c.len_utf8();
+        }
+
+        let len = line.len_bytes();
+        let mut content_len = len;
+        while content_len > 0 && matches!(line.byte(content_len - 1), b'\n' | b'\r') {
+            content_len -= 1;
+        }
+
+        Self {
+            start,
+            len,
+            content_len,
+            wide_chars,
+        }
+    }
+
+    /**
+        Converts a column in the given encoding to a byte column, clamped to
+        the line, rounding down to the start of any character it is inside of.
+
+        Byte columns are only clamped, and never rounded, just like in
+        [`position_to_byte_offset`](crate::text_utils::position_to_byte_offset).
+    */
+    fn col_to_utf8(&self, col: usize, encoding: Encoding) -> usize {
+        if encoding == Encoding::UTF8 {
+            return col.min(self.len);
+        }
+        let mut units = 0;
+        let mut bytes = 0;
+        for wide in &self.wide_chars {
+            let gap = wide.col - bytes;
+            if col - units <= gap {
+                return bytes + (col - units);
+            }
+            units += gap;
+            bytes = wide.col;
+
+            let len = wide.len(encoding);
+            if col < units + len {
+                return bytes;
+            }
+            units += len;
+            bytes += wide.len_utf8;
+        }
+        (bytes + (col - units)).min(self.len)
+    }
+
+    /**
+        Converts a byte column to a column in the given encoding, clamped to
+        the line, rounding down to the start of any character it is inside of.
+    */
+    fn utf8_to_col(&self, col: usize, encoding: Encoding) -> usize {
+        let col = col.min(self.len);
+        let mut units = 0;
+        let mut bytes = 0;
+        for wide in &self.wide_chars {
+            if col <= wide.col {
+                break;
+            }
+            units += wide.col - bytes;
+            bytes = wide.col;
+
+            if col < wide.col + wide.len_utf8 {
+                return units;
+            }
+            units += wide.len(encoding);
+            bytes += wide.len_utf8;
+        }
+        units + (col - bytes)
+    }
+}
+
+impl WideChar {
+    fn len(self, encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::UTF8 => self.len_utf8,
+            Encoding::UTF16 => self.len_utf16,
+            Encoding::UTF32 => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use crate::text_utils::{
+        Encoding, Position, byte_offset_to_position, position_to_byte_offset, position_to_encoding,
+    };
+
+    use super::LineIndex;
+
+    const TEXT: &str = "ab\r\na🙂b é\n\rx\u{2028}y\r\n";
+    const ENCODINGS: [Encoding; 3] = [Encoding::UTF8, Encoding::UTF16, Encoding::UTF32];
+
+    fn assert_matches_rope(text: &Rope, index: &LineIndex) {
+        assert_eq!(index, &LineIndex::new(text));
+        for line in 0..=text.len_lines() {
+            for col in 0..text.line(line.min(text.len_lines() - 1)).len_bytes() + 2 {
+                let position = Position { line, col };
+                for source in ENCODINGS {
+                    for target in ENCODINGS {
+                        assert_eq!(
+                            index.position_to_encoding(position, source, target),
+                            position_to_encoding(text, position, source, target),
+                            "{position:?} from {source:?} to {target:?}"
+                        );
+                    }
+                    assert_eq!(
+                        index.position_to_byte_offset(position, source),
+                        position_to_byte_offset(text, position, source),
+                        "{position:?} in {source:?}"
+                    );
+                }
+            }
+        }
+        for offset in 0..=text.len_bytes() + 1 {
+            for encoding in ENCODINGS {
+                assert_eq!(
+                    index.byte_offset_to_position::<Position>(offset, encoding),
+                    byte_offset_to_position::<Position>(text, offset, encoding),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn conversions_match_the_rope_conversions() {
+        let text = Rope::from_str(TEXT);
+        assert_matches_rope(&text, &LineIndex::new(&text));
+        assert_matches_rope(&Rope::new(), &LineIndex::default());
+    }
+
+    #[test]
+    fn edits_update_the_index() {
+        let edits = [
+            (4..4, "🙂\n"),
+            (2..3, ""),
+            (2..3, "\r"),
+            (0..0, "\r"),
+            (1..1, "\n"),
+            (0..4, "x"),
+            (6..12, "\r\nz"),
+            (13..14, "é\r"),
+            (6..8, ""),
+        ];
+
+        let mut text = Rope::from_str(TEXT);
+        let mut index = LineIndex::new(&text);
+        for (range, new_text) in edits {
+            let (start, end) = (text.byte_to_char(range.start), text.byte_to_char(range.end));
+            text.remove(start..end);
+            text.insert(start, new_text);
+            index.edit(&text, range, new_text.len());
+            assert_matches_rope(&text, &index);
+        }
+    }
+}
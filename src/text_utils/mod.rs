@@ -1,9 +1,34 @@
 mod conversions;
+mod decode;
+mod diff;
+mod edits;
 mod encoding;
+mod line_index;
 mod position;
+mod range_conversions;
 mod range_ext;
+mod search;
+mod words;
 
-pub use self::conversions::position_to_encoding;
+pub use self::conversions::{
+    PositionOutOfRange, byte_offset_to_position, position_to_byte_offset, position_to_encoding,
+    try_position_to_encoding,
+};
+pub(crate) use self::decode::read_text_file;
+pub use self::diff::diff;
+pub(crate) use self::edits::sort_edits;
+pub use self::edits::{OverlappingEdits, apply_edits};
 pub use self::encoding::Encoding;
+pub use self::line_index::LineIndex;
 pub use self::position::Position;
+pub use self::range_conversions::{byte_range_to_lsp, lsp_range_to_byte};
 pub use self::range_ext::RangeExt;
+pub use self::search::{FindOptions, find_all, find_all_with};
+pub use self::words::{is_identifier_char, next_word_boundary, prev_word_boundary, word_range_at};
+
+#[cfg(feature = "tree-sitter")]
+pub(crate) use self::range_conversions::byte_offset_to_ts;
+#[cfg(feature = "tree-sitter")]
+pub use self::range_conversions::{
+    byte_range_to_ts, lsp_range_to_ts, ts_range_to_byte, ts_range_to_lsp,
+};
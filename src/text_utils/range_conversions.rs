@@ -0,0 +1,166 @@
+use std::ops::Range;
+
+use async_lsp::lsp_types::Range as LspRange;
+use ropey::Rope;
+
+#[cfg(feature = "tree-sitter")]
+use tree_sitter::{Point as TsPoint, Range as TsRange};
+
+use super::{
+    conversions::{byte_offset_to_position, position_to_byte_offset},
+    encoding::Encoding,
+};
+
+#[cfg(feature = "tree-sitter")]
+use super::position::Position;
+
+/**
+    Converts a byte range to an LSP range, using the given encoding.
+
+    Both ends of the range are converted in the same way as for [`byte_offset_to_position`].
+*/
+pub fn byte_range_to_lsp(
+    rope: &Rope,
+    range: Range<usize>,
+    encoding: impl Into<Encoding>,
+) -> LspRange {
+    let encoding = encoding.into();
+    LspRange {
+        start: byte_offset_to_position(rope, range.start, encoding),
+        end: byte_offset_to_position(rope, range.end, encoding),
+    }
+}
+
+/**
+    Converts an LSP range, using the given encoding, to a byte range.
+
+    Both ends of the range are converted in the same way as for [`position_to_byte_offset`].
+*/
+pub fn lsp_range_to_byte(
+    rope: &Rope,
+    range: LspRange,
+    encoding: impl Into<Encoding>,
+) -> Range<usize> {
+    let encoding = encoding.into();
+    position_to_byte_offset(rope, range.start, encoding)
+        ..position_to_byte_offset(rope, range.end, encoding)
+}
+
+/**
+    Converts a byte range to a tree-sitter range.
+
+    Both ends of the range are clamped in the same way as for [`byte_offset_to_position`].
+*/
+#[cfg(feature = "tree-sitter")]
+#[must_use]
+pub fn byte_range_to_ts(rope: &Rope, range: Range<usize>) -> TsRange {
+    let (start_byte, start_point) = byte_offset_to_ts(rope, range.start);
+    let (end_byte, end_point) = byte_offset_to_ts(rope, range.end);
+    TsRange {
+        start_byte,
+        end_byte,
+        start_point,
+        end_point,
+    }
+}
+
+/**
+    Converts a tree-sitter range to a byte range.
+*/
+#[cfg(feature = "tree-sitter")]
+#[must_use]
+pub const fn ts_range_to_byte(range: TsRange) -> Range<usize> {
+    range.start_byte..range.end_byte
+}
+
+/**
+    Converts an LSP range, using the given encoding, to a tree-sitter range.
+
+    Both ends of the range are clamped in the same way as for [`position_to_byte_offset`].
+*/
+#[cfg(feature = "tree-sitter")]
+pub fn lsp_range_to_ts(rope: &Rope, range: LspRange, encoding: impl Into<Encoding>) -> TsRange {
+    byte_range_to_ts(rope, lsp_range_to_byte(rope, range, encoding))
+}
+
+/**
+    Converts a tree-sitter range to an LSP range, using the given encoding.
+
+    Both ends of the range are clamped in the same way as for [`byte_offset_to_position`].
+*/
+#[cfg(feature = "tree-sitter")]
+pub fn ts_range_to_lsp(rope: &Rope, range: TsRange, encoding: impl Into<Encoding>) -> LspRange {
+    byte_range_to_lsp(rope, ts_range_to_byte(range), encoding)
+}
+
+/**
+    Converts a byte offset to a clamped byte offset and its tree-sitter point.
+*/
+#[cfg(feature = "tree-sitter")]
+pub(crate) fn byte_offset_to_ts(rope: &Rope, offset: usize) -> (usize, TsPoint) {
+    let position: Position = byte_offset_to_position(rope, offset, Encoding::UTF8);
+    let offset = rope.line_to_byte(position.line) + position.col;
+    (offset, TsPoint::new(position.line, position.col))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+    use ropey::Rope;
+
+    use crate::text_utils::Encoding;
+
+    use super::{byte_range_to_lsp, lsp_range_to_byte};
+
+    #[test]
+    fn byte_ranges_convert_to_lsp_ranges_and_back() {
+        let rope = Rope::from_str("ab\na🙂b");
+        let range = Range::new(Position::new(0, 1), Position::new(1, 3));
+
+        assert_eq!(byte_range_to_lsp(&rope, 1..8, Encoding::UTF16), range);
+        assert_eq!(lsp_range_to_byte(&rope, range, Encoding::UTF16), 1..8);
+        assert_eq!(lsp_range_to_byte(&rope, range, Encoding::UTF8), 1..6);
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn bytes_convert_to_points() {
+        use tree_sitter::Point;
+
+        use super::byte_offset_to_ts;
+
+        let text = Rope::from_str("ab\ncdé\nf");
+        assert_eq!(byte_offset_to_ts(&text, 0), (0, Point::new(0, 0)));
+        assert_eq!(byte_offset_to_ts(&text, 3), (3, Point::new(1, 0)));
+        assert_eq!(byte_offset_to_ts(&text, 7), (7, Point::new(1, 4)));
+        assert_eq!(byte_offset_to_ts(&text, 9), (9, Point::new(2, 1)));
+        assert_eq!(byte_offset_to_ts(&text, 6), (5, Point::new(1, 2)));
+        assert_eq!(byte_offset_to_ts(&text, 99), (9, Point::new(2, 1)));
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn lsp_ranges_convert_to_tree_sitter_ranges_and_back() {
+        use tree_sitter::Point;
+
+        use super::{lsp_range_to_ts, ts_range_to_lsp};
+
+        let rope = Rope::from_str("ab\na🙂b");
+        let range = Range::new(Position::new(0, 1), Position::new(1, 3));
+
+        let ts_range = lsp_range_to_ts(&rope, range, Encoding::UTF16);
+        assert_eq!(ts_range.start_byte, 1);
+        assert_eq!(ts_range.end_byte, 8);
+        assert_eq!(ts_range.start_point, Point::new(0, 1));
+        assert_eq!(ts_range.end_point, Point::new(1, 5));
+        assert_eq!(ts_range_to_lsp(&rope, ts_range, Encoding::UTF16), range);
+
+        let clamped = lsp_range_to_ts(
+            &rope,
+            Range::new(range.start, Position::new(1, 99)),
+            Encoding::UTF8,
+        );
+        assert_eq!(clamped.end_byte, 9);
+        assert_eq!(clamped.end_point, Point::new(1, 6));
+    }
+}
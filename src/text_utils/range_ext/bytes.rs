@@ -11,10 +11,19 @@ impl super::RangeExt for ByteRange {
         (left, right)
     }
 
-    fn shrink(self, amount_left: usize, amount_right: usize) -> Self {
-        let new_start = self.start.saturating_add(amount_left).min(self.end);
-        let new_end = self.end.saturating_sub(amount_right).max(self.start);
-        new_start..new_end
+    fn shrink(self, text: &str, amount_left: usize, amount_right: usize) -> Self {
+        let ((start, _, _), (end, _, _)) = super::shrink_relative(text, amount_left, amount_right);
+        (self.start + start)..(self.start + end)
+    }
+
+    fn expand(self, amount_left: usize, amount_right: usize) -> Self {
+        self.start.saturating_sub(amount_left)..self.end.saturating_add(amount_right)
+    }
+
+    fn expand_within(self, text: &str, amount_left: usize, amount_right: usize) -> Self {
+        let (start, end) =
+            super::expand_bytes_within(text, self.start, self.end, amount_left, amount_right);
+        start..end
     }
 
     fn sub(self, _text: &str, from: Self::Position, to: Self::Position) -> Self {
@@ -24,6 +33,31 @@ impl super::RangeExt for ByteRange {
         (self.start + from)..(self.start + to)
     }
 
+    fn contains(&self, position: Self::Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+
+    fn contains_range(&self, other: &Self) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        self.intersects(other)
+            .then(|| self.start.max(other.start)..self.end.min(other.end))
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        self.start.min(other.start)..self.end.max(other.end)
+    }
+
+    fn sub_bytes(self, text: &str, from: usize, to: usize) -> Self {
+        self.sub(text, from, to)
+    }
+
     fn sub_delimited(self, text: &str, delim: char) -> (Option<Self>, Option<Self>) {
         assert_eq!(
             text.len(),
@@ -34,7 +34,7 @@ fn basic_split_off_right() {
 
 #[test]
 fn basic_shrink() {
-    let shrunk = r(0, 10).shrink(2, 3);
+    let shrunk = r(0, 10).shrink("0123456789", 2, 3);
     assert_eq!(shrunk, r(2, 7));
 }
 
@@ -121,3 +121,120 @@ fn sub_delimited_tri_no_delimiters() {
     assert_eq!(second, None);
     assert_eq!(third, None);
 }
+
+// Set operation tests
+
+#[test]
+fn contains_includes_end() {
+    assert!(RangeExt::contains(&r(2, 5), 2));
+    assert!(RangeExt::contains(&r(2, 5), 5));
+    assert!(!RangeExt::contains(&r(2, 5), 6));
+    assert!(r(2, 8).contains_range(&r(3, 8)));
+    assert!(!r(2, 8).contains_range(&r(1, 4)));
+}
+
+#[test]
+fn intersection_and_union() {
+    assert_eq!(r(0, 5).intersection(&r(3, 8)), Some(r(3, 5)));
+    assert_eq!(r(0, 5).intersection(&r(5, 8)), Some(r(5, 5)));
+    assert_eq!(r(0, 5).intersection(&r(6, 8)), None);
+    assert!(!r(0, 5).intersects(&r(6, 8)));
+    assert_eq!(r(0, 5).union(&r(6, 8)), r(0, 8));
+}
+
+#[test]
+fn expand_grows_both_sides() {
+    assert_eq!(r(2, 5).expand(1, 2), r(1, 7));
+    assert_eq!(r(1, 5).expand(3, 0), r(0, 5));
+}
+
+#[test]
+fn expand_within_respects_characters() {
+    let text = "«quoted»";
+    let inner = r(2, 8);
+    assert_eq!(&text[inner.clone()], "quoted");
+    assert_eq!(inner.clone().expand_within(text, 1, 1), r(0, 10));
+    assert_eq!(inner.expand_within(text, 5, 5), r(0, 10));
+}
+
+#[test]
+fn shrink_never_inverts() {
+    assert_eq!(r(0, 5).shrink("hello", 4, 4), r(4, 4));
+}
+
+#[test]
+fn shrink_respects_characters() {
+    let text = "«quoted»";
+    assert_eq!(r(0, 10).shrink(text, 1, 1), r(2, 8));
+    assert_eq!(r(0, 10).shrink(text, 5, 5), r(6, 6));
+    assert_eq!(r(5, 15).shrink(text, 1, 1), r(7, 13));
+}
+
+#[test]
+fn sub_delimited_all_splits_every_delimiter() {
+    let parts = r(0, 7).sub_delimited_all("a/b/c/d", D1);
+    assert_eq!(
+        parts,
+        [Some(r(0, 1)), Some(r(2, 3)), Some(r(4, 5)), Some(r(6, 7))]
+    );
+    let parts = r(0, 4).sub_delimited_all("/a/b", D1);
+    assert_eq!(parts, [None, Some(r(1, 2)), Some(r(3, 4))]);
+    let parts = r(0, 3).sub_delimited_all("a//", D1);
+    assert_eq!(parts, [Some(r(0, 1)), None, None]);
+    assert_eq!(r(0, 0).sub_delimited_all(T, D1), [None]);
+}
+
+#[test]
+fn sub_delimited_escaped_skips_escaped_delimiters() {
+    const E: char = '\\';
+    assert_eq!(
+        r(0, 9).sub_delimited_escaped(r"a\/b/c\/d", D1, E),
+        (Some(r(0, 4)), Some(r(5, 9)))
+    );
+    assert_eq!(
+        r(0, 4).sub_delimited_escaped(r"a\/b", D1, E),
+        (Some(r(0, 4)), None)
+    );
+    assert_eq!(
+        r(0, 4).sub_delimited_escaped(r"a\\/", D1, E),
+        (Some(r(0, 3)), None)
+    );
+    assert_eq!(
+        r(0, 2).sub_delimited_escaped(r"/\", D1, E),
+        (None, Some(r(1, 2)))
+    );
+    assert_eq!(r(0, 0).sub_delimited_escaped(T, D1, E), (None, None));
+}
+
+#[test]
+fn sub_delimited_last_splits_the_last_delimiter() {
+    assert_eq!(
+        r(10, 17).sub_delimited_last("a/b/cde", D1),
+        (Some(r(10, 13)), Some(r(14, 17)))
+    );
+    assert_eq!(r(0, 2).sub_delimited_last("/a", D1), (None, Some(r(1, 2))));
+    assert_eq!(r(0, 3).sub_delimited_last("a//", D1), (Some(r(0, 2)), None));
+    assert_eq!(r(0, 3).sub_delimited_last("abc", D1), (Some(r(0, 3)), None));
+    assert_eq!(r(0, 0).sub_delimited_last(T, D1), (None, None));
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn sub_matches_and_captures() {
+    let regex = regex::Regex::new(r"(\w+)=(\d+)?").unwrap();
+    let text = "a=1 bc=";
+    assert_eq!(r(10, 17).sub_matches(text, &regex), [r(10, 13), r(14, 17)]);
+    assert_eq!(
+        r(10, 17).sub_captures(text, &regex),
+        [
+            vec![Some(r(10, 13)), Some(r(10, 11)), Some(r(12, 13))],
+            vec![Some(r(14, 17)), Some(r(14, 16)), None],
+        ]
+    );
+}
+
+#[test]
+fn trimmed_excludes_whitespace() {
+    assert_eq!(r(10, 24).trimmed("\t hello 🙂 \n"), r(12, 22));
+    assert_eq!(r(10, 13).trimmed("   "), r(10, 10));
+}
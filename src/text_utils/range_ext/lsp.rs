@@ -28,35 +28,58 @@ impl super::RangeExt for LspRange {
     }
 
     #[allow(clippy::cast_possible_truncation)]
-    fn shrink(self, amount_left: usize, amount_right: usize) -> Self {
-        assert_eq!(
-            self.start.line, self.end.line,
-            "shrink only supports single-line ranges"
-        );
+    fn shrink(self, text: &str, amount_left: usize, amount_right: usize) -> Self {
+        let start_col = self.start.character as usize;
+        let to_position = |(_, line, col): (usize, usize, usize)| LspPosition {
+            line: self.start.line + line as u32,
+            character: super::relative_to_absolute_col(start_col, line, col) as u32,
+        };
 
-        let start_char = self
-            .start
-            .character
-            .saturating_add(amount_left as u32)
-            .min(self.end.character);
-        let end_char = self
-            .end
-            .character
-            .saturating_sub(amount_right as u32)
-            .max(self.start.character);
+        let (start, end) = super::shrink_relative(text, amount_left, amount_right);
 
+        LspRange {
+            start: to_position(start),
+            end: to_position(end),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn expand(self, amount_left: usize, amount_right: usize) -> Self {
         LspRange {
             start: LspPosition {
                 line: self.start.line,
-                character: start_char,
+                character: self.start.character.saturating_sub(amount_left as u32),
             },
             end: LspPosition {
                 line: self.end.line,
-                character: end_char,
+                character: self.end.character.saturating_add(amount_right as u32),
             },
         }
     }
 
+    #[allow(clippy::cast_possible_truncation)]
+    fn expand_within(self, text: &str, amount_left: usize, amount_right: usize) -> Self {
+        let to_byte = |position: LspPosition| {
+            super::line_col_to_byte(text, position.line as usize, position.character as usize)
+        };
+        let to_position = |byte: usize| {
+            let (line, col) = super::byte_to_line_col(text, byte);
+            LspPosition {
+                line: line as u32,
+                character: col as u32,
+            }
+        };
+
+        let start = to_byte(self.start);
+        let end = to_byte(self.end).max(start);
+        let (start, end) = super::expand_bytes_within(text, start, end, amount_left, amount_right);
+
+        LspRange {
+            start: to_position(start),
+            end: to_position(end),
+        }
+    }
+
     fn sub(self, _text: &str, from: Self::Position, to: Self::Position) -> Self {
         assert!(from <= to);
 
@@ -88,6 +111,54 @@ impl super::RangeExt for LspRange {
         }
     }
 
+    fn contains(&self, position: Self::Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+
+    fn contains_range(&self, other: &Self) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        self.intersects(other).then(|| LspRange {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        })
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        LspRange {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn sub_bytes(self, text: &str, from: usize, to: usize) -> Self {
+        assert!(from <= to);
+
+        let to_absolute = |offset: usize| {
+            let (line, col) = super::byte_to_line_col(text, offset);
+            LspPosition {
+                line: self.start.line + line as u32,
+                character: if line == 0 {
+                    self.start.character + col as u32
+                } else {
+                    col as u32
+                },
+            }
+        };
+
+        LspRange {
+            start: to_absolute(from),
+            end: to_absolute(to),
+        }
+    }
+
     fn sub_delimited(self, text: &str, delim: char) -> (Option<Self>, Option<Self>) {
         assert_eq!(
             delim.len_utf8(),
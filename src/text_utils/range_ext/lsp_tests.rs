@@ -41,7 +41,7 @@ fn basic_split_off_right() {
 
 #[test]
 fn basic_shrink() {
-    let shrunk = r(p(0, 0), p(0, 5)).shrink(1, 2);
+    let shrunk = r(p(0, 0), p(0, 5)).shrink("hello", 1, 2);
     assert_eq!(shrunk, r(p(0, 1), p(0, 3)));
 }
 
@@ -156,3 +156,111 @@ fn sub_delimited_tri_multiline() {
     assert_eq!(second, Some(r(p(1, 0), p(2, 0))));
     assert_eq!(third, Some(r(p(2, 1), p(2, 3))));
 }
+
+// Set operation tests
+
+#[test]
+fn contains_includes_end() {
+    let range = r(p(1, 4), p(2, 2));
+    assert!(range.contains(p(1, 4)));
+    assert!(range.contains(p(1, 20)));
+    assert!(range.contains(p(2, 2)));
+    assert!(!range.contains(p(2, 3)));
+    assert!(range.contains_range(&r(p(1, 5), p(2, 0))));
+    assert!(!range.contains_range(&r(p(0, 5), p(2, 0))));
+}
+
+#[test]
+fn intersection_and_union() {
+    let a = r(p(0, 0), p(1, 5));
+    let b = r(p(1, 2), p(3, 0));
+    assert_eq!(a.intersection(&b), Some(r(p(1, 2), p(1, 5))));
+    assert_eq!(a.union(&b), r(p(0, 0), p(3, 0)));
+    assert!(!a.intersects(&r(p(2, 0), p(3, 0))));
+    assert_eq!(a.intersection(&r(p(2, 0), p(3, 0))), None);
+}
+
+#[test]
+fn expand_grows_both_sides() {
+    assert_eq!(r(p(0, 2), p(0, 5)).expand(1, 2), r(p(0, 1), p(0, 7)));
+    assert_eq!(r(p(1, 1), p(1, 5)).expand(3, 0), r(p(1, 0), p(1, 5)));
+}
+
+#[test]
+fn expand_within_crosses_lines() {
+    let text = "one {\ntwo\n}";
+    let inner = r(p(1, 0), p(1, 3));
+    assert_eq!(inner.expand_within(text, 2, 2), r(p(0, 4), p(2, 1)));
+    assert_eq!(inner.expand_within(text, 100, 100), r(p(0, 0), p(2, 1)));
+}
+
+#[test]
+fn shrink_crosses_lines() {
+    let text = "{\ntwo\n}";
+    let range = r(p(0, 4), p(2, 1));
+    assert_eq!(range.shrink(text, 1, 1), r(p(0, 5), p(2, 0)));
+    assert_eq!(range.shrink(text, 2, 2), r(p(1, 0), p(1, 3)));
+}
+
+#[test]
+fn shrink_moves_start_to_the_next_line() {
+    let text = "ab\ncd";
+    let range = r(p(0, 3), p(1, 2));
+    assert_eq!(range.shrink(text, 3, 0), r(p(1, 0), p(1, 2)));
+    assert_eq!(range.shrink(text, 10, 10), r(p(1, 2), p(1, 2)));
+}
+
+#[test]
+fn sub_delimited_all_splits_every_delimiter() {
+    let parts = r(p(2, 4), p(2, 11)).sub_delimited_all("a/bc/de", D1);
+    assert_eq!(
+        parts,
+        [
+            Some(r(p(2, 4), p(2, 5))),
+            Some(r(p(2, 6), p(2, 8))),
+            Some(r(p(2, 9), p(2, 11)))
+        ]
+    );
+}
+
+#[test]
+fn sub_delimited_escaped_spans_lines() {
+    let (left, right) = r(p(2, 4), p(3, 3)).sub_delimited_escaped("a\\/b\nc/d", D1, '\\');
+    assert_eq!(left, Some(r(p(2, 4), p(3, 1))));
+    assert_eq!(right, Some(r(p(3, 2), p(3, 3))));
+}
+
+#[test]
+fn sub_delimited_last_spans_lines() {
+    let (left, right) = r(p(2, 4), p(3, 3)).sub_delimited_last("a/b\nc/d", D1);
+    assert_eq!(left, Some(r(p(2, 4), p(3, 1))));
+    assert_eq!(right, Some(r(p(3, 2), p(3, 3))));
+}
+
+#[test]
+fn sub_bytes_uses_relative_offsets() {
+    let text = "one\ntwo";
+    let range = r(p(3, 2), p(4, 3));
+    assert_eq!(range.sub_bytes(text, 1, 3), r(p(3, 3), p(3, 5)));
+    assert_eq!(range.sub_bytes(text, 2, 6), r(p(3, 4), p(4, 2)));
+}
+
+#[test]
+fn trimmed_spans_lines() {
+    let text = "\n  one\ntwo \r\n";
+    let range = r(p(3, 2), p(6, 0));
+    assert_eq!(range.trimmed(text), r(p(4, 2), p(5, 3)));
+    assert_eq!(r(p(1, 4), p(2, 1)).trimmed(" \n "), r(p(1, 4), p(1, 4)));
+    assert_eq!(r(p(1, 4), p(1, 7)).trimmed("abc"), r(p(1, 4), p(1, 7)));
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn sub_matches_span_lines() {
+    let regex = regex::Regex::new(r"\d+").unwrap();
+    let text = "a1\nb22";
+    assert_eq!(
+        r(p(1, 4), p(2, 3)).sub_matches(text, &regex),
+        [r(p(1, 5), p(1, 6)), r(p(2, 1), p(2, 3))]
+    );
+}
@@ -1,5 +1,8 @@
 use std::any::type_name;
 
+#[cfg(feature = "regex")]
+use regex::Regex;
+
 mod bytes;
 mod lsp;
 
@@ -12,6 +15,95 @@ mod tree_sitter;
 #[cfg(all(test, feature = "tree-sitter"))]
 mod tree_sitter_tests;
 
+/**
+    Converts a zero-based line and UTF-8 column in the text to a byte offset,
+    clamping the column to the end of the line and the line to the end of the text.
+*/
+fn line_col_to_byte(text: &str, line: usize, col: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(offset) => line_start += offset + 1,
+            None => return text.len(),
+        }
+    }
+    let line_end = text[line_start..]
+        .find('\n')
+        .map_or(text.len(), |offset| line_start + offset);
+    (line_start + col).min(line_end)
+}
+
+/**
+    Converts a byte offset in the text to a zero-based line and UTF-8 column.
+*/
+fn byte_to_line_col(text: &str, byte: usize) -> (usize, usize) {
+    let before = &text[..byte];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |offset| offset + 1);
+    (line, byte - line_start)
+}
+
+/**
+    Expands a byte range in the text by the given character counts, on both
+    the left and right, keeping both ends on character boundaries.
+*/
+fn expand_bytes_within(
+    text: &str,
+    start: usize,
+    end: usize,
+    amount_left: usize,
+    amount_right: usize,
+) -> (usize, usize) {
+    let start = text[..start]
+        .char_indices()
+        .rev()
+        .take(amount_left)
+        .last()
+        .map_or(start, |(offset, _)| offset);
+    let end = text[end..]
+        .char_indices()
+        .nth(amount_right)
+        .map_or(text.len(), |(offset, _)| end + offset);
+    (start, end)
+}
+
+/**
+    Shrinks the given text by the given character counts, on both the left
+    and right, keeping both ends on character boundaries.
+
+    Returns the byte offset, line, and UTF-8 column of the new start and end,
+    relative to the start of the text.
+*/
+fn shrink_relative(
+    text: &str,
+    amount_left: usize,
+    amount_right: usize,
+) -> ((usize, usize, usize), (usize, usize, usize)) {
+    let start = text
+        .char_indices()
+        .nth(amount_left)
+        .map_or(text.len(), |(offset, _)| offset);
+    let end = text[start..]
+        .char_indices()
+        .rev()
+        .take(amount_right)
+        .last()
+        .map_or(text.len(), |(offset, _)| start + offset);
+    let locate = |byte: usize| {
+        let (line, col) = byte_to_line_col(text, byte);
+        (byte, line, col)
+    };
+    (locate(start), locate(end))
+}
+
+/**
+    Converts a line and column relative to the start of a range into an
+    absolute column, given the absolute column that the range starts at.
+*/
+fn relative_to_absolute_col(start_col: usize, line: usize, col: usize) -> usize {
+    if line == 0 { start_col + col } else { col }
+}
+
 /**
     Extension trait for different kinds of ranges:
 
@@ -22,8 +114,9 @@ mod tree_sitter_tests;
     Provides methods for:
 
     - Splitting ranges into parts
-    - Expanding and shrinking ranges
-    - Creating subranges based on positions and/or string delimiters
+    - Expanding and shrinking ranges, optionally across lines
+    - Creating subranges based on positions, byte offsets, string delimiters, and regexes
+    - Checking containment and intersection, and combining ranges
 */
 pub trait RangeExt: Sized {
     type Position;
@@ -67,12 +160,40 @@ pub trait RangeExt: Sized {
     }
 
     /**
-        Shrinks the same-line range by the given character count, on both the left and right.
+        Shrinks the range by the given character count, on both the left and right,
+        such as to exclude surrounding delimiters or quotes.
+
+        The range is shrunk across lines where needed, and never past the
+        other end of the range, so that it can not become inverted.
+
+        - The `text` parameter must be the exact text corresponding to this range.
+        - Columns of LSP and tree-sitter ranges must use UTF-8 encoding.
+    */
+    #[must_use]
+    fn shrink(self, text: &str, amount_left: usize, amount_right: usize) -> Self;
+
+    /**
+        Expands the range by the given character count, on both the left and right,
+        such as to include surrounding delimiters or quotes.
 
-        Panics if the range spans across multiple lines.
+        The range is expanded within its start and end lines only, and the start
+        is clamped to the start of its line. Use [`RangeExt::expand_within`]
+        to expand the range across lines instead.
     */
     #[must_use]
-    fn shrink(self, amount_left: usize, amount_right: usize) -> Self;
+    fn expand(self, amount_left: usize, amount_right: usize) -> Self;
+
+    /**
+        Expands the range by the given character count, on both the left and right,
+        moving across lines where needed, and clamped to the start and end of the text.
+
+        - The `text` parameter must be the _full_ text that the range is
+          absolute to, such as the text of a document, and not only the text
+          corresponding to this range.
+        - Columns of LSP and tree-sitter ranges must use UTF-8 encoding.
+    */
+    #[must_use]
+    fn expand_within(self, text: &str, amount_left: usize, amount_right: usize) -> Self;
 
     /**
         Returns a subrange of the range, starting at `from` and ending at `to`.
@@ -85,6 +206,110 @@ pub trait RangeExt: Sized {
     #[must_use]
     fn sub(self, text: &str, from: Self::Position, to: Self::Position) -> Self;
 
+    /**
+        Returns a subrange of the range, starting at byte offset `from` and ending at byte offset `to`.
+
+        - The `text` parameter must be the exact text corresponding to this range.
+        - Both offsets are _relative_ to the start of the range, and must be on
+          character boundaries in the text.
+        - Columns of LSP and tree-sitter ranges use UTF-8 encoding.
+    */
+    #[must_use]
+    fn sub_bytes(self, text: &str, from: usize, to: usize) -> Self;
+
+    /**
+        Shrinks the range to exclude any leading and trailing whitespace,
+        including line endings, such as when turning the range of a
+        captured node into a hover or rename target.
+
+        - The `text` parameter must be the exact text corresponding to this range.
+        - Ranges that only contain whitespace are shrunk to an empty range at their start.
+    */
+    #[must_use]
+    fn trimmed(self, text: &str) -> Self {
+        let trimmed = text.trim_end();
+        let start = trimmed.len() - trimmed.trim_start().len();
+        self.sub_bytes(text, start, trimmed.len())
+    }
+
+    /**
+        Returns the subranges for all matches of the given regex in the text of the range.
+
+        The range should be the exact range corresponding to the given text.
+    */
+    #[cfg(feature = "regex")]
+    #[must_use]
+    fn sub_matches(self, text: &str, regex: &Regex) -> Vec<Self>
+    where
+        Self: Clone,
+    {
+        regex
+            .find_iter(text)
+            .map(|m| self.clone().sub_bytes(text, m.start(), m.end()))
+            .collect()
+    }
+
+    /**
+        Returns the subranges for all capture groups, for all matches of the given
+        regex in the text of the range, with one list of groups per match.
+
+        The first group of each match is the entire match, and groups that did
+        not participate in the match are `None`, just like for [`regex::Captures`].
+
+        The range should be the exact range corresponding to the given text.
+    */
+    #[cfg(feature = "regex")]
+    #[must_use]
+    fn sub_captures(self, text: &str, regex: &Regex) -> Vec<Vec<Option<Self>>>
+    where
+        Self: Clone,
+    {
+        regex
+            .captures_iter(text)
+            .map(|captures| {
+                captures
+                    .iter()
+                    .map(|m| m.map(|m| self.clone().sub_bytes(text, m.start(), m.end())))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /**
+        Checks if the given position is within the range, including the end of the
+        range, so that a cursor placed right after an identifier is within it.
+
+        Note that byte ranges also have the inherent [`std::ops::Range::contains`]
+        method, which excludes the end, and is used with method call syntax.
+    */
+    #[must_use]
+    fn contains(&self, position: Self::Position) -> bool;
+
+    /**
+        Checks if the given range is fully within the range.
+    */
+    #[must_use]
+    fn contains_range(&self, other: &Self) -> bool;
+
+    /**
+        Checks if the range and the given range overlap or touch.
+    */
+    #[must_use]
+    fn intersects(&self, other: &Self) -> bool;
+
+    /**
+        Returns the range where the range and the given range overlap, if they
+        intersect - ranges that only touch produce an empty intersection.
+    */
+    #[must_use]
+    fn intersection(&self, other: &Self) -> Option<Self>;
+
+    /**
+        Returns the smallest range containing both the range and the given range.
+    */
+    #[must_use]
+    fn union(&self, other: &Self) -> Self;
+
     /**
         Splits the given range into two optional subranges, using the given delimiter.
 
@@ -125,6 +350,127 @@ pub trait RangeExt: Sized {
         )
     }
 
+    /**
+        Splits the given range into two optional subranges, on the _last_
+        occurrence of the given delimiter, such as for the final segment of a
+        qualified name - the counterpart to [`RangeExt::sub_delimited`].
+
+        The range should be the exact range corresponding to the given text.
+
+        # Example Usage
+
+        ```rust no_run
+        const D: char = '.';
+
+        (0..15).sub_delimited_last("scope.path.Name", D);
+        // --> (Some(0..10), Some(11..15))
+
+        (0..5).sub_delimited_last(".Name", D);
+        // --> (None, Some(1..5))
+
+        (0..6).sub_delimited_last("scope.", D);
+        // --> (Some(0..5), None)
+
+        (0..4).sub_delimited_last("Name", D);
+        // --> (Some(0..4), None)
+
+        (0..0).sub_delimited_last("", D);
+        // --> (None, None)
+        ```
+
+        # Panics
+
+        - Panics if the text and range are not the exact same length.
+        - Panics if the delimiter is not a single-byte UTF8 character.
+    */
+    #[must_use]
+    fn sub_delimited_last(self, text: &str, delimiter: char) -> (Option<Self>, Option<Self>)
+    where
+        Self: Clone,
+    {
+        assert_eq!(
+            delimiter.len_utf8(),
+            1,
+            "delimiter must be a single-byte UTF8 character"
+        );
+
+        let Some(offset) = text.rfind(delimiter) else {
+            return ((!text.is_empty()).then_some(self), None);
+        };
+
+        let left = (offset > 0).then(|| self.clone().sub_bytes(text, 0, offset));
+        let right = (offset + 1 < text.len()).then(|| self.sub_bytes(text, offset + 1, text.len()));
+        (left, right)
+    }
+
+    /**
+        Splits the given range into two optional subranges, on the first
+        occurrence of the given delimiter that is not escaped, such as for
+        the contents of string literals - otherwise the same as [`RangeExt::sub_delimited`].
+
+        Any character directly after the escape character is escaped, including
+        the escape character itself, meaning that `\\/` is an escaped backslash
+        followed by a delimiter. The returned subranges still contain any escapes.
+
+        The range should be the exact range corresponding to the given text.
+
+        # Example Usage
+
+        ```rust no_run
+        const D: char = '/';
+        const E: char = '\\';
+
+        (0..9).sub_delimited_escaped(r"a\/b/c\/d", D, E);
+        // --> (Some(0..4), Some(5..9))
+
+        (0..4).sub_delimited_escaped(r"a\/b", D, E);
+        // --> (Some(0..4), None)
+
+        (0..4).sub_delimited_escaped(r"a\\/", D, E);
+        // --> (Some(0..3), None)
+        ```
+
+        # Panics
+
+        - Panics if the text and range are not the exact same length.
+        - Panics if the delimiter is not a single-byte UTF8 character.
+    */
+    #[must_use]
+    fn sub_delimited_escaped(
+        self,
+        text: &str,
+        delimiter: char,
+        escape: char,
+    ) -> (Option<Self>, Option<Self>)
+    where
+        Self: Clone,
+    {
+        assert_eq!(
+            delimiter.len_utf8(),
+            1,
+            "delimiter must be a single-byte UTF8 character"
+        );
+
+        let mut chars = text.char_indices();
+        let mut found = None;
+        while let Some((offset, c)) = chars.next() {
+            if c == escape {
+                chars.next();
+            } else if c == delimiter {
+                found = Some(offset);
+                break;
+            }
+        }
+
+        let Some(offset) = found else {
+            return ((!text.is_empty()).then_some(self), None);
+        };
+
+        let left = (offset > 0).then(|| self.clone().sub_bytes(text, 0, offset));
+        let right = (offset + 1 < text.len()).then(|| self.sub_bytes(text, offset + 1, text.len()));
+        (left, right)
+    }
+
     /**
         Splits the given range into _three_ optional subranges,
         using the two given delimiters, consecutively.
@@ -168,4 +514,59 @@ pub trait RangeExt: Sized {
             type_name::<Self>()
         )
     }
+
+    /**
+        Splits the given range into optional subranges, on _every_
+        occurrence of the given delimiter, such as for path-like text.
+
+        There is always one more subrange than there are delimiters in the text,
+        and subranges that would be empty are `None`, just like for [`RangeExt::sub_delimited`].
+
+        The range should be the exact range corresponding to the given text.
+
+        # Example Usage
+
+        ```rust no_run
+        const D: char = '/';
+
+        (0..7).sub_delimited_all("a/b/c/d", D);
+        // --> [Some(0..1), Some(2..3), Some(4..5), Some(6..7)]
+
+        (0..4).sub_delimited_all("/a/b", D);
+        // --> [None, Some(1..2), Some(3..4)]
+
+        (0..3).sub_delimited_all("a//", D);
+        // --> [Some(0..1), None, None]
+
+        (0..0).sub_delimited_all("", D);
+        // --> [None]
+        ```
+
+        # Panics
+
+        - Panics if the text and range are not the exact same length.
+        - Panics if the delimiter is not a single-byte UTF8 character.
+    */
+    #[must_use]
+    fn sub_delimited_all(self, text: &str, delimiter: char) -> Vec<Option<Self>> {
+        let mut parts = Vec::new();
+        let mut range = self;
+        let mut text = text;
+        loop {
+            let Some(offset) = text.find(delimiter) else {
+                parts.push((!text.is_empty()).then_some(range));
+                return parts;
+            };
+            let (left, right) = range.sub_delimited(text, delimiter);
+            parts.push(left);
+            text = &text[offset + 1..];
+            let Some(right) = right else {
+                // NOTE: The delimiter was the last character,
+                // so there is one final, empty, subrange after it
+                parts.push(None);
+                return parts;
+            };
+            range = right;
+        }
+    }
 }
@@ -60,45 +60,60 @@ impl super::RangeExt for TsRange {
         (left, right)
     }
 
-    fn shrink(self, amount_left: usize, amount_right: usize) -> Self {
-        assert_eq!(
-            self.start_point.row, self.end_point.row,
-            "shrink only supports single-line ranges"
-        );
+    fn shrink(self, text: &str, amount_left: usize, amount_right: usize) -> Self {
+        let to_point = |line: usize, col: usize| TsPosition {
+            row: self.start_point.row + line,
+            column: super::relative_to_absolute_col(self.start_point.column, line, col),
+        };
+
+        let ((start, start_line, start_col), (end, end_line, end_col)) =
+            super::shrink_relative(text, amount_left, amount_right);
+
+        TsRange {
+            start_byte: self.start_byte + start,
+            end_byte: self.start_byte + end,
+            start_point: to_point(start_line, start_col),
+            end_point: to_point(end_line, end_col),
+        }
+    }
 
-        let start_col = self
-            .start_point
-            .column
-            .saturating_add(amount_left)
-            .min(self.end_point.column);
-        let end_col = self
-            .end_point
-            .column
-            .saturating_sub(amount_right)
-            .max(self.start_point.column);
-        let start_byte = self
-            .start_byte
-            .saturating_add(amount_left)
-            .min(self.end_byte);
-        let end_byte = self
-            .end_byte
-            .saturating_sub(amount_right)
-            .max(self.start_byte);
+    fn expand(self, amount_left: usize, amount_right: usize) -> Self {
+        let amount_left = amount_left
+            .min(self.start_point.column)
+            .min(self.start_byte);
 
         TsRange {
-            start_byte,
-            end_byte,
+            start_byte: self.start_byte - amount_left,
+            end_byte: self.end_byte.saturating_add(amount_right),
             start_point: TsPosition {
                 row: self.start_point.row,
-                column: start_col,
+                column: self.start_point.column - amount_left,
             },
             end_point: TsPosition {
                 row: self.end_point.row,
-                column: end_col,
+                column: self.end_point.column.saturating_add(amount_right),
             },
         }
     }
 
+    fn expand_within(self, text: &str, amount_left: usize, amount_right: usize) -> Self {
+        let to_point = |byte: usize| {
+            let (row, column) = super::byte_to_line_col(text, byte);
+            TsPosition { row, column }
+        };
+
+        let start = self.start_byte.min(text.len());
+        let end = self.end_byte.clamp(start, text.len());
+        let (start, end) = super::expand_bytes_within(text, start, end, amount_left, amount_right);
+
+        TsRange {
+            start_byte: start,
+            end_byte: end,
+            start_point: to_point(start),
+            end_point: to_point(end),
+        }
+    }
+
     fn sub(self, text: &str, from: Self::Position, to: Self::Position) -> Self {
         assert!(from <= to);
 
@@ -170,6 +185,88 @@ impl super::RangeExt for TsRange {
         }
     }
 
+    fn contains(&self, position: Self::Position) -> bool {
+        self.start_point <= position && position <= self.end_point
+    }
+
+    fn contains_range(&self, other: &Self) -> bool {
+        self.start_byte <= other.start_byte && other.end_byte <= self.end_byte
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.start_byte <= other.end_byte && other.start_byte <= self.end_byte
+    }
+
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        if !self.intersects(other) {
+            return None;
+        }
+        let start = if self.start_byte >= other.start_byte {
+            self
+        } else {
+            other
+        };
+        let end = if self.end_byte <= other.end_byte {
+            self
+        } else {
+            other
+        };
+        Some(TsRange {
+            start_byte: start.start_byte,
+            end_byte: end.end_byte,
+            start_point: start.start_point,
+            end_point: end.end_point,
+        })
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let start = if self.start_byte <= other.start_byte {
+            self
+        } else {
+            other
+        };
+        let end = if self.end_byte >= other.end_byte {
+            self
+        } else {
+            other
+        };
+        TsRange {
+            start_byte: start.start_byte,
+            end_byte: end.end_byte,
+            start_point: start.start_point,
+            end_point: end.end_point,
+        }
+    }
+
+    fn sub_bytes(self, text: &str, from: usize, to: usize) -> Self {
+        assert!(from <= to);
+
+        assert_eq!(
+            text.len(),
+            self.end_byte - self.start_byte,
+            "text and range must be the same length"
+        );
+
+        let to_absolute = |offset: usize| {
+            let (row, column) = super::byte_to_line_col(text, offset);
+            TsPosition {
+                row: self.start_point.row + row,
+                column: if row == 0 {
+                    self.start_point.column + column
+                } else {
+                    column
+                },
+            }
+        };
+
+        TsRange {
+            start_byte: self.start_byte + from,
+            end_byte: self.start_byte + to,
+            start_point: to_absolute(from),
+            end_point: to_absolute(to),
+        }
+    }
+
     fn sub_delimited(self, text: &str, delim: char) -> (Option<Self>, Option<Self>) {
         assert_eq!(
             text.len(),
@@ -50,7 +50,7 @@ fn basic_split_off_right() {
 
 #[test]
 fn basic_shrink() {
-    let shrunk = r(0, p(0, 0), 5, p(0, 5)).shrink(1, 2);
+    let shrunk = r(0, p(0, 0), 5, p(0, 5)).shrink("hello", 1, 2);
     assert_eq!(shrunk, r(1, p(0, 1), 3, p(0, 3)));
 }
 
@@ -206,3 +206,96 @@ fn sub_delimited_complex_multiline() {
     assert_eq!(left, Some(r(0, p(0, 0), 11, p(1, 5))));
     assert_eq!(right, Some(r(12, p(1, 6), 22, p(2, 3))));
 }
+
+// Set operation tests
+
+#[test]
+fn contains_includes_end() {
+    let range = r(4, p(0, 4), 12, p(1, 2));
+    assert!(range.contains(p(0, 4)));
+    assert!(range.contains(p(1, 2)));
+    assert!(!range.contains(p(1, 3)));
+    assert!(range.contains_range(&r(6, p(0, 6), 12, p(1, 2))));
+    assert!(!range.contains_range(&r(2, p(0, 2), 6, p(0, 6))));
+}
+
+#[test]
+fn intersection_and_union() {
+    let a = r(0, p(0, 0), 8, p(1, 2));
+    let b = r(4, p(0, 4), 14, p(2, 1));
+    assert_eq!(a.intersection(&b), Some(r(4, p(0, 4), 8, p(1, 2))));
+    assert_eq!(a.union(&b), r(0, p(0, 0), 14, p(2, 1)));
+    assert_eq!(a.intersection(&r(9, p(1, 3), 14, p(2, 1))), None);
+}
+
+#[test]
+fn expand_grows_both_sides() {
+    let range = r(6, p(1, 2), 9, p(1, 5));
+    assert_eq!(range.expand(1, 2), r(5, p(1, 1), 11, p(1, 7)));
+    assert_eq!(range.expand(4, 0), r(4, p(1, 0), 9, p(1, 5)));
+}
+
+#[test]
+fn expand_within_crosses_lines() {
+    let text = "one {\ntwo\n}";
+    let inner = r(6, p(1, 0), 9, p(1, 3));
+    assert_eq!(inner.expand_within(text, 2, 2), r(4, p(0, 4), 11, p(2, 1)));
+}
+
+#[test]
+fn shrink_crosses_lines() {
+    let text = "{\ntwo\n}";
+    let range = r(4, p(0, 4), 11, p(2, 1));
+    assert_eq!(range.shrink(text, 1, 1), r(5, p(0, 5), 10, p(2, 0)));
+    assert_eq!(range.shrink(text, 2, 2), r(6, p(1, 0), 9, p(1, 3)));
+}
+
+#[test]
+fn shrink_keeps_bytes_and_points_in_sync() {
+    let text = "ab\ncd";
+    let range = r(10, p(0, 3), 15, p(1, 2));
+    assert_eq!(range.shrink(text, 3, 0), r(13, p(1, 0), 15, p(1, 2)));
+    assert_eq!(range.shrink(text, 10, 10), r(15, p(1, 2), 15, p(1, 2)));
+}
+
+#[test]
+fn sub_delimited_all_splits_every_delimiter() {
+    let parts = r(10, p(1, 0), 15, p(1, 5)).sub_delimited_all("a/bc/", D1);
+    assert_eq!(
+        parts,
+        [
+            Some(r(10, p(1, 0), 11, p(1, 1))),
+            Some(r(12, p(1, 2), 14, p(1, 4))),
+            None
+        ]
+    );
+}
+
+#[test]
+fn sub_delimited_escaped_spans_lines() {
+    let range = r(10, p(1, 0), 18, p(2, 3));
+    let (left, right) = range.sub_delimited_escaped("a\\/b\nc/d", D1, '\\');
+    assert_eq!(left, Some(r(10, p(1, 0), 16, p(2, 1))));
+    assert_eq!(right, Some(r(17, p(2, 2), 18, p(2, 3))));
+}
+
+#[test]
+fn sub_delimited_last_spans_lines() {
+    let (left, right) = r(10, p(1, 0), 17, p(2, 3)).sub_delimited_last("a/b\nc/d", D1);
+    assert_eq!(left, Some(r(10, p(1, 0), 15, p(2, 1))));
+    assert_eq!(right, Some(r(16, p(2, 2), 17, p(2, 3))));
+}
+
+#[test]
+fn sub_bytes_uses_relative_offsets() {
+    let text = "one\ntwo";
+    let range = r(20, p(3, 2), 27, p(4, 3));
+    assert_eq!(range.sub_bytes(text, 2, 6), r(22, p(3, 4), 26, p(4, 2)));
+}
+
+#[test]
+fn trimmed_spans_lines() {
+    let text = "\n  one\ntwo \r\n";
+    let range = r(20, p(3, 2), 33, p(6, 0));
+    assert_eq!(range.trimmed(text), r(23, p(4, 2), 30, p(5, 3)));
+}
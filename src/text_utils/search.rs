@@ -0,0 +1,205 @@
+use async_lsp::lsp_types::Range as LspRange;
+use ropey::Rope;
+
+use super::{
+    conversions::position_to_byte_offset, encoding::Encoding, range_conversions::byte_range_to_lsp,
+};
+
+/**
+    Options for searching through text using [`find_all_with`].
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FindOptions {
+    case_insensitive: bool,
+    range: Option<LspRange>,
+}
+
+impl FindOptions {
+    /**
+        Creates new search options, for case-sensitive searches through the full text.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Sets whether ASCII letters should match regardless of their case.
+
+        Other characters must always match exactly, since changing their case
+        may change their length, and the ranges of matches would be ambiguous.
+    */
+    #[must_use]
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /**
+        Restricts the search to matches fully contained in the given range.
+
+        The range must use UTF-8 columns, and is clamped to the
+        text in the same way as for [`position_to_byte_offset`].
+
+        [`position_to_byte_offset`]: crate::text_utils::position_to_byte_offset
+    */
+    #[must_use]
+    pub fn with_range(mut self, range: LspRange) -> Self {
+        self.range = Some(range);
+        self
+    }
+}
+
+/**
+    Finds all occurrences of the needle in the given text, in order,
+    and without any overlapping matches - handy for naive references
+    or document highlight implementations.
+
+    The text is searched through chunk-by-chunk, without allocating a
+    copy of it. All returned ranges use UTF-8 columns, and an empty
+    needle never matches anything.
+
+    See [`find_all_with`] for case-insensitive or range-restricted searches.
+*/
+#[must_use]
+pub fn find_all(rope: &Rope, needle: &str) -> Vec<LspRange> {
+    find_all_with(rope, needle, FindOptions::default())
+}
+
+/**
+    Finds all occurrences of the needle in the given text, using the given options.
+
+    See [`find_all`] for more information.
+*/
+#[must_use]
+pub fn find_all_with(rope: &Rope, needle: &str, options: FindOptions) -> Vec<LspRange> {
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    let fold = |byte: u8| {
+        if options.case_insensitive {
+            byte.to_ascii_lowercase()
+        } else {
+            byte
+        }
+    };
+    let needle = needle.bytes().map(fold).collect::<Vec<_>>();
+    let failure = failure_table(&needle);
+
+    let (start, end) = match options.range {
+        Some(range) => {
+            // NOTE: Columns inside of multi-byte characters are rounded down,
+            // since slicing the text there would split the characters
+            let to_byte = |position| {
+                let byte = position_to_byte_offset(rope, position, Encoding::UTF8);
+                rope.char_to_byte(rope.byte_to_char(byte))
+            };
+            let start = to_byte(range.start);
+            (start, to_byte(range.end).max(start))
+        }
+        None => (0, rope.len_bytes()),
+    };
+
+    // NOTE: Both the needle and the text are valid UTF-8, so any byte match
+    // is guaranteed to start and end on character boundaries in the text
+    let mut matches = Vec::new();
+    let mut matched = 0;
+    for (index, byte) in rope.byte_slice(start..end).bytes().enumerate() {
+        let byte = fold(byte);
+        while matched > 0 && needle[matched] != byte {
+            matched = failure[matched - 1];
+        }
+        if needle[matched] == byte {
+            matched += 1;
+        }
+        if matched == needle.len() {
+            let match_end = start + index + 1;
+            let match_start = match_end - needle.len();
+            matches.push(byte_range_to_lsp(
+                rope,
+                match_start..match_end,
+                Encoding::UTF8,
+            ));
+            matched = 0;
+        }
+    }
+    matches
+}
+
+/**
+    Computes the failure table for a Knuth-Morris-Pratt search, where
+    each entry is the length of the longest proper prefix of the needle
+    that is also a suffix of the needle up to and including that entry.
+*/
+fn failure_table(needle: &[u8]) -> Vec<usize> {
+    let mut table = vec![0; needle.len()];
+    let mut len = 0;
+    for index in 1..needle.len() {
+        while len > 0 && needle[index] != needle[len] {
+            len = table[len - 1];
+        }
+        if needle[index] == needle[len] {
+            len += 1;
+        }
+        table[index] = len;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+    use ropey::Rope;
+
+    use super::{FindOptions, failure_table, find_all, find_all_with};
+
+    fn r(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn failure_tables_track_repeated_prefixes() {
+        assert_eq!(failure_table(b"aabaaa"), [0, 1, 0, 1, 2, 2]);
+    }
+
+    #[test]
+    fn matches_are_found_across_chunks() {
+        let text = "let 🙂 = foo;\n".repeat(2000);
+        let rope = Rope::from_str(&text);
+        assert!(rope.chunks().count() > 1);
+
+        let matches = find_all(&rope, "foo;\nlet");
+        assert_eq!(matches.len(), 1999);
+        assert_eq!(
+            matches[0],
+            Range::new(Position::new(0, 11), Position::new(1, 3))
+        );
+        assert!(find_all(&rope, "").is_empty());
+    }
+
+    #[test]
+    fn matches_do_not_overlap() {
+        let rope = Rope::from_str("aaaa aabaa");
+        assert_eq!(
+            find_all(&rope, "aa"),
+            [r(0, 0, 2), r(0, 2, 4), r(0, 5, 7), r(0, 8, 10)]
+        );
+        assert_eq!(find_all(&rope, "aab"), [r(0, 5, 8)]);
+    }
+
+    #[test]
+    fn options_restrict_matches() {
+        let rope = Rope::from_str("Foo foo\nFOO fOo");
+        let options = FindOptions::new().with_case_insensitive(true);
+        assert_eq!(find_all(&rope, "foo"), [r(0, 4, 7)]);
+        assert_eq!(find_all_with(&rope, "foo", options).len(), 4);
+
+        let options = options.with_range(Range::new(Position::new(0, 5), Position::new(1, 5)));
+        assert_eq!(find_all_with(&rope, "FOO", options), [r(1, 0, 3)]);
+
+        let rope = Rope::from_str("🙂a🙂a");
+        let options = FindOptions::new().with_range(r(0, 2, 9));
+        assert_eq!(find_all_with(&rope, "a", options), [r(0, 4, 5)]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+
+use async_lsp::lsp_types::Range as LspRange;
+use ropey::Rope;
+
+use super::{
+    conversions::{byte_offset_to_position, position_to_byte_offset},
+    encoding::Encoding,
+    position::Position,
+};
+
+/**
+    Returns `true` if the given character may be part of an identifier,
+    meaning that it is alphanumeric or an underscore, otherwise `false`.
+
+    This is the default word character predicate used by [`Document::word_at_position`].
+
+    [`Document::word_at_position`]: crate::server::Document::word_at_position
+*/
+#[must_use]
+pub fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/**
+    Finds the range of the word at the given position, if any,
+    where the given predicate decides which characters are part of a word.
+
+    A position directly after the end of a word, such as the cursor
+    position while typing the word, is also considered to be part of that word.
+    Words never span multiple lines, and columns inside of a multi-byte
+    character refer to the start of that character.
+
+    Both the given position and the returned range use UTF-8 columns.
+*/
+pub fn word_range_at(
+    rope: &Rope,
+    position: impl Into<Position>,
+    is_word_char: impl Fn(char) -> bool,
+) -> Option<LspRange> {
+    let position = position.into();
+    if position.line >= rope.len_lines() {
+        return None;
+    }
+
+    let text = Cow::<str>::from(rope.line(position.line));
+    let text = text.trim_end_matches(['\n', '\r']);
+    let col = floor_char_boundary(text, position.col.min(text.len()));
+
+    let start = text[..col]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word_char(*c))
+        .last()
+        .map_or(col, |(idx, _)| idx);
+    let end = text[col..]
+        .char_indices()
+        .find(|(_, c)| !is_word_char(*c))
+        .map_or(text.len(), |(idx, _)| col + idx);
+
+    if start == end {
+        return None;
+    }
+
+    let line = position.line;
+    Some(LspRange::new(
+        Position { line, col: start }.into_lsp(),
+        Position { line, col: end }.into_lsp(),
+    ))
+}
+
+/**
+    Finds the closest word boundary before the given position, or the start of
+    the text if there is none, where the given predicate decides which characters
+    are part of a word - useful for moving a cursor or selection word-by-word.
+
+    Word boundaries are found wherever a word starts or ends, and may
+    be on other lines than the given position, since line endings are
+    never considered to be part of a word.
+
+    Both the given and the returned position use UTF-8 columns.
+*/
+pub fn prev_word_boundary<P>(rope: &Rope, position: P, is_word_char: impl Fn(char) -> bool) -> P
+where
+    P: Into<Position>,
+    P: From<Position>,
+{
+    let offset = position_to_byte_offset(rope, position, Encoding::UTF8);
+    let mut chars = rope.chars_at(rope.byte_to_char(offset));
+
+    let mut index = rope.byte_to_char(offset);
+    if let Some(first) = chars.prev() {
+        let is_word = is_word_char(first);
+        index -= 1;
+        while chars.prev().is_some_and(|c| is_word_char(c) == is_word) {
+            index -= 1;
+        }
+    }
+
+    byte_offset_to_position(rope, rope.char_to_byte(index), Encoding::UTF8)
+}
+
+/**
+    Finds the closest word boundary after the given position, or the end of
+    the text if there is none, where the given predicate decides which characters
+    are part of a word - useful for moving a cursor or selection word-by-word.
+
+    See [`prev_word_boundary`] for more information.
+*/
+pub fn next_word_boundary<P>(rope: &Rope, position: P, is_word_char: impl Fn(char) -> bool) -> P
+where
+    P: Into<Position>,
+    P: From<Position>,
+{
+    let offset = position_to_byte_offset(rope, position, Encoding::UTF8);
+    let mut chars = rope.chars_at(rope.byte_to_char(offset));
+
+    let mut index = rope.byte_to_char(offset);
+    if let Some(first) = chars.next() {
+        let is_word = is_word_char(first);
+        index += 1;
+        while chars.next().is_some_and(|c| is_word_char(c) == is_word) {
+            index += 1;
+        }
+    }
+
+    byte_offset_to_position(rope, rope.char_to_byte(index), Encoding::UTF8)
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+    use ropey::Rope;
+
+    use super::{is_identifier_char, next_word_boundary, prev_word_boundary, word_range_at};
+
+    #[test]
+    fn word_ranges_exclude_line_endings() {
+        let rope = Rope::from_str("first\r\nsecond_word\n");
+        assert_eq!(
+            word_range_at(&rope, Position::new(0, 99), is_identifier_char),
+            Some(Range::new(Position::new(0, 0), Position::new(0, 5)))
+        );
+        assert_eq!(
+            word_range_at(&rope, Position::new(1, 3), is_identifier_char),
+            Some(Range::new(Position::new(1, 0), Position::new(1, 11)))
+        );
+        assert_eq!(
+            word_range_at(&rope, Position::new(2, 0), is_identifier_char),
+            None
+        );
+        assert_eq!(
+            word_range_at(&rope, Position::new(3, 0), is_identifier_char),
+            None
+        );
+    }
+
+    #[test]
+    fn word_boundaries_are_found_across_lines() {
+        let rope = Rope::from_str("let héllo = 1;\nfoo");
+        let prev =
+            |line, col| prev_word_boundary(&rope, Position::new(line, col), is_identifier_char);
+        let next =
+            |line, col| next_word_boundary(&rope, Position::new(line, col), is_identifier_char);
+
+        assert_eq!(prev(0, 8), Position::new(0, 4));
+        assert_eq!(prev(0, 4), Position::new(0, 3));
+        assert_eq!(prev(1, 0), Position::new(0, 14));
+        assert_eq!(prev(0, 0), Position::new(0, 0));
+
+        assert_eq!(next(0, 4), Position::new(0, 10));
+        assert_eq!(next(0, 14), Position::new(1, 0));
+        assert_eq!(next(1, 1), Position::new(1, 3));
+        assert_eq!(next(1, 3), Position::new(1, 3));
+    }
+}
@@ -4,13 +4,16 @@ use std::{
     fmt,
     io::Result,
     net::SocketAddr,
+    path::Path,
     pin::Pin,
     task::{Context, Poll},
 };
 
 use futures::{AsyncRead, AsyncWrite};
 use tokio::{
-    io::{AsyncRead as _, AsyncWrite as _, ReadBuf, Stdin, Stdout},
+    io::{
+        AsyncRead as _, AsyncWrite as _, DuplexStream, ReadBuf, ReadHalf, Stdin, Stdout, WriteHalf,
+    },
     net::{
         TcpStream,
         tcp::{OwnedReadHalf, OwnedWriteHalf},
@@ -64,6 +67,48 @@ impl Transport {
     }
 }
 
+/**
+    Connects to the Unix domain socket or Windows named pipe at the
+    given path, and creates the reader and writer for the connection.
+*/
+pub(crate) async fn pipe_read_write(
+    path: &Path,
+) -> ServerResult<(LspTransportRead, LspTransportWrite)> {
+    #[cfg(unix)]
+    {
+        let stream = tokio::net::UnixStream::connect(path)
+            .await
+            .map_err(|_| ServerError::PipeConnect(path.to_path_buf()))?;
+        let (read, write) = stream.into_split();
+        Ok((LspTransportRead::Pipe(read), LspTransportWrite::Pipe(write)))
+    }
+
+    #[cfg(windows)]
+    {
+        let client = tokio::net::windows::named_pipe::ClientOptions::new()
+            .open(path)
+            .map_err(|_| ServerError::PipeConnect(path.to_path_buf()))?;
+        let (read, write) = tokio::io::split(client);
+        Ok((LspTransportRead::Pipe(read), LspTransportWrite::Pipe(write)))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(ServerError::PipeConnect(path.to_path_buf()))
+    }
+}
+
+/**
+    Creates the reader and writer for one end of an in-memory duplex stream.
+*/
+pub(crate) fn duplex_read_write(stream: DuplexStream) -> (LspTransportRead, LspTransportWrite) {
+    let (read, write) = tokio::io::split(stream);
+    (
+        LspTransportRead::Duplex(read),
+        LspTransportWrite::Duplex(write),
+    )
+}
+
 impl fmt::Display for Transport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -80,6 +125,11 @@ impl fmt::Display for Transport {
 pub enum LspTransportRead {
     Socket(OwnedReadHalf),
     Stdio(Stdin),
+    Duplex(ReadHalf<DuplexStream>),
+    #[cfg(unix)]
+    Pipe(tokio::net::unix::OwnedReadHalf),
+    #[cfg(windows)]
+    Pipe(ReadHalf<tokio::net::windows::named_pipe::NamedPipeClient>),
 }
 
 impl AsyncRead for LspTransportRead {
@@ -93,6 +143,9 @@ impl AsyncRead for LspTransportRead {
         let poll_result = match self.get_mut() {
             Self::Socket(s) => Pin::new(s).poll_read(cx, &mut read_buf),
             Self::Stdio(s) => Pin::new(s).poll_read(cx, &mut read_buf),
+            Self::Duplex(s) => Pin::new(s).poll_read(cx, &mut read_buf),
+            #[cfg(any(unix, windows))]
+            Self::Pipe(s) => Pin::new(s).poll_read(cx, &mut read_buf),
         };
 
         match poll_result {
@@ -110,6 +163,11 @@ impl AsyncRead for LspTransportRead {
 pub enum LspTransportWrite {
     Socket(OwnedWriteHalf),
     Stdio(Stdout),
+    Duplex(WriteHalf<DuplexStream>),
+    #[cfg(unix)]
+    Pipe(tokio::net::unix::OwnedWriteHalf),
+    #[cfg(windows)]
+    Pipe(WriteHalf<tokio::net::windows::named_pipe::NamedPipeClient>),
 }
 
 impl AsyncWrite for LspTransportWrite {
@@ -117,6 +175,9 @@ impl AsyncWrite for LspTransportWrite {
         match self.get_mut() {
             Self::Socket(s) => Pin::new(s).poll_write(cx, buf),
             Self::Stdio(s) => Pin::new(s).poll_write(cx, buf),
+            Self::Duplex(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(any(unix, windows))]
+            Self::Pipe(s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
@@ -124,6 +185,9 @@ impl AsyncWrite for LspTransportWrite {
         match self.get_mut() {
             Self::Socket(s) => Pin::new(s).poll_flush(cx),
             Self::Stdio(s) => Pin::new(s).poll_flush(cx),
+            Self::Duplex(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(any(unix, windows))]
+            Self::Pipe(s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -131,6 +195,9 @@ impl AsyncWrite for LspTransportWrite {
         match self.get_mut() {
             Self::Socket(s) => Pin::new(s).poll_shutdown(cx),
             Self::Stdio(s) => Pin::new(s).poll_shutdown(cx),
+            Self::Duplex(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(any(unix, windows))]
+            Self::Pipe(s) => Pin::new(s).poll_shutdown(cx),
         }
     }
 }
@@ -0,0 +1,147 @@
+use ropey::Rope;
+use tree_sitter::{Query, QueryCursor};
+
+use crate::document::Document;
+
+/**
+    An indentation analyzer for a document, built from the captures of an indents
+    query, such as a grammar's `indents.scm`, which can compute the expected
+    indentation for any line - useful for on-type and range formatting.
+
+    The query should use the common capture names for indents queries:
+
+    - `@indent` or `@indent.begin` for nodes that indent all of their lines,
+      except for the line that they start on
+    - `@outdent`, `@indent.end`, `@indent.branch`, or `@indent.dedent` for
+      nodes that outdent the line they start on, such as closing brackets
+
+    Several indenting nodes starting on the same line only indent once.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Indents {
+    text: Rope,
+    indent_rows: Vec<(usize, usize)>,
+    outdent_points: Vec<(usize, usize)>,
+}
+
+impl Indents {
+    /**
+        Analyzes the indentation of the given document, using the given indents query.
+
+        The query must be compiled for the tree-sitter language of the document.
+        Documents without a syntax tree will never have any indentation.
+    */
+    #[must_use]
+    pub fn new(document: &Document, query: &Query) -> Self {
+        let mut indents = Self {
+            text: document.text().clone(),
+            ..Self::default()
+        };
+
+        let mut cursor = QueryCursor::new();
+        let Some(captures) = document.query_iter(query, &mut cursor) else {
+            return indents;
+        };
+
+        for (name, node) in captures {
+            let start = node.start_position();
+            let end = node.end_position();
+            match name {
+                "indent" | "indent.begin" => indents.indent_rows.push((start.row, end.row)),
+                "outdent" | "indent.end" | "indent.branch" | "indent.dedent" => {
+                    indents.outdent_points.push((start.row, start.column));
+                }
+                _ => {}
+            }
+        }
+
+        indents
+    }
+
+    /**
+        Computes the expected indentation level for the given zero-based line.
+    */
+    #[must_use]
+    pub fn level_for_line(&self, line: usize) -> usize {
+        let mut starts = self
+            .indent_rows
+            .iter()
+            .filter(|(start, end)| *start < line && line <= *end)
+            .map(|(start, _)| *start)
+            .collect::<Vec<_>>();
+        starts.sort_unstable();
+        starts.dedup();
+
+        let outdented = self
+            .first_column(line)
+            .is_some_and(|column| self.outdent_points.contains(&(line, column)));
+
+        starts.len().saturating_sub(usize::from(outdented))
+    }
+
+    /**
+        Computes the expected indentation for the given zero-based line,
+        repeating the given indentation unit, such as a tab or four spaces,
+        once for each level of indentation.
+    */
+    #[must_use]
+    pub fn indentation_for_line(&self, line: usize, unit: &str) -> String {
+        unit.repeat(self.level_for_line(line))
+    }
+
+    /**
+        Finds the byte column of the first non-whitespace character on the given line.
+    */
+    fn first_column(&self, line: usize) -> Option<usize> {
+        if line >= self.text.len_lines() {
+            return None;
+        }
+        let mut column = 0;
+        for c in self.text.line(line).chars() {
+            if c == '\n' || c == '\r' {
+                return None;
+            }
+            if !c.is_whitespace() {
+                return Some(column);
+            }
+            column += c.len_utf8();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Indents;
+
+    /*
+        fn main() {
+            if x {
+                y
+            }
+        }
+    */
+    fn indents() -> Indents {
+        Indents {
+            text: "fn main() {\nif x {\ny\n    }\n}\n".into(),
+            indent_rows: vec![(0, 4), (0, 4), (1, 3)],
+            outdent_points: vec![(3, 4), (4, 0)],
+        }
+    }
+
+    #[test]
+    fn lines_are_indented_by_enclosing_nodes() {
+        let indents = indents();
+        let levels = (0..6)
+            .map(|line| indents.level_for_line(line))
+            .collect::<Vec<_>>();
+        assert_eq!(levels, [0, 1, 2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn indentation_repeats_the_unit() {
+        let indents = indents();
+        assert_eq!(indents.indentation_for_line(2, "  "), "    ");
+        assert_eq!(indents.indentation_for_line(4, "\t"), "");
+    }
+}
@@ -1,10 +1,24 @@
 use std::collections::VecDeque;
 
 use async_lsp::lsp_types::{Position as LspPosition, Range as LspRange};
-use tree_sitter::{Node, Point as TsPoint, Range as TsRange};
+use ropey::Rope;
+use tree_sitter::{
+    Node, ParseOptions, Parser, Point as TsPoint, Range as TsRange, TextProvider, Tree,
+};
 
 use crate::text_utils::Position;
 
+mod indents;
+mod scopes;
+mod semantic_tokens;
+
+pub use self::indents::Indents;
+pub use self::scopes::Scopes;
+pub use self::semantic_tokens::{
+    SemanticTokensMapping, default_semantic_tokens_legend, semantic_tokens_from_highlights,
+    semantic_tokens_from_highlights_with_mapping,
+};
+
 /**
     Converts a tree sitter `Point` to an LSP `Position`
 
@@ -201,6 +215,73 @@ where
     }
 }
 
+/**
+    A [`TextProvider`] that reads the text of nodes directly from the chunks
+    of a [`Rope`], so that queries with text predicates, such as `#eq?` and
+    `#match?`, can run without first copying the text into a contiguous string.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct RopeTextProvider<'a> {
+    text: &'a Rope,
+}
+
+impl<'a> RopeTextProvider<'a> {
+    /**
+        Creates a new text provider for the given text.
+    */
+    #[must_use]
+    pub const fn new(text: &'a Rope) -> Self {
+        Self { text }
+    }
+}
+
+impl<'a> TextProvider<&'a [u8]> for RopeTextProvider<'a> {
+    type I = std::iter::Map<ropey::iter::Chunks<'a>, fn(&'a str) -> &'a [u8]>;
+
+    fn text(&mut self, node: Node) -> Self::I {
+        self.text
+            .byte_slice(node.byte_range())
+            .chunks()
+            .map(str::as_bytes)
+    }
+}
+
+/**
+    Parses the given text using the given parser, reading directly from the
+    chunks of the [`Rope`], without first copying it into a contiguous string.
+
+    See [`Parser::parse`] for more information.
+*/
+pub fn parse_rope(parser: &mut Parser, text: &Rope, old_tree: Option<&Tree>) -> Option<Tree> {
+    parse_rope_with_options(parser, text, old_tree, None)
+}
+
+/**
+    Same as [`parse_rope`], but with additional parsing options,
+    such as a progress callback that may be used to halt parsing.
+
+    See [`Parser::parse_with_options`] for more information.
+*/
+pub fn parse_rope_with_options(
+    parser: &mut Parser,
+    text: &Rope,
+    old_tree: Option<&Tree>,
+    options: Option<ParseOptions>,
+) -> Option<Tree> {
+    let len = text.len_bytes();
+    parser.parse_with_options(
+        &mut |byte, _| {
+            if byte >= len {
+                return &[][..];
+            }
+            let (chunk, chunk_start, _, _) = text.chunk_at_byte(byte);
+            &chunk.as_bytes()[byte - chunk_start..]
+        },
+        old_tree,
+        options,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use tree_sitter::{Point, Range};
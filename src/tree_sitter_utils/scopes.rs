@@ -0,0 +1,222 @@
+use std::cmp::Reverse;
+
+use async_lsp::lsp_types::{Position, Range};
+use tree_sitter::{Query, QueryCursor};
+
+use crate::document::Document;
+
+/**
+    A scope analyzer for a document, built from the captures of a
+    locals query, such as a grammar's `locals.scm`, which can find the
+    definition of identifiers, and all references to a definition.
+
+    The query should use the standard capture names for locals queries:
+
+    - `@local.scope` for nodes that introduce a new scope
+    - `@local.definition` for identifiers that define a local,
+      including any suffixed names such as `@local.definition.var`
+    - `@local.reference` for identifiers that reference a local
+
+    References resolve to the closest definition with the same name that
+    comes before the reference, in the innermost scope that contains both.
+    References without any such definition are assumed to be globals.
+
+    # LSP Compatibility
+
+    All positions and ranges use UTF-8 encoding, and must be
+    converted to the encoding negotiated with the client.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Scopes {
+    scope_ranges: Vec<Range>,
+    definitions: Vec<Local>,
+    references: Vec<Local>,
+}
+
+#[derive(Debug, Clone)]
+struct Local {
+    name: String,
+    range: Range,
+}
+
+impl Scopes {
+    /**
+        Analyzes the scopes of the given document, using the given locals query.
+
+        The query must be compiled for the tree-sitter language of the document.
+        Documents without a syntax tree will have no scopes, definitions, or references.
+    */
+    #[must_use]
+    pub fn new(document: &Document, query: &Query) -> Self {
+        let mut scopes = Self::default();
+
+        let mut cursor = QueryCursor::new();
+        let Some(captures) = document.query_iter(query, &mut cursor) else {
+            return scopes;
+        };
+
+        for (name, node) in captures {
+            let range = super::ts_range_to_lsp_range(node.range());
+            if name == "local.scope" {
+                scopes.scope_ranges.push(range);
+            } else if name == "local.definition" || name.starts_with("local.definition.") {
+                scopes.add_definition(document.node_text(node), range);
+            } else if name == "local.reference" {
+                scopes.add_reference(document.node_text(node), range);
+            }
+        }
+
+        scopes
+    }
+
+    fn add_definition(&mut self, name: String, range: Range) {
+        self.definitions.push(Local { name, range });
+    }
+
+    fn add_reference(&mut self, name: String, range: Range) {
+        self.references.push(Local { name, range });
+    }
+
+    /**
+        Finds the range of the definition for the identifier at the given position.
+
+        If the position is on a definition, the range of that definition is returned.
+    */
+    #[must_use]
+    pub fn definition_at(&self, position: Position) -> Option<Range> {
+        self.definition_index_at(position)
+            .map(|index| self.definitions[index].range)
+    }
+
+    /**
+        Finds the ranges of all references to the definition of the identifier at the
+        given position, sorted by their position, optionally including the definition.
+    */
+    #[must_use]
+    pub fn references_at(&self, position: Position, include_definition: bool) -> Vec<Range> {
+        let Some(index) = self.definition_index_at(position) else {
+            return Vec::new();
+        };
+
+        let mut ranges = self
+            .references
+            .iter()
+            .filter(|reference| self.resolve(reference) == Some(index))
+            .map(|reference| reference.range)
+            .collect::<Vec<_>>();
+        if include_definition {
+            ranges.push(self.definitions[index].range);
+        }
+
+        ranges.sort_by_key(|range| range.start);
+        ranges.dedup();
+        ranges
+    }
+
+    fn definition_index_at(&self, position: Position) -> Option<usize> {
+        if let Some(index) = self
+            .definitions
+            .iter()
+            .position(|definition| range_contains(definition.range, position))
+        {
+            return Some(index);
+        }
+        self.references
+            .iter()
+            .find(|reference| range_contains(reference.range, position))
+            .and_then(|reference| self.resolve(reference))
+    }
+
+    fn resolve(&self, reference: &Local) -> Option<usize> {
+        // NOTE: Scopes that contain both the definition and the reference are
+        // always nested within each other, so the innermost one starts last
+        self.definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, definition)| {
+                definition.name == reference.name && definition.range.start <= reference.range.start
+            })
+            .filter_map(|(index, definition)| {
+                let scope = self.innermost_scope(definition.range);
+                let visible =
+                    scope.is_none_or(|scope| range_contains_range(scope, reference.range));
+                let depth = scope.map(|scope| (scope.start, Reverse(scope.end)));
+                visible.then_some((depth, index))
+            })
+            .max()
+            .map(|(_, index)| index)
+    }
+
+    fn innermost_scope(&self, range: Range) -> Option<Range> {
+        self.scope_ranges
+            .iter()
+            .filter(|scope| range_contains_range(**scope, range))
+            .max_by_key(|scope| (scope.start, Reverse(scope.end)))
+            .copied()
+    }
+}
+
+fn range_contains(range: Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+fn range_contains_range(outer: Range, inner: Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{Position, Range};
+
+    use super::Scopes;
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    /*
+        let x = 1;
+        { let x = 2; x }
+        x
+    */
+    fn scopes() -> Scopes {
+        let mut scopes = Scopes::default();
+        scopes.scope_ranges.push(range(1, 0, 16));
+        scopes.add_definition("x".into(), range(0, 4, 5));
+        scopes.add_definition("x".into(), range(1, 6, 7));
+        scopes.add_reference("x".into(), range(1, 13, 14));
+        scopes.add_reference("x".into(), range(2, 0, 1));
+        scopes
+    }
+
+    #[test]
+    fn references_resolve_to_the_innermost_definition() {
+        let scopes = scopes();
+        assert_eq!(
+            scopes.definition_at(Position::new(1, 13)),
+            Some(range(1, 6, 7))
+        );
+        assert_eq!(
+            scopes.definition_at(Position::new(2, 0)),
+            Some(range(0, 4, 5))
+        );
+        assert_eq!(
+            scopes.definition_at(Position::new(0, 4)),
+            Some(range(0, 4, 5))
+        );
+        assert_eq!(scopes.definition_at(Position::new(0, 0)), None);
+    }
+
+    #[test]
+    fn references_are_found_for_definitions() {
+        let scopes = scopes();
+        assert_eq!(
+            scopes.references_at(Position::new(2, 1), true),
+            [range(0, 4, 5), range(2, 0, 1)]
+        );
+        assert_eq!(
+            scopes.references_at(Position::new(1, 6), false),
+            [range(1, 13, 14)]
+        );
+    }
+}
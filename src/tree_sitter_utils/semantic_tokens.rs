@@ -0,0 +1,618 @@
+use async_lsp::lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensLegend,
+};
+use ropey::Rope;
+use tree_sitter::{Point as TsPoint, Query, QueryCursor, StreamingIterator};
+
+use super::RopeTextProvider;
+use crate::{
+    document::Document,
+    text_utils::{Encoding, Position},
+};
+
+/**
+    The default mapping from highlight capture names, as commonly used
+    in `highlights.scm` queries, to semantic token types.
+
+    Lookups always try the most specific capture name first, so
+    only names that differ from their less specific prefix are listed.
+*/
+const DEFAULT_TOKEN_TYPES: &[(&str, SemanticTokenType)] = &[
+    ("attribute", SemanticTokenType::DECORATOR),
+    ("decorator", SemanticTokenType::DECORATOR),
+    ("comment", SemanticTokenType::COMMENT),
+    ("constant", SemanticTokenType::VARIABLE),
+    ("constant.character", SemanticTokenType::STRING),
+    ("constant.numeric", SemanticTokenType::NUMBER),
+    ("number", SemanticTokenType::NUMBER),
+    ("float", SemanticTokenType::NUMBER),
+    ("boolean", SemanticTokenType::KEYWORD),
+    ("character", SemanticTokenType::STRING),
+    ("class", SemanticTokenType::CLASS),
+    ("constructor", SemanticTokenType::CLASS),
+    ("enum", SemanticTokenType::ENUM),
+    ("enum.variant", SemanticTokenType::ENUM_MEMBER),
+    ("enumMember", SemanticTokenType::ENUM_MEMBER),
+    ("variant", SemanticTokenType::ENUM_MEMBER),
+    ("event", SemanticTokenType::EVENT),
+    ("function", SemanticTokenType::FUNCTION),
+    ("function.macro", SemanticTokenType::MACRO),
+    ("function.method", SemanticTokenType::METHOD),
+    ("method", SemanticTokenType::METHOD),
+    ("macro", SemanticTokenType::MACRO),
+    ("interface", SemanticTokenType::INTERFACE),
+    ("keyword", SemanticTokenType::KEYWORD),
+    ("keyword.modifier", SemanticTokenType::MODIFIER),
+    ("keyword.storage.modifier", SemanticTokenType::MODIFIER),
+    ("keyword.operator", SemanticTokenType::OPERATOR),
+    ("conditional", SemanticTokenType::KEYWORD),
+    ("exception", SemanticTokenType::KEYWORD),
+    ("include", SemanticTokenType::KEYWORD),
+    ("repeat", SemanticTokenType::KEYWORD),
+    ("storageclass", SemanticTokenType::MODIFIER),
+    ("modifier", SemanticTokenType::MODIFIER),
+    ("module", SemanticTokenType::NAMESPACE),
+    ("namespace", SemanticTokenType::NAMESPACE),
+    ("operator", SemanticTokenType::OPERATOR),
+    ("parameter", SemanticTokenType::PARAMETER),
+    ("property", SemanticTokenType::PROPERTY),
+    ("field", SemanticTokenType::PROPERTY),
+    ("string", SemanticTokenType::STRING),
+    ("string.regex", SemanticTokenType::REGEXP),
+    ("string.regexp", SemanticTokenType::REGEXP),
+    ("struct", SemanticTokenType::STRUCT),
+    ("type", SemanticTokenType::TYPE),
+    ("type.enum", SemanticTokenType::ENUM),
+    ("type.enum.variant", SemanticTokenType::ENUM_MEMBER),
+    ("type.parameter", SemanticTokenType::TYPE_PARAMETER),
+    ("type.qualifier", SemanticTokenType::MODIFIER),
+    ("variable", SemanticTokenType::VARIABLE),
+    ("variable.member", SemanticTokenType::PROPERTY),
+    ("variable.other.member", SemanticTokenType::PROPERTY),
+    ("variable.parameter", SemanticTokenType::PARAMETER),
+];
+
+/**
+    The default mapping from parts of highlight capture names,
+    such as the `builtin` in `function.builtin`, to semantic token modifiers.
+*/
+const DEFAULT_TOKEN_MODIFIERS: &[(&str, SemanticTokenModifier)] = &[
+    ("async", SemanticTokenModifier::ASYNC),
+    ("builtin", SemanticTokenModifier::DEFAULT_LIBRARY),
+    ("constant", SemanticTokenModifier::READONLY),
+    ("declaration", SemanticTokenModifier::DECLARATION),
+    ("definition", SemanticTokenModifier::DEFINITION),
+    ("deprecated", SemanticTokenModifier::DEPRECATED),
+    ("documentation", SemanticTokenModifier::DOCUMENTATION),
+    ("readonly", SemanticTokenModifier::READONLY),
+    ("static", SemanticTokenModifier::STATIC),
+];
+
+/**
+    A mapping from highlight capture names, such as `function` or
+    `string.special`, to semantic token types and modifiers.
+
+    The default mapping covers the capture names commonly used in
+    `highlights.scm` queries, and entries may be added or overridden using
+    [`SemanticTokensMapping::with_token_type`] and
+    [`SemanticTokensMapping::with_token_modifier`].
+*/
+#[derive(Debug, Clone)]
+pub struct SemanticTokensMapping {
+    token_types: Vec<(String, SemanticTokenType)>,
+    token_modifiers: Vec<(String, SemanticTokenModifier)>,
+}
+
+impl SemanticTokensMapping {
+    /**
+        Creates a new mapping without any entries.
+
+        Use [`SemanticTokensMapping::default`] to start from the default mapping instead.
+    */
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            token_types: Vec::new(),
+            token_modifiers: Vec::new(),
+        }
+    }
+
+    /**
+        Maps the given capture name, and any more specific capture names
+        that are not mapped themselves, to the given semantic token type.
+
+        Replaces any previous token type for the same capture name.
+    */
+    #[must_use]
+    pub fn with_token_type(
+        mut self,
+        capture_name: impl Into<String>,
+        token_type: SemanticTokenType,
+    ) -> Self {
+        upsert(&mut self.token_types, capture_name.into(), token_type);
+        self
+    }
+
+    /**
+        Maps the given part of capture names, such as the `builtin`
+        in `function.builtin`, to the given semantic token modifier.
+
+        Replaces any previous token modifier for the same part.
+    */
+    #[must_use]
+    pub fn with_token_modifier(
+        mut self,
+        capture_part: impl Into<String>,
+        token_modifier: SemanticTokenModifier,
+    ) -> Self {
+        upsert(
+            &mut self.token_modifiers,
+            capture_part.into(),
+            token_modifier,
+        );
+        self
+    }
+
+    /**
+        Finds the semantic token type for the given capture name, trying the most
+        specific name first - `function.method.builtin` will try
+        `function.method.builtin`, `function.method`, and finally `function`.
+    */
+    #[must_use]
+    pub fn token_type(&self, capture_name: &str) -> Option<&SemanticTokenType> {
+        capture_name_prefixes(capture_name).find_map(|prefix| self.exact_token_type(prefix))
+    }
+
+    /**
+        Finds the semantic token modifiers for all parts of the given capture name.
+    */
+    #[must_use]
+    pub fn token_modifiers(&self, capture_name: &str) -> Vec<SemanticTokenModifier> {
+        capture_name
+            .split('.')
+            .filter_map(|part| self.exact_token_modifier(part))
+            .cloned()
+            .collect()
+    }
+
+    /**
+        Creates a semantic tokens legend, containing all of the
+        token types and modifiers that capture names are mapped to.
+
+        This legend should be sent to the client in the server capabilities,
+        and then used with [`semantic_tokens_from_highlights_with_mapping`].
+    */
+    #[must_use]
+    pub fn legend(&self) -> SemanticTokensLegend {
+        let mut token_types = Vec::<SemanticTokenType>::new();
+        for (_, token_type) in &self.token_types {
+            if !token_types.contains(token_type) {
+                token_types.push(token_type.clone());
+            }
+        }
+
+        let mut token_modifiers = Vec::<SemanticTokenModifier>::new();
+        for (_, token_modifier) in &self.token_modifiers {
+            if !token_modifiers.contains(token_modifier) {
+                token_modifiers.push(token_modifier.clone());
+            }
+        }
+
+        SemanticTokensLegend {
+            token_types,
+            token_modifiers,
+        }
+    }
+
+    fn exact_token_type(&self, capture_name: &str) -> Option<&SemanticTokenType> {
+        self.token_types
+            .iter()
+            .find(|(name, _)| name == capture_name)
+            .map(|(_, token_type)| token_type)
+    }
+
+    fn exact_token_modifier(&self, capture_part: &str) -> Option<&SemanticTokenModifier> {
+        self.token_modifiers
+            .iter()
+            .find(|(part, _)| part == capture_part)
+            .map(|(_, token_modifier)| token_modifier)
+    }
+}
+
+impl Default for SemanticTokensMapping {
+    fn default() -> Self {
+        Self {
+            token_types: to_owned_entries(DEFAULT_TOKEN_TYPES),
+            token_modifiers: to_owned_entries(DEFAULT_TOKEN_MODIFIERS),
+        }
+    }
+}
+
+fn to_owned_entries<T: Clone>(entries: &[(&str, T)]) -> Vec<(String, T)> {
+    entries
+        .iter()
+        .map(|(name, value)| ((*name).to_string(), value.clone()))
+        .collect()
+}
+
+fn upsert<T>(entries: &mut Vec<(String, T)>, name: String, value: T) {
+    if let Some(entry) = entries.iter_mut().find(|(n, _)| *n == name) {
+        entry.1 = value;
+    } else {
+        entries.push((name, value));
+    }
+}
+
+/**
+    Creates the default semantic tokens legend, containing all of the token
+    types and modifiers that highlight captures are mapped to by default.
+
+    This legend should be sent to the client in the server capabilities,
+    and then used with [`semantic_tokens_from_highlights`].
+*/
+#[must_use]
+pub fn default_semantic_tokens_legend() -> SemanticTokensLegend {
+    SemanticTokensMapping::default().legend()
+}
+
+/**
+    Produces semantic tokens for a document, using the captures
+    of a highlights query, such as a grammar's `highlights.scm`.
+
+    Capture names are matched against the token types in the legend, trying
+    the most specific name first - `function.method.builtin` will try
+    `function.method.builtin`, `function.method`, and finally `function`.
+    Names that are not in the legend are mapped using the default
+    [`SemanticTokensMapping`], and any parts of the capture name that
+    match a token modifier in the legend are added as modifiers.
+
+    When captures overlap, the capture that starts first is used, and
+    captures spanning multiple lines are split into one token per line.
+    Captures with no matching token type, and documents without
+    a tree-sitter tree, produce no tokens.
+
+    # LSP Compatibility
+
+    Positions in semantic tokens must use the position encoding negotiated
+    with the client, which should be given as `encoding`, and is
+    available using [`ServerState::position_encoding`].
+
+    [`ServerState::position_encoding`]: crate::server::ServerState::position_encoding
+*/
+#[must_use]
+pub fn semantic_tokens_from_highlights(
+    document: &Document,
+    query: &Query,
+    legend: &SemanticTokensLegend,
+    encoding: Encoding,
+) -> SemanticTokens {
+    let mapping = SemanticTokensMapping::default();
+    semantic_tokens_from_highlights_with_mapping(document, query, legend, &mapping, encoding)
+}
+
+/**
+    Produces semantic tokens for a document, using the captures of a highlights
+    query, and the given mapping for capture names that are not in the legend.
+
+    See [`semantic_tokens_from_highlights`] for more information.
+*/
+#[must_use]
+pub fn semantic_tokens_from_highlights_with_mapping(
+    document: &Document,
+    query: &Query,
+    legend: &SemanticTokensLegend,
+    mapping: &SemanticTokensMapping,
+    encoding: Encoding,
+) -> SemanticTokens {
+    let Some(root) = document.node_at_root() else {
+        return SemanticTokens::default();
+    };
+
+    let capture_tokens = query
+        .capture_names()
+        .iter()
+        .map(|name| capture_token(name, legend, mapping))
+        .collect::<Vec<_>>();
+
+    let text = document.text();
+    let line_index = document.line_index();
+    let text_provider = RopeTextProvider::new(text);
+
+    let mut cursor = QueryCursor::new();
+    let mut it = cursor.captures(query, root, text_provider);
+
+    let mut tokens = Vec::new();
+    let mut last_end = 0;
+    while let Some((matched, index)) = it.next() {
+        let capture = matched.captures[*index];
+        let Some((token_type, token_modifiers)) = capture_tokens[capture.index as usize] else {
+            continue;
+        };
+
+        // NOTE: Captures are ordered by their start, so any capture
+        // starting before the end of the previous token is nested
+        // inside of it, and the outer capture takes precedence
+        let node = capture.node;
+        if node.start_byte() < last_end {
+            continue;
+        }
+        last_end = node.end_byte();
+
+        for (line, start, end) in split_lines(text, node.start_position(), node.end_position()) {
+            let convert = |col| {
+                let position = Position { line, col };
+                line_index
+                    .position_to_encoding(position, Encoding::UTF8, encoding)
+                    .col
+            };
+            let (start, end) = (convert(start), convert(end));
+            if end > start {
+                tokens.push(AbsoluteToken {
+                    line,
+                    col: start,
+                    length: end - start,
+                    token_type,
+                    token_modifiers,
+                });
+            }
+        }
+    }
+
+    SemanticTokens {
+        result_id: None,
+        data: encode_tokens(tokens),
+    }
+}
+
+/**
+    Finds the index of the token type, and the bitset of token modifiers,
+    in the given legend for a single highlight capture name.
+*/
+fn capture_token(
+    name: &str,
+    legend: &SemanticTokensLegend,
+    mapping: &SemanticTokensMapping,
+) -> Option<(u32, u32)> {
+    let type_index = |token_type: &str| {
+        legend
+            .token_types
+            .iter()
+            .position(|t| t.as_str() == token_type)
+    };
+
+    let token_type = capture_name_prefixes(name).find_map(|prefix| {
+        type_index(prefix).or_else(|| {
+            mapping
+                .exact_token_type(prefix)
+                .and_then(|token_type| type_index(token_type.as_str()))
+        })
+    })?;
+
+    let mut token_modifiers = 0u32;
+    for part in name.split('.') {
+        let modifier = mapping
+            .exact_token_modifier(part)
+            .map_or(part, SemanticTokenModifier::as_str);
+        if let Some(index) = legend
+            .token_modifiers
+            .iter()
+            .position(|m| m.as_str() == modifier)
+            .filter(|index| *index < 32)
+        {
+            token_modifiers |= 1 << index;
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    Some((token_type as u32, token_modifiers))
+}
+
+/**
+    Iterates over the given capture name, and all of its less specific
+    prefixes, such as `function.method`, followed by `function`.
+*/
+fn capture_name_prefixes(name: &str) -> impl Iterator<Item = &str> {
+    let mut next = Some(name);
+    std::iter::from_fn(move || {
+        let current = next?;
+        next = current.rfind('.').map(|index| &current[..index]);
+        Some(current)
+    })
+}
+
+/**
+    Splits a range in the given text into one `(line, start, end)`
+    range per line, excluding any line endings, using UTF-8 columns.
+*/
+fn split_lines(text: &Rope, start: TsPoint, end: TsPoint) -> Vec<(usize, usize, usize)> {
+    if start.row == end.row {
+        return vec![(start.row, start.column, end.column)];
+    }
+
+    let mut lines = Vec::new();
+    for row in start.row..=end.row {
+        let first = if row == start.row { start.column } else { 0 };
+        let last = if row == end.row {
+            end.column
+        } else if row < text.len_lines() {
+            let line = text.line(row);
+            let mut len = line.len_bytes();
+            while len > 0 && matches!(line.byte(len - 1), b'\n' | b'\r') {
+                len -= 1;
+            }
+            len
+        } else {
+            0
+        };
+        lines.push((row, first, last));
+    }
+    lines
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AbsoluteToken {
+    line: usize,
+    col: usize,
+    length: usize,
+    token_type: u32,
+    token_modifiers: u32,
+}
+
+/**
+    Encodes tokens with absolute positions, sorted by their position,
+    into tokens relative to the previous token, as the LSP requires.
+*/
+#[allow(clippy::cast_possible_truncation)]
+fn encode_tokens(tokens: impl IntoIterator<Item = AbsoluteToken>) -> Vec<SemanticToken> {
+    let mut prev_line = 0;
+    let mut prev_col = 0;
+    tokens
+        .into_iter()
+        .map(|token| {
+            let delta_line = token.line - prev_line;
+            let delta_start = if delta_line == 0 {
+                token.col - prev_col
+            } else {
+                token.col
+            };
+            prev_line = token.line;
+            prev_col = token.col;
+            SemanticToken {
+                delta_line: delta_line as u32,
+                delta_start: delta_start as u32,
+                length: token.length as u32,
+                token_type: token.token_type,
+                token_modifiers_bitset: token.token_modifiers,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types::{
+        SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+    };
+    use ropey::Rope;
+    use tree_sitter::Point;
+
+    use super::{
+        AbsoluteToken, SemanticTokensMapping, capture_token, default_semantic_tokens_legend,
+        encode_tokens, split_lines,
+    };
+
+    fn index_of(legend: &SemanticTokensLegend, token_type: &SemanticTokenType) -> u32 {
+        let index = legend.token_types.iter().position(|t| t == token_type);
+        u32::try_from(index.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn tokens_are_delta_encoded() {
+        let token = |line, col, length| AbsoluteToken {
+            line,
+            col,
+            length,
+            token_type: 1,
+            token_modifiers: 0,
+        };
+        let encoded = encode_tokens([token(2, 4, 3), token(2, 10, 1), token(5, 2, 6)]);
+        let expected =
+            [(2, 4, 3), (0, 6, 1), (3, 2, 6)].map(|(delta_line, delta_start, length)| {
+                SemanticToken {
+                    delta_line,
+                    delta_start,
+                    length,
+                    token_type: 1,
+                    token_modifiers_bitset: 0,
+                }
+            });
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn capture_names_fall_back_to_less_specific_names() {
+        let legend = default_semantic_tokens_legend();
+        let mapping = SemanticTokensMapping::default();
+        let method = index_of(&legend, &SemanticTokenType::METHOD);
+        let function = index_of(&legend, &SemanticTokenType::FUNCTION);
+        assert_eq!(
+            capture_token("function.method", &legend, &mapping),
+            Some((method, 0))
+        );
+        assert_eq!(
+            capture_token("function.call", &legend, &mapping),
+            Some((function, 0))
+        );
+        assert_eq!(
+            capture_token("punctuation.bracket", &legend, &mapping),
+            None
+        );
+
+        let library = legend
+            .token_modifiers
+            .iter()
+            .position(|m| *m == SemanticTokenModifier::DEFAULT_LIBRARY)
+            .unwrap();
+        assert_eq!(
+            capture_token("function.builtin", &legend, &mapping),
+            Some((function, 1 << library))
+        );
+    }
+
+    #[test]
+    fn capture_names_prefer_custom_legend_types() {
+        let mapping = SemanticTokensMapping::default();
+        let legend = SemanticTokensLegend {
+            token_types: vec![
+                SemanticTokenType::new("punctuation"),
+                SemanticTokenType::STRING,
+            ],
+            token_modifiers: vec![],
+        };
+        assert_eq!(
+            capture_token("punctuation.bracket", &legend, &mapping),
+            Some((0, 0))
+        );
+        assert_eq!(
+            capture_token("string.special", &legend, &mapping),
+            Some((1, 0))
+        );
+        assert_eq!(capture_token("keyword", &legend, &mapping), None);
+    }
+
+    #[test]
+    fn mappings_can_be_overridden() {
+        let mapping = SemanticTokensMapping::default()
+            .with_token_type("keyword.return", SemanticTokenType::MACRO)
+            .with_token_type("label", SemanticTokenType::new("label"));
+        assert_eq!(
+            mapping.token_type("keyword.return.value"),
+            Some(&SemanticTokenType::MACRO)
+        );
+        assert_eq!(
+            mapping.token_type("keyword.function"),
+            Some(&SemanticTokenType::KEYWORD)
+        );
+        assert_eq!(
+            mapping.token_modifiers("variable.builtin.static"),
+            [
+                SemanticTokenModifier::DEFAULT_LIBRARY,
+                SemanticTokenModifier::STATIC
+            ]
+        );
+
+        let legend = mapping.legend();
+        let label = index_of(&legend, &SemanticTokenType::new("label"));
+        assert_eq!(
+            capture_token("label.special", &legend, &mapping),
+            Some((label, 0))
+        );
+    }
+
+    #[test]
+    fn multiline_ranges_are_split_per_line() {
+        let text = Rope::from_str("a = \"one\r\ntwo\nthree\"\n");
+        let lines = split_lines(&text, Point::new(0, 4), Point::new(2, 6));
+        assert_eq!(lines, [(0, 4, 8), (1, 0, 3), (2, 0, 6)]);
+    }
+}
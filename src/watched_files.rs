@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use async_lsp::lsp_types::{
+    DidChangeWatchedFilesRegistrationOptions, FileEvent, FileSystemWatcher, GlobPattern,
+    Registration, RegistrationParams, request::RegisterCapability,
+};
+
+use crate::{server_state::ServerState, server_trait::Server, workspace_index};
+
+/**
+    Returns `true` if the client supports dynamically registering
+    file watchers, meaning we do not need to watch files ourselves.
+*/
+pub(crate) fn client_can_watch(state: &ServerState) -> bool {
+    state
+        .client_capabilities()
+        .workspace
+        .as_ref()
+        .and_then(|w| w.did_change_watched_files.as_ref())
+        .and_then(|d| d.dynamic_registration)
+        .unwrap_or(false)
+}
+
+/**
+    Asks the client to watch all files matched by the document matchers
+    of the server, and send `workspace/didChangeWatchedFiles` for them.
+*/
+pub(crate) fn register(state: ServerState) {
+    let globs = state.watched_url_globs();
+    if globs.is_empty() {
+        return;
+    }
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    let watchers = globs
+        .into_iter()
+        .map(|glob| FileSystemWatcher {
+            glob_pattern: GlobPattern::String(glob),
+            kind: None,
+        })
+        .collect();
+    let register_options = DidChangeWatchedFilesRegistrationOptions { watchers };
+
+    handle.spawn(async move {
+        let _ = state
+            .client()
+            .request::<RegisterCapability>(RegistrationParams {
+                registrations: vec![Registration {
+                    id: "async-language-server.watchedFiles".into(),
+                    method: "workspace/didChangeWatchedFiles".into(),
+                    register_options: serde_json::to_value(register_options).ok(),
+                }],
+            })
+            .await;
+    });
+}
+
+/**
+    Refreshes tracked and indexed documents that are not opened
+    by the client, after their files were changed on disk.
+*/
+pub(crate) fn apply_changes<T>(
+    server: &Arc<T>,
+    state: &ServerState,
+    indexing: bool,
+    changes: impl IntoIterator<Item = FileEvent>,
+) where
+    T: Server + Send + Sync + 'static,
+{
+    for change in changes {
+        state.handle_watched_file_change(&change.uri, change.typ);
+        if indexing {
+            workspace_index::index_changed_file(
+                Arc::clone(server),
+                state.clone(),
+                &change.uri,
+                change.typ,
+            );
+        }
+    }
+}
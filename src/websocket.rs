@@ -0,0 +1,325 @@
+use std::io;
+
+use futures::{
+    SinkExt, StreamExt,
+    future::{Either, select},
+    stream::{SplitSink, SplitStream},
+};
+use tokio::{
+    io::{
+        AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf,
+    },
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+use tokio_tungstenite::{
+    WebSocketStream, accept_async,
+    tungstenite::{Message, Utf8Bytes},
+};
+
+use crate::{
+    result::{ServerError, ServerResult},
+    serve::serve_read_write,
+    server_trait::Server,
+    transport::duplex_read_write,
+};
+
+const DUPLEX_BUFFER_SIZE: usize = 64 * 1024;
+
+type MessageSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+type MessageStream = SplitStream<WebSocketStream<TcpStream>>;
+
+/**
+    Serves a language server over WebSocket connections, accepted on the given address.
+
+    Each message sent over the WebSocket must contain exactly one JSON-RPC
+    message, without any `Content-Length` header, which is the format used by
+    browser-based editors such as Monaco and `CodeMirror` with their LSP clients.
+
+    Every accepted connection gets its own clone of the server, and its own state,
+    just as if it had been started separately using [`serve`](crate::server::serve).
+
+    This will keep accepting connections until an error occurs while accepting.
+
+    # Errors
+
+    - If the address could not be bound to
+    - If an I/O error occurs while accepting a connection
+*/
+pub async fn serve_websocket<S>(server: S, addr: impl ToSocketAddrs) -> ServerResult<()>
+where
+    S: Server + Clone,
+    S: Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            #[allow(unused_variables)]
+            if let Err(e) = serve_websocket_connection(stream, server).await {
+                #[cfg(feature = "tracing")]
+                tracing::error!("WebSocket connection failed: {e}");
+            }
+        });
+    }
+}
+
+async fn serve_websocket_connection<S>(stream: TcpStream, server: S) -> ServerResult<()>
+where
+    S: Server + Clone,
+    S: Send + Sync + 'static,
+{
+    let socket = accept_async(stream).await.map_err(ServerError::unknown)?;
+    let (sink, messages) = socket.split();
+
+    let (ours, theirs) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+    let (mut ours_read, mut ours_write) = tokio::io::split(ours);
+
+    // NOTE: The server only stops once its input ends, so whichever side of the
+    // connection ends first must stop the other pump and shut down the duplex,
+    // or a client that disconnects without a close frame would keep it running
+    let pumps = async move {
+        let (result, closed) = {
+            let incoming = std::pin::pin!(pump_incoming(messages, &mut ours_write));
+            let outgoing = std::pin::pin!(pump_outgoing(&mut ours_read, sink));
+            match select(incoming, outgoing).await {
+                Either::Left((result, _)) => {
+                    let closed = result.is_ok();
+                    (result, closed)
+                }
+                Either::Right((result, _)) => (result, false),
+            }
+        };
+        let _ = ours_write.shutdown().await;
+        (result, closed)
+    };
+
+    let (reader, writer) = duplex_read_write(theirs);
+    let (served, (pumped, closed)) =
+        futures::join!(serve_read_write(reader, writer, server), pumps);
+
+    // NOTE: The server sees the end of its input as an unexpected EOF, but
+    // that is how every connection ends once the client has disconnected
+    let served = match served {
+        Err(ServerError::Lsp(async_lsp::Error::Eof)) if closed => Ok(()),
+        served => served,
+    };
+    served.and(pumped)
+}
+
+/**
+    Reads WebSocket messages from the client and forwards
+    them to the server, using regular LSP message framing.
+*/
+async fn pump_incoming(
+    mut messages: MessageStream,
+    lsp: &mut WriteHalf<DuplexStream>,
+) -> ServerResult<()> {
+    while let Some(message) = messages.next().await {
+        let message = message.map_err(ServerError::unknown)?;
+        let payload: &[u8] = match &message {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(bytes) => bytes,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let header = format!("Content-Length: {}\r\n\r\n", payload.len());
+        lsp.write_all(header.as_bytes()).await?;
+        lsp.write_all(payload).await?;
+        lsp.flush().await?;
+    }
+    Ok(())
+}
+
+/**
+    Reads LSP messages from the server, and sends them
+    to the client as WebSocket text messages without headers.
+*/
+async fn pump_outgoing(
+    lsp: &mut ReadHalf<DuplexStream>,
+    mut sink: MessageSink,
+) -> ServerResult<()> {
+    let mut lsp = BufReader::new(lsp);
+    while let Some(message) = read_lsp_message(&mut lsp).await? {
+        let text = Utf8Bytes::try_from(message).map_err(ServerError::unknown)?;
+        sink.send(Message::Text(text))
+            .await
+            .map_err(ServerError::unknown)?;
+    }
+    sink.close().await.map_err(ServerError::unknown)
+}
+
+async fn read_lsp_message<R>(read: &mut R) -> io::Result<Option<Vec<u8>>>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if read.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "LSP message is missing Content-Length",
+        )
+    })?;
+    let mut message = vec![0; length];
+    read.read_exact(&mut message).await?;
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+    use tokio_tungstenite::{client_async, tungstenite::Message};
+
+    use crate::server::{Server, ServerResult};
+
+    use super::{read_lsp_message, serve_websocket_connection};
+
+    const INITIALIZE: &str =
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#;
+
+    #[derive(Clone)]
+    struct TestServer;
+
+    impl Server for TestServer {}
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime can be created")
+    }
+
+    async fn serve_one() -> (TcpStream, JoinHandle<ServerResult<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_websocket_connection(stream, TestServer).await
+        });
+        (TcpStream::connect(addr).await.unwrap(), handle)
+    }
+
+    async fn finished(handle: JoinHandle<ServerResult<()>>) -> ServerResult<()> {
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("connection finishes")
+            .expect("connection does not panic")
+    }
+
+    async fn raw_handshake(stream: TcpStream, request_line: &str) -> BufReader<TcpStream> {
+        let mut stream = BufReader::new(stream);
+        let request = format!(
+            "{request_line}\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+            Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+            Sec-WebSocket-Version: 13\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream
+    }
+
+    #[test]
+    fn lsp_messages_are_read_using_content_length() {
+        let input = b"Content-Length: 2\r\nContent-Type: x\r\n\r\n{}Content-Length: 4\r\n\r\nnull";
+        let mut input = &input[..];
+
+        futures::executor::block_on(async {
+            let first = read_lsp_message(&mut input).await.unwrap();
+            let second = read_lsp_message(&mut input).await.unwrap();
+            let third = read_lsp_message(&mut input).await.unwrap();
+            assert_eq!(first.as_deref(), Some(&b"{}"[..]));
+            assert_eq!(second.as_deref(), Some(&b"null"[..]));
+            assert_eq!(third, None);
+        });
+    }
+
+    #[test]
+    fn websocket_connections_serve_requests() {
+        runtime().block_on(async {
+            let (stream, handle) = serve_one().await;
+            let (mut socket, _) = client_async("ws://localhost/", stream).await.unwrap();
+
+            socket.send(Message::text(INITIALIZE)).await.unwrap();
+            let response = socket.next().await.unwrap().unwrap();
+            let Message::Text(text) = response else {
+                panic!("expected text message, got {response:?}");
+            };
+            let payload: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(payload["id"], 1);
+            assert!(payload["result"]["capabilities"].is_object());
+
+            socket.close(None).await.unwrap();
+            assert!(finished(handle).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn dropped_connections_stop_the_server() {
+        runtime().block_on(async {
+            let (stream, handle) = serve_one().await;
+            let (mut socket, _) = client_async("ws://localhost/", stream).await.unwrap();
+
+            socket.send(Message::text(INITIALIZE)).await.unwrap();
+            socket.next().await.unwrap().unwrap();
+            drop(socket);
+
+            let _ = finished(handle).await;
+        });
+    }
+
+    #[test]
+    fn non_upgrade_requests_are_rejected() {
+        runtime().block_on(async {
+            let (stream, handle) = serve_one().await;
+            let mut stream = raw_handshake(stream, "POST / HTTP/1.1").await;
+
+            let mut status = String::new();
+            stream.read_line(&mut status).await.unwrap();
+            assert!(!status.contains("101"));
+            assert!(finished(handle).await.is_err());
+        });
+    }
+
+    #[test]
+    fn unmasked_client_frames_are_rejected() {
+        runtime().block_on(async {
+            let (stream, handle) = serve_one().await;
+            let mut stream = raw_handshake(stream, "GET / HTTP/1.1").await;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+            }
+
+            let mut frame = vec![0x81, u8::try_from(INITIALIZE.len()).unwrap()];
+            frame.extend_from_slice(INITIALIZE.as_bytes());
+            stream.write_all(&frame).await.unwrap();
+
+            assert!(finished(handle).await.is_err());
+        });
+    }
+}
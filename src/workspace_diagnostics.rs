@@ -185,10 +185,12 @@ fn disable_workspace_diagnostics(result: &mut InitializeResult) {
 }
 
 fn enable_workspace_folder_tracking(result: &mut InitializeResult) {
-    if result.capabilities.diagnostic_provider.is_none() {
-        return;
+    if result.capabilities.diagnostic_provider.is_some() {
+        enable_workspace_folders(result);
     }
+}
 
+pub(crate) fn enable_workspace_folders(result: &mut InitializeResult) {
     let workspace = result
         .capabilities
         .workspace
@@ -331,6 +333,7 @@ fn apply_enabled(state: ServerState, enabled: bool) {
 }
 
 fn refresh_diagnostics(state: ServerState) {
+    state.diagnostic_results().clear();
     if !state.workspace_diagnostics().can_refresh() {
         return;
     }
@@ -395,13 +398,22 @@ where
             continue;
         };
         let version = doc.version();
+        let previous_result_id = previous_result_ids.get(&url).cloned();
+        if let Some(result) = <crate::requests::DocumentDiagnostics as Request>::unchanged_response(
+            &state,
+            &doc,
+            previous_result_id.as_deref(),
+        ) {
+            push_workspace_reports_from_document_result(&state, url, result, &mut items);
+            continue;
+        }
         let mut result = server
             .document_diagnostics(
                 state.clone(),
                 document_diagnostic_params(
                     url.clone(),
                     identifier.clone(),
-                    previous_result_ids.get(&url).cloned(),
+                    previous_result_id.clone(),
                 ),
             )
             .await
@@ -422,6 +434,13 @@ where
             &doc,
             &mut result,
         );
+        <crate::requests::DocumentDiagnostics as Request>::apply_result_id(
+            &state,
+            &url,
+            Some(version),
+            previous_result_id.as_deref(),
+            &mut result,
+        );
         push_workspace_reports_from_document_result(&state, url, result, &mut items);
     }
 
@@ -0,0 +1,348 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use async_lsp::lsp_types::{DocumentSymbol, FileChangeType, Url};
+use dashmap::DashMap;
+
+use crate::{
+    document::Document,
+    progress::Progress,
+    result::{ServerError, ServerResult},
+    server_state::{ServerState, url_is_in_roots},
+    server_trait::Server,
+    server_with_state::catch_panic,
+    text_utils::read_text_file,
+    workspace_walker::{WorkspaceWalkConfig, WorkspaceWalker, path_to_url},
+};
+
+/**
+    An index of all documents in the workspace folders of the client,
+    together with the symbols that the server extracted from each of them.
+
+    Documents are matched using the [`DocumentMatcher`] globs of the server,
+    and indexed in the background using [`Server::index_document`] - once
+    after the client has been initialized, whenever workspace folders
+    change, and for each individual document whenever it is saved.
+
+    Indexing must be enabled using [`ServerOptions::with_workspace_indexing`],
+    and the index will otherwise always be empty.
+
+    [`DocumentMatcher`]: crate::server::DocumentMatcher
+    [`ServerOptions::with_workspace_indexing`]: crate::server::ServerOptions::with_workspace_indexing
+*/
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceIndex {
+    inner: Arc<WorkspaceIndexInner>,
+}
+
+#[derive(Debug, Default)]
+struct WorkspaceIndexInner {
+    files: DashMap<Url, IndexedFile>,
+    indexing: AtomicBool,
+    generation: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedFile {
+    document: Document,
+    symbols: Vec<DocumentSymbol>,
+}
+
+impl WorkspaceIndex {
+    /**
+        Returns `true` if the workspace is currently being indexed.
+    */
+    #[must_use]
+    pub fn is_indexing(&self) -> bool {
+        self.inner.indexing.load(Ordering::Relaxed)
+    }
+
+    /**
+        Returns the number of documents in the index.
+    */
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inner.files.len()
+    }
+
+    /**
+        Returns `true` if the index contains no documents.
+    */
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.files.is_empty()
+    }
+
+    /**
+        Returns the URLs of all documents in the index, in sorted order.
+    */
+    #[must_use]
+    pub fn urls(&self) -> Vec<Url> {
+        let mut urls: Vec<_> = self
+            .inner
+            .files
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    /**
+        Gets the indexed snapshot of a document by its URL.
+
+        Note that the indexed snapshot may be older than the document that
+        is tracked by the server - use [`ServerState::document`] to get the
+        latest contents of documents that are currently opened by the client.
+
+        Returns `None` if the document is not indexed.
+    */
+    #[must_use]
+    pub fn document(&self, url: &Url) -> Option<Document> {
+        let entry = self.inner.files.get(url)?;
+        Some(entry.document.clone())
+    }
+
+    /**
+        Gets the indexed snapshots of all documents in the index.
+
+        See [`WorkspaceIndex::document`] for more information.
+    */
+    #[must_use]
+    pub fn documents(&self) -> Vec<Document> {
+        self.inner
+            .files
+            .iter()
+            .map(|entry| entry.document.clone())
+            .collect()
+    }
+
+    /**
+        Gets the symbols that were extracted from a document by its URL.
+
+        Returns `None` if the document is not indexed.
+    */
+    #[must_use]
+    pub fn symbols(&self, url: &Url) -> Option<Vec<DocumentSymbol>> {
+        let entry = self.inner.files.get(url)?;
+        Some(entry.symbols.clone())
+    }
+
+    fn insert(&self, document: Document, symbols: Vec<DocumentSymbol>) {
+        self.inner
+            .files
+            .insert(document.url().clone(), IndexedFile { document, symbols });
+    }
+
+    fn begin(&self) -> u64 {
+        self.inner.indexing.store(true, Ordering::Relaxed);
+        self.inner.generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.inner.generation.load(Ordering::Relaxed) == generation
+    }
+
+    fn finish(&self, generation: u64) {
+        if self.is_current(generation) {
+            self.inner.indexing.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/**
+    Indexes all matched documents in the workspace folders in the background,
+    replacing any previous index, and cancelling any indexing that is in progress.
+*/
+pub(crate) fn index_workspace<T>(server: Arc<T>, state: ServerState)
+where
+    T: Server + Send + Sync + 'static,
+{
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    let generation = state.workspace_index().begin();
+    handle.spawn(async move {
+        let result = run_index_workspace(&server, &state, generation).await;
+        state.workspace_index().finish(generation);
+        #[allow(unused_variables)]
+        if let Err(e) = result {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to index workspace: {e}");
+        }
+    });
+}
+
+/**
+    Re-indexes a single tracked document in the background,
+    if it is matched and inside one of the workspace folders.
+*/
+pub(crate) fn index_tracked_document<T>(server: Arc<T>, state: ServerState, url: &Url)
+where
+    T: Server + Send + Sync + 'static,
+{
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    if !is_indexable(&state, url) {
+        return;
+    }
+    let Some(document) = state.document(url) else {
+        return;
+    };
+
+    handle.spawn(async move {
+        index_document(&server, &state, document).await;
+    });
+}
+
+/**
+    Re-indexes a single document in the background after its file was changed
+    on disk, unless it is opened by the client, or removes it if it was deleted.
+*/
+pub(crate) fn index_changed_file<T>(
+    server: Arc<T>,
+    state: ServerState,
+    url: &Url,
+    typ: FileChangeType,
+) where
+    T: Server + Send + Sync + 'static,
+{
+    if typ == FileChangeType::DELETED {
+        state.workspace_index().inner.files.remove(url);
+        return;
+    }
+
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    if state.document_is_open(url) || !is_indexable(&state, url) {
+        return;
+    }
+
+    let url = url.clone();
+    handle.spawn(async move {
+        if let Some(document) = read_document(&state, &url).await {
+            index_document(&server, &state, document).await;
+        }
+    });
+}
+
+fn is_indexable(state: &ServerState, url: &Url) -> bool {
+    state.url_language(url).is_some() && url_is_in_roots(url, &state.workspace_roots())
+}
+
+async fn run_index_workspace<T>(
+    server: &Arc<T>,
+    state: &ServerState,
+    generation: u64,
+) -> ServerResult<()>
+where
+    T: Server + Send + Sync + 'static,
+{
+    let index = state.workspace_index();
+    let urls = workspace_urls(state).await?;
+    if !index.is_current(generation) {
+        return Ok(());
+    }
+
+    let progress = create_progress(state).await;
+    let total = urls.len().max(1);
+    for (indexed, url) in urls.iter().enumerate() {
+        // NOTE: Progress is ended automatically when dropped, and documents
+        // from any previous run are kept in the index if we stop early here
+        if !index.is_current(generation) || progress.as_ref().is_some_and(Progress::is_cancelled) {
+            return Ok(());
+        }
+
+        if let Some(document) = read_document(state, url).await {
+            index_document(server, state, document).await;
+        }
+
+        if let Some(progress) = &progress {
+            let percentage = u32::try_from((indexed + 1) * 100 / total).unwrap_or(100);
+            let _ = progress
+                .report_percentage_with_message(percentage, format!("{}/{total}", indexed + 1));
+        }
+    }
+
+    if index.is_current(generation) {
+        let urls: HashSet<_> = urls.into_iter().collect();
+        index.inner.files.retain(|url, _| urls.contains(url));
+    }
+    if let Some(progress) = progress {
+        let _ = progress.end(None::<String>);
+    }
+
+    Ok(())
+}
+
+async fn workspace_urls(state: &ServerState) -> ServerResult<Vec<Url>> {
+    let roots = state.workspace_roots();
+    if roots.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let paths = tokio::task::spawn_blocking(move || -> ServerResult<Vec<PathBuf>> {
+        WorkspaceWalker::new(&roots, WorkspaceWalkConfig::default())?.files()
+    })
+    .await
+    .map_err(ServerError::unknown)??;
+
+    let mut urls = Vec::new();
+    for path in paths {
+        let url = path_to_url(&path)?;
+        if state.url_language(&url).is_some() {
+            urls.push(url);
+        }
+    }
+    Ok(urls)
+}
+
+async fn read_document(state: &ServerState, url: &Url) -> Option<Document> {
+    // NOTE: Documents opened by the client may have unsaved
+    // changes, and their contents should be preferred if so
+    if let Some(document) = state.document(url) {
+        return Some(document);
+    }
+
+    let path = state.file_path(url)?;
+    let text = tokio::task::spawn_blocking(move || read_text_file(path))
+        .await
+        .ok()?
+        .ok()?;
+    let language = state.url_language(url).unwrap_or_default();
+    Some(state.create_document(url.clone(), text, 0, language))
+}
+
+async fn index_document<T>(server: &Arc<T>, state: &ServerState, document: Document)
+where
+    T: Server + Send + Sync + 'static,
+{
+    let result = catch_panic(
+        "index_document",
+        server.index_document(state.clone(), document.clone()),
+    )
+    .await;
+
+    match result {
+        Some(Ok(symbols)) => state.workspace_index().insert(document, symbols),
+        #[allow(unused_variables)]
+        Some(Err(e)) => {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to index document {}: {e}", document.url());
+        }
+        None => {}
+    }
+}
+
+async fn create_progress(state: &ServerState) -> Option<Progress> {
+    state.create_progress("Indexing workspace").await.ok()
+}